@@ -1,7 +1,7 @@
 use buckle::{
 	config::Config,
-	migration::{plans::migrations, run_migrations},
-	server::Server,
+	migration::{DEFAULT_STATE_PATH, plans::migrations, run_migrations},
+	server::{Server, shutdown_signal},
 };
 
 #[tokio::main]
@@ -10,7 +10,13 @@ pub async fn main() -> Result<(), anyhow::Error> {
 		match std::env::args().nth(1).unwrap().as_str() {
 			"migrate" => {
 				print!("running migrations...");
-				if let Err(e) = run_migrations(migrations(), Default::default()).await {
+				if let Err(e) = run_migrations(
+					migrations(),
+					Default::default(),
+					DEFAULT_STATE_PATH.as_ref(),
+				)
+				.await
+				{
 					println!("error: {}", e);
 				}
 				println!("done.");
@@ -26,11 +32,20 @@ pub async fn main() -> Result<(), anyhow::Error> {
 		Config::default()
 	};
 
-	if let Err(e) = run_migrations(migrations(), Default::default()).await {
+	if let Err(e) = run_migrations(
+		migrations(),
+		Default::default(),
+		DEFAULT_STATE_PATH.as_ref(),
+	)
+	.await
+	{
 		tracing::error!("Error running migrations: {}", e);
 	}
 
-	if let Err(e) = Server::new_with_config(Some(config)).start()?.await {
+	if let Err(e) = Server::new_with_config(Some(config))
+		.start_with_shutdown(shutdown_signal())?
+		.await
+	{
 		tracing::error!("Error while running service: {}", e.to_string());
 		return Err(e.into());
 	}
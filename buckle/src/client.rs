@@ -1,21 +1,29 @@
 use crate::{
 	grpc::{
-		GrpcLogDirection, GrpcLogMessage, GrpcLogParams, GrpcPortForward, GrpcProtocol,
-		GrpcUnitName, GrpcUnitSettings, PingResult, UnitEnabledState, UnitListFilter,
-		UnitRuntimeState, ZfsListFilter, ZfsName,
+		GrpcLogDirection, GrpcLogMessage, GrpcLogParams, GrpcLogPriority, GrpcPortForward,
+		GrpcPortMapping, GrpcProtocol, GrpcUnitName, GrpcUnitSettings, PingResult,
+		UnitEnabledState, UnitRuntimeState, ZfsListFilter, ZfsName, ZfsReceive, ZfsRollback,
+		ZfsSend, ZfsSnapshotName, ZfsStreamChunk,
 		network_client::NetworkClient as GRPCNetworkClient,
+		power_client::PowerClient as GRPCPowerClient,
 		status_client::StatusClient as GRPCStatusClient,
 		systemd_client::SystemdClient as GRPCSystemdClient, zfs_client::ZfsClient as GRPCZfsClient,
 	},
-	systemd::{LogDirection, Unit, UnitSettings},
-	upnp::Protocol,
+	systemd::{
+		LogDirection, LogPriority, PowerAction, Unit, UnitFileChange, UnitFilter, UnitSettings,
+	},
+	upnp::{PortForward, Protocol},
 };
 // we expose these types we should serve them
 pub use crate::{
-	sysinfo::Info,
-	zfs::{Dataset, ModifyDataset, ModifyVolume, Volume, ZFSStat},
+	sysinfo::{DiskInfo, Info, NetworkInfo},
+	zfs::{
+		CloneSnapshot, Dataset, ModifyDataset, ModifyVolume, PoolStatus, ScrubState, ScrubStatus,
+		Volume, ZFSDetail, ZFSKind, ZFSSnapshot, ZFSStat,
+	},
 };
 use std::path::PathBuf;
+use tokio_stream::{Stream, StreamExt};
 use tonic::{Request, Streaming, transport::Channel};
 
 type Result<T> = std::result::Result<T, tonic::Status>;
@@ -33,6 +41,10 @@ pub struct StatusClient {
 	client: GRPCStatusClient<Channel>,
 }
 
+pub struct PowerClient {
+	client: GRPCPowerClient<Channel>,
+}
+
 pub struct ZFSClient {
 	client: GRPCZfsClient<Channel>,
 }
@@ -58,6 +70,12 @@ impl Client {
 		Ok(StatusClient { client })
 	}
 
+	pub async fn power(&self) -> anyhow::Result<PowerClient> {
+		let client =
+			GRPCPowerClient::connect(format!("unix://{}", self.socket.to_str().unwrap())).await?;
+		Ok(PowerClient { client })
+	}
+
 	pub async fn zfs(&self) -> anyhow::Result<ZFSClient> {
 		let client =
 			GRPCZfsClient::connect(format!("unix://{}", self.socket.to_str().unwrap())).await?;
@@ -97,6 +115,30 @@ impl NetworkClient {
 			.await?;
 		Ok(())
 	}
+
+	// fails with NotFound unless buckle made this mapping itself
+	pub async fn remove_port(&mut self, port: u16, protocol: Protocol) -> Result<()> {
+		let protocol: GrpcProtocol = protocol.into();
+		self.client
+			.remove_port(tonic::Request::new(GrpcPortMapping {
+				port: port.into(),
+				protocol: protocol.into(),
+			}))
+			.await?;
+		Ok(())
+	}
+
+	pub async fn list_ports(&mut self) -> Result<Vec<PortForward>> {
+		Ok(self
+			.client
+			.list_ports(tonic::Request::new(()))
+			.await?
+			.into_inner()
+			.ports
+			.into_iter()
+			.map(Into::into)
+			.collect())
+	}
 }
 
 impl SystemdClient {
@@ -114,6 +156,30 @@ impl SystemdClient {
 		Ok(())
 	}
 
+	pub async fn enable(&mut self, name: String) -> Result<Vec<UnitFileChange>> {
+		Ok(self
+			.client
+			.enable_unit(Request::new(GrpcUnitName { name }))
+			.await?
+			.into_inner()
+			.changes
+			.into_iter()
+			.map(Into::into)
+			.collect())
+	}
+
+	pub async fn disable(&mut self, name: String) -> Result<Vec<UnitFileChange>> {
+		Ok(self
+			.client
+			.disable_unit(Request::new(GrpcUnitName { name }))
+			.await?
+			.into_inner()
+			.changes
+			.into_iter()
+			.map(Into::into)
+			.collect())
+	}
+
 	pub async fn unit_info(&mut self, name: String) -> Result<Unit> {
 		let unit = self
 			.client
@@ -128,11 +194,19 @@ impl SystemdClient {
 	}
 
 	pub async fn list(&mut self, filter: Option<String>) -> Result<Vec<Unit>> {
-		let filter = UnitListFilter {
-			filter: filter.unwrap_or_default(),
-		};
+		self.list_filtered(UnitFilter {
+			name: filter,
+			..Default::default()
+		})
+		.await
+	}
 
-		let units = self.client.list(Request::new(filter)).await?.into_inner();
+	pub async fn list_filtered(&mut self, filter: UnitFilter) -> Result<Vec<Unit>> {
+		let units = self
+			.client
+			.list(Request::new(filter.into()))
+			.await?
+			.into_inner();
 		let mut v = Vec::new();
 		for unit in units.items {
 			v.push(unit.into())
@@ -153,7 +227,7 @@ impl SystemdClient {
 
 	pub async fn unit_log(
 		&mut self, name: &str, count: usize, cursor: Option<String>,
-		direction: Option<LogDirection>,
+		direction: Option<LogDirection>, priority: Option<LogPriority>, follow: bool,
 	) -> Result<Streaming<GrpcLogMessage>> {
 		let resp = self
 			.client
@@ -162,6 +236,8 @@ impl SystemdClient {
 				count: count as u64,
 				cursor: cursor.unwrap_or_default(),
 				direction: Into::<GrpcLogDirection>::into(direction.unwrap_or_default()).into(),
+				priority: priority.map(|x| Into::<GrpcLogPriority>::into(x).into()),
+				follow,
 			})
 			.await?
 			.into_inner();
@@ -175,6 +251,17 @@ impl StatusClient {
 	}
 }
 
+impl PowerClient {
+	// returns once the action is queued; the host goes down shortly after
+	pub async fn power(&mut self, action: PowerAction) -> Result<()> {
+		match action {
+			PowerAction::Reboot => self.client.reboot(Request::new(())).await?,
+			PowerAction::Poweroff => self.client.poweroff(Request::new(())).await?,
+		};
+		Ok(())
+	}
+}
+
 impl ZFSClient {
 	pub async fn root_path(&mut self) -> Result<String> {
 		self.client
@@ -183,6 +270,29 @@ impl ZFSClient {
 			.map(|x| x.into_inner().root)
 	}
 
+	pub async fn pool_status(&mut self) -> Result<PoolStatus> {
+		Ok(self
+			.client
+			.pool_status(Request::new(()))
+			.await?
+			.into_inner()
+			.into())
+	}
+
+	pub async fn scrub(&mut self) -> Result<()> {
+		self.client.scrub(Request::new(())).await?;
+		Ok(())
+	}
+
+	pub async fn scrub_status(&mut self) -> Result<ScrubStatus> {
+		Ok(self
+			.client
+			.scrub_status(Request::new(()))
+			.await?
+			.into_inner()
+			.into())
+	}
+
 	pub async fn create_dataset(&mut self, dataset: Dataset) -> Result<()> {
 		self.client
 			.create_dataset(Request::new(dataset.into()))
@@ -220,8 +330,103 @@ impl ZFSClient {
 			.into())
 	}
 
+	pub async fn get(&mut self, name: String) -> Result<ZFSDetail> {
+		Ok(self
+			.client
+			.get(Request::new(ZfsName { name }))
+			.await?
+			.into_inner()
+			.into())
+	}
+
 	pub async fn destroy(&mut self, name: String) -> Result<()> {
 		self.client.destroy(Request::new(ZfsName { name })).await?;
 		Ok(())
 	}
+
+	pub async fn clone_snapshot(&mut self, info: CloneSnapshot) -> Result<()> {
+		self.client
+			.clone_snapshot(Request::new(info.into()))
+			.await?;
+		Ok(())
+	}
+
+	pub async fn promote(&mut self, name: String) -> Result<()> {
+		self.client.promote(Request::new(ZfsName { name })).await?;
+		Ok(())
+	}
+
+	pub async fn snapshot(&mut self, dataset: String, snapshot: String) -> Result<()> {
+		self.client
+			.snapshot(Request::new(ZfsSnapshotName { dataset, snapshot }))
+			.await?;
+		Ok(())
+	}
+
+	pub async fn list_snapshots(&mut self, filter: Option<String>) -> Result<Vec<ZFSSnapshot>> {
+		Ok(self
+			.client
+			.list_snapshots(Request::new(ZfsListFilter { filter }))
+			.await?
+			.into_inner()
+			.into())
+	}
+
+	pub async fn rollback(&mut self, dataset: String, snapshot: String, force: bool) -> Result<()> {
+		self.client
+			.rollback(Request::new(ZfsRollback {
+				dataset,
+				snapshot,
+				force,
+			}))
+			.await?;
+		Ok(())
+	}
+
+	// the stream of `dataset@snapshot`, incremental from `base` if it is given. an error at the
+	// end of the stream means it is incomplete.
+	pub async fn send(
+		&mut self, dataset: String, snapshot: String, base: Option<String>,
+	) -> Result<Streaming<ZfsStreamChunk>> {
+		Ok(self
+			.client
+			.send(Request::new(ZfsSend {
+				dataset,
+				snapshot,
+				base,
+			}))
+			.await?
+			.into_inner())
+	}
+
+	// creates `target` from the chunks of a stream that send() produced
+	pub async fn receive<S>(&mut self, target: String, data: S) -> Result<()>
+	where
+		S: Stream<Item = Vec<u8>> + Send + 'static,
+	{
+		self.client
+			.receive(Request::new(data.map(move |data| ZfsReceive {
+				target: target.clone(),
+				data,
+			})))
+			.await?;
+		Ok(())
+	}
+
+	pub async fn destroy_snapshot(&mut self, dataset: String, snapshot: String) -> Result<()> {
+		self.client
+			.destroy_snapshot(Request::new(ZfsSnapshotName { dataset, snapshot }))
+			.await?;
+		Ok(())
+	}
+
+	pub async fn mount(&mut self, name: String) -> Result<()> {
+		self.client.mount(Request::new(ZfsName { name })).await?;
+		Ok(())
+	}
+
+	pub async fn unmount(&mut self, name: String) -> Result<()> {
+		self.client.unmount(Request::new(ZfsName { name })).await?;
+		Ok(())
+	}
 }
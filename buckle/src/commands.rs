@@ -0,0 +1,79 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::{path::PathBuf, sync::OnceLock};
+
+const ZFS_DEFAULT_COMMAND: &str = "zfs";
+const ZPOOL_DEFAULT_COMMAND: &str = "zpool";
+const PODMAN_DEFAULT_COMMAND: &str = "podman";
+const SYSTEMCTL_DEFAULT_COMMAND: &str = "systemctl";
+
+static COMMAND_PATHS: OnceLock<CommandPaths> = OnceLock::new();
+
+fn default_zfs() -> PathBuf {
+	ZFS_DEFAULT_COMMAND.into()
+}
+
+fn default_zpool() -> PathBuf {
+	ZPOOL_DEFAULT_COMMAND.into()
+}
+
+fn default_podman() -> PathBuf {
+	PODMAN_DEFAULT_COMMAND.into()
+}
+
+fn default_systemctl() -> PathBuf {
+	SYSTEMCTL_DEFAULT_COMMAND.into()
+}
+
+// the programs buckle runs, found on PATH unless configured otherwise.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct CommandPaths {
+	#[serde(default = "default_zfs")]
+	pub zfs: PathBuf,
+	#[serde(default = "default_zpool")]
+	pub zpool: PathBuf,
+	#[serde(default = "default_podman")]
+	pub podman: PathBuf,
+	#[serde(default = "default_systemctl")]
+	pub systemctl: PathBuf,
+}
+
+impl Default for CommandPaths {
+	fn default() -> Self {
+		Self {
+			zfs: default_zfs(),
+			zpool: default_zpool(),
+			podman: default_podman(),
+			systemctl: default_systemctl(),
+		}
+	}
+}
+
+// decided once per process, either by set_command_paths() while starting up or as the defaults
+// when first asked for.
+pub fn command_paths() -> &'static CommandPaths {
+	COMMAND_PATHS.get_or_init(Default::default)
+}
+
+pub fn set_command_paths(paths: CommandPaths) -> Result<()> {
+	COMMAND_PATHS
+		.set(paths)
+		.map_err(|_| anyhow!("command paths were already decided for this process"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::CommandPaths;
+
+	#[test]
+	fn parse() {
+		let paths: CommandPaths = serde_yaml_ng::from_str("zfs: /usr/local/sbin/zfs").unwrap();
+		assert_eq!(
+			paths,
+			CommandPaths {
+				zfs: "/usr/local/sbin/zfs".into(),
+				..Default::default()
+			}
+		);
+	}
+}
@@ -1,4 +1,7 @@
-use crate::zfs::Pool;
+use crate::{
+	commands::{CommandPaths, set_command_paths},
+	zfs::Pool,
+};
 use anyhow::Result;
 use serde::Deserialize;
 use tracing::info;
@@ -6,11 +9,21 @@ use tracing_subscriber::FmtSubscriber;
 
 pub(crate) const CONFIG_PATH: &str = "/trunk/config.yaml";
 pub(crate) const DEFAULT_ZPOOL: &str = "trunk";
+// seconds
+pub const DEFAULT_ZFS_TIMEOUT: u64 = 60;
 
 fn default_zpool() -> String {
 	DEFAULT_ZPOOL.to_string()
 }
 
+fn default_zfs_timeout() -> u64 {
+	DEFAULT_ZFS_TIMEOUT
+}
+
+fn default_port_state() -> std::path::PathBuf {
+	crate::upnp::DEFAULT_PORT_STATE_PATH.into()
+}
+
 #[derive(Debug, Clone, Default, Deserialize)]
 pub enum LogLevel {
 	#[serde(rename = "warn")]
@@ -55,17 +68,31 @@ pub struct Config {
 	pub socket: std::path::PathBuf,
 	pub zfs: ZFSConfig,
 	pub log_level: LogLevel,
+	#[serde(default)]
+	pub commands: CommandPaths,
+	// the port mappings buckle has opened on the router; see upnp::PortTable
+	#[serde(default = "default_port_state")]
+	pub port_state: std::path::PathBuf,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ZFSConfig {
 	#[serde(default = "default_zpool")]
 	pub pool: String,
+	// seconds a zfs command may run before it is killed; a faulted pool can hang them forever
+	#[serde(default = "default_zfs_timeout")]
+	pub timeout: u64,
+	// bytes; given to datasets created without a quota of their own, so one package that
+	// forgot to declare a size can't fill the pool. a dataset can still ask for a quota of 0.
+	#[serde(default)]
+	pub default_quota: Option<u64>,
 }
 
 impl ZFSConfig {
 	pub fn controller(&self) -> Pool {
 		Pool::new(&self.pool)
+			.with_timeout(std::time::Duration::from_secs(self.timeout))
+			.with_default_quota(self.default_quota)
 	}
 }
 
@@ -73,6 +100,7 @@ impl Config {
 	pub fn from_file(filename: std::path::PathBuf) -> Result<Self> {
 		let r = std::fs::OpenOptions::new().read(true).open(filename)?;
 		let this: Self = serde_yaml_ng::from_reader(r)?;
+		set_command_paths(this.commands.clone())?;
 		info!("Configuration parsed successfully.");
 		Ok(this)
 	}
@@ -1,4 +1,5 @@
 pub mod client;
+pub mod commands;
 pub mod config;
 pub(crate) mod grpc;
 pub(crate) mod middleware;
@@ -1,7 +1,7 @@
 #![allow(dead_code, unused_variables, unused_mut)]
 use std::{
 	collections::{HashMap, HashSet},
-	path::PathBuf,
+	path::{Path, PathBuf},
 	pin::Pin,
 	sync::Arc,
 };
@@ -42,31 +42,108 @@ impl From<anyhow::Error> for MigrationError {
 
 pub type Migration = Vec<Box<dyn BoxedMigrationClosure>>;
 
-pub async fn run_migrations<'a>(
-	map: HashMap<&'static str, Migration>, mut state: MigrationState,
-) -> anyhow::Result<()> {
-	let mut completed: HashSet<String> = match std::fs::OpenOptions::new()
-		.read(true)
-		.open("/trunk/.buckle-migrations.json")
-	{
-		Ok(mut f) => {
-			let v: Vec<String> = serde_json::from_reader(&mut f)?;
-			let mut map = HashSet::new();
-
-			for s in v {
-				map.insert(s);
+// a migration and the names of the migrations that must have run before it
+pub struct RegisteredMigration {
+	pub dependencies: Vec<&'static str>,
+	pub migration: Migration,
+}
+
+impl RegisteredMigration {
+	pub fn new(dependencies: Vec<&'static str>, migration: Migration) -> Self {
+		Self {
+			dependencies,
+			migration,
+		}
+	}
+}
+
+// where the names of completed migrations are kept between runs
+pub const DEFAULT_STATE_PATH: &str = "/trunk/.buckle-migrations.json";
+
+// every migration comes after its dependencies. names are visited in sorted order so the result is
+// the same from run to run.
+pub fn migration_order(
+	map: &HashMap<&'static str, RegisteredMigration>,
+) -> anyhow::Result<Vec<&'static str>> {
+	fn visit(
+		name: &'static str, map: &HashMap<&'static str, RegisteredMigration>,
+		chain: &mut Vec<&'static str>, order: &mut Vec<&'static str>,
+	) -> anyhow::Result<()> {
+		if order.contains(&name) {
+			return Ok(());
+		}
+
+		if chain.contains(&name) {
+			chain.push(name);
+			return Err(anyhow::anyhow!(
+				"Migration dependency cycle: {}",
+				chain.join(" -> ")
+			));
+		}
+
+		chain.push(name);
+		let mut dependencies = map[name].dependencies.clone();
+		dependencies.sort();
+
+		for dep in dependencies {
+			if !map.contains_key(dep) {
+				return Err(anyhow::anyhow!(
+					"Migration {} depends on {}, which does not exist",
+					name,
+					dep
+				));
 			}
 
-			map
+			visit(dep, map, chain, order)?;
 		}
-		Err(_) => HashSet::new(),
-	};
 
-	for (name, migration) in map {
+		chain.pop();
+		order.push(name);
+		Ok(())
+	}
+
+	let mut names: Vec<&'static str> = map.keys().copied().collect();
+	names.sort();
+
+	let mut order = Vec::new();
+	for name in names {
+		visit(name, map, &mut Vec::new(), &mut order)?;
+	}
+
+	Ok(order)
+}
+
+pub async fn run_migrations<'a>(
+	mut map: HashMap<&'static str, RegisteredMigration>, mut state: MigrationState,
+	state_path: &Path,
+) -> anyhow::Result<()> {
+	let order = migration_order(&map)?;
+
+	let mut tmp_path = state_path.as_os_str().to_owned();
+	tmp_path.push(".tmp");
+	let tmp_path = PathBuf::from(tmp_path);
+
+	let mut completed: HashSet<String> =
+		match std::fs::OpenOptions::new().read(true).open(state_path) {
+			Ok(mut f) => {
+				let v: Vec<String> = serde_json::from_reader(&mut f)?;
+				let mut map = HashSet::new();
+
+				for s in v {
+					map.insert(s);
+				}
+
+				map
+			}
+			Err(_) => HashSet::new(),
+		};
+
+	for name in order {
 		if completed.contains(name) {
 			continue;
 		}
 
+		let migration = map.remove(name).unwrap().migration;
 		state = run_migration(migration, state.clone()).await?;
 		completed.insert(name.to_string());
 
@@ -74,15 +151,12 @@ pub async fn run_migrations<'a>(
 			.write(true)
 			.create(true)
 			.truncate(true)
-			.open("/trunk/.buckle-migrations.json.tmp")?;
+			.open(&tmp_path)?;
 
 		serde_json::to_writer(&mut f, &completed)?;
 		drop(f);
 
-		std::fs::rename(
-			"/trunk/.buckle-migrations.json.tmp",
-			"/trunk/.buckle-migrations.json",
-		)?;
+		std::fs::rename(&tmp_path, state_path)?;
 	}
 
 	Ok(())
@@ -200,4 +274,73 @@ mod tests {
 
 		assert!(res.is_ok());
 	}
+
+	#[tokio::test]
+	async fn test_run_migrations_state_path() {
+		static RUNS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+		let dir = tempfile::tempdir().unwrap();
+		let state_path = dir.path().join("migrations.json");
+
+		let set = || {
+			let state: MigrationState = Default::default();
+			let mut map: HashMap<&'static str, RegisteredMigration> = HashMap::new();
+			map.insert(
+				"count",
+				RegisteredMigration::new(
+					vec![],
+					build_migration_set!(state, {
+						RUNS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+						Ok(state)
+					}),
+				),
+			);
+			map
+		};
+
+		run_migrations(set(), Default::default(), &state_path)
+			.await
+			.unwrap();
+		assert_eq!(RUNS.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+		let completed: Vec<String> =
+			serde_json::from_reader(std::fs::File::open(&state_path).unwrap()).unwrap();
+		assert_eq!(completed, vec!["count".to_string()]);
+		assert!(!dir.path().join("migrations.json.tmp").exists());
+
+		// already recorded as done, so it is skipped
+		run_migrations(set(), Default::default(), &state_path)
+			.await
+			.unwrap();
+		assert_eq!(RUNS.load(std::sync::atomic::Ordering::SeqCst), 1);
+	}
+
+	#[test]
+	fn test_migration_order() {
+		let graph = |edges: &[(&'static str, &[&'static str])]| {
+			edges
+				.iter()
+				.map(|(name, deps)| (*name, RegisteredMigration::new(deps.to_vec(), Vec::new())))
+				.collect::<HashMap<_, _>>()
+		};
+
+		let order = migration_order(&graph(&[
+			("grafana", &["prometheus"]),
+			("prometheus", &["node-exporter"]),
+			("node-exporter", &[]),
+			("alerts", &["prometheus", "grafana"]),
+		]))
+		.unwrap();
+		assert_eq!(
+			order,
+			vec!["node-exporter", "prometheus", "grafana", "alerts"]
+		);
+
+		let err =
+			migration_order(&graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])])).unwrap_err();
+		assert!(err.to_string().contains("a -> b -> c -> a"), "{}", err);
+
+		let err = migration_order(&graph(&[("a", &["missing"])])).unwrap_err();
+		assert!(err.to_string().contains("depends on missing"), "{}", err);
+	}
 }
@@ -3,11 +3,20 @@ use crate::{build_migration_set, make_migration_func};
 use std::{collections::HashMap, time::Duration};
 
 // NOTE: if they're not in this list, they basically don't exist
-pub fn migrations() -> HashMap<&'static str, Migration> {
+pub fn migrations() -> HashMap<&'static str, RegisteredMigration> {
 	HashMap::from([
-		("node-exporter", node_exporter()),
-		("prometheus", prometheus()),
-		("grafana", grafana()),
+		(
+			"node-exporter",
+			RegisteredMigration::new(vec![], node_exporter()),
+		),
+		(
+			"prometheus",
+			RegisteredMigration::new(vec!["node-exporter"], prometheus()),
+		),
+		(
+			"grafana",
+			RegisteredMigration::new(vec!["prometheus"], grafana()),
+		),
 	])
 }
 
@@ -17,16 +26,25 @@ pub async fn boot_service(name: &str) -> anyhow::Result<()> {
 	tokio::time::sleep(Duration::from_millis(200)).await;
 	client.stop(name.to_string()).await?;
 	client.start(name.to_string()).await?;
-	client.enable(name.to_string()).await?;
+	for change in client.enable(name.to_string()).await? {
+		tracing::info!(
+			"Enabling {}: {} {} -> {}",
+			name,
+			change.kind,
+			change.file,
+			change.destination
+		);
+	}
 	Ok(())
 }
 
 #[macro_export]
 #[rustfmt::skip]
 macro_rules! build_container_migration {
-	($name:expr, $description:expr, $command:expr) => {{
+	($name:expr, $description:expr, $podman_args:expr) => {{
 		use super::utils::*;
-		use crate::systemd_unit;
+		use $crate::commands::command_paths;
+		use $crate::systemd_unit;
 
 		let state = MigrationState::default();
 		build_migration_set!(state, {
@@ -47,8 +65,8 @@ macro_rules! build_container_migration {
         )),
         ("Service", (
           ("Type" => "exec"),
-          ("ExecStart" => $command),
-          ("ExecStartPre" => &format!("podman rm -f trunk-{}", $name)),
+          ("ExecStart" => &format!("{} {}", command_paths().podman.display(), $podman_args)),
+          ("ExecStartPre" => &format!("{} rm -f trunk-{}", command_paths().podman.display(), $name)),
           ("Restart" => "always"),
           ("TimeoutSec" => "300"),
         )),
@@ -70,7 +88,7 @@ fn prometheus() -> Migration {
 	build_container_migration!(
 		"prometheus",
 		"Prometheus Query Service",
-		"podman run -u 0 --security-opt label=disable --net host -it -v /trunk/prometheus:/prometheus:Z --name trunk-prometheus quay.io/trunk-os/prometheus"
+		"run -u 0 --security-opt label=disable --net host -it -v /trunk/prometheus:/prometheus:Z --name trunk-prometheus quay.io/trunk-os/prometheus"
 	)
 }
 
@@ -78,7 +96,7 @@ fn grafana() -> Migration {
 	build_container_migration!(
 		"grafana",
 		"Grafana Dashboard Service",
-		"podman run -u 0 --security-opt label=disable --net host -it --name trunk-grafana -v /trunk/grafana:/var/lib/grafana:Z quay.io/trunk-os/grafana"
+		"run -u 0 --security-opt label=disable --net host -it --name trunk-grafana -v /trunk/grafana:/var/lib/grafana:Z quay.io/trunk-os/grafana"
 	)
 }
 
@@ -86,6 +104,6 @@ fn node_exporter() -> Migration {
 	build_container_migration!(
 		"node-exporter",
 		"node-exporter Metrics Service",
-		"podman run --security-opt label=disable --cap-add SYS_TIME --name trunk-node-exporter --net host --pid host -v /:/host:ro,rslave quay.io/trunk-os/node-exporter --path.rootfs=/host"
+		"run --security-opt label=disable --cap-add SYS_TIME --name trunk-node-exporter --net host --pid host -v /:/host:ro,rslave quay.io/trunk-os/node-exporter --path.rootfs=/host"
 	)
 }
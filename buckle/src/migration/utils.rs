@@ -1,18 +1,18 @@
 use anyhow::Result;
-use std::{collections::HashMap, io::Write, path::PathBuf};
+use std::{
+	collections::HashMap,
+	io::Write,
+	path::{Path, PathBuf},
+};
 
-use crate::migration::MigrationError;
+use crate::{commands::command_paths, migration::MigrationError};
 
-const PODMAN_COMMAND: &str = "podman";
-const ZFS_COMMAND: &str = "zfs";
-const SYSTEMCTL_COMMAND: &str = "systemctl";
-
-pub async fn command(cmd: &str, args: Vec<&str>) -> Result<(String, String), MigrationError> {
+pub async fn command(cmd: &Path, args: Vec<&str>) -> Result<(String, String), MigrationError> {
 	let output = tokio::process::Command::new(cmd)
 		.args(&args)
 		.output()
 		.await
-		.map_err(|e| MigrationError::CommandLaunch(cmd.into(), e.to_string()))?;
+		.map_err(|e| MigrationError::CommandLaunch(cmd.to_path_buf(), e.to_string()))?;
 
 	if output.status.success() {
 		Ok((
@@ -23,7 +23,7 @@ pub async fn command(cmd: &str, args: Vec<&str>) -> Result<(String, String), Mig
 		))
 	} else {
 		Err(MigrationError::Command(
-			format!("{} {}", cmd, args.join(" ")),
+			format!("{} {}", cmd.display(), args.join(" ")),
 			String::from_utf8_lossy(&output.stderr).to_string(),
 			output.status.code().unwrap_or_default(),
 		))
@@ -31,11 +31,15 @@ pub async fn command(cmd: &str, args: Vec<&str>) -> Result<(String, String), Mig
 }
 
 pub async fn podman(args: Vec<&str>) -> Result<(String, String), MigrationError> {
-	command(PODMAN_COMMAND, args).await
+	command(&command_paths().podman, args).await
 }
 
 pub async fn zfs(args: Vec<&str>) -> Result<(String, String), MigrationError> {
-	command(ZFS_COMMAND, args).await
+	command(&command_paths().zfs, args).await
+}
+
+pub async fn systemctl(args: Vec<&str>) -> Result<(String, String), MigrationError> {
+	command(&command_paths().systemctl, args).await
 }
 
 #[macro_export]
@@ -1,38 +1,119 @@
 use crate::{
 	grpc::{
-		GrpcLogMessage, GrpcLogParams, GrpcPortForward, GrpcUnit, GrpcUnitList, GrpcUnitName,
-		GrpcUnitSettings, PingResult, UnitListFilter, ZfsDataset, ZfsList, ZfsListFilter,
-		ZfsModifyDataset, ZfsModifyVolume, ZfsName, ZfsRoot, ZfsVolume,
+		GrpcLogMessage, GrpcLogParams, GrpcLogPriority, GrpcPortForward, GrpcPortForwardList,
+		GrpcPortMapping, GrpcUnit, GrpcUnitFileChanges, GrpcUnitList, GrpcUnitName,
+		GrpcUnitSettings, PingResult, UnitListFilter, ZfsCloneSnapshot, ZfsDataset, ZfsDetail,
+		ZfsList, ZfsListFilter, ZfsModifyDataset, ZfsModifyVolume, ZfsName, ZfsPoolStatus,
+		ZfsReceive, ZfsRollback, ZfsRoot, ZfsScrubStatus, ZfsSend, ZfsSnapshotList,
+		ZfsSnapshotName, ZfsStreamChunk, ZfsVolume,
 		network_server::{Network, NetworkServer},
+		power_server::{Power, PowerServer},
 		status_server::{Status, StatusServer},
 		systemd_server::{Systemd, SystemdServer},
 		zfs_server::{Zfs, ZfsServer},
 	},
 	sysinfo::Info,
-	upnp::PortForward,
+	systemd::{InvalidCursorError, PowerAction},
+	upnp::{PortForward, PortTable, Protocol},
+	zfs::{DependentClonesError, LaterSnapshotsError, Pool, TimeoutError, UnsupportedJSONError},
 };
-use std::{fs::Permissions, os::unix::fs::PermissionsExt, pin::Pin};
+use std::{fs::Permissions, io::Read, os::unix::fs::PermissionsExt, pin::Pin, sync::Arc};
 use tokio_stream::{Stream, wrappers::ReceiverStream};
-use tonic::{Request, Response, Result, transport::Server as TransportServer};
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, Response, Result, Streaming, transport::Server as TransportServer};
 use tonic_middleware::MiddlewareLayer;
 use tracing::info;
 
-// FIXME needs a way to shut down
+// how long a reboot or poweroff waits before it's started, so the reply to it can get out first
+const POWER_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+// how much of a zfs send stream goes in each message
+const STREAM_CHUNK_SIZE: usize = 1024 * 1024;
+// chunks buffered between zfs and the client, in either direction
+const STREAM_CHANNEL_DEPTH: usize = 8;
+
+// the data of a Receive call, read by zfs receive as one stream
+struct ChunkReader {
+	rx: tokio::sync::mpsc::Receiver<std::io::Result<Vec<u8>>>,
+	chunk: Vec<u8>,
+	pos: usize,
+}
+
+impl Read for ChunkReader {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		while self.pos >= self.chunk.len() {
+			match self.rx.blocking_recv() {
+				Some(chunk) => {
+					self.chunk = chunk?;
+					self.pos = 0;
+				}
+				None => return Ok(0),
+			}
+		}
+
+		let n = buf.len().min(self.chunk.len() - self.pos);
+		buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+		self.pos += n;
+		Ok(n)
+	}
+}
+
+// resolves on SIGTERM or SIGINT; for handing to start_with_shutdown() in the daemons.
+pub async fn shutdown_signal() {
+	let mut term = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+		Ok(term) => term,
+		Err(e) => {
+			tracing::error!("Could not listen for SIGTERM: {}", e);
+			let _ = tokio::signal::ctrl_c().await;
+			return;
+		}
+	};
+
+	tokio::select! {
+		_ = term.recv() => info!("Received SIGTERM, shutting down."),
+		_ = tokio::signal::ctrl_c() => info!("Received SIGINT, shutting down."),
+	}
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Server {
 	config: crate::config::Config,
+	// held while the router and the port table are changed, so the two stay in step
+	ports: Arc<tokio::sync::Mutex<()>>,
+	// held by everything that changes the pool, so renames and property changes from concurrent
+	// calls can't interleave. reads don't take it.
+	zfs_writes: Arc<tokio::sync::Mutex<()>>,
+	// cancelled once the shutdown signal arrives. streams that would otherwise never end, like a
+	// followed log, stop on it so the server isn't left waiting on them.
+	shutdown: CancellationToken,
 }
 
 impl Server {
 	pub fn new_with_config(config: Option<crate::config::Config>) -> Self {
 		match config {
-			Some(config) => Self { config },
+			Some(config) => Self {
+				config,
+				ports: Default::default(),
+				zfs_writes: Default::default(),
+				shutdown: Default::default(),
+			},
 			None => Self::default(),
 		}
 	}
 
+	fn port_table(&self) -> PortTable {
+		PortTable::new(&self.config.port_state)
+	}
+
 	pub fn start(
 		&self,
+	) -> anyhow::Result<impl std::future::Future<Output = Result<(), tonic::transport::Error>>> {
+		self.start_with_shutdown(std::future::pending())
+	}
+
+	// the returned future resolves once signal does and the requests already in flight have
+	// finished.
+	pub fn start_with_shutdown(
+		&self, signal: impl std::future::Future<Output = ()>,
 	) -> anyhow::Result<impl std::future::Future<Output = Result<(), tonic::transport::Error>>> {
 		info!("Starting service.");
 
@@ -49,13 +130,44 @@ impl Server {
 
 		std::fs::set_permissions(&self.config.socket, Permissions::from_mode(0o600))?;
 
+		let shutdown = self.shutdown.clone();
+		let signal = async move {
+			signal.await;
+			shutdown.cancel();
+		};
+
 		Ok(TransportServer::builder()
 			.layer(MiddlewareLayer::new(crate::middleware::LogMiddleware))
 			.add_service(StatusServer::new(self.clone()))
 			.add_service(ZfsServer::new(self.clone()))
 			.add_service(SystemdServer::new(self.clone()))
 			.add_service(NetworkServer::new(self.clone()))
-			.serve_with_incoming(uds_stream))
+			.add_service(PowerServer::new(self.clone()))
+			.serve_with_incoming_shutdown(uds_stream, signal))
+	}
+
+	// zfs operations wait on subprocesses, so they run on the blocking pool instead of holding up
+	// a runtime thread for as long as zfs takes.
+	async fn zfs<T, F>(&self, f: F) -> Result<T>
+	where
+		F: FnOnce(Pool) -> anyhow::Result<T> + Send + 'static,
+		T: Send + 'static,
+	{
+		let pool = self.config.zfs.controller();
+		tokio::task::spawn_blocking(move || f(pool))
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.map_err(zfs_status)
+	}
+
+	// zfs(), for operations that change the pool
+	async fn zfs_mut<T, F>(&self, f: F) -> Result<T>
+	where
+		F: FnOnce(Pool) -> anyhow::Result<T> + Send + 'static,
+		T: Send + 'static,
+	{
+		let _lock = self.zfs_writes.lock().await;
+		self.zfs(f).await
 	}
 }
 
@@ -65,13 +177,18 @@ impl Network for Server {
 		let port_forward: PortForward = req.into_inner().into();
 		let service = port_forward.name.clone();
 		let port = port_forward.port;
+		let record = port_forward.clone();
 		let port_forward: easy_upnp::UpnpConfig = port_forward.into();
+
+		let _lock = self.ports.lock().await;
 		let results: Vec<Result<(), easy_upnp::Error>> =
 			easy_upnp::add_ports([port_forward]).collect();
 
+		let mut added = true;
 		for result in results {
 			match result {
 				Err(e) => {
+					added = false;
 					tracing::error!(
 						"Error forwarding uPnP port {} for service {}: {}",
 						port,
@@ -82,13 +199,68 @@ impl Network for Server {
 				_ => {}
 			}
 		}
+
+		if added {
+			self.port_table()
+				.record(record)
+				.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		}
+
 		Ok(Response::new(()))
 	}
 
 	async fn un_expose_port(&self, req: tonic::Request<GrpcPortForward>) -> Result<Response<()>> {
 		let port_forward: PortForward = req.into_inner().into();
+		let _lock = self.ports.lock().await;
+		self.delete_port(port_forward)
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		Ok(Response::new(()))
+	}
+
+	async fn remove_port(&self, req: tonic::Request<GrpcPortMapping>) -> Result<Response<()>> {
+		let req = req.into_inner();
+		let protocol: Protocol = req.protocol().into();
+		let port = req.port as u16;
+
+		let _lock = self.ports.lock().await;
+		let Some(port_forward) = self
+			.port_table()
+			.find(port, protocol)
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+		else {
+			return Err(tonic::Status::new(
+				tonic::Code::NotFound,
+				format!(
+					"No port mapping for {:?} port {} was made by buckle",
+					protocol, port
+				),
+			));
+		};
+
+		self.delete_port(port_forward)
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		Ok(Response::new(()))
+	}
+
+	async fn list_ports(&self, _: tonic::Request<()>) -> Result<Response<GrpcPortForwardList>> {
+		let ports = self
+			.port_table()
+			.list()
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		Ok(Response::new(GrpcPortForwardList {
+			ports: ports.into_iter().map(Into::into).collect(),
+		}))
+	}
+}
+
+impl Server {
+	// the caller holds self.ports. a mapping the router refuses to delete is still forgotten: it
+	// has most likely expired or been removed on the router already, and keeping it would leave an
+	// entry that can never be cleaned up.
+	fn delete_port(&self, port_forward: PortForward) -> anyhow::Result<()> {
 		let service = port_forward.name.clone();
 		let port = port_forward.port;
+		let protocol = port_forward.protocol;
 		let port_forward: easy_upnp::UpnpConfig = port_forward.into();
 		let results: Vec<Result<(), easy_upnp::Error>> =
 			easy_upnp::delete_ports([port_forward]).collect();
@@ -97,7 +269,7 @@ impl Network for Server {
 			match result {
 				Err(e) => {
 					tracing::error!(
-						"Error forwarding uPnP port {} for service {}: {}",
+						"Error removing uPnP port {} for service {}: {}",
 						port,
 						service,
 						e
@@ -106,27 +278,29 @@ impl Network for Server {
 				_ => {}
 			}
 		}
-		Ok(Response::new(()))
+
+		self.port_table().forget(port, protocol)?;
+		Ok(())
 	}
 }
 
 #[tonic::async_trait]
 impl Systemd for Server {
 	async fn unit_info(&self, req: tonic::Request<GrpcUnitName>) -> Result<Response<GrpcUnit>> {
+		let name = req.into_inner().name;
 		let unit = crate::systemd::Systemd::new_system()
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
-			.list(Some(req.into_inner().name))
+			.unit(name.clone())
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
-		if let Some(unit) = unit.first() {
-			Ok(Response::new(unit.clone().into()))
-		} else {
-			Err(tonic::Status::new(
-				tonic::Code::Internal,
-				"Unit does not exist".to_string(),
-			))
+		match unit {
+			Some(unit) => Ok(Response::new(unit.into())),
+			None => Err(tonic::Status::new(
+				tonic::Code::NotFound,
+				format!("Unit '{}' does not exist", name),
+			)),
 		}
 	}
 
@@ -152,6 +326,36 @@ impl Systemd for Server {
 		))
 	}
 
+	async fn enable_unit(
+		&self, req: tonic::Request<GrpcUnitName>,
+	) -> Result<Response<GrpcUnitFileChanges>> {
+		let changes = crate::systemd::Systemd::new_system()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.enable(req.into_inner().name)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(Response::new(GrpcUnitFileChanges {
+			changes: changes.into_iter().map(Into::into).collect(),
+		}))
+	}
+
+	async fn disable_unit(
+		&self, req: tonic::Request<GrpcUnitName>,
+	) -> Result<Response<GrpcUnitFileChanges>> {
+		let changes = crate::systemd::Systemd::new_system()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.disable(req.into_inner().name)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(Response::new(GrpcUnitFileChanges {
+			changes: changes.into_iter().map(Into::into).collect(),
+		}))
+	}
+
 	async fn reload(&self, _: tonic::Request<()>) -> Result<Response<()>> {
 		Ok(Response::new(
 			crate::systemd::Systemd::new_system()
@@ -168,15 +372,10 @@ impl Systemd for Server {
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 		let mut v = Vec::new();
-		let filter = filter.into_inner();
-
-		let mut out = None;
-		if !filter.filter.is_empty() {
-			out = Some(filter.filter);
-		}
+		let filter: crate::systemd::UnitFilter = filter.into_inner().into();
 
 		for item in systemd
-			.list(out)
+			.list_filtered(&filter)
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
 		{
@@ -188,8 +387,15 @@ impl Systemd for Server {
 
 	type UnitLogStream = Pin<Box<dyn Stream<Item = Result<GrpcLogMessage>> + Send>>;
 
-	async fn set_unit(&self, _filter: Request<GrpcUnitSettings>) -> Result<Response<()>> {
-		Ok(Response::new(()))
+	async fn set_unit(&self, req: Request<GrpcUnitSettings>) -> Result<Response<()>> {
+		Ok(Response::new(
+			crate::systemd::Systemd::new_system()
+				.await
+				.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+				.set_unit(&req.into_inner().into())
+				.await
+				.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?,
+		))
 	}
 
 	// FIXME: this really is only a streaming method because of memory usage concerns. Maybe
@@ -198,24 +404,53 @@ impl Systemd for Server {
 		&self, params: Request<GrpcLogParams>,
 	) -> Result<Response<Self::UnitLogStream>> {
 		let params = params.into_inner();
-		let (tx, rx) = tokio::sync::mpsc::channel(params.count as usize);
+		// count can be zero when following from the tail
+		let (tx, rx) = tokio::sync::mpsc::channel((params.count as usize).max(1));
 		let output_stream = ReceiverStream::new(rx);
 		let systemd = crate::systemd::Systemd::new_system()
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
-		let p2 = params.clone();
+		let mut rcv = systemd
+			.log(
+				&params.name,
+				params.count as usize,
+				Some(params.cursor.clone()),
+				Some(params.direction().into()),
+				params.priority.map(|_| params.priority().into()),
+				params.follow,
+			)
+			.await
+			.map_err(|e| {
+				let code = if e.is::<InvalidCursorError>() {
+					tonic::Code::InvalidArgument
+				} else {
+					tonic::Code::Internal
+				};
+				tonic::Status::new(code, e.to_string())
+			})?;
+
+		let shutdown = self.shutdown.clone();
 		tokio::spawn(async move {
-			let params = p2;
-			let mut rcv = systemd
-				.log(&params.name, params.count as usize, None, None)
-				.await
-				.unwrap();
-			while let Some(items) = rcv.recv().await {
+			loop {
+				// a follower on a quiet unit may not send anything for a long time, so notice the
+				// client leaving, or the server stopping, without waiting for the next entry
+				let items = tokio::select! {
+					items = rcv.recv() => match items {
+						Some(items) => items,
+						None => break,
+					},
+					_ = tx.closed() => break,
+					_ = shutdown.cancelled() => break,
+				};
+
 				let mut time: Option<std::time::SystemTime> = None;
 				let mut msg: Option<String> = None;
 				let mut pid: Option<u64> = None;
 				let mut cursor: Option<String> = None;
+				let mut priority: Option<crate::systemd::LogPriority> = None;
+				let mut identifier: Option<String> = None;
+				let mut unit: Option<String> = None;
 
 				for (key, value) in items {
 					match key.as_str() {
@@ -228,23 +463,32 @@ impl Systemd for Server {
 						"MESSAGE" => msg = Some(value),
 						"_PID" => pid = Some(value.parse().unwrap()),
 						"CURSOR" => cursor = Some(value),
+						"PRIORITY" => priority = value.parse().ok(),
+						"SYSLOG_IDENTIFIER" => identifier = Some(value),
+						"_SYSTEMD_UNIT" => unit = Some(value),
 						_ => {}
 					}
+				}
 
-					if time.is_some() && msg.is_some() && pid.is_some() {
-						tx.send(Ok(GrpcLogMessage {
+				// entries are only complete once all keys have been seen; the optional fields sort
+				// on both sides of the required ones.
+				if let (Some(time), Some(msg), Some(pid)) = (time, msg, pid) {
+					// the client went away; dropping rcv stops the journal reader too
+					if tx
+						.send(Ok(GrpcLogMessage {
 							service_name: params.name.clone(),
-							msg: msg.clone().unwrap(),
-							pid: pid.unwrap(),
-							time: time.map(Into::into),
-							cursor: cursor.unwrap(),
+							msg,
+							pid,
+							time: Some(time.into()),
+							cursor: cursor.unwrap_or_default(),
+							priority: priority.map(|x| Into::<GrpcLogPriority>::into(x).into()),
+							identifier: identifier.unwrap_or_default(),
+							unit: unit.unwrap_or_default(),
 						}))
 						.await
-						.unwrap();
-						time = None;
-						msg = None;
-						pid = None;
-						cursor = None;
+						.is_err()
+					{
+						break;
 					}
 				}
 			}
@@ -254,6 +498,37 @@ impl Systemd for Server {
 	}
 }
 
+#[tonic::async_trait]
+impl Power for Server {
+	async fn reboot(&self, _: Request<()>) -> Result<Response<()>> {
+		self.power(PowerAction::Reboot).await
+	}
+
+	async fn poweroff(&self, _: Request<()>) -> Result<Response<()>> {
+		self.power(PowerAction::Poweroff).await
+	}
+}
+
+impl Server {
+	// systemd is connected to up front so the caller still hears about it being unreachable;
+	// only starting the target is left for later.
+	async fn power(&self, action: PowerAction) -> Result<Response<()>> {
+		let systemd = crate::systemd::Systemd::new_system()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		info!("Host {} requested, starting in {:?}", action, POWER_DELAY);
+		tokio::spawn(async move {
+			tokio::time::sleep(POWER_DELAY).await;
+			if let Err(e) = systemd.power(action).await {
+				tracing::error!("Could not start {}: {}", action.target(), e);
+			}
+		});
+
+		Ok(Response::new(()))
+	}
+}
+
 #[tonic::async_trait]
 impl Status for Server {
 	async fn ping(&self, _: Request<()>) -> Result<Response<PingResult>> {
@@ -263,6 +538,22 @@ impl Status for Server {
 	}
 }
 
+// zfs errors the caller can make sense of get their own codes
+fn zfs_status(e: anyhow::Error) -> tonic::Status {
+	let code = if e.is::<DependentClonesError>()
+		|| e.is::<LaterSnapshotsError>()
+		|| e.is::<UnsupportedJSONError>()
+	{
+		tonic::Code::FailedPrecondition
+	} else if e.is::<TimeoutError>() {
+		tonic::Code::DeadlineExceeded
+	} else {
+		tonic::Code::Internal
+	};
+
+	tonic::Status::new(code, e.to_string())
+}
+
 #[tonic::async_trait]
 impl Zfs for Server {
 	async fn root_path(&self, _: Request<()>) -> Result<Response<ZfsRoot>> {
@@ -271,61 +562,208 @@ impl Zfs for Server {
 		}))
 	}
 
+	async fn pool_status(&self, _: Request<()>) -> Result<Response<ZfsPoolStatus>> {
+		Ok(Response::new(self.zfs(|pool| pool.status()).await?.into()))
+	}
+
+	async fn scrub(&self, _: Request<()>) -> Result<Response<()>> {
+		self.zfs_mut(|pool| pool.scrub()).await?;
+		Ok(Response::new(()))
+	}
+
+	async fn scrub_status(&self, _: Request<()>) -> Result<Response<ZfsScrubStatus>> {
+		Ok(Response::new(
+			self.zfs(|pool| pool.scrub_status()).await?.into(),
+		))
+	}
+
 	async fn modify_dataset(&self, info: Request<ZfsModifyDataset>) -> Result<Response<()>> {
-		self.config
-			.zfs
-			.controller()
-			.modify_dataset(info.into_inner().into())
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		let info = info.into_inner().into();
+		self.zfs_mut(move |pool| pool.modify_dataset(info)).await?;
 		Ok(Response::new(()))
 	}
 
 	async fn modify_volume(&self, info: Request<ZfsModifyVolume>) -> Result<Response<()>> {
-		self.config
-			.zfs
-			.controller()
-			.modify_volume(info.into_inner().into())
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		let info = info.into_inner().into();
+		self.zfs_mut(move |pool| pool.modify_volume(info)).await?;
 		Ok(Response::new(()))
 	}
 
 	async fn list(&self, filter: Request<ZfsListFilter>) -> Result<Response<ZfsList>> {
-		let list = self
-			.config
-			.zfs
-			.controller()
-			.list(filter.get_ref().filter.clone())
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		let filter = filter.into_inner().filter;
+		let list = self.zfs(move |pool| pool.list(filter)).await?;
 		return Ok(Response::new(list.into()));
 	}
 
-	async fn create_dataset(&self, dataset: Request<ZfsDataset>) -> Result<Response<()>> {
-		self.config
-			.zfs
-			.controller()
-			.create_dataset(&dataset.into_inner().into())
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+	async fn get(&self, name: Request<ZfsName>) -> Result<Response<ZfsDetail>> {
+		let name = name.into_inner().name;
+		let inner = name.clone();
+		match self.zfs(move |pool| pool.get(&inner)).await? {
+			Some(detail) => Ok(Response::new(detail.into())),
+			None => Err(tonic::Status::new(
+				tonic::Code::NotFound,
+				format!("No dataset or volume named '{}'", name),
+			)),
+		}
+	}
 
+	async fn create_dataset(&self, dataset: Request<ZfsDataset>) -> Result<Response<()>> {
+		let dataset = dataset.into_inner().into();
+		self.zfs_mut(move |pool| pool.create_dataset(&dataset))
+			.await?;
 		return Ok(Response::new(()));
 	}
 
 	async fn create_volume(&self, volume: Request<ZfsVolume>) -> Result<Response<()>> {
-		self.config
-			.zfs
-			.controller()
-			.create_volume(&volume.into_inner().into())
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		let volume = volume.into_inner().into();
+		self.zfs_mut(move |pool| pool.create_volume(&volume))
+			.await?;
 		return Ok(Response::new(()));
 	}
 
 	async fn destroy(&self, name: Request<ZfsName>) -> Result<Response<()>> {
-		self.config
-			.zfs
-			.controller()
-			.destroy(name.get_ref().name.clone())
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		let name = name.into_inner().name;
+		self.zfs_mut(move |pool| pool.destroy(name)).await?;
 		return Ok(Response::new(()));
 	}
+
+	async fn clone_snapshot(&self, info: Request<ZfsCloneSnapshot>) -> Result<Response<()>> {
+		let info = info.into_inner().into();
+		self.zfs_mut(move |pool| pool.clone_snapshot(&info)).await?;
+		Ok(Response::new(()))
+	}
+
+	async fn mount(&self, name: Request<ZfsName>) -> Result<Response<()>> {
+		let name = name.into_inner().name;
+		self.zfs_mut(move |pool| pool.mount(&name)).await?;
+		Ok(Response::new(()))
+	}
+
+	async fn unmount(&self, name: Request<ZfsName>) -> Result<Response<()>> {
+		let name = name.into_inner().name;
+		self.zfs_mut(move |pool| pool.unmount(&name)).await?;
+		Ok(Response::new(()))
+	}
+
+	async fn promote(&self, name: Request<ZfsName>) -> Result<Response<()>> {
+		let name = name.into_inner().name;
+		self.zfs_mut(move |pool| pool.promote(name)).await?;
+		Ok(Response::new(()))
+	}
+
+	async fn snapshot(&self, name: Request<ZfsSnapshotName>) -> Result<Response<()>> {
+		let name = name.into_inner();
+		self.zfs_mut(move |pool| pool.snapshot(&name.dataset, &name.snapshot))
+			.await?;
+		Ok(Response::new(()))
+	}
+
+	async fn list_snapshots(
+		&self, filter: Request<ZfsListFilter>,
+	) -> Result<Response<ZfsSnapshotList>> {
+		let filter = filter.into_inner().filter;
+		let list = self.zfs(move |pool| pool.list_snapshots(filter)).await?;
+		Ok(Response::new(list.into()))
+	}
+
+	async fn rollback(&self, info: Request<ZfsRollback>) -> Result<Response<()>> {
+		let info = info.into_inner();
+		self.zfs_mut(move |pool| pool.rollback(&info.dataset, &info.snapshot, info.force))
+			.await?;
+		Ok(Response::new(()))
+	}
+
+	type SendStream = Pin<Box<dyn Stream<Item = Result<ZfsStreamChunk>> + Send>>;
+
+	async fn send(&self, info: Request<ZfsSend>) -> Result<Response<Self::SendStream>> {
+		let info = info.into_inner();
+		let mut stream = self
+			.zfs(move |pool| pool.send(&info.dataset, &info.snapshot, info.base.as_deref()))
+			.await?;
+
+		let (tx, rx) = tokio::sync::mpsc::channel(STREAM_CHANNEL_DEPTH);
+		tokio::task::spawn_blocking(move || {
+			let mut buf = vec![0; STREAM_CHUNK_SIZE];
+			loop {
+				let msg = match stream.read(&mut buf) {
+					Ok(0) => break,
+					Ok(n) => Ok(ZfsStreamChunk {
+						data: buf[..n].to_vec(),
+					}),
+					Err(e) => Err(tonic::Status::new(tonic::Code::Internal, e.to_string())),
+				};
+
+				// the client went away, or zfs failed; either way dropping the stream kills zfs
+				let failed = msg.is_err();
+				if tx.blocking_send(msg).is_err() || failed {
+					break;
+				}
+			}
+		});
+
+		Ok(Response::new(
+			Box::pin(ReceiverStream::new(rx)) as Self::SendStream
+		))
+	}
+
+	async fn receive(&self, chunks: Request<Streaming<ZfsReceive>>) -> Result<Response<()>> {
+		let mut chunks = chunks.into_inner();
+		let first = match chunks.message().await? {
+			Some(x) => x,
+			None => {
+				return Err(tonic::Status::new(
+					tonic::Code::InvalidArgument,
+					"nothing was sent to receive",
+				));
+			}
+		};
+
+		let (tx, rx) = tokio::sync::mpsc::channel(STREAM_CHANNEL_DEPTH);
+		let target = first.target;
+		// not under zfs_writes; that would hold up every other change for as long as the sender
+		// takes
+		let receiving = self.zfs(move |pool| {
+			pool.receive(
+				&target,
+				ChunkReader {
+					rx,
+					chunk: Vec::new(),
+					pos: 0,
+				},
+			)
+		});
+
+		// stops early if zfs gives up, since that drops the reader
+		let feeding = async move {
+			if tx.send(Ok(first.data)).await.is_err() {
+				return;
+			}
+
+			loop {
+				let chunk = match chunks.message().await {
+					Ok(Some(x)) => Ok(x.data),
+					Ok(None) => break,
+					Err(e) => Err(std::io::Error::other(e.to_string())),
+				};
+
+				let failed = chunk.is_err();
+				if tx.send(chunk).await.is_err() || failed {
+					break;
+				}
+			}
+		};
+
+		let (result, _) = tokio::join!(receiving, feeding);
+		result?;
+		Ok(Response::new(()))
+	}
+
+	async fn destroy_snapshot(&self, name: Request<ZfsSnapshotName>) -> Result<Response<()>> {
+		let name = name.into_inner();
+		self.zfs_mut(move |pool| pool.destroy_snapshot(&name.dataset, &name.snapshot))
+			.await?;
+		Ok(Response::new(()))
+	}
 }
 
 #[cfg(test)]
@@ -334,10 +772,81 @@ mod tests {
 		use tokio_stream::StreamExt;
 
 		use crate::{
-			grpc::{GrpcLogDirection, GrpcLogParams},
-			testutil::{get_systemd_client, make_server},
+			grpc::{
+				GrpcLogDirection, GrpcLogParams, GrpcUnitName, GrpcUnitSettings, UnitEnabledState,
+				UnitLastRunState, UnitRuntimeState,
+			},
+			server::Server,
+			systemd::{RuntimeState, Systemd},
+			testutil::{DEFAULT_CONFIG, find_listener, get_systemd_client, make_server},
 		};
 
+		#[tokio::test]
+		async fn test_unit_info() {
+			let mut client = get_systemd_client(make_server(None).await.unwrap())
+				.await
+				.unwrap();
+
+			let unit = client
+				.unit_info(GrpcUnitName {
+					name: "init.scope".into(),
+				})
+				.await
+				.unwrap()
+				.into_inner();
+			assert_eq!(unit.name, "init.scope");
+			assert!(!unit.object_path.is_empty());
+			let status = unit.status.unwrap();
+			assert_eq!(status.runtime_state(), UnitRuntimeState::Started);
+			assert_eq!(status.last_run_state(), UnitLastRunState::Running);
+
+			let err = client
+				.unit_info(GrpcUnitName {
+					name: "buckle-does-not-exist.service".into(),
+				})
+				.await
+				.unwrap_err();
+			assert_eq!(err.code(), tonic::Code::NotFound);
+		}
+
+		#[tokio::test]
+		async fn test_set_unit() {
+			let mut client = get_systemd_client(make_server(None).await.unwrap())
+				.await
+				.unwrap();
+			let systemd = Systemd::new_system().await.unwrap();
+			let path = systemd.get_unit("network.target".into()).await.unwrap();
+
+			// network.target is static, so asking for it enabled leaves it be
+			for (requested, expected) in [
+				(UnitRuntimeState::Stopped, RuntimeState::Stopped),
+				(UnitRuntimeState::Started, RuntimeState::Started),
+			] {
+				client
+					.set_unit(GrpcUnitSettings {
+						name: "network.target".into(),
+						runtime_state: requested.into(),
+						enabled_state: UnitEnabledState::Enabled.into(),
+					})
+					.await
+					.unwrap();
+
+				let status = systemd.status(path.clone()).await.unwrap();
+				assert_eq!(status.runtime_state, expected);
+			}
+
+			assert!(
+				client
+					.set_unit(GrpcUnitSettings {
+						name: "buckle-does-not-exist.service".into(),
+						runtime_state: UnitRuntimeState::Started.into(),
+						enabled_state: UnitEnabledState::Enabled.into(),
+					})
+					.await
+					.is_err()
+			);
+		}
+
 		#[tokio::test]
 		async fn test_log() {
 			let mut client = get_systemd_client(make_server(None).await.unwrap())
@@ -349,6 +858,8 @@ mod tests {
 					count: 100,
 					cursor: "".into(),
 					direction: GrpcLogDirection::Forward.into(),
+					priority: None,
+					follow: false,
 				})
 				.await
 				.unwrap();
@@ -363,12 +874,62 @@ mod tests {
 				assert_ne!(!item.time.unwrap().seconds, 0);
 				assert_ne!(item.pid, 0);
 				assert!(!item.cursor.is_empty());
+				// these entries are written by systemd itself, which always tags them
+				assert!(item.priority.is_some());
+				assert!(!item.identifier.is_empty());
 				total += 1;
 			}
 
 			assert!(total < 100);
 			assert!(total > 0);
 		}
+
+		#[tokio::test]
+		async fn test_shutdown_with_follower() {
+			let mut config = DEFAULT_CONFIG.clone();
+			config.socket = find_listener().unwrap();
+			config.port_state = config.socket.with_extension("ports.json");
+			let socket = config.socket.clone();
+
+			let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+			let handle = tokio::spawn(async move {
+				Server::new_with_config(Some(config))
+					.start_with_shutdown(async move {
+						let _ = rx.await;
+					})
+					.unwrap()
+					.await
+			});
+			tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+			let mut client = get_systemd_client(socket).await.unwrap();
+			let mut log = client
+				.unit_log(GrpcLogParams {
+					name: "network.target".into(),
+					count: 0,
+					cursor: "".into(),
+					direction: GrpcLogDirection::Forward.into(),
+					priority: None,
+					follow: true,
+				})
+				.await
+				.unwrap()
+				.into_inner();
+
+			tx.send(()).unwrap();
+			tokio::time::timeout(std::time::Duration::from_secs(5), handle)
+				.await
+				.expect("server did not stop with a follower attached")
+				.unwrap()
+				.unwrap();
+
+			// and the follower hears that it's over instead of waiting for more
+			tokio::time::timeout(std::time::Duration::from_secs(5), async {
+				while let Some(Ok(_)) = log.next().await {}
+			})
+			.await
+			.unwrap();
+		}
 	}
 
 	mod status {
@@ -394,6 +955,24 @@ mod tests {
 			assert!(!info.kernel_version.is_empty());
 			assert_ne!(info.load_average, [0.0, 0.0, 0.0]);
 			assert_ne!(info.processes, 0);
+			assert!(!info.disks.is_empty());
+			assert!(info.disks.iter().all(|d| !d.mount_point.is_empty()));
+		}
+	}
+
+	mod network {
+		use crate::{client::Client, testutil::make_server, upnp::Protocol};
+
+		#[tokio::test]
+		async fn test_ports_untracked() {
+			let client = Client::new(make_server(None).await.unwrap()).unwrap();
+			let mut network = client.network().await.unwrap();
+
+			assert!(network.list_ports().await.unwrap().is_empty());
+
+			// nothing buckle made, so there is nothing to remove
+			let err = network.remove_port(8080, Protocol::TCP).await.unwrap_err();
+			assert_eq!(err.code(), tonic::Code::NotFound);
 		}
 	}
 
@@ -404,7 +983,8 @@ mod tests {
 				ZfsVolume,
 			},
 			testutil::{
-				BUCKLE_TEST_ZPOOL_PREFIX, create_zpool, destroy_zpool, get_zfs_client, make_server,
+				BUCKLE_TEST_ZPOOL_PREFIX, DEFAULT_CONFIG, create_zpool, destroy_zpool,
+				get_zfs_client, make_server,
 			},
 		};
 
@@ -514,6 +1094,7 @@ mod tests {
 					modifications: Some(ZfsDataset {
 						name: "dataset2".into(),
 						quota: Some(5 * 1024 * 1024),
+						properties: Default::default(),
 					}),
 				}))
 				.await
@@ -669,5 +1250,69 @@ mod tests {
 
 			destroy_zpool("default", Some(&file)).unwrap();
 		}
+
+		#[tokio::test]
+		async fn test_zfs_concurrent_modify() {
+			let _ = destroy_zpool("concurrent", None);
+			let (_, file) = create_zpool("concurrent").unwrap();
+			let mut config = DEFAULT_CONFIG.clone();
+			config.zfs.pool = format!("{}-concurrent", BUCKLE_TEST_ZPOOL_PREFIX);
+			let mut client = get_zfs_client(make_server(Some(config)).await.unwrap())
+				.await
+				.unwrap();
+
+			client
+				.create_dataset(tonic::Request::new(ZfsDataset {
+					name: "dataset".into(),
+					..Default::default()
+				}))
+				.await
+				.unwrap();
+
+			// every one of these renames the same dataset, so only the first to run can succeed
+			let mut handles = Vec::new();
+			for i in 0..5 {
+				let mut client = client.clone();
+				handles.push(tokio::spawn(async move {
+					client
+						.modify_dataset(tonic::Request::new(ZfsModifyDataset {
+							name: "dataset".into(),
+							modifications: Some(ZfsDataset {
+								name: format!("dataset{}", i),
+								quota: Some(5 * 1024 * 1024),
+								properties: Default::default(),
+							}),
+						}))
+						.await
+						.map(|_| format!("dataset{}", i))
+				}));
+			}
+
+			let mut renamed = Vec::new();
+			for handle in handles {
+				if let Ok(name) = handle.await.unwrap() {
+					renamed.push(name);
+				}
+			}
+			assert_eq!(renamed.len(), 1);
+
+			let res = client
+				.list(tonic::Request::new(ZfsListFilter::default()))
+				.await
+				.unwrap()
+				.into_inner()
+				.entries;
+			assert_eq!(res.len(), 1);
+			assert_eq!(res[0].name, renamed[0]);
+			assert_eq!(
+				res[0].mountpoint,
+				Some(format!(
+					"/{}-concurrent/{}",
+					BUCKLE_TEST_ZPOOL_PREFIX, renamed[0]
+				))
+			);
+
+			destroy_zpool("concurrent", Some(&file)).unwrap();
+		}
 	}
 }
@@ -1,4 +1,4 @@
-use crate::grpc::SystemInfo;
+use crate::grpc::{DiskInfo as GrpcDiskInfo, NetworkInfo as GrpcNetworkInfo, SystemInfo};
 use fancy_duration::AsFancyDuration;
 use serde::{Deserialize, Serialize};
 use sysinfo::System;
@@ -15,8 +15,112 @@ pub struct Info {
 	pub kernel_version: String, // only the version string
 	pub load_average: [f64; 3], // 1, 5, 15 min
 	pub processes: usize,       // just the count
-	pub total_disk: u64,        // bytes
-	pub available_disk: u64,    // bytes
+	pub total_disk: u64,        // bytes, trunk disks only
+	pub available_disk: u64,    // bytes, trunk disks only
+	#[serde(default)]
+	pub disks: Vec<DiskInfo>, // every mounted disk, trunk or not
+	#[serde(default)]
+	pub networks: Vec<NetworkInfo>, // sorted by name
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkInfo {
+	pub name: String,
+	pub received: u64,            // bytes since the interface came up
+	pub transmitted: u64,         // bytes since the interface came up
+	pub packets_received: u64,    // since the interface came up
+	pub packets_transmitted: u64, // since the interface came up
+	pub receive_rate: u64,        // bytes per second, over the sampling interval
+	pub transmit_rate: u64,       // bytes per second, over the sampling interval
+}
+
+impl NetworkInfo {
+	// data must have been refreshed twice, interval apart, for the rates to mean anything
+	fn new(name: &str, data: &sysinfo::NetworkData, interval: std::time::Duration) -> Self {
+		let rate = |bytes: u64| {
+			let millis = interval.as_millis().max(1) as u64;
+			bytes.saturating_mul(1000) / millis
+		};
+
+		Self {
+			name: name.to_string(),
+			received: data.total_received(),
+			transmitted: data.total_transmitted(),
+			packets_received: data.total_packets_received(),
+			packets_transmitted: data.total_packets_transmitted(),
+			receive_rate: rate(data.received()),
+			transmit_rate: rate(data.transmitted()),
+		}
+	}
+}
+
+impl From<GrpcNetworkInfo> for NetworkInfo {
+	fn from(value: GrpcNetworkInfo) -> Self {
+		Self {
+			name: value.name,
+			received: value.received,
+			transmitted: value.transmitted,
+			packets_received: value.packets_received,
+			packets_transmitted: value.packets_transmitted,
+			receive_rate: value.receive_rate,
+			transmit_rate: value.transmit_rate,
+		}
+	}
+}
+
+impl From<NetworkInfo> for GrpcNetworkInfo {
+	fn from(value: NetworkInfo) -> Self {
+		Self {
+			name: value.name,
+			received: value.received,
+			transmitted: value.transmitted,
+			packets_received: value.packets_received,
+			packets_transmitted: value.packets_transmitted,
+			receive_rate: value.receive_rate,
+			transmit_rate: value.transmit_rate,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskInfo {
+	pub name: String,
+	pub mount_point: String,
+	pub total: u64,     // bytes
+	pub available: u64, // bytes
+}
+
+impl From<&sysinfo::Disk> for DiskInfo {
+	fn from(value: &sysinfo::Disk) -> Self {
+		Self {
+			name: value.name().to_string_lossy().to_string(),
+			mount_point: value.mount_point().to_string_lossy().to_string(),
+			total: value.total_space(),
+			available: value.available_space(),
+		}
+	}
+}
+
+impl From<GrpcDiskInfo> for DiskInfo {
+	fn from(value: GrpcDiskInfo) -> Self {
+		Self {
+			name: value.name,
+			mount_point: value.mount_point,
+			total: value.total,
+			available: value.available,
+		}
+	}
+}
+
+impl From<DiskInfo> for GrpcDiskInfo {
+	fn from(value: DiskInfo) -> Self {
+		Self {
+			name: value.name,
+			mount_point: value.mount_point,
+			total: value.total,
+			available: value.available,
+		}
+	}
 }
 
 impl Default for Info {
@@ -25,13 +129,33 @@ impl Default for Info {
 		let time = std::time::Instant::now();
 		let mut s = System::new_all();
 
+		// throughput, like cpu usage, is the difference between two samples, so both share the
+		// same wait
+		let mut networks = sysinfo::Networks::new_with_refreshed_list();
+		let sampled = std::time::Instant::now();
+
 		s.refresh_cpu_usage();
 		std::thread::sleep(std::time::Duration::from_millis(200));
 		s.refresh_cpu_usage();
 
+		networks.refresh(true);
+		let interval = sampled.elapsed();
+		let mut networks: Vec<NetworkInfo> = networks
+			.iter()
+			.map(|(name, data)| NetworkInfo::new(name, data, interval))
+			.collect();
+		networks.sort_by(|a, b| a.name.cmp(&b.name));
+
 		let la = System::load_average();
 		let la = [la.one, la.five, la.fifteen];
 
+		let disks = sysinfo::Disks::new_with_refreshed_list();
+		let trunk_disks = || {
+			disks
+				.iter()
+				.filter(|d| d.name().to_string_lossy().starts_with("trunk"))
+		};
+
 		let this = Self {
 			uptime: System::uptime(),
 			available_memory: s.available_memory(),
@@ -42,18 +166,16 @@ impl Default for Info {
 			kernel_version: System::kernel_version().unwrap_or("unknown".into()),
 			load_average: la,
 			processes: s.processes().len(),
-			total_disk: sysinfo::Disks::new_with_refreshed_list()
-				.iter()
-				.filter(|d| d.name().to_string_lossy().starts_with("trunk"))
+			total_disk: trunk_disks()
 				.map(|d| d.total_space())
 				.reduce(|a, e| a + e)
 				.unwrap_or_default(),
-			available_disk: sysinfo::Disks::new_with_refreshed_list()
-				.iter()
-				.filter(|d| d.name().to_string_lossy().starts_with("trunk"))
+			available_disk: trunk_disks()
 				.map(|d| d.available_space())
 				.reduce(|a, e| a + e)
 				.unwrap_or_default(),
+			disks: disks.iter().map(Into::into).collect(),
+			networks,
 		};
 
 		trace!(
@@ -85,6 +207,8 @@ impl From<SystemInfo> for Info {
 			processes: value.processes as usize,
 			total_disk: value.total_disk,
 			available_disk: value.available_disk,
+			disks: value.disks.into_iter().map(Into::into).collect(),
+			networks: value.networks.into_iter().map(Into::into).collect(),
 		}
 	}
 }
@@ -103,6 +227,8 @@ impl From<Info> for SystemInfo {
 			processes: value.processes as u64,
 			total_disk: value.total_disk,
 			available_disk: value.available_disk,
+			disks: value.disks.into_iter().map(Into::into).collect(),
+			networks: value.networks.into_iter().map(Into::into).collect(),
 		}
 	}
 }
@@ -122,5 +248,22 @@ mod tests {
 		assert!(!info.kernel_version.is_empty());
 		assert_ne!(info.load_average, [0.0, 0.0, 0.0]);
 		assert_ne!(info.processes, 0);
+		assert!(!info.disks.is_empty());
+
+		// survives the trip through grpc
+		let disks = info.disks.clone();
+		assert_eq!(Info::from(SystemInfo::from(info)).disks, disks);
+	}
+
+	#[test]
+	fn info_networks() {
+		let info = Info::default();
+		assert!(
+			info.networks.iter().any(|n| n.name == "lo"),
+			"loopback interface should be listed"
+		);
+
+		let networks = info.networks.clone();
+		assert_eq!(Info::from(SystemInfo::from(info)).networks, networks);
 	}
 }
@@ -8,7 +8,8 @@ use zbus_systemd::{
 };
 
 use crate::grpc::{
-	GrpcLogDirection, GrpcLogMessage, GrpcUnit, GrpcUnitStatus, UnitEnabledState, UnitLastRunState,
+	GrpcLogDirection, GrpcLogMessage, GrpcLogPriority, GrpcUnit, GrpcUnitFileChange,
+	GrpcUnitSettings, GrpcUnitStatus, UnitEnabledState, UnitLastRunState, UnitListFilter,
 	UnitLoadState, UnitRuntimeState,
 };
 
@@ -19,17 +20,155 @@ pub struct LogMessage {
 	service_name: String,
 	pid: u64,
 	cursor: String,
+	#[serde(default)]
+	priority: Option<LogPriority>,
+	#[serde(default)]
+	identifier: String,
+	#[serde(default)]
+	unit: String,
+}
+
+impl LogMessage {
+	pub fn message(&self) -> &str {
+		&self.message
+	}
+
+	pub fn time(&self) -> SystemTime {
+		self.time
+	}
+
+	pub fn service_name(&self) -> &str {
+		&self.service_name
+	}
+
+	pub fn pid(&self) -> u64 {
+		self.pid
+	}
+
+	pub fn cursor(&self) -> &str {
+		&self.cursor
+	}
+
+	// not every journal entry carries a priority; kernel and some stdout entries may not.
+	pub fn priority(&self) -> Option<LogPriority> {
+		self.priority.clone()
+	}
+
+	// SYSLOG_IDENTIFIER, usually the name of the program that wrote the entry
+	pub fn identifier(&self) -> &str {
+		&self.identifier
+	}
+
+	// _SYSTEMD_UNIT, the unit the writing process belonged to
+	pub fn unit(&self) -> &str {
+		&self.unit
+	}
 }
 
 impl From<GrpcLogMessage> for LogMessage {
 	fn from(value: GrpcLogMessage) -> Self {
 		Self {
+			priority: value.priority.map(|_| value.priority().into()),
 			message: value.msg,
 			time: SystemTime::UNIX_EPOCH
 				+ std::time::Duration::from_secs(value.time.unwrap_or_default().seconds as u64),
 			service_name: value.service_name,
 			pid: value.pid,
 			cursor: value.cursor,
+			identifier: value.identifier,
+			unit: value.unit,
+		}
+	}
+}
+
+// Syslog priorities as stored by journald. Ordering follows severity, so Emergency < Debug.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LogPriority {
+	Emergency,
+	Alert,
+	Critical,
+	Error,
+	Warning,
+	Notice,
+	Info,
+	Debug,
+}
+
+impl LogPriority {
+	// the numeric syslog level, as journald stores it in the PRIORITY field
+	pub fn level(&self) -> u8 {
+		match self {
+			Self::Emergency => 0,
+			Self::Alert => 1,
+			Self::Critical => 2,
+			Self::Error => 3,
+			Self::Warning => 4,
+			Self::Notice => 5,
+			Self::Info => 6,
+			Self::Debug => 7,
+		}
+	}
+}
+
+impl std::fmt::Display for LogPriority {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Emergency => "emerg",
+			Self::Alert => "alert",
+			Self::Critical => "crit",
+			Self::Error => "err",
+			Self::Warning => "warning",
+			Self::Notice => "notice",
+			Self::Info => "info",
+			Self::Debug => "debug",
+		})
+	}
+}
+
+impl std::str::FromStr for LogPriority {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		Ok(match s {
+			"0" | "emerg" => Self::Emergency,
+			"1" | "alert" => Self::Alert,
+			"2" | "crit" => Self::Critical,
+			"3" | "err" => Self::Error,
+			"4" | "warning" => Self::Warning,
+			"5" | "notice" => Self::Notice,
+			"6" | "info" => Self::Info,
+			"7" | "debug" => Self::Debug,
+			s => return Err(anyhow!("invalid log priority '{}'", s)),
+		})
+	}
+}
+
+impl From<GrpcLogPriority> for LogPriority {
+	fn from(value: GrpcLogPriority) -> Self {
+		match value {
+			GrpcLogPriority::Emergency => Self::Emergency,
+			GrpcLogPriority::Alert => Self::Alert,
+			GrpcLogPriority::Critical => Self::Critical,
+			GrpcLogPriority::Error => Self::Error,
+			GrpcLogPriority::Warning => Self::Warning,
+			GrpcLogPriority::Notice => Self::Notice,
+			GrpcLogPriority::Info => Self::Info,
+			GrpcLogPriority::Debug => Self::Debug,
+		}
+	}
+}
+
+impl From<LogPriority> for GrpcLogPriority {
+	fn from(value: LogPriority) -> Self {
+		match value {
+			LogPriority::Emergency => Self::Emergency,
+			LogPriority::Alert => Self::Alert,
+			LogPriority::Critical => Self::Critical,
+			LogPriority::Error => Self::Error,
+			LogPriority::Warning => Self::Warning,
+			LogPriority::Notice => Self::Notice,
+			LogPriority::Info => Self::Info,
+			LogPriority::Debug => Self::Debug,
 		}
 	}
 }
@@ -188,6 +327,139 @@ pub struct UnitSettings {
 	pub runtime_state: RuntimeState,
 }
 
+// one link systemd made or removed while enabling or disabling a unit
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct UnitFileChange {
+	// "symlink" or "unlink"
+	pub kind: String,
+	pub file: String,
+	pub destination: String,
+}
+
+impl From<(String, String, String)> for UnitFileChange {
+	fn from(value: (String, String, String)) -> Self {
+		Self {
+			kind: value.0,
+			file: value.1,
+			destination: value.2,
+		}
+	}
+}
+
+impl From<GrpcUnitFileChange> for UnitFileChange {
+	fn from(value: GrpcUnitFileChange) -> Self {
+		Self {
+			kind: value.kind,
+			file: value.file,
+			destination: value.destination,
+		}
+	}
+}
+
+impl From<UnitFileChange> for GrpcUnitFileChange {
+	fn from(value: UnitFileChange) -> Self {
+		Self {
+			kind: value.kind,
+			file: value.file,
+			destination: value.destination,
+		}
+	}
+}
+
+impl From<GrpcUnitSettings> for UnitSettings {
+	fn from(value: GrpcUnitSettings) -> Self {
+		Self {
+			enabled_state: value.enabled_state().into(),
+			runtime_state: value.runtime_state().into(),
+			name: value.name,
+		}
+	}
+}
+
+// Filter for unit listing. States that are unset match everything; offset and limit are applied
+// after filtering, over the units sorted by name.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Default)]
+pub struct UnitFilter {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub name: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub load_state: Option<LoadState>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub runtime_state: Option<RuntimeState>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_run_state: Option<LastRunState>,
+	#[serde(default)]
+	pub offset: usize,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub limit: Option<usize>,
+}
+
+impl UnitFilter {
+	pub fn matches(&self, unit: &Unit) -> bool {
+		if let Some(name) = &self.name
+			&& !unit.name.contains(name)
+		{
+			return false;
+		}
+
+		if let Some(load_state) = &self.load_state
+			&& unit.status.load_state != *load_state
+		{
+			return false;
+		}
+
+		if let Some(runtime_state) = &self.runtime_state
+			&& unit.status.runtime_state != *runtime_state
+		{
+			return false;
+		}
+
+		if let Some(last_run_state) = &self.last_run_state
+			&& unit.status.last_run_state != *last_run_state
+		{
+			return false;
+		}
+
+		true
+	}
+}
+
+impl From<UnitListFilter> for UnitFilter {
+	fn from(value: UnitListFilter) -> Self {
+		Self {
+			name: if value.filter.is_empty() {
+				None
+			} else {
+				Some(value.filter.clone())
+			},
+			load_state: value.load_state.map(|_| value.load_state().into()),
+			runtime_state: value.runtime_state.map(|_| value.runtime_state().into()),
+			last_run_state: value.last_run_state.map(|_| value.last_run_state().into()),
+			offset: value.offset as usize,
+			limit: value.limit.map(|x| x as usize),
+		}
+	}
+}
+
+impl From<UnitFilter> for UnitListFilter {
+	fn from(value: UnitFilter) -> Self {
+		Self {
+			filter: value.name.unwrap_or_default(),
+			load_state: value
+				.load_state
+				.map(|x| Into::<UnitLoadState>::into(x).into()),
+			runtime_state: value
+				.runtime_state
+				.map(|x| Into::<UnitRuntimeState>::into(x).into()),
+			last_run_state: value
+				.last_run_state
+				.map(|x| Into::<UnitLastRunState>::into(x).into()),
+			offset: value.offset as u64,
+			limit: value.limit.map(|x| x as u64),
+		}
+	}
+}
+
 impl From<GrpcUnit> for Unit {
 	fn from(value: GrpcUnit) -> Self {
 		Self {
@@ -342,6 +614,30 @@ pub struct Status {
 	pub last_run_state: LastRunState,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub enum PowerAction {
+	Reboot,
+	Poweroff,
+}
+
+impl PowerAction {
+	pub fn target(&self) -> &'static str {
+		match self {
+			Self::Reboot => "reboot.target",
+			Self::Poweroff => "poweroff.target",
+		}
+	}
+}
+
+impl std::fmt::Display for PowerAction {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Reboot => "reboot",
+			Self::Poweroff => "poweroff",
+		})
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct Systemd {
 	client: Connection,
@@ -417,14 +713,73 @@ impl Systemd {
 		Ok(())
 	}
 
-	pub async fn enable(&self, name: String) -> Result<()> {
+	// the same job `systemctl reboot` and `systemctl poweroff` queue: units are stopped in order
+	// on the way down, and nothing queued after can cancel it.
+	pub async fn power(&self, action: PowerAction) -> Result<()> {
 		self.manager
-			.enable_unit_files(vec![name], true, true)
+			.start_unit(action.target().into(), "replace-irreversibly".into())
 			.await?;
-
 		Ok(())
 	}
 
+	// enable and disable take unit names, and return the links systemd changed. nothing is
+	// changed for units without an [Install] section. enabling links under /etc, so it lasts
+	// across reboots.
+	pub async fn enable(&self, name: String) -> Result<Vec<UnitFileChange>> {
+		let (_, changes) = self
+			.manager
+			.enable_unit_files(vec![name], false, true)
+			.await?;
+
+		Ok(changes.into_iter().map(Into::into).collect())
+	}
+
+	// removes the links under /etc and those under /run, whichever way the unit was enabled
+	pub async fn disable(&self, name: String) -> Result<Vec<UnitFileChange>> {
+		let mut changes = self
+			.manager
+			.disable_unit_files(vec![name.clone()], false)
+			.await?;
+		changes.append(&mut self.manager.disable_unit_files(vec![name], true).await?);
+		Ok(changes.into_iter().map(Into::into).collect())
+	}
+
+	// brings the unit named in the settings (f.e., 'sshd.service') to them. enablement is only
+	// changed for units that can be enabled at all; static units and the like are left be.
+	pub async fn set_unit(&self, settings: &UnitSettings) -> Result<()> {
+		let unit =
+			UnitProxy::new(&self.client, self.get_unit(settings.name.clone()).await?).await?;
+
+		if unit.load_state().await?.parse::<LoadState>()? == LoadState::Unloaded {
+			return Err(anyhow!("unit '{}' does not exist", settings.name));
+		}
+
+		// the canonical name, in case the settings used an alias
+		let name = unit.id().await?;
+
+		let changes = match (
+			&settings.enabled_state,
+			unit.unit_file_state().await?.as_str(),
+		) {
+			(EnabledState::Enabled, "disabled") => self.enable(name.clone()).await?,
+			(EnabledState::Disabled, "enabled" | "enabled-runtime") => {
+				self.disable(name.clone()).await?
+			}
+			_ => Vec::new(),
+		};
+
+		if !changes.is_empty() {
+			self.reload().await?;
+		}
+
+		match settings.runtime_state {
+			RuntimeState::Started => self.start(name).await,
+			RuntimeState::Stopped => self.stop(name).await,
+			RuntimeState::Restarted => self.restart(name).await,
+			RuntimeState::Reloaded => self.reload_unit(name).await,
+		}
+	}
+
 	pub async fn load_unit(&self, name: String) -> Result<()> {
 		self.manager.load_unit(name).await?;
 		Ok(())
@@ -440,6 +795,27 @@ impl Systemd {
 		})
 	}
 
+	// everything list() would say about the unit named (f.e., 'sshd.service'), or None if systemd
+	// has no such unit
+	pub async fn unit(&self, name: String) -> Result<Option<Unit>> {
+		let object_path = self.get_unit(name).await?;
+		let unit = UnitProxy::new(&self.client, object_path.clone()).await?;
+		let status = self.status(object_path.clone()).await?;
+
+		if status.load_state == LoadState::Unloaded {
+			return Ok(None);
+		}
+
+		Ok(Some(Unit {
+			name: unit.id().await?,
+			description: unit.description().await?,
+			// as in list(), this comes from the active state
+			enabled_state: unit.active_state().await?.parse()?,
+			object_path,
+			status,
+		}))
+	}
+
 	// gets the object path for the unit name (f.e., 'sshd.service')
 	// required for all the above management calls
 	pub async fn get_unit(&self, name: String) -> Result<String> {
@@ -483,77 +859,185 @@ impl Systemd {
 		Ok(v)
 	}
 
+	pub async fn list_filtered(&self, filter: &UnitFilter) -> Result<Vec<Unit>> {
+		let mut list = self
+			.list(filter.name.clone())
+			.await?
+			.into_iter()
+			.filter(|unit| filter.matches(unit))
+			.collect::<Vec<Unit>>();
+
+		// systemd does not guarantee any order, so sort to keep pages stable between calls
+		list.sort_by(|a, b| a.name.cmp(&b.name));
+
+		Ok(list
+			.into_iter()
+			.skip(filter.offset)
+			.take(filter.limit.unwrap_or(usize::MAX))
+			.collect())
+	}
+
+	// if priority is provided, only entries of that priority or more severe are returned. if follow
+	// is set, the direction is always forward and the receiver keeps getting new entries as they
+	// are written, until it is dropped. otherwise at most count entries are sent.
 	pub async fn log(
 		&self, name: &str, count: usize, cursor: Option<String>, direction: Option<LogDirection>,
+		priority: Option<LogPriority>, follow: bool,
 	) -> Result<tokio::sync::mpsc::UnboundedReceiver<BTreeMap<String, String>>> {
 		let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+		// the journal can only be used from the thread that opened it, so whether opening and
+		// seeking worked is reported back from there.
+		let (ready_tx, ready_rx) = tokio::sync::oneshot::channel();
+
+		let unit = name.to_string();
+		// the journal API is entirely blocking, and in follow mode this can live for a long time.
+		tokio::task::spawn_blocking(move || {
+			let (mut journal, seen) = match open_journal(&unit, count, cursor, priority) {
+				Ok(opened) => {
+					let _ = ready_tx.send(Ok(()));
+					opened
+				}
+				Err(e) => {
+					let _ = ready_tx.send(Err(e));
+					return;
+				}
+			};
 
-		let name = name.to_string();
-		tokio::spawn(async move {
-			let mut journal = systemd::journal::OpenOptions::default()
-				.system(true)
-				.all_namespaces(true)
-				.open()
-				.unwrap();
-
-			let journal = journal.match_add("UNIT", name).unwrap();
-
-			// the logic here is:
-			// if there is a cursor, seek to it,
-			// otherwise, seek to the end and rewind count entries.
-			// then, for a direction forward or backward, send count log messages.
-			//
-			// this leads to weird logic conclusions like "seek to the end, rewind, and then play
-			// the previous 100 lines". I think it's better this way because it's consistently
-			// weird.
-
-			if let Some(cursor) = cursor
-				&& !cursor.is_empty()
-			{
-				journal.seek_cursor(cursor).unwrap();
+			let direction = if follow {
+				LogDirection::Forward
 			} else {
-				journal.seek_tail().unwrap();
-
-				// do the seek manually as there is no direct support for seeking by entry count that I
-				// can find. this is probably subject to some kind of race condition, but it really
-				// doesn't matter unless an extreme amount of log messages arrive in the window between
-				// the rewind and fast-forward.
-				let mut total = 0;
-				while let Ok(Some(_)) = journal.previous_entry() {
-					total += 1;
-					if total > count {
-						break;
+				direction.unwrap_or_default()
+			};
+
+			let mut sent = 0;
+			while follow || sent < count {
+				// FIXME: the struct should really be constructed here, not in the service handler
+				let mut entry = match direction {
+					LogDirection::Forward => journal.next_entry(),
+					LogDirection::Backward => journal.previous_entry(),
+				};
+
+				if follow && matches!(entry, Ok(None)) {
+					// wake up every so often so a dropped receiver is noticed on a quiet unit
+					if tx.is_closed() {
+						return;
 					}
-				}
-			}
 
-			match direction.unwrap_or_default() {
-				LogDirection::Forward => {
-					// FIXME: the struct should really be constructed here, not in the service handler
-					while let Ok(Some(mut entry)) = journal.next_entry() {
-						// Add the cursor so it can be pulled out later
-						entry.insert("CURSOR".into(), journal.cursor().unwrap());
-						tx.send(entry).unwrap()
+					entry = journal.await_next_entry(Some(std::time::Duration::from_secs(1)));
+					if matches!(entry, Ok(None)) {
+						continue;
 					}
 				}
-				LogDirection::Backward => {
-					// FIXME: the struct should really be constructed here, not in the service handler
-					while let Ok(Some(mut entry)) = journal.previous_entry() {
-						// Add the cursor so it can be pulled out later
-						entry.insert("CURSOR".into(), journal.cursor().unwrap());
-						tx.send(entry).unwrap()
-					}
+
+				let Ok(Some(mut entry)) = entry else {
+					return;
+				};
+
+				let cursor = journal.cursor().unwrap();
+				if seen.as_ref() == Some(&cursor) {
+					continue;
 				}
+
+				// Add the cursor so it can be pulled out later
+				entry.insert("CURSOR".into(), cursor);
+				if tx.send(entry).is_err() {
+					return;
+				}
+				sent += 1;
 			}
 		});
 
+		ready_rx
+			.await
+			.map_err(|_| anyhow!("journal reader for {} exited before it started", name))??;
+
 		Ok(rx)
 	}
 }
 
+// the cursor handed to Systemd::log isn't one the journal can seek to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCursorError {
+	pub cursor: String,
+	pub reason: String,
+}
+
+impl std::fmt::Display for InvalidCursorError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"invalid journal cursor '{}': {}",
+			self.cursor, self.reason
+		)
+	}
+}
+
+impl std::error::Error for InvalidCursorError {}
+
+// opens the unit's journal and positions it for Systemd::log. also returns the cursor the read
+// resumes from, if any, since the entry it points at is skipped.
+fn open_journal(
+	name: &str, count: usize, cursor: Option<String>, priority: Option<LogPriority>,
+) -> Result<(systemd::journal::Journal, Option<String>)> {
+	let mut journal = systemd::journal::OpenOptions::default()
+		.system(true)
+		.all_namespaces(true)
+		.open()?;
+
+	journal.match_add("UNIT", name)?;
+
+	// matches on the same field are OR'd together by journald, and AND'd with the UNIT match
+	// above, so this selects every level at or above the threshold.
+	if let Some(priority) = priority {
+		for level in 0..=priority.level() {
+			journal.match_add("PRIORITY", level.to_string())?;
+		}
+	}
+
+	// the logic here is:
+	// if there is a cursor, seek to it,
+	// otherwise, seek to the end and rewind count entries.
+	// then, for a direction forward or backward, send count log messages.
+	//
+	// this leads to weird logic conclusions like "seek to the end, rewind, and then play the
+	// previous 100 lines". I think it's better this way because it's consistently weird.
+
+	if let Some(cursor) = cursor
+		&& !cursor.is_empty()
+	{
+		journal
+			.seek_cursor(cursor.clone())
+			.map_err(|e| InvalidCursorError {
+				cursor: cursor.clone(),
+				reason: e.to_string(),
+			})?;
+		// the first entry after seeking is the one the cursor points at, which whoever handed us
+		// the cursor already has. it is skipped so resuming doesn't repeat it.
+		return Ok((journal, Some(cursor)));
+	}
+
+	journal.seek_tail()?;
+
+	// do the seek manually as there is no direct support for seeking by entry count that I can
+	// find. this is probably subject to some kind of race condition, but it really doesn't matter
+	// unless an extreme amount of log messages arrive in the window between the rewind and
+	// fast-forward.
+	let mut total = 0;
+	while let Ok(Some(_)) = journal.previous_entry() {
+		total += 1;
+		if total > count {
+			break;
+		}
+	}
+
+	Ok((journal, None))
+}
+
 #[cfg(test)]
 mod tests {
-	use crate::systemd::{LastRunState, RuntimeState, Systemd};
+	use crate::systemd::{
+		InvalidCursorError, LastRunState, LogPriority, RuntimeState, Systemd, UnitFilter,
+	};
 
 	#[tokio::test]
 	async fn test_status() {
@@ -576,6 +1060,51 @@ mod tests {
 		assert_eq!(status.last_run_state, LastRunState::Running);
 	}
 
+	#[tokio::test]
+	async fn test_enable_static() {
+		let systemd = Systemd::new_system().await.unwrap();
+		// targets like this one have no [Install] section, so there is nothing to link or unlink
+		assert!(
+			systemd
+				.enable("network.target".into())
+				.await
+				.unwrap()
+				.is_empty()
+		);
+		assert!(
+			systemd
+				.disable("network.target".into())
+				.await
+				.unwrap()
+				.is_empty()
+		);
+	}
+
+	#[tokio::test]
+	async fn test_enable_disable() {
+		let name = "buckle-test-enable.service";
+		let unit = std::path::Path::new("/etc/systemd/system").join(name);
+		let link = std::path::Path::new("/etc/systemd/system/multi-user.target.wants").join(name);
+		std::fs::write(
+			&unit,
+			"[Service]\nExecStart=/bin/true\n\n[Install]\nWantedBy=multi-user.target\n",
+		)
+		.unwrap();
+
+		let systemd = Systemd::new_system().await.unwrap();
+		systemd.reload().await.unwrap();
+
+		// a unit enabled the usual way, so it's still enabled after a reboot
+		assert!(!systemd.enable(name.into()).await.unwrap().is_empty());
+		assert!(link.is_symlink());
+
+		assert!(!systemd.disable(name.into()).await.unwrap().is_empty());
+		assert!(!link.is_symlink());
+
+		std::fs::remove_file(&unit).unwrap();
+		systemd.reload().await.unwrap();
+	}
+
 	#[tokio::test]
 	async fn test_list() {
 		let systemd = Systemd::new_system().await.unwrap();
@@ -590,11 +1119,50 @@ mod tests {
 		assert!(found, "did not find item in systemd to check")
 	}
 
+	#[tokio::test]
+	async fn test_list_filtered() {
+		let systemd = Systemd::new_system().await.unwrap();
+
+		let running = systemd
+			.list_filtered(&UnitFilter {
+				last_run_state: Some(LastRunState::Running),
+				..Default::default()
+			})
+			.await
+			.unwrap();
+		assert!(running.iter().any(|x| x.name == "init.scope"));
+		assert!(
+			running
+				.iter()
+				.all(|x| x.status.last_run_state == LastRunState::Running)
+		);
+
+		let first = systemd
+			.list_filtered(&UnitFilter {
+				limit: Some(1),
+				..Default::default()
+			})
+			.await
+			.unwrap();
+		assert_eq!(first.len(), 1);
+
+		let second = systemd
+			.list_filtered(&UnitFilter {
+				offset: 1,
+				limit: Some(1),
+				..Default::default()
+			})
+			.await
+			.unwrap();
+		assert_eq!(second.len(), 1);
+		assert!(first[0].name < second[0].name);
+	}
+
 	#[tokio::test]
 	async fn test_log() {
 		let systemd = Systemd::new_system().await.unwrap();
 		let mut r = systemd
-			.log("multi-user.target", 10, None, None)
+			.log("multi-user.target", 10, None, None, None, false)
 			.await
 			.unwrap();
 
@@ -609,4 +1177,88 @@ mod tests {
 
 		assert_eq!(i, 10);
 	}
+
+	#[tokio::test]
+	async fn test_log_priority() {
+		let systemd = Systemd::new_system().await.unwrap();
+		let mut r = systemd
+			.log(
+				"multi-user.target",
+				10,
+				None,
+				None,
+				Some(LogPriority::Notice),
+				false,
+			)
+			.await
+			.unwrap();
+
+		while let Some(item) = r.recv().await {
+			let priority: LogPriority = item.get("PRIORITY").unwrap().parse().unwrap();
+			assert!(priority <= LogPriority::Notice);
+		}
+	}
+
+	#[tokio::test]
+	async fn test_log_cursor() {
+		let systemd = Systemd::new_system().await.unwrap();
+		let mut r = systemd
+			.log("multi-user.target", 10, None, None, None, false)
+			.await
+			.unwrap();
+
+		let mut cursors = Vec::new();
+		while let Some(item) = r.recv().await {
+			cursors.push(item.get("CURSOR").unwrap().clone());
+		}
+		assert_eq!(cursors.len(), 10);
+
+		// resuming from a cursor should pick up at the entry after it, not repeat it
+		let mut r = systemd
+			.log(
+				"multi-user.target",
+				10,
+				Some(cursors[4].clone()),
+				None,
+				None,
+				false,
+			)
+			.await
+			.unwrap();
+
+		let next = r.recv().await.unwrap();
+		assert_eq!(next.get("CURSOR").unwrap(), &cursors[5]);
+
+		// without following, a cursor doesn't read on to the end of the journal
+		let mut r = systemd
+			.log(
+				"multi-user.target",
+				2,
+				Some(cursors[4].clone()),
+				None,
+				None,
+				false,
+			)
+			.await
+			.unwrap();
+
+		let mut resumed = Vec::new();
+		while let Some(item) = r.recv().await {
+			resumed.push(item.get("CURSOR").unwrap().clone());
+		}
+		assert_eq!(resumed, cursors[5..7]);
+
+		let err = systemd
+			.log(
+				"multi-user.target",
+				10,
+				Some("not a cursor".into()),
+				None,
+				None,
+				false,
+			)
+			.await
+			.unwrap_err();
+		assert!(err.downcast_ref::<InvalidCursorError>().is_some());
+	}
 }
@@ -14,8 +14,12 @@ pub static DEFAULT_CONFIG: LazyLock<crate::config::Config> =
 		socket: "/tmp/buckled.sock".into(),
 		zfs: crate::config::ZFSConfig {
 			pool: format!("{}-default", BUCKLE_TEST_ZPOOL_PREFIX),
+			timeout: crate::config::DEFAULT_ZFS_TIMEOUT,
+			default_quota: None,
 		},
 		log_level: LogLevel::Error,
+		commands: Default::default(),
+		port_state: "/tmp/buckle-ports.json".into(),
 	});
 
 pub fn find_listener() -> Result<std::path::PathBuf> {
@@ -28,6 +32,7 @@ pub fn find_listener() -> Result<std::path::PathBuf> {
 pub async fn make_server(config: Option<crate::config::Config>) -> Result<std::path::PathBuf> {
 	let mut config = config.unwrap_or_else(|| DEFAULT_CONFIG.clone());
 	config.socket = find_listener()?;
+	config.port_state = config.socket.with_extension("ports.json");
 	let server = Server::new_with_config(Some(config.clone()));
 
 	tokio::spawn(async move { server.start().unwrap().await.unwrap() });
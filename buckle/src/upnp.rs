@@ -1,16 +1,19 @@
 use easy_upnp::{PortMappingProtocol, UpnpConfig};
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 use crate::grpc::{GrpcPortForward, GrpcProtocol};
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub const DEFAULT_PORT_STATE_PATH: &str = "/trunk/.buckle-ports.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Protocol {
 	#[default]
 	TCP,
 	UDP,
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PortForward {
 	pub port: u16,
 	pub protocol: Protocol,
@@ -27,6 +30,17 @@ impl From<GrpcPortForward> for PortForward {
 	}
 }
 
+impl From<PortForward> for GrpcPortForward {
+	fn from(value: PortForward) -> Self {
+		let protocol: GrpcProtocol = value.protocol.into();
+		Self {
+			port: value.port.into(),
+			protocol: protocol.into(),
+			name: value.name,
+		}
+	}
+}
+
 impl From<GrpcProtocol> for Protocol {
 	fn from(value: GrpcProtocol) -> Self {
 		match value {
@@ -65,3 +79,127 @@ impl Into<UpnpConfig> for PortForward {
 		}
 	}
 }
+
+// the mappings buckle has made on the router, so they can be listed and removed later without
+// trusting whatever else the router has open. one port and protocol has at most one entry.
+#[derive(Debug, Clone)]
+pub(crate) struct PortTable {
+	path: PathBuf,
+}
+
+impl PortTable {
+	pub(crate) fn new(path: &Path) -> Self {
+		Self {
+			path: path.to_path_buf(),
+		}
+	}
+
+	pub(crate) fn list(&self) -> anyhow::Result<Vec<PortForward>> {
+		match std::fs::OpenOptions::new().read(true).open(&self.path) {
+			Ok(f) => Ok(serde_json::from_reader(f)?),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	pub(crate) fn record(&self, forward: PortForward) -> anyhow::Result<()> {
+		let mut list = self.list()?;
+		list.retain(|x| !(x.port == forward.port && x.protocol == forward.protocol));
+		list.push(forward);
+		self.write(&list)
+	}
+
+	// returns the entry that was removed, if there was one
+	pub(crate) fn forget(
+		&self, port: u16, protocol: Protocol,
+	) -> anyhow::Result<Option<PortForward>> {
+		let mut list = self.list()?;
+		let Some(pos) = list
+			.iter()
+			.position(|x| x.port == port && x.protocol == protocol)
+		else {
+			return Ok(None);
+		};
+
+		let forward = list.remove(pos);
+		self.write(&list)?;
+		Ok(Some(forward))
+	}
+
+	pub(crate) fn find(
+		&self, port: u16, protocol: Protocol,
+	) -> anyhow::Result<Option<PortForward>> {
+		Ok(self
+			.list()?
+			.into_iter()
+			.find(|x| x.port == port && x.protocol == protocol))
+	}
+
+	fn write(&self, list: &[PortForward]) -> anyhow::Result<()> {
+		if let Some(parent) = self.path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		let mut tmp = self.path.as_os_str().to_owned();
+		tmp.push(".tmp");
+
+		let f = std::fs::OpenOptions::new()
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.open(&tmp)?;
+		serde_json::to_writer(f, list)?;
+		std::fs::rename(&tmp, &self.path)?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn port_table() {
+		let dir = tempfile::tempdir().unwrap();
+		let table = PortTable::new(&dir.path().join("ports.json"));
+		assert!(table.list().unwrap().is_empty());
+
+		let forward = |port, protocol, name: &str| PortForward {
+			port,
+			protocol,
+			name: name.into(),
+		};
+
+		table
+			.record(forward(8080, Protocol::TCP, "plex-1.0"))
+			.unwrap();
+		table
+			.record(forward(8080, Protocol::UDP, "plex-1.0"))
+			.unwrap();
+		// the same port and protocol replaces the old entry
+		table
+			.record(forward(8080, Protocol::TCP, "plex-1.1"))
+			.unwrap();
+
+		// persisted, so a new table over the same file sees it
+		let table = PortTable::new(&dir.path().join("ports.json"));
+		assert_eq!(
+			table.list().unwrap(),
+			vec![
+				forward(8080, Protocol::UDP, "plex-1.0"),
+				forward(8080, Protocol::TCP, "plex-1.1"),
+			]
+		);
+		assert_eq!(
+			table.find(8080, Protocol::TCP).unwrap(),
+			Some(forward(8080, Protocol::TCP, "plex-1.1"))
+		);
+
+		assert_eq!(
+			table.forget(8080, Protocol::UDP).unwrap(),
+			Some(forward(8080, Protocol::UDP, "plex-1.0"))
+		);
+		assert_eq!(table.forget(8080, Protocol::UDP).unwrap(), None);
+		assert_eq!(table.list().unwrap().len(), 1);
+	}
+}
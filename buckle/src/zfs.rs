@@ -1,25 +1,49 @@
-use crate::grpc::{
-	ZfsDataset, ZfsEntry, ZfsList, ZfsModifyDataset, ZfsModifyVolume, ZfsType, ZfsVolume,
+use crate::{
+	commands::command_paths,
+	config::DEFAULT_ZFS_TIMEOUT,
+	grpc::{
+		ZfsCloneSnapshot, ZfsDataset, ZfsDetail, ZfsEntry, ZfsList, ZfsModifyDataset,
+		ZfsModifyVolume, ZfsPoolStatus, ZfsScrubState, ZfsScrubStatus, ZfsSnapshot,
+		ZfsSnapshotList, ZfsType, ZfsVolume,
+	},
 };
 use anyhow::{Result, anyhow};
 use fancy_duration::AsFancyDuration;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, str::FromStr};
+use std::{
+	collections::HashMap,
+	io::Read,
+	path::Path,
+	str::FromStr,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tracing::{debug, error, trace};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum ZFSKind {
+	#[default]
 	Dataset,
 	Volume,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+// the properties a dataset may be given besides its quota. anything else could change where or
+// whether it is mounted, or who may touch it.
+pub const DATASET_PROPERTIES: &[&str] = &["atime", "compression", "recordsize", "relatime", "sync"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Dataset {
 	pub name: String,
+	// in bytes. None takes the pool's default quota, if one is configured; Some(0) is no quota
+	// at all, for datasets that really should be able to fill the pool.
 	pub quota: Option<u64>,
+	// zfs properties to set, from DATASET_PROPERTIES. Pool::get reads them back: every property
+	// is in ZFSDetail's `properties` as zfs prints it, and compression and recordsize are also
+	// parsed out on their own.
+	#[serde(default)]
+	pub properties: HashMap<String, String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ModifyDataset {
 	pub name: String,
 	pub modifications: Dataset,
@@ -37,13 +61,182 @@ pub struct ModifyVolume {
 	pub modifications: Volume,
 }
 
+// a new dataset or volume `name`, sharing its blocks with `snapshot` (given as dataset@snapshot)
+// until either is written to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CloneSnapshot {
+	pub snapshot: String,
+	pub name: String,
+}
+
+// a snapshot of a dataset or volume, as zfs lists it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ZFSSnapshot {
+	// pool/dataset@snapshot
+	pub full_name: String,
+	// without the pool, like ZFSStat's name
+	pub dataset: String,
+	pub snapshot_name: String,
+	// bytes held only by this snapshot
+	pub used: u64,
+	// seconds since the epoch
+	pub creation: u64,
+}
+
+// a dataset can't be destroyed while clones of its snapshots exist; the clones are listed so the
+// caller can destroy them, or promote them so they take the snapshots over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependentClonesError {
+	pub name: String,
+	pub clones: Vec<String>,
+}
+
+impl std::fmt::Display for DependentClonesError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"'{}' has snapshots that clones depend on ({}); destroy or promote the clones first",
+			self.name,
+			self.clones.join(", ")
+		)
+	}
+}
+
+impl std::error::Error for DependentClonesError {}
+
+// a rollback that would destroy newer snapshots, and wasn't forced to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaterSnapshotsError {
+	pub dataset: String,
+	pub snapshot: String,
+	pub later: Vec<String>,
+}
+
+impl std::fmt::Display for LaterSnapshotsError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"rolling '{}' back to '{}' would destroy the snapshots taken since ({}); force it to destroy them",
+			self.dataset,
+			self.snapshot,
+			self.later.join(", ")
+		)
+	}
+}
+
+impl std::error::Error for LaterSnapshotsError {}
+
+// a zfs command that was killed for running past its timeout; usually a sign of a faulted pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeoutError {
+	pub command: String,
+	pub timeout: Duration,
+}
+
+impl std::fmt::Display for TimeoutError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"[{}] did not finish within {}, and was killed",
+			self.command,
+			self.timeout.fancy_duration()
+		)
+	}
+}
+
+impl std::error::Error for TimeoutError {}
+
+// the version of zfs's JSON output that the structs below are written against (OpenZFS 2.3).
+// minor versions only add to the output, so any minor version of this major version is read.
+pub const ZFS_JSON_VERSION: (u64, u64) = (0, 1);
+
+// zfs printed JSON in a layout buckle doesn't know how to read. `detected` is None when the
+// output had no version at all, which is what zfs releases before 2.3 do, if they accept -j.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedJSONError {
+	pub command: String,
+	pub detected: Option<(u64, u64)>,
+}
+
+impl std::fmt::Display for UnsupportedJSONError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.detected {
+			Some((major, minor)) => write!(
+				f,
+				"unsupported zfs JSON version {}.{} from [{}], need {}.x",
+				major, minor, self.command, ZFS_JSON_VERSION.0
+			),
+			None => write!(
+				f,
+				"unsupported zfs JSON output from [{}]: no output version, need {}.{} or later (OpenZFS 2.3)",
+				self.command, ZFS_JSON_VERSION.0, ZFS_JSON_VERSION.1
+			),
+		}
+	}
+}
+
+impl std::error::Error for UnsupportedJSONError {}
+
+// a running `zfs send`; reading it gives the stream. if zfs fails partway the read that would
+// have ended the stream fails instead, so a truncated stream isn't taken for a whole one.
+// dropping it before the end kills zfs.
+pub struct SendStream {
+	command: String,
+	child: std::process::Child,
+	stdout: std::process::ChildStdout,
+	stderr: Option<std::thread::JoinHandle<Vec<u8>>>,
+	finished: bool,
+}
+
+impl Read for SendStream {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if self.finished {
+			return Ok(0);
+		}
+
+		let n = self.stdout.read(buf)?;
+		if n == 0 && !buf.is_empty() {
+			self.finished = true;
+
+			let status = self.child.wait()?;
+			if !status.success() {
+				let stderr = self
+					.stderr
+					.take()
+					.and_then(|x| x.join().ok())
+					.unwrap_or_default();
+				return Err(std::io::Error::other(format!(
+					"[{}] failed: {}",
+					self.command,
+					String::from_utf8_lossy(stderr.trim_ascii())
+				)));
+			}
+		}
+
+		Ok(n)
+	}
+}
+
+impl Drop for SendStream {
+	fn drop(&mut self) {
+		if !self.finished {
+			let _ = self.child.kill();
+			let _ = self.child.wait();
+		}
+	}
+}
+
+// how often a running command is checked on
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
 #[derive(Debug, Clone)]
 pub struct Pool {
 	name: String,
 	controller: Controller,
+	default_quota: Option<u64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ZFSStat {
 	pub kind: ZFSKind,
 	pub name: String,
@@ -53,9 +246,61 @@ pub struct ZFSStat {
 	pub avail: u64,
 	pub refer: u64,
 	pub mountpoint: Option<String>,
+	// how full it is, 0 to 100: of its quota for a dataset, of its volsize for a volume. a dataset
+	// without a quota is limited only by the pool, and reports 0.
+	#[serde(default)]
+	pub used_percent: f64,
 	// FIXME collect options (like quotas)
 }
 
+// everything zfs knows about one dataset or volume. the common properties are parsed out;
+// `properties` has all of them, as zfs printed them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ZFSDetail {
+	pub stat: ZFSStat,
+	pub compression: String,
+	// volumes have a volblocksize instead
+	pub recordsize: Option<u64>,
+	pub reservation: u64,
+	pub quota: Option<u64>,
+	// seconds since the epoch
+	pub creation: u64,
+	// the snapshot this was cloned from, if it is a clone
+	pub origin: Option<String>,
+	pub properties: HashMap<String, String>,
+}
+
+// space accounting for the pool as a whole, taken from its root dataset
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolStatus {
+	pub name: String,
+	pub used: u64,
+	pub avail: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrubState {
+	// the pool has never been scrubbed
+	#[default]
+	None,
+	Scanning,
+	Finished,
+	Canceled,
+}
+
+// the most recent scrub of the pool
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScrubStatus {
+	pub state: ScrubState,
+	// 0 to 100, of the data the scrub set out to check
+	pub percent_done: f64,
+	// checksum and I/O errors it has found, repaired or not
+	pub errors: u64,
+	// seconds until a running scrub is done, once it has gone long enough to tell
+	pub eta: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZFSOutputInfo {
 	command: String,
@@ -75,6 +320,7 @@ pub struct ZFSGetItem<T> {
 	#[serde(rename = "type")]
 	typ: String,
 	pool: String,
+	#[serde(deserialize_with = "number_or_string")]
 	createtxg: u64,
 	properties: HashMap<String, ZFSValue<T>>,
 }
@@ -91,6 +337,7 @@ pub struct ZFSListItem {
 	#[serde(rename = "type")]
 	typ: String,
 	pool: String,
+	#[serde(deserialize_with = "number_or_string")]
 	createtxg: u64,
 	properties: ZFSListItemProperties,
 }
@@ -103,6 +350,58 @@ pub struct ZFSListItemProperties {
 	mountpoint: ZFSValue<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZFSSnapshotList {
+	output_version: ZFSOutputInfo,
+	datasets: HashMap<String, ZFSSnapshotItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZFSSnapshotItem {
+	name: String,
+	#[serde(deserialize_with = "number_or_string")]
+	createtxg: u64,
+	properties: ZFSSnapshotItemProperties,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZFSSnapshotItemProperties {
+	used: ZFSValue<u64>,
+	creation: ZFSValue<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZPoolStatus {
+	output_version: ZFSOutputInfo,
+	pools: HashMap<String, ZPoolStatusItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZPoolStatusItem {
+	// missing until the pool is first scrubbed or resilvered
+	scan_stats: Option<ZPoolScanStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZPoolScanStats {
+	function: String,
+	state: String,
+	#[serde(deserialize_with = "number_or_string")]
+	to_examine: u64,
+	#[serde(deserialize_with = "number_or_string")]
+	issued: u64,
+	#[serde(deserialize_with = "number_or_string")]
+	errors: u64,
+	// the current pass, which restarts when the pool is imported or a paused scrub resumes
+	#[serde(deserialize_with = "number_or_string")]
+	pass_start: u64,
+	#[serde(deserialize_with = "number_or_string")]
+	issued_bytes_per_scan: u64,
+	#[serde(deserialize_with = "number_or_string")]
+	scrub_spent_paused: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZFSValue<T> {
 	value: T,
@@ -157,6 +456,7 @@ impl From<Dataset> for ZfsDataset {
 		Self {
 			name: value.name,
 			quota: value.quota,
+			properties: value.properties,
 		}
 	}
 }
@@ -166,6 +466,25 @@ impl From<ZfsDataset> for Dataset {
 		Self {
 			name: value.name,
 			quota: value.quota,
+			properties: value.properties,
+		}
+	}
+}
+
+impl From<CloneSnapshot> for ZfsCloneSnapshot {
+	fn from(value: CloneSnapshot) -> Self {
+		Self {
+			snapshot: value.snapshot,
+			name: value.name,
+		}
+	}
+}
+
+impl From<ZfsCloneSnapshot> for CloneSnapshot {
+	fn from(value: ZfsCloneSnapshot) -> Self {
+		Self {
+			snapshot: value.snapshot,
+			name: value.name,
 		}
 	}
 }
@@ -208,6 +527,44 @@ impl From<Vec<ZFSStat>> for ZfsList {
 	}
 }
 
+impl From<ZfsSnapshot> for ZFSSnapshot {
+	fn from(value: ZfsSnapshot) -> Self {
+		Self {
+			full_name: value.full_name,
+			dataset: value.dataset,
+			snapshot_name: value.snapshot_name,
+			used: value.used,
+			creation: value.creation,
+		}
+	}
+}
+
+impl From<ZFSSnapshot> for ZfsSnapshot {
+	fn from(value: ZFSSnapshot) -> Self {
+		Self {
+			full_name: value.full_name,
+			dataset: value.dataset,
+			snapshot_name: value.snapshot_name,
+			used: value.used,
+			creation: value.creation,
+		}
+	}
+}
+
+impl From<ZfsSnapshotList> for Vec<ZFSSnapshot> {
+	fn from(value: ZfsSnapshotList) -> Self {
+		value.entries.into_iter().map(Into::into).collect()
+	}
+}
+
+impl From<Vec<ZFSSnapshot>> for ZfsSnapshotList {
+	fn from(value: Vec<ZFSSnapshot>) -> Self {
+		Self {
+			entries: value.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+
 impl From<ZfsEntry> for ZFSStat {
 	fn from(value: ZfsEntry) -> Self {
 		Self {
@@ -222,6 +579,7 @@ impl From<ZfsEntry> for ZFSStat {
 			avail: value.avail,
 			refer: value.refer,
 			mountpoint: value.mountpoint,
+			used_percent: value.used_percent,
 		}
 	}
 }
@@ -241,6 +599,90 @@ impl From<ZFSStat> for ZfsEntry {
 			avail: value.avail,
 			refer: value.refer,
 			mountpoint: value.mountpoint,
+			used_percent: value.used_percent,
+		}
+	}
+}
+
+impl From<ZfsDetail> for ZFSDetail {
+	fn from(value: ZfsDetail) -> Self {
+		Self {
+			stat: value.entry.unwrap_or_default().into(),
+			compression: value.compression,
+			recordsize: value.recordsize,
+			reservation: value.reservation,
+			quota: value.quota,
+			creation: value.creation,
+			origin: value.origin,
+			properties: value.properties,
+		}
+	}
+}
+
+impl From<ZFSDetail> for ZfsDetail {
+	fn from(value: ZFSDetail) -> Self {
+		Self {
+			entry: Some(value.stat.into()),
+			compression: value.compression,
+			recordsize: value.recordsize,
+			reservation: value.reservation,
+			quota: value.quota,
+			creation: value.creation,
+			origin: value.origin,
+			properties: value.properties,
+		}
+	}
+}
+
+impl From<ZfsPoolStatus> for PoolStatus {
+	fn from(value: ZfsPoolStatus) -> Self {
+		Self {
+			name: value.name,
+			used: value.used,
+			avail: value.avail,
+		}
+	}
+}
+
+impl From<PoolStatus> for ZfsPoolStatus {
+	fn from(value: PoolStatus) -> Self {
+		Self {
+			name: value.name,
+			used: value.used,
+			avail: value.avail,
+		}
+	}
+}
+
+impl From<ZfsScrubStatus> for ScrubStatus {
+	fn from(value: ZfsScrubStatus) -> Self {
+		Self {
+			state: match value.state() {
+				ZfsScrubState::None => ScrubState::None,
+				ZfsScrubState::Scanning => ScrubState::Scanning,
+				ZfsScrubState::Finished => ScrubState::Finished,
+				ZfsScrubState::Canceled => ScrubState::Canceled,
+			},
+			percent_done: value.percent_done,
+			errors: value.errors,
+			eta: value.eta,
+		}
+	}
+}
+
+impl From<ScrubStatus> for ZfsScrubStatus {
+	fn from(value: ScrubStatus) -> Self {
+		Self {
+			state: match value.state {
+				ScrubState::None => ZfsScrubState::None,
+				ScrubState::Scanning => ZfsScrubState::Scanning,
+				ScrubState::Finished => ZfsScrubState::Finished,
+				ScrubState::Canceled => ZfsScrubState::Canceled,
+			}
+			.into(),
+			percent_done: value.percent_done,
+			errors: value.errors,
+			eta: value.eta,
 		}
 	}
 }
@@ -249,19 +691,34 @@ impl Pool {
 	pub fn new(name: &str) -> Self {
 		Self {
 			name: name.to_string(),
-			controller: Controller,
+			controller: Controller::default(),
+			default_quota: None,
 		}
 	}
 
+	// how long a single zfs command may run before it is killed
+	pub fn with_timeout(mut self, timeout: Duration) -> Self {
+		self.controller.timeout = timeout;
+		self
+	}
+
+	// the quota given to datasets created without one
+	pub fn with_default_quota(mut self, quota: Option<u64>) -> Self {
+		self.default_quota = quota;
+		self
+	}
+
 	pub fn create_dataset(&self, info: &Dataset) -> Result<()> {
-		let mut options: Option<CommandOptions> = None;
+		validate_properties(&info.properties)?;
 
-		if let Some(quota) = &info.quota {
-			let mut tmp = CommandOptions::default();
-			tmp.insert("quota".to_string(), format!("{}", quota));
-			options = Some(tmp);
+		let mut options = CommandOptions(info.properties.clone());
+
+		if let Some(quota) = info.quota.or(self.default_quota).filter(|q| *q != 0) {
+			options.insert("quota".to_string(), format!("{}", quota));
 		}
 
+		let options = (!options.is_empty()).then_some(options);
+
 		if let Err(e) = self
 			.controller
 			.create_dataset(&self.name, &info.name, options)
@@ -270,7 +727,7 @@ impl Pool {
 			return Err(e);
 		}
 
-		self.controller.mount(&self.name)?;
+		self.mount(&info.name)?;
 
 		Ok(())
 	}
@@ -287,7 +744,14 @@ impl Pool {
 	}
 
 	pub fn modify_dataset(&self, info: ModifyDataset) -> Result<()> {
-		let mut map = HashMap::default();
+		validate_properties(&info.modifications.properties)?;
+
+		let mut map = info
+			.modifications
+			.properties
+			.iter()
+			.map(|(key, value)| (key.as_str(), value.clone()))
+			.collect::<HashMap<_, _>>();
 		if let Some(quota) = &info.modifications.quota {
 			map.insert("quota", format!("{}", quota));
 		}
@@ -298,7 +762,7 @@ impl Pool {
 		}
 
 		if !info.modifications.name.is_empty() && info.name != info.modifications.name {
-			self.controller.unmount(&self.name, &info.name)?;
+			self.controller.unmount(&self.name, &info.name, true)?;
 
 			if let Err(e) = self
 				.controller
@@ -308,7 +772,7 @@ impl Pool {
 				return Err(e);
 			}
 
-			self.controller.mount(&self.name)?;
+			self.mount(&info.modifications.name)?;
 		}
 
 		Ok(())
@@ -340,6 +804,15 @@ impl Pool {
 
 	pub fn destroy(&self, name: String) -> Result<()> {
 		if let Err(e) = self.controller.destroy(&self.name, &name) {
+			// snapshots go with -r, but clones made from them live elsewhere and keep them pinned
+			let clones = self
+				.controller
+				.clones(&self.name, &name)
+				.unwrap_or_default();
+			if !clones.is_empty() {
+				return Err(DependentClonesError { name, clones }.into());
+			}
+
 			error!("Destroying dataset: {}", e.to_string());
 			return Err(e);
 		}
@@ -347,93 +820,481 @@ impl Pool {
 		Ok(())
 	}
 
-	pub fn list(&self, filter: Option<String>) -> Result<Vec<ZFSStat>> {
-		let mut ret = Vec::new();
-		let list = match self.controller.list() {
-			Ok(x) => x,
-			Err(e) => {
-				error!("Listing datasets: {}", e.to_string());
-				return Err(e);
-			}
+	// whether a dataset is mounted, or None for a volume, which never is
+	fn mounted(&self, name: &str) -> Result<Option<bool>> {
+		let detail = match self.get(name)? {
+			Some(detail) => detail,
+			None => return Err(anyhow!("No dataset named '{}'", name)),
 		};
 
-		for (name, item) in list.datasets {
-			if let Some(filter) = &filter
-				&& !item.name.starts_with(&format!("{}/{}", self.name, filter))
-			{
-				continue;
+		Ok(match detail.stat.kind {
+			ZFSKind::Volume => None,
+			ZFSKind::Dataset => {
+				Some(detail.properties.get("mounted").map(|x| x.as_str()) == Some("yes"))
 			}
+		})
+	}
 
-			if !name.starts_with(&self.name) {
-				continue;
+	// mounting something already mounted, or unmounting something that isn't, does nothing
+	pub fn mount(&self, name: &str) -> Result<()> {
+		match self.mounted(name)? {
+			None => Err(anyhow!("'{}' is a volume and can't be mounted", name)),
+			Some(true) => Ok(()),
+			Some(false) => {
+				if let Err(e) = self.controller.mount_dataset(&self.name, name) {
+					error!("Mounting dataset: {}", e.to_string());
+					return Err(e);
+				}
+
+				Ok(())
 			}
+		}
+	}
 
-			if name == self.name {
-				// skip root-level datasets since they correspond to pools
-				continue;
+	// not forced, so a dataset that is in use stays mounted and this fails
+	pub fn unmount(&self, name: &str) -> Result<()> {
+		match self.mounted(name)? {
+			None => Err(anyhow!("'{}' is a volume and can't be unmounted", name)),
+			Some(false) => Ok(()),
+			Some(true) => {
+				if let Err(e) = self.controller.unmount(&self.name, name, false) {
+					error!("Unmounting dataset: {}", e.to_string());
+					return Err(e);
+				}
+
+				Ok(())
 			}
+		}
+	}
 
-			let short_name = name
-				.strip_prefix(&format!("{}/", self.name))
-				.unwrap_or_else(|| &name)
-				.to_owned();
+	pub fn clone_snapshot(&self, info: &CloneSnapshot) -> Result<()> {
+		if !info.snapshot.contains('@') {
+			return Err(anyhow!(
+				"'{}' is not a snapshot; expected dataset@snapshot",
+				info.snapshot
+			));
+		}
 
-			ret.push(ZFSStat {
-				// volumes don't have a mountpath, '-' is indicated
-				// FIXME relying on datasets being mounted is a thing we're doing right now, it'll
-				//       probably have to change eventually, but zfs handles all the mounting for
-				//       us at create and destroy time.
-				kind: if item.typ == "VOLUME" {
-					ZFSKind::Volume
-				} else {
-					ZFSKind::Dataset
-				},
-				full_name: name.clone(),
-				name: short_name.clone(), // strip the pool
-				used: item.properties.used.value,
-				avail: item.properties.available.value,
-				// this is just easier to use in places
-				size: if item.typ == "VOLUME" {
-					match self.controller.get(&self.name, &short_name, "volsize") {
-						Ok(x) => x,
-						Err(e) => {
-							error!("Getting volume size for {}: {}", name, e.to_string());
-							return Err(e);
-						}
-					}
-				} else {
-					let quota = self
-						.controller
-						.get(&self.name, &short_name, "quota")
-						.unwrap_or_default();
+		// unlike create_dataset, an existing target is not the same thing as success here
+		if self
+			.controller
+			.exists(format!("{}/{}", self.name, info.name))?
+		{
+			return Err(anyhow!("'{}' already exists", info.name));
+		}
 
-					if quota != 0 {
-						quota
-					} else {
-						self.controller.get(&self.name, &short_name, "available")?
-					}
-				},
-				refer: item.properties.referenced.value,
-				mountpoint: if item.properties.mountpoint.value == "-" {
-					None
-				} else {
-					Some(item.properties.mountpoint.value)
-				},
-			})
+		if let Err(e) = self
+			.controller
+			.clone_snapshot(&self.name, &info.snapshot, &info.name)
+		{
+			error!("Cloning snapshot: {}", e.to_string());
+			return Err(e);
 		}
-		Ok(ret)
+
+		Ok(())
 	}
-}
 
-#[derive(Debug, Clone, Default)]
-struct CommandOptions(HashMap<String, String>);
+	pub fn snapshot(&self, name: &str, snapshot: &str) -> Result<()> {
+		validate_snapshot(name, snapshot)?;
 
-impl CommandOptions {
-	fn to_options(&self) -> Vec<String> {
-		let mut args = Vec::new();
-		for (key, value) in &self.0 {
-			args.push("-o".to_string());
-			args.push(format!("{}={}", key, value));
+		if let Err(e) = self.controller.snapshot(&self.name, name, snapshot) {
+			error!("Creating snapshot: {}", e.to_string());
+			return Err(e);
+		}
+
+		Ok(())
+	}
+
+	// snapshots of the pool's datasets and volumes, oldest first for each. the filter works like
+	// list()'s, against the dataset's name.
+	pub fn list_snapshots(&self, filter: Option<String>) -> Result<Vec<ZFSSnapshot>> {
+		let list = match self.controller.list_snapshots(&self.name) {
+			Ok(x) => x,
+			Err(e) => {
+				error!("Listing snapshots: {}", e.to_string());
+				return Err(e);
+			}
+		};
+
+		let prefix = format!("{}/", self.name);
+		let mut ret = Vec::new();
+
+		for item in list.datasets.into_values() {
+			let Some((dataset, snapshot_name)) = item.name.split_once('@') else {
+				continue;
+			};
+
+			// snapshots of the pool's root dataset are skipped, like the root is in list()
+			let Some(dataset) = dataset.strip_prefix(&prefix) else {
+				continue;
+			};
+
+			if let Some(filter) = &filter
+				&& !dataset.starts_with(filter.as_str())
+			{
+				continue;
+			}
+
+			ret.push(ZFSSnapshot {
+				full_name: item.name.clone(),
+				dataset: dataset.to_string(),
+				snapshot_name: snapshot_name.to_string(),
+				used: item.properties.used.value,
+				creation: item.properties.creation.value,
+			});
+		}
+
+		ret.sort_by(|a, b| {
+			(&a.dataset, a.creation, &a.snapshot_name).cmp(&(
+				&b.dataset,
+				b.creation,
+				&b.snapshot_name,
+			))
+		});
+		Ok(ret)
+	}
+
+	pub fn destroy_snapshot(&self, name: &str, snapshot: &str) -> Result<()> {
+		validate_snapshot(name, snapshot)?;
+
+		if let Err(e) = self.controller.destroy_snapshot(&self.name, name, snapshot) {
+			error!("Destroying snapshot: {}", e.to_string());
+			return Err(e);
+		}
+
+		Ok(())
+	}
+
+	// throws away everything written to `dataset` since `snapshot`. snapshots taken after it are
+	// in the way; unless forced, that is refused, rather than destroying them as well.
+	pub fn rollback(&self, dataset: &str, snapshot: &str, force: bool) -> Result<()> {
+		validate_snapshot(dataset, snapshot)?;
+
+		if !force {
+			let later = self.later_snapshots(dataset, snapshot)?;
+			if !later.is_empty() {
+				return Err(LaterSnapshotsError {
+					dataset: dataset.to_string(),
+					snapshot: snapshot.to_string(),
+					later,
+				}
+				.into());
+			}
+		}
+
+		if let Err(e) = self
+			.controller
+			.rollback(&self.name, dataset, snapshot, force)
+		{
+			error!("Rolling back dataset: {}", e.to_string());
+			return Err(e);
+		}
+
+		Ok(())
+	}
+
+	// the names of snapshots of `name` taken after `snapshot`, by transaction group, since
+	// snapshots taken within the same second have the same creation time.
+	fn later_snapshots(&self, name: &str, snapshot: &str) -> Result<Vec<String>> {
+		let list = self.controller.list_snapshots(&self.name)?;
+		let dataset = format!("{}/{}", self.name, name);
+
+		let snapshots = list
+			.datasets
+			.into_values()
+			.filter_map(|item| {
+				let (x, snapshot) = item.name.split_once('@')?;
+				(x == dataset).then(|| (snapshot.to_string(), item.createtxg))
+			})
+			.collect::<Vec<_>>();
+
+		let Some(txg) = snapshots
+			.iter()
+			.find(|(x, _)| x == snapshot)
+			.map(|(_, txg)| *txg)
+		else {
+			return Err(anyhow!("'{}@{}' does not exist", name, snapshot));
+		};
+
+		let mut later = snapshots
+			.into_iter()
+			.filter(|(_, x)| *x > txg)
+			.collect::<Vec<_>>();
+		later.sort_by_key(|(_, x)| *x);
+		Ok(later.into_iter().map(|(x, _)| x).collect())
+	}
+
+	// streams `dataset@snapshot`, or with `base`, only what changed between `dataset@base` and it
+	pub fn send(&self, dataset: &str, snapshot: &str, base: Option<&str>) -> Result<SendStream> {
+		validate_snapshot(dataset, snapshot)?;
+		if let Some(base) = base {
+			validate_snapshot(dataset, base)?;
+		}
+
+		self.controller.send(&self.name, dataset, snapshot, base)
+	}
+
+	// creates `target` from a stream made by send(). an incremental stream needs `target` to
+	// already have the stream's base snapshot, and nothing written since.
+	pub fn receive(&self, target: &str, mut reader: impl Read) -> Result<()> {
+		if target.is_empty() || target.contains('@') {
+			return Err(anyhow!("'{}' is not a valid dataset name", target));
+		}
+
+		if let Err(e) = self.controller.receive(&self.name, target, &mut reader) {
+			error!("Receiving dataset: {}", e.to_string());
+			return Err(e);
+		}
+
+		Ok(())
+	}
+
+	// swaps a clone with its origin, so the origin's snapshots belong to the clone and the origin
+	// can be destroyed.
+	pub fn promote(&self, name: String) -> Result<()> {
+		if let Err(e) = self.controller.promote(&self.name, &name) {
+			error!("Promoting clone: {}", e.to_string());
+			return Err(e);
+		}
+
+		Ok(())
+	}
+
+	pub fn status(&self) -> Result<PoolStatus> {
+		let list = match self.controller.list() {
+			Ok(x) => x,
+			Err(e) => {
+				error!("Listing datasets: {}", e.to_string());
+				return Err(e);
+			}
+		};
+
+		match list.datasets.get(&self.name) {
+			Some(item) => Ok(PoolStatus {
+				name: self.name.clone(),
+				used: item.properties.used.value,
+				avail: item.properties.available.value,
+			}),
+			None => Err(anyhow!("Pool '{}' does not exist", self.name)),
+		}
+	}
+
+	pub fn scrub(&self) -> Result<()> {
+		if let Err(e) = self.controller.scrub(&self.name) {
+			error!("Starting scrub: {}", e.to_string());
+			return Err(e);
+		}
+
+		Ok(())
+	}
+
+	pub fn scrub_status(&self) -> Result<ScrubStatus> {
+		let status = match self.controller.pool_status(&self.name) {
+			Ok(x) => x,
+			Err(e) => {
+				error!("Getting pool status: {}", e.to_string());
+				return Err(e);
+			}
+		};
+
+		let Some(item) = status.pools.get(&self.name) else {
+			return Err(anyhow!("Pool '{}' does not exist", self.name));
+		};
+
+		let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+		Ok(item
+			.scan_stats
+			.as_ref()
+			.map(|scan| scrub_status(scan, now))
+			.unwrap_or_default())
+	}
+
+	// None if there is no such dataset or volume
+	pub fn get(&self, name: &str) -> Result<Option<ZFSDetail>> {
+		let full_name = format!("{}/{}", self.name, name);
+
+		let out = match self.controller.get_all(&self.name, name) {
+			Ok(x) => x,
+			Err(e) => {
+				// zfs says the same thing for a missing dataset as for any other failure
+				if !self.controller.exists(full_name.clone())? {
+					return Ok(None);
+				}
+
+				error!("Getting properties for {}: {}", full_name, e.to_string());
+				return Err(e);
+			}
+		};
+
+		let item = match out.datasets.get(&full_name) {
+			Some(item) => item,
+			None => return Ok(None),
+		};
+
+		// --json-int makes numeric properties numbers, but leave room for them coming as strings
+		let number = |property: &str| -> Option<u64> {
+			match &item.properties.get(property)?.value {
+				serde_json::Value::Number(x) => x.as_u64(),
+				serde_json::Value::String(x) => x.parse().ok(),
+				_ => None,
+			}
+		};
+
+		let properties: HashMap<String, String> = item
+			.properties
+			.iter()
+			.map(|(key, value)| {
+				(
+					key.clone(),
+					match &value.value {
+						serde_json::Value::String(x) => x.clone(),
+						x => x.to_string(),
+					},
+				)
+			})
+			.collect();
+		let string = |property: &str| -> Option<String> {
+			properties
+				.get(property)
+				.filter(|x| x.as_str() != "-")
+				.cloned()
+		};
+
+		let volume = item.typ == "VOLUME";
+		let quota = number("quota").filter(|x| *x != 0);
+		let used = number("used").unwrap_or_default();
+		let refer = number("referenced").unwrap_or_default();
+
+		Ok(Some(ZFSDetail {
+			stat: ZFSStat {
+				kind: if volume {
+					ZFSKind::Volume
+				} else {
+					ZFSKind::Dataset
+				},
+				name: name.to_string(),
+				full_name: full_name.clone(),
+				// the same as list() reports
+				size: if volume {
+					number("volsize").unwrap_or_default()
+				} else {
+					quota.unwrap_or_else(|| number("available").unwrap_or_default())
+				},
+				used,
+				avail: number("available").unwrap_or_default(),
+				refer,
+				mountpoint: string("mountpoint"),
+				used_percent: if volume {
+					used_percent(refer, number("volsize"))
+				} else {
+					used_percent(used, quota)
+				},
+			},
+			compression: string("compression").unwrap_or_default(),
+			recordsize: number("recordsize"),
+			reservation: number("reservation").unwrap_or_default(),
+			quota,
+			creation: number("creation").unwrap_or_default(),
+			origin: string("origin"),
+			properties,
+		}))
+	}
+
+	pub fn list(&self, filter: Option<String>) -> Result<Vec<ZFSStat>> {
+		let mut ret = Vec::new();
+		let list = match self.controller.list() {
+			Ok(x) => x,
+			Err(e) => {
+				error!("Listing datasets: {}", e.to_string());
+				return Err(e);
+			}
+		};
+
+		for (name, item) in list.datasets {
+			if let Some(filter) = &filter
+				&& !item.name.starts_with(&format!("{}/{}", self.name, filter))
+			{
+				continue;
+			}
+
+			if !name.starts_with(&self.name) {
+				continue;
+			}
+
+			if name == self.name {
+				// skip root-level datasets since they correspond to pools
+				continue;
+			}
+
+			let short_name = name
+				.strip_prefix(&format!("{}/", self.name))
+				.unwrap_or_else(|| &name)
+				.to_owned();
+
+			let volume = item.typ == "VOLUME";
+			// the volsize of a volume, or the quota of a dataset that has one
+			let limit = if volume {
+				match self.controller.get(&self.name, &short_name, "volsize") {
+					Ok(x) => Some(x),
+					Err(e) => {
+						error!("Getting volume size for {}: {}", name, e.to_string());
+						return Err(e);
+					}
+				}
+			} else {
+				Some(
+					self.controller
+						.get(&self.name, &short_name, "quota")
+						.unwrap_or_default(),
+				)
+				.filter(|x| *x != 0)
+			};
+
+			ret.push(ZFSStat {
+				// volumes don't have a mountpath, '-' is indicated
+				// FIXME relying on datasets being mounted is a thing we're doing right now, it'll
+				//       probably have to change eventually, but zfs handles all the mounting for
+				//       us at create and destroy time.
+				kind: if volume {
+					ZFSKind::Volume
+				} else {
+					ZFSKind::Dataset
+				},
+				full_name: name.clone(),
+				name: short_name.clone(), // strip the pool
+				used: item.properties.used.value,
+				avail: item.properties.available.value,
+				// this is just easier to use in places
+				size: match limit {
+					Some(x) => x,
+					None => self.controller.get(&self.name, &short_name, "available")?,
+				},
+				used_percent: if volume {
+					used_percent(item.properties.referenced.value, limit)
+				} else {
+					used_percent(item.properties.used.value, limit)
+				},
+				refer: item.properties.referenced.value,
+				mountpoint: if item.properties.mountpoint.value == "-" {
+					None
+				} else {
+					Some(item.properties.mountpoint.value)
+				},
+			})
+		}
+		Ok(ret)
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+struct CommandOptions(HashMap<String, String>);
+
+impl CommandOptions {
+	fn to_options(&self) -> Vec<String> {
+		let mut args = Vec::new();
+		for (key, value) in &self.0 {
+			args.push("-o".to_string());
+			args.push(format!("{}={}", key, value));
 		}
 		args
 	}
@@ -453,25 +1314,112 @@ impl std::ops::DerefMut for CommandOptions {
 	}
 }
 
-#[derive(Debug, Clone, Default)]
-struct Controller;
+// what a command printed, trimmed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct CommandOutput {
+	stdout: String,
+	stderr: String,
+}
+
+impl std::fmt::Display for CommandOutput {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "stdout: [{}], stderr: [{}]", self.stdout, self.stderr)
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Controller {
+	timeout: Duration,
+}
+
+impl Default for Controller {
+	fn default() -> Self {
+		Self {
+			timeout: Duration::from_secs(DEFAULT_ZFS_TIMEOUT),
+		}
+	}
+}
 
 impl Controller {
-	fn run(command: &str, args: Vec<String>) -> Result<String> {
-		debug!("Running command: [{}, {}]", command, args.join(", "));
+	fn zfs(&self, args: Vec<String>) -> Result<String> {
+		self.run(&command_paths().zfs, args)
+	}
+
+	fn zfs_json<T>(&self, args: Vec<String>) -> Result<T>
+	where
+		T: for<'de> serde::Deserialize<'de>,
+	{
+		self.versioned_json(&command_paths().zfs, args)
+	}
+
+	fn zpool(&self, args: Vec<String>) -> Result<String> {
+		self.run(&command_paths().zpool, args)
+	}
+
+	fn zpool_json<T>(&self, args: Vec<String>) -> Result<T>
+	where
+		T: for<'de> serde::Deserialize<'de>,
+	{
+		self.versioned_json(&command_paths().zpool, args)
+	}
+
+	// zfs and zpool both stamp their JSON with the version of its layout
+	fn versioned_json<T>(&self, program: &Path, args: Vec<String>) -> Result<T>
+	where
+		T: for<'de> serde::Deserialize<'de>,
+	{
+		let command = format!("{}, {}", program.display(), args.join(", "));
+		let value: serde_json::Value = self.json(program, args)?;
+		check_output_version(&command, &value)?;
+
+		T::deserialize(&value)
+			.map_err(|e| anyhow!("Could not parse output of [{}]: {}; {}", command, e, value))
+	}
+
+	fn run(&self, command: &Path, args: Vec<String>) -> Result<String> {
+		Ok(self.run_output(command, args)?.stdout)
+	}
+
+	// parses what the command prints as JSON. when that fails, the error carries everything the
+	// command printed, since that is usually the only clue (a warning on stdout, or a zfs whose
+	// output has changed shape).
+	fn json<T>(&self, command: &Path, args: Vec<String>) -> Result<T>
+	where
+		T: for<'de> serde::Deserialize<'de>,
+	{
+		let out = self.run_output(command, args.clone())?;
+
+		serde_json::from_str(&out.stdout).map_err(|e| {
+			anyhow!(
+				"Could not parse output of [{}, {}]: {}; {}",
+				command.display(),
+				args.join(", "),
+				e,
+				out
+			)
+		})
+	}
+
+	fn run_output(&self, command: &Path, args: Vec<String>) -> Result<CommandOutput> {
+		debug!(
+			"Running command: [{}, {}]",
+			command.display(),
+			args.join(", ")
+		);
 		let time = std::time::Instant::now();
 
-		let out = match std::process::Command::new(command)
+		let mut child = match std::process::Command::new(command)
 			.args(args.clone())
+			.stdin(std::process::Stdio::null())
 			.stdout(std::process::Stdio::piped())
 			.stderr(std::process::Stdio::piped())
-			.output()
+			.spawn()
 		{
 			Ok(x) => x,
 			Err(e) => {
 				error!(
 					"Error running command: [{}, {}]: {}",
-					command,
+					command.display(),
 					args.join(", "),
 					e.to_string()
 				);
@@ -479,30 +1427,67 @@ impl Controller {
 			}
 		};
 
+		// drained while the command runs, so it can't stall on a full pipe
+		let drain = |pipe: Option<Box<dyn Read + Send>>| {
+			pipe.map(|mut pipe| {
+				std::thread::spawn(move || {
+					let mut buf = Vec::new();
+					let _ = pipe.read_to_end(&mut buf);
+					buf
+				})
+			})
+		};
+		let stdout = drain(child.stdout.take().map(|x| Box::new(x) as _));
+		let stderr = drain(child.stderr.take().map(|x| Box::new(x) as _));
+
+		let status = loop {
+			if let Some(status) = child.try_wait()? {
+				break status;
+			}
+
+			if time.elapsed() >= self.timeout {
+				let _ = child.kill();
+				let _ = child.wait();
+
+				let err = TimeoutError {
+					command: format!("{}, {}", command.display(), args.join(", ")),
+					timeout: self.timeout,
+				};
+				error!("{}", err);
+				return Err(err.into());
+			}
+
+			std::thread::sleep(WAIT_INTERVAL);
+		};
+
+		let stdout = stdout.and_then(|x| x.join().ok()).unwrap_or_default();
+		let stderr = stderr.and_then(|x| x.join().ok()).unwrap_or_default();
+
 		trace!(
 			"ZFS command took {}",
 			(std::time::Instant::now() - time).fancy_duration()
 		);
 
-		if out.status.success() {
-			Ok(String::from_utf8(out.stdout.trim_ascii().to_vec())?)
+		let out = CommandOutput {
+			stdout: String::from_utf8_lossy(stdout.trim_ascii()).to_string(),
+			stderr: String::from_utf8_lossy(stderr.trim_ascii()).to_string(),
+		};
+
+		if status.success() {
+			Ok(out)
+		} else if out.stdout.is_empty() {
+			Err(anyhow!("Error: {}", out.stderr))
 		} else {
-			Err(anyhow!(
-				"Error: {}",
-				String::from_utf8(out.stderr.trim_ascii().to_vec())?.as_str()
-			))
+			Err(anyhow!("Error: {} (stdout: {})", out.stderr, out.stdout))
 		}
 	}
 
 	fn list(&self) -> Result<ZFSList> {
-		Ok(serde_json::from_str(&Self::run(
-			"zfs",
-			vec![
-				"list".to_string(),
-				"-j".to_string(),
-				"--json-int".to_string(),
-			],
-		)?)?)
+		self.zfs_json(vec![
+			"list".to_string(),
+			"-j".to_string(),
+			"--json-int".to_string(),
+		])
 	}
 
 	fn exists(&self, name: String) -> Result<bool> {
@@ -517,18 +1502,201 @@ impl Controller {
 	}
 
 	fn destroy(&self, pool: &str, name: &str) -> Result<()> {
-		Self::run(
-			"zfs",
-			vec![
-				"destroy".to_string(),
-				"-r".to_string(),
-				"-f".to_string(),
-				format!("{}/{}", pool, name),
-			],
-		)?;
+		self.zfs(vec![
+			"destroy".to_string(),
+			"-r".to_string(),
+			"-f".to_string(),
+			format!("{}/{}", pool, name),
+		])?;
+		Ok(())
+	}
+
+	fn clone_snapshot(&self, pool: &str, snapshot: &str, name: &str) -> Result<()> {
+		self.zfs(vec![
+			"clone".to_string(),
+			format!("{}/{}", pool, snapshot),
+			format!("{}/{}", pool, name),
+		])?;
+		Ok(())
+	}
+
+	fn snapshot(&self, pool: &str, name: &str, snapshot: &str) -> Result<()> {
+		self.zfs(vec![
+			"snapshot".to_string(),
+			format!("{}/{}@{}", pool, name, snapshot),
+		])?;
+		Ok(())
+	}
+
+	fn list_snapshots(&self, pool: &str) -> Result<ZFSSnapshotList> {
+		self.zfs_json(vec![
+			"list".to_string(),
+			"-j".to_string(),
+			"--json-int".to_string(),
+			"-r".to_string(),
+			"-t".to_string(),
+			"snapshot".to_string(),
+			"-o".to_string(),
+			"used,creation".to_string(),
+			pool.to_string(),
+		])
+	}
+
+	// send and receive take as long as the data does, so they don't have a timeout like run()
+	fn send(
+		&self, pool: &str, name: &str, snapshot: &str, base: Option<&str>,
+	) -> Result<SendStream> {
+		let mut args = vec!["send".to_string()];
+		if let Some(base) = base {
+			args.push("-i".to_string());
+			args.push(format!("@{}", base));
+		}
+		args.push(format!("{}/{}@{}", pool, name, snapshot));
+
+		let command = &command_paths().zfs;
+		debug!(
+			"Running command: [{}, {}]",
+			command.display(),
+			args.join(", ")
+		);
+
+		let mut child = std::process::Command::new(command)
+			.args(&args)
+			.stdin(std::process::Stdio::null())
+			.stdout(std::process::Stdio::piped())
+			.stderr(std::process::Stdio::piped())
+			.spawn()?;
+
+		let stdout = child
+			.stdout
+			.take()
+			.ok_or_else(|| anyhow!("zfs send has no stdout"))?;
+		let stderr = child.stderr.take().map(|mut x| {
+			std::thread::spawn(move || {
+				let mut buf = Vec::new();
+				let _ = x.read_to_end(&mut buf);
+				buf
+			})
+		});
+
+		Ok(SendStream {
+			command: format!("{}, {}", command.display(), args.join(", ")),
+			child,
+			stdout,
+			stderr,
+			finished: false,
+		})
+	}
+
+	fn receive(&self, pool: &str, target: &str, reader: &mut dyn Read) -> Result<()> {
+		let args = vec!["receive".to_string(), format!("{}/{}", pool, target)];
+		let command = &command_paths().zfs;
+		debug!(
+			"Running command: [{}, {}]",
+			command.display(),
+			args.join(", ")
+		);
+
+		let mut child = std::process::Command::new(command)
+			.args(&args)
+			.stdin(std::process::Stdio::piped())
+			.stdout(std::process::Stdio::null())
+			.stderr(std::process::Stdio::piped())
+			.spawn()?;
+
+		let stderr = child.stderr.take().map(|mut x| {
+			std::thread::spawn(move || {
+				let mut buf = Vec::new();
+				let _ = x.read_to_end(&mut buf);
+				buf
+			})
+		});
+
+		// closing stdin afterwards is what tells zfs the stream is over
+		let copied = match child.stdin.take() {
+			Some(mut stdin) => std::io::copy(reader, &mut stdin),
+			None => Err(std::io::Error::other("zfs receive has no stdin")),
+		};
+
+		// zfs stops reading when it gives up on a stream, which makes the copy fail too; its own
+		// error is the more useful one, so it's checked first.
+		let status = child.wait()?;
+		let stderr = stderr.and_then(|x| x.join().ok()).unwrap_or_default();
+
+		if !status.success() {
+			return Err(anyhow!(
+				"Error: {}",
+				String::from_utf8_lossy(stderr.trim_ascii())
+			));
+		}
+
+		copied?;
+		Ok(())
+	}
+
+	// -r destroys the snapshots newer than this one; without it zfs refuses when there are any
+	fn rollback(&self, pool: &str, name: &str, snapshot: &str, force: bool) -> Result<()> {
+		let mut args = vec!["rollback".to_string()];
+		if force {
+			args.push("-r".to_string());
+		}
+		args.push(format!("{}/{}@{}", pool, name, snapshot));
+
+		self.zfs(args)?;
 		Ok(())
 	}
 
+	// not recursive, unlike destroy(): only this one snapshot goes
+	fn destroy_snapshot(&self, pool: &str, name: &str, snapshot: &str) -> Result<()> {
+		self.zfs(vec![
+			"destroy".to_string(),
+			format!("{}/{}@{}", pool, name, snapshot),
+		])?;
+		Ok(())
+	}
+
+	fn promote(&self, pool: &str, name: &str) -> Result<()> {
+		self.zfs(vec!["promote".to_string(), format!("{}/{}", pool, name)])?;
+		Ok(())
+	}
+
+	fn scrub(&self, pool: &str) -> Result<()> {
+		self.zpool(vec!["scrub".to_string(), pool.to_string()])?;
+		Ok(())
+	}
+
+	fn pool_status(&self, pool: &str) -> Result<ZPoolStatus> {
+		self.zpool_json(vec![
+			"status".to_string(),
+			"-j".to_string(),
+			"--json-int".to_string(),
+			pool.to_string(),
+		])
+	}
+
+	// clones of any snapshot of the dataset or its children, without the pool
+	fn clones(&self, pool: &str, name: &str) -> Result<Vec<String>> {
+		let out = self.zfs(vec![
+			"list".to_string(),
+			"-H".to_string(),
+			"-r".to_string(),
+			"-t".to_string(),
+			"snapshot".to_string(),
+			"-o".to_string(),
+			"clones".to_string(),
+			format!("{}/{}", pool, name),
+		])?;
+
+		let prefix = format!("{}/", pool);
+		Ok(out
+			.lines()
+			.flat_map(|x| x.split(','))
+			.map(|x| x.trim())
+			.filter(|x| !x.is_empty() && *x != "-")
+			.map(|x| x.strip_prefix(&prefix).unwrap_or(x).to_string())
+			.collect())
+	}
+
 	fn create_dataset(
 		&self, pool: &str, name: &str, options: Option<CommandOptions>,
 	) -> Result<()> {
@@ -542,7 +1710,7 @@ impl Controller {
 			args.append(&mut options.to_options())
 		}
 
-		Self::run("zfs", args)?;
+		self.zfs(args)?;
 		Ok(())
 	}
 
@@ -557,7 +1725,7 @@ impl Controller {
 		.map(|s| s.to_string())
 		.collect();
 
-		Self::run("zfs", args)?;
+		self.zfs(args)?;
 		Ok(())
 	}
 
@@ -574,7 +1742,7 @@ impl Controller {
 
 		args.push(format!("{}/{}", pool, name));
 
-		Self::run("zfs", args)?;
+		self.zfs(args)?;
 		Ok(())
 	}
 
@@ -591,19 +1759,30 @@ impl Controller {
 			format!("{}/{}", pool, name),
 		];
 
-		let out: ZFSGet<T> = serde_json::from_str(&Self::run("zfs", args)?)?;
+		let out: ZFSGet<T> = self.zfs_json(args)?;
 
-		Ok(
-			out.datasets[&format!("{}/{}", pool, name)].properties[property]
-				.value
-				.clone(),
-		)
+		out.datasets
+			.get(&format!("{}/{}", pool, name))
+			.and_then(|x| x.properties.get(property))
+			.map(|x| x.value.clone())
+			.ok_or_else(|| anyhow!("zfs did not report '{}' for {}/{}", property, pool, name))
+	}
+
+	fn get_all(&self, pool: &str, name: &str) -> Result<ZFSGet<serde_json::Value>> {
+		let args = vec![
+			"get".to_string(),
+			"-j".to_string(),
+			"--json-int".to_string(),
+			"all".to_string(),
+			format!("{}/{}", pool, name),
+		];
+
+		self.zfs_json(args)
 	}
 
-	fn mount(&self, pool: &str) -> Result<()> {
-		Self::run(
-			"zfs",
-			["mount", "-R", pool]
+	fn mount_dataset(&self, pool: &str, name: &str) -> Result<()> {
+		self.zfs(
+			["mount", &format!("{}/{}", pool, name)]
 				.iter()
 				.map(|x| x.to_string())
 				.collect::<Vec<String>>(),
@@ -611,14 +1790,14 @@ impl Controller {
 		Ok(())
 	}
 
-	fn unmount(&self, pool: &str, name: &str) -> Result<()> {
-		Self::run(
-			"zfs",
-			["unmount", "-f", &format!("{}/{}", pool, name)]
-				.iter()
-				.map(|x| x.to_string())
-				.collect::<Vec<String>>(),
-		)?;
+	fn unmount(&self, pool: &str, name: &str, force: bool) -> Result<()> {
+		let mut args = vec!["unmount".to_string()];
+		if force {
+			args.push("-f".to_string());
+		}
+		args.push(format!("{}/{}", pool, name));
+
+		self.zfs(args)?;
 		Ok(())
 	}
 
@@ -644,19 +1823,852 @@ impl Controller {
 			args.append(&mut options.to_options())
 		}
 
-		Self::run("zfs", args)?;
+		self.zfs(args)?;
 		Ok(())
 	}
 }
 
+// the last scan may have been a resilver, which says nothing about when the pool was scrubbed.
+fn scrub_status(scan: &ZPoolScanStats, now: u64) -> ScrubStatus {
+	if scan.function != "SCRUB" {
+		return ScrubStatus::default();
+	}
+
+	let state = match scan.state.as_str() {
+		"SCANNING" => ScrubState::Scanning,
+		"FINISHED" => ScrubState::Finished,
+		"CANCELED" => ScrubState::Canceled,
+		_ => ScrubState::None,
+	};
+
+	let percent_done = match state {
+		ScrubState::Finished => 100.0,
+		_ if scan.to_examine == 0 => 0.0,
+		_ => (scan.issued as f64 / scan.to_examine as f64 * 100.0).min(100.0),
+	};
+
+	// the same estimate `zpool status` prints, from how fast this pass has gone so far
+	let elapsed = now
+		.saturating_sub(scan.pass_start)
+		.saturating_sub(scan.scrub_spent_paused);
+	let eta = (state == ScrubState::Scanning && elapsed > 0 && scan.issued_bytes_per_scan > 0)
+		.then(|| {
+			let rate = scan.issued_bytes_per_scan / elapsed;
+			scan.to_examine.saturating_sub(scan.issued) / rate.max(1)
+		});
+
+	ScrubStatus {
+		state,
+		percent_done,
+		errors: scan.errors,
+		eta,
+	}
+}
+
+// a volume's `used` counts the space reserved for all of it, so it is always near its volsize;
+// what has been written to it is `referenced`, which is what callers pass in for volumes.
+fn used_percent(used: u64, limit: Option<u64>) -> f64 {
+	match limit {
+		Some(limit) if limit != 0 => used as f64 / limit as f64 * 100.0,
+		_ => 0.0,
+	}
+}
+
+// keys outside DATASET_PROPERTIES are refused, as are values zfs would take as more than one.
+fn validate_properties(properties: &HashMap<String, String>) -> Result<()> {
+	for (key, value) in properties {
+		if !DATASET_PROPERTIES.contains(&key.as_str()) {
+			return Err(anyhow!(
+				"'{}' can't be set on a dataset; allowed properties are {}",
+				key,
+				DATASET_PROPERTIES.join(", ")
+			));
+		}
+
+		if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == ',') {
+			return Err(anyhow!("'{}' is not a valid value for '{}'", value, key));
+		}
+	}
+
+	Ok(())
+}
+
+// the names come from users; anything that would make zfs read them as something other than
+// dataset@snapshot is turned away before it gets that far.
+fn validate_snapshot(name: &str, snapshot: &str) -> Result<()> {
+	if name.is_empty() || name.contains('@') {
+		return Err(anyhow!("'{}' is not a valid dataset name", name));
+	}
+
+	if snapshot.is_empty() || snapshot.contains('@') || snapshot.contains('/') {
+		return Err(anyhow!("'{}' is not a valid snapshot name", snapshot));
+	}
+
+	Ok(())
+}
+
+// refuses JSON whose layout is a different major version than the one buckle reads.
+fn check_output_version(command: &str, value: &serde_json::Value) -> Result<()> {
+	let detected = value
+		.get("output_version")
+		.and_then(|v| ZFSOutputInfo::deserialize(v).ok())
+		.map(|info| (info.vers_major, info.vers_minor));
+
+	match detected {
+		Some((major, _)) if major == ZFS_JSON_VERSION.0 => Ok(()),
+		detected => Err(UnsupportedJSONError {
+			command: command.to_string(),
+			detected,
+		}
+		.into()),
+	}
+}
+
+// without --json-int, zfs prints numbers as strings; accept either.
+fn number_or_string<'de, D>(deserializer: D) -> std::result::Result<u64, D::Error>
+where
+	D: serde::Deserializer<'de>,
+{
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum NumberOrString {
+		Number(u64),
+		String(String),
+	}
+
+	match NumberOrString::deserialize(deserializer)? {
+		NumberOrString::Number(x) => Ok(x),
+		NumberOrString::String(x) => x.parse().map_err(serde::de::Error::custom),
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	mod controller {
 		use super::super::Pool;
 		use crate::{
 			testutil::{BUCKLE_TEST_ZPOOL_PREFIX, create_zpool, destroy_zpool},
-			zfs::{Dataset, ModifyDataset, ModifyVolume, Volume, ZFSKind},
+			zfs::{
+				CloneSnapshot, Dataset, DependentClonesError, LaterSnapshotsError, ModifyDataset,
+				ModifyVolume, Volume, ZFSKind,
+			},
 		};
+
+		#[test]
+		fn test_timeout() {
+			let controller = super::super::Controller {
+				timeout: std::time::Duration::from_millis(200),
+			};
+
+			let time = std::time::Instant::now();
+			let err = controller
+				.run(std::path::Path::new("sleep"), vec!["10".to_string()])
+				.unwrap_err();
+			assert!(time.elapsed() < std::time::Duration::from_secs(5));
+			assert!(err.is::<super::super::TimeoutError>());
+
+			assert_eq!(
+				controller
+					.run(std::path::Path::new("echo"), vec!["hello".to_string()])
+					.unwrap(),
+				"hello"
+			);
+			assert!(
+				controller
+					.run(std::path::Path::new("false"), Vec::new())
+					.is_err()
+			);
+		}
+
+		#[test]
+		fn test_command_output() {
+			let controller = super::super::Controller::default();
+			let sh = std::path::Path::new("sh");
+			let script = |x: &str| vec!["-c".to_string(), x.to_string()];
+
+			assert_eq!(
+				controller
+					.run_output(sh, script("echo out; echo err >&2"))
+					.unwrap(),
+				super::super::CommandOutput {
+					stdout: "out".into(),
+					stderr: "err".into(),
+				}
+			);
+
+			let err = controller
+				.run_output(sh, script("echo out; echo err >&2; exit 1"))
+				.unwrap_err()
+				.to_string();
+			assert!(err.contains("err") && err.contains("out"), "{}", err);
+
+			let err = controller
+				.json::<serde_json::Value>(sh, script("echo 'warning: not json'"))
+				.unwrap_err()
+				.to_string();
+			assert!(err.contains("warning: not json"), "{}", err);
+
+			assert_eq!(controller.json::<u64>(sh, script("echo 42")).unwrap(), 42);
+		}
+
+		#[test]
+		fn test_output_version() {
+			use super::super::{UnsupportedJSONError, ZFSList, check_output_version};
+
+			let list = |major: u64, minor: u64| {
+				serde_json::json!({
+					"output_version": {"command": "zfs list", "vers_major": major, "vers_minor": minor},
+					"datasets": {
+						"pool/a": {
+							"name": "pool/a",
+							"type": "FILESYSTEM",
+							"pool": "pool",
+							"createtxg": "12",
+							"properties": {
+								"used": {"value": 1, "source": {"type": "NONE", "data": "-"}},
+								"available": {"value": 2, "source": {"type": "NONE", "data": "-"}},
+								"referenced": {"value": 3, "source": {"type": "NONE", "data": "-"}},
+								"mountpoint": {"value": "/pool/a", "source": {"type": "DEFAULT", "data": "-"}},
+							}
+						}
+					}
+				})
+			};
+
+			for value in [list(0, 1), list(0, 7)] {
+				assert!(check_output_version("zfs list", &value).is_ok());
+				let parsed: ZFSList = serde_json::from_value(value).unwrap();
+				assert_eq!(parsed.datasets["pool/a"].createtxg, 12);
+			}
+
+			let err = check_output_version("zfs list", &list(1, 0)).unwrap_err();
+			assert_eq!(
+				err.downcast_ref::<UnsupportedJSONError>(),
+				Some(&UnsupportedJSONError {
+					command: "zfs list".into(),
+					detected: Some((1, 0)),
+				})
+			);
+			assert!(err.to_string().contains("1.0"), "{}", err);
+
+			let err =
+				check_output_version("zfs list", &serde_json::json!({"datasets": {}})).unwrap_err();
+			assert_eq!(
+				err.downcast_ref::<UnsupportedJSONError>().unwrap().detected,
+				None
+			);
+		}
+
+		#[test]
+		fn test_scrub_status() {
+			use super::super::{ScrubState, ZPoolStatus, scrub_status};
+
+			let status: ZPoolStatus = serde_json::from_value(serde_json::json!({
+				"output_version": {"command": "zpool status", "vers_major": 0, "vers_minor": 1},
+				"pools": {
+					"tank": {
+						"scan_stats": {
+							"function": "SCRUB",
+							"state": "SCANNING",
+							"start_time": 1000,
+							"end_time": 0,
+							"to_examine": 4000,
+							"examined": 1500,
+							"issued": 1000,
+							"errors": 2,
+							"pass_start": 1000,
+							"scrub_pause": "-",
+							"scrub_spent_paused": 0,
+							"issued_bytes_per_scan": 1000
+						}
+					},
+					"never": {}
+				}
+			}))
+			.unwrap();
+
+			assert!(status.pools["never"].scan_stats.is_none());
+
+			let scan = status.pools["tank"].scan_stats.as_ref().unwrap();
+			let running = scrub_status(scan, 1010);
+			assert_eq!(running.state, ScrubState::Scanning);
+			assert_eq!(running.percent_done, 25.0);
+			assert_eq!(running.errors, 2);
+			// 100 bytes a second, 3000 to go
+			assert_eq!(running.eta, Some(30));
+
+			let mut scan = scan.clone();
+			scan.state = "FINISHED".into();
+			let finished = scrub_status(&scan, 1010);
+			assert_eq!(finished.state, ScrubState::Finished);
+			assert_eq!(finished.percent_done, 100.0);
+			assert_eq!(finished.eta, None);
+
+			scan.function = "RESILVER".into();
+			assert_eq!(scrub_status(&scan, 1010).state, ScrubState::None);
+		}
+
+		#[test]
+		fn test_scrub() {
+			use super::super::ScrubState;
+
+			let _ = destroy_zpool("controller-scrub", None);
+			let (_, file) = create_zpool("controller-scrub").unwrap();
+			let pool = Pool::new(&format!("{}-controller-scrub", BUCKLE_TEST_ZPOOL_PREFIX));
+
+			assert_eq!(pool.scrub_status().unwrap().state, ScrubState::None);
+
+			pool.scrub().unwrap();
+
+			// there is next to nothing in the pool, so this doesn't take long
+			let mut status = pool.scrub_status().unwrap();
+			for _ in 0..100 {
+				if status.state == ScrubState::Finished {
+					break;
+				}
+				assert_eq!(status.state, ScrubState::Scanning);
+				std::thread::sleep(std::time::Duration::from_millis(100));
+				status = pool.scrub_status().unwrap();
+			}
+
+			assert_eq!(status.state, ScrubState::Finished);
+			assert_eq!(status.percent_done, 100.0);
+			assert_eq!(status.errors, 0);
+			assert_eq!(status.eta, None);
+
+			destroy_zpool("controller-scrub", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_pool_status() {
+			let _ = destroy_zpool("pool-status", None);
+			let (_, file) = create_zpool("pool-status").unwrap();
+			let pool = Pool::new(&format!("{}-pool-status", BUCKLE_TEST_ZPOOL_PREFIX));
+
+			let before = pool.status().unwrap();
+			assert_eq!(
+				before.name,
+				format!("{}-pool-status", BUCKLE_TEST_ZPOOL_PREFIX)
+			);
+			assert_ne!(before.avail, 0);
+
+			pool.create_volume(&Volume {
+				name: "volume".to_string(),
+				size: 100 * 1024 * 1024,
+			})
+			.unwrap();
+
+			// volumes reserve their whole size up front
+			let after = pool.status().unwrap();
+			assert!(after.avail < before.avail);
+			assert!(after.used > before.used);
+
+			assert!(
+				Pool::new(&format!("{}-nonexistent", BUCKLE_TEST_ZPOOL_PREFIX))
+					.status()
+					.is_err()
+			);
+
+			destroy_zpool("pool-status", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_get() {
+			let _ = destroy_zpool("controller-get", None);
+			let (_, file) = create_zpool("controller-get").unwrap();
+			let pool = Pool::new(&format!("{}-controller-get", BUCKLE_TEST_ZPOOL_PREFIX));
+
+			assert!(pool.get("dataset").unwrap().is_none());
+
+			pool.create_dataset(&Dataset {
+				name: "dataset".to_string(),
+				quota: Some(5 * 1024 * 1024),
+				properties: Default::default(),
+			})
+			.unwrap();
+			pool.create_volume(&Volume {
+				name: "volume".to_string(),
+				size: 100 * 1024 * 1024,
+			})
+			.unwrap();
+
+			let detail = pool.get("dataset").unwrap().unwrap();
+			assert_eq!(detail.stat.kind, ZFSKind::Dataset);
+			assert_eq!(detail.stat.name, "dataset");
+			assert_eq!(
+				detail.stat.full_name,
+				format!("{}-controller-get/dataset", BUCKLE_TEST_ZPOOL_PREFIX),
+			);
+			assert_eq!(detail.stat.size, 5 * 1024 * 1024);
+			assert_eq!(detail.quota, Some(5 * 1024 * 1024));
+			assert_eq!(
+				detail.stat.mountpoint,
+				Some(format!(
+					"/{}-controller-get/dataset",
+					BUCKLE_TEST_ZPOOL_PREFIX
+				))
+			);
+			assert_ne!(detail.recordsize, None);
+			assert_ne!(detail.creation, 0);
+			assert!(!detail.compression.is_empty());
+			assert_eq!(detail.origin, None);
+			assert_eq!(
+				detail.properties.get("quota"),
+				Some(&(5 * 1024 * 1024).to_string())
+			);
+
+			let detail = pool.get("volume").unwrap().unwrap();
+			assert_eq!(detail.stat.kind, ZFSKind::Volume);
+			assert_eq!(detail.stat.size, 100 * 1024 * 1024);
+			assert_eq!(detail.stat.mountpoint, None);
+			assert_eq!(detail.recordsize, None);
+			assert_eq!(detail.quota, None);
+			assert!(detail.properties.contains_key("volblocksize"));
+
+			destroy_zpool("controller-get", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_properties() {
+			let _ = destroy_zpool("controller-properties", None);
+			let (_, file) = create_zpool("controller-properties").unwrap();
+			let pool = Pool::new(&format!(
+				"{}-controller-properties",
+				BUCKLE_TEST_ZPOOL_PREFIX
+			));
+			let properties = |x: &[(&str, &str)]| {
+				x.iter()
+					.map(|(k, v)| (k.to_string(), v.to_string()))
+					.collect::<std::collections::HashMap<_, _>>()
+			};
+
+			for bad in [
+				properties(&[("mountpoint", "/etc")]),
+				properties(&[("quota", "1")]),
+				properties(&[("compression", "lz4 -o mountpoint=/etc")]),
+				properties(&[("compression", "")]),
+			] {
+				assert!(
+					pool.create_dataset(&Dataset {
+						name: "dataset".to_string(),
+						quota: None,
+						properties: bad,
+					})
+					.is_err()
+				);
+			}
+			assert!(pool.get("dataset").unwrap().is_none());
+
+			pool.create_dataset(&Dataset {
+				name: "dataset".to_string(),
+				quota: None,
+				properties: properties(&[
+					("compression", "lz4"),
+					("atime", "off"),
+					("recordsize", "65536"),
+				]),
+			})
+			.unwrap();
+
+			let detail = pool.get("dataset").unwrap().unwrap();
+			assert_eq!(detail.compression, "lz4");
+			assert_eq!(detail.recordsize, Some(65536));
+			assert_eq!(detail.properties["atime"], "off");
+
+			pool.modify_dataset(ModifyDataset {
+				name: "dataset".to_string(),
+				modifications: Dataset {
+					name: "dataset".to_string(),
+					quota: None,
+					properties: properties(&[("compression", "off")]),
+				},
+			})
+			.unwrap();
+			assert!(
+				pool.modify_dataset(ModifyDataset {
+					name: "dataset".to_string(),
+					modifications: Dataset {
+						name: "dataset".to_string(),
+						quota: None,
+						properties: properties(&[("canmount", "off")]),
+					},
+				})
+				.is_err()
+			);
+
+			let detail = pool.get("dataset").unwrap().unwrap();
+			assert_eq!(detail.compression, "off");
+			assert_eq!(detail.properties["atime"], "off");
+
+			destroy_zpool("controller-properties", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_default_quota() {
+			let _ = destroy_zpool("controller-quota", None);
+			let (_, file) = create_zpool("controller-quota").unwrap();
+			let pool = Pool::new(&format!("{}-controller-quota", BUCKLE_TEST_ZPOOL_PREFIX))
+				.with_default_quota(Some(5 * 1024 * 1024));
+
+			for (name, quota) in [
+				("default", None),
+				("explicit", Some(10 * 1024 * 1024)),
+				("unlimited", Some(0)),
+			] {
+				pool.create_dataset(&Dataset {
+					name: name.to_string(),
+					quota,
+					properties: Default::default(),
+				})
+				.unwrap();
+			}
+
+			let quota = |name| pool.get(name).unwrap().unwrap().quota;
+			assert_eq!(quota("default"), Some(5 * 1024 * 1024));
+			assert_eq!(quota("explicit"), Some(10 * 1024 * 1024));
+			assert_eq!(quota("unlimited"), None);
+
+			destroy_zpool("controller-quota", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_used_percent() {
+			let _ = destroy_zpool("controller-percent", None);
+			let (_, file) = create_zpool("controller-percent").unwrap();
+			let poolname = format!("{}-controller-percent", BUCKLE_TEST_ZPOOL_PREFIX);
+			let pool = Pool::new(&poolname);
+
+			for (name, quota) in [("quota", Some(10 * 1024 * 1024)), ("unlimited", None)] {
+				pool.create_dataset(&Dataset {
+					name: name.to_string(),
+					quota,
+					properties: Default::default(),
+				})
+				.unwrap();
+			}
+
+			// compression would make zeroes take up nothing
+			let mut state = 0x2545f4914f6cdd1du64;
+			let data = (0..4 * 1024 * 1024)
+				.map(|_| {
+					state ^= state << 13;
+					state ^= state >> 7;
+					state ^= state << 17;
+					state as u8
+				})
+				.collect::<Vec<u8>>();
+			std::fs::write(format!("/{}/quota/data", poolname), &data).unwrap();
+			// space accounting only catches up once the transaction group is written out
+			assert!(
+				std::process::Command::new("zpool")
+					.args(["sync", &poolname])
+					.status()
+					.unwrap()
+					.success()
+			);
+
+			let list = pool.list(None).unwrap();
+			let percent = |name| list.iter().find(|x| x.name == name).unwrap().used_percent;
+			assert!(
+				(30.0..60.0).contains(&percent("quota")),
+				"{}",
+				percent("quota")
+			);
+			assert_eq!(percent("unlimited"), 0.0);
+
+			destroy_zpool("controller-percent", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_clone() {
+			let _ = destroy_zpool("controller-clone", None);
+			let (_, file) = create_zpool("controller-clone").unwrap();
+			let poolname = format!("{}-controller-clone", BUCKLE_TEST_ZPOOL_PREFIX);
+			let pool = Pool::new(&poolname);
+
+			pool.create_dataset(&Dataset {
+				name: "origin".to_string(),
+				quota: None,
+				properties: Default::default(),
+			})
+			.unwrap();
+			std::fs::write(format!("/{}/origin/data", poolname), "hello").unwrap();
+
+			pool.snapshot("origin", "copy").unwrap();
+
+			assert!(
+				pool.clone_snapshot(&CloneSnapshot {
+					snapshot: "origin".to_string(),
+					name: "copy".to_string(),
+				})
+				.is_err()
+			);
+
+			let info = CloneSnapshot {
+				snapshot: "origin@copy".to_string(),
+				name: "copy".to_string(),
+			};
+			pool.clone_snapshot(&info).unwrap();
+			// the target is there now
+			assert!(pool.clone_snapshot(&info).is_err());
+
+			assert_eq!(
+				std::fs::read_to_string(format!("/{}/copy/data", poolname)).unwrap(),
+				"hello"
+			);
+			assert_eq!(
+				pool.get("copy").unwrap().unwrap().origin,
+				Some(format!("{}/origin@copy", poolname))
+			);
+			assert_eq!(pool.get("origin").unwrap().unwrap().origin, None);
+
+			let err = pool.destroy("origin".to_string()).unwrap_err();
+			assert_eq!(
+				err.downcast_ref::<DependentClonesError>(),
+				Some(&DependentClonesError {
+					name: "origin".to_string(),
+					clones: vec!["copy".to_string()],
+				})
+			);
+
+			pool.promote("copy".to_string()).unwrap();
+			assert_eq!(pool.get("copy").unwrap().unwrap().origin, None);
+			pool.destroy("origin".to_string()).unwrap();
+			assert_eq!(
+				std::fs::read_to_string(format!("/{}/copy/data", poolname)).unwrap(),
+				"hello"
+			);
+
+			destroy_zpool("controller-clone", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_snapshot() {
+			let _ = destroy_zpool("controller-snapshot", None);
+			let (_, file) = create_zpool("controller-snapshot").unwrap();
+			let poolname = format!("{}-controller-snapshot", BUCKLE_TEST_ZPOOL_PREFIX);
+			let pool = Pool::new(&poolname);
+
+			for name in ["dataset", "other"] {
+				pool.create_dataset(&Dataset {
+					name: name.to_string(),
+					quota: None,
+					properties: Default::default(),
+				})
+				.unwrap();
+			}
+
+			assert!(pool.list_snapshots(None).unwrap().is_empty());
+
+			for (name, snapshot) in [
+				("dataset@x", "snap"),
+				("dataset", "a@b"),
+				("dataset", "a/b"),
+				("dataset", ""),
+				("", "snap"),
+			] {
+				assert!(
+					pool.snapshot(name, snapshot).is_err(),
+					"{}@{}",
+					name,
+					snapshot
+				);
+				assert!(pool.destroy_snapshot(name, snapshot).is_err());
+			}
+
+			// nothing to snapshot
+			assert!(pool.snapshot("missing", "snap").is_err());
+
+			pool.snapshot("dataset", "first").unwrap();
+			pool.snapshot("dataset", "second").unwrap();
+			pool.snapshot("other", "first").unwrap();
+			// the same name twice is refused by zfs
+			assert!(pool.snapshot("dataset", "first").is_err());
+
+			let list = pool.list_snapshots(None).unwrap();
+			assert_eq!(list.len(), 3);
+			assert_eq!(list[0].full_name, format!("{}/dataset@first", poolname));
+			assert_eq!(list[0].dataset, "dataset");
+			assert_eq!(list[0].snapshot_name, "first");
+			assert_ne!(list[0].creation, 0);
+			assert_eq!(list[1].snapshot_name, "second");
+			assert_eq!(list[2].dataset, "other");
+
+			let list = pool.list_snapshots(Some("other".to_string())).unwrap();
+			assert_eq!(list.len(), 1);
+			assert_eq!(list[0].full_name, format!("{}/other@first", poolname));
+
+			pool.destroy_snapshot("dataset", "first").unwrap();
+			assert!(pool.destroy_snapshot("dataset", "first").is_err());
+			let list = pool.list_snapshots(Some("dataset".to_string())).unwrap();
+			assert_eq!(list.len(), 1);
+			assert_eq!(list[0].snapshot_name, "second");
+
+			// the dataset itself is untouched
+			assert!(pool.get("dataset").unwrap().is_some());
+
+			destroy_zpool("controller-snapshot", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_rollback() {
+			let _ = destroy_zpool("controller-rollback", None);
+			let (_, file) = create_zpool("controller-rollback").unwrap();
+			let poolname = format!("{}-controller-rollback", BUCKLE_TEST_ZPOOL_PREFIX);
+			let pool = Pool::new(&poolname);
+			let path = format!("/{}/dataset/data", poolname);
+			let rollback = |snapshot: &str, force| pool.rollback("dataset", snapshot, force);
+
+			pool.create_dataset(&Dataset {
+				name: "dataset".to_string(),
+				quota: None,
+				properties: Default::default(),
+			})
+			.unwrap();
+
+			std::fs::write(&path, "one").unwrap();
+			pool.snapshot("dataset", "one").unwrap();
+			std::fs::write(&path, "two").unwrap();
+			pool.snapshot("dataset", "two").unwrap();
+			std::fs::write(&path, "three").unwrap();
+
+			assert!(rollback("missing", false).is_err());
+			assert!(rollback("a@b", true).is_err());
+
+			// the newest snapshot needs no force
+			rollback("two", false).unwrap();
+			assert_eq!(std::fs::read_to_string(&path).unwrap(), "two");
+
+			let err = rollback("one", false).unwrap_err();
+			assert_eq!(
+				err.downcast_ref::<LaterSnapshotsError>(),
+				Some(&LaterSnapshotsError {
+					dataset: "dataset".to_string(),
+					snapshot: "one".to_string(),
+					later: vec!["two".to_string()],
+				})
+			);
+			assert_eq!(std::fs::read_to_string(&path).unwrap(), "two");
+
+			rollback("one", true).unwrap();
+			assert_eq!(std::fs::read_to_string(&path).unwrap(), "one");
+			let list = pool.list_snapshots(None).unwrap();
+			assert_eq!(list.len(), 1);
+			assert_eq!(list[0].snapshot_name, "one");
+
+			destroy_zpool("controller-rollback", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_send_receive() {
+			use std::io::Read;
+
+			let _ = destroy_zpool("controller-send", None);
+			let (_, file) = create_zpool("controller-send").unwrap();
+			let poolname = format!("{}-controller-send", BUCKLE_TEST_ZPOOL_PREFIX);
+			let pool = Pool::new(&poolname);
+			let send = |snapshot: &str, base: Option<&str>| {
+				let mut buf = Vec::new();
+				pool.send("origin", snapshot, base)?.read_to_end(&mut buf)?;
+				anyhow::Ok(buf)
+			};
+			let snapshots = |dataset: &str| {
+				pool.list_snapshots(Some(dataset.to_string()))
+					.unwrap()
+					.into_iter()
+					.map(|x| x.snapshot_name)
+					.collect::<Vec<_>>()
+			};
+
+			pool.create_dataset(&Dataset {
+				name: "origin".to_string(),
+				quota: None,
+				properties: Default::default(),
+			})
+			.unwrap();
+			std::fs::write(format!("/{}/origin/data", poolname), "one").unwrap();
+			pool.snapshot("origin", "one").unwrap();
+
+			// zfs only finds out the snapshot is missing once it is running
+			assert!(send("missing", None).is_err());
+			assert!(pool.send("origin", "a@b", None).is_err());
+			assert!(pool.receive("copy@one", std::io::empty()).is_err());
+
+			let full = send("one", None).unwrap();
+			assert!(!full.is_empty());
+			// a truncated stream is refused and leaves nothing behind
+			assert!(pool.receive("copy", &full[..full.len() / 2]).is_err());
+			assert!(pool.get("copy").unwrap().is_none());
+
+			pool.receive("copy", full.as_slice()).unwrap();
+			assert_eq!(snapshots("copy"), vec!["one".to_string()]);
+
+			std::fs::write(format!("/{}/origin/data", poolname), "two").unwrap();
+			pool.snapshot("origin", "two").unwrap();
+			let incremental = send("two", Some("one")).unwrap();
+			pool.receive("copy", incremental.as_slice()).unwrap();
+
+			assert_eq!(snapshots("copy"), snapshots("origin"));
+			let list = pool.list(None).unwrap();
+			let origin = list.iter().find(|x| x.name == "origin").unwrap();
+			let copy = list.iter().find(|x| x.name == "copy").unwrap();
+			assert_eq!(copy.kind, origin.kind);
+			assert_eq!(copy.mountpoint, Some(format!("/{}/copy", poolname)));
+			assert_eq!(
+				std::fs::read_to_string(format!("/{}/copy/data", poolname)).unwrap(),
+				"two"
+			);
+
+			destroy_zpool("controller-send", Some(&file)).unwrap();
+		}
+
+		#[test]
+		fn test_mount() {
+			let _ = destroy_zpool("controller-mount", None);
+			let (_, file) = create_zpool("controller-mount").unwrap();
+			let pool = Pool::new(&format!("{}-controller-mount", BUCKLE_TEST_ZPOOL_PREFIX));
+			let mounted =
+				|name: &str| pool.get(name).unwrap().unwrap().properties["mounted"].clone();
+
+			assert!(pool.mount("dataset").is_err());
+
+			pool.create_dataset(&Dataset {
+				name: "dataset".to_string(),
+				quota: None,
+				properties: Default::default(),
+			})
+			.unwrap();
+			assert_eq!(mounted("dataset"), "yes");
+
+			pool.unmount("dataset").unwrap();
+			assert_eq!(mounted("dataset"), "no");
+			pool.unmount("dataset").unwrap();
+
+			// created again, it comes back mounted
+			pool.create_dataset(&Dataset {
+				name: "dataset".to_string(),
+				quota: None,
+				properties: Default::default(),
+			})
+			.unwrap();
+			assert_eq!(mounted("dataset"), "yes");
+
+			pool.unmount("dataset").unwrap();
+			pool.mount("dataset").unwrap();
+			assert_eq!(mounted("dataset"), "yes");
+			pool.mount("dataset").unwrap();
+
+			pool.create_volume(&Volume {
+				name: "volume".to_string(),
+				size: 100 * 1024 * 1024,
+			})
+			.unwrap();
+			assert!(pool.mount("volume").is_err());
+			assert!(pool.unmount("volume").is_err());
+
+			destroy_zpool("controller-mount", Some(&file)).unwrap();
+		}
+
 		#[test]
 		fn test_controller_zfs_lifecycle() {
 			let _ = destroy_zpool("controller-list", None);
@@ -667,6 +2679,7 @@ mod tests {
 			pool.create_dataset(&crate::zfs::Dataset {
 				name: "dataset".to_string(),
 				quota: None,
+				properties: Default::default(),
 			})
 			.unwrap();
 			// repeated creates should not fail
@@ -674,6 +2687,7 @@ mod tests {
 				pool.create_dataset(&crate::zfs::Dataset {
 					name: "dataset".to_string(),
 					quota: None,
+					properties: Default::default(),
 				})
 				.unwrap();
 			}
@@ -780,6 +2794,7 @@ mod tests {
 				modifications: Dataset {
 					name: "dataset2".into(),
 					quota: Some(5 * 1024 * 1024),
+					properties: Default::default(),
 				},
 			})
 			.unwrap();
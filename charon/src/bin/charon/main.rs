@@ -1,7 +1,7 @@
 use anyhow::Result;
 use charon::{
-	Client, Global, GlobalRegistry, PackageTitle, Registry, SourcePackage, SystemdUnit,
-	generate_command, stop_package,
+	Client, CommandPaths, Global, GlobalRegistry, InstallStatus, LifecycleAction, PackageTitle,
+	Registry, SourcePackage, SystemdUnit, run_package, set_command_paths, stop_package,
 };
 use clap::{Parser, Subcommand};
 use fancy_duration::AsFancyDuration;
@@ -18,6 +18,15 @@ struct MainArgs {
 	#[arg(short = 'b', long = "buckle", help = "Path to buckle socket")]
 	buckle_socket: Option<PathBuf>,
 
+	#[arg(long = "podman", help = "Path to podman, if not found on PATH")]
+	podman: Option<PathBuf>,
+
+	#[arg(
+		long = "qemu",
+		help = "Path to qemu-system-x86_64, if not found on PATH"
+	)]
+	qemu: Option<PathBuf>,
+
 	#[command(subcommand)]
 	command: Commands,
 }
@@ -26,6 +35,7 @@ struct MainArgs {
 enum Commands {
 	NewPackage(NewPackageArgs),
 	RemovePackage(RemovePackageArgs),
+	ImportPackage(ImportPackageArgs),
 	Launch(LaunchArgs),
 	Stop(StopArgs),
 	CreateUnit(CreateUnitArgs),
@@ -45,6 +55,14 @@ struct RemoteArgs {
 enum RemoteCommands {
 	Ping,
 	WriteUnit(CreateUnitArgs),
+	Status(RemoteStatusArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(about="Show whether a package is installed, and the state of its VM if it has one", long_about=None)]
+struct RemoteStatusArgs {
+	package_name: String,
+	package_version: String,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -89,11 +107,28 @@ struct NewPackageArgs {
 #[command(about="Remove a package completely from the registry", long_about=None)]
 struct RemovePackageArgs {
 	name: String,
+	#[arg(
+		long,
+		help = "Remove the package even if it is installed, which will break its service"
+	)]
+	force: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(about="Add a package to the registry from a tar archive", long_about=None)]
+struct ImportPackageArgs {
+	archive: PathBuf,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
 	let args = MainArgs::parse();
+	let defaults = CommandPaths::default();
+	set_command_paths(CommandPaths {
+		podman: args.podman.clone().unwrap_or(defaults.podman.clone()),
+		qemu: args.qemu.clone().unwrap_or(defaults.qemu.clone()),
+		..defaults
+	})?;
 	let cwd = std::env::current_dir()?;
 	match args.command {
 		Commands::NewPackage(new_args) => {
@@ -117,15 +152,30 @@ async fn main() -> Result<()> {
 		Commands::RemovePackage(rp_args) => {
 			let r = Registry::new(args.registry_path.clone().unwrap_or(cwd.clone()));
 			let gr = GlobalRegistry::new(args.registry_path.unwrap_or(cwd));
-			r.remove(&rp_args.name)?;
+			r.remove(&rp_args.name, rp_args.force)?;
 			gr.remove(&rp_args.name)?;
 		}
+		Commands::ImportPackage(ip_args) => {
+			let r = Registry::new(args.registry_path.unwrap_or(cwd));
+			let title = r.import_archive(&ip_args.archive)?;
+			println!("Imported {}", title);
+		}
 		Commands::Launch(l_args) => {
 			let r = Registry::new(args.registry_path.clone().unwrap_or(cwd.clone()));
-			let pkg = r
-				.load(&l_args.package_name, &l_args.package_version)?
-				.compile()
-				.await?;
+			let events = r.event_log();
+			let title = PackageTitle {
+				name: l_args.package_name.clone(),
+				version: l_args.package_version.clone(),
+			};
+
+			let pkg = async {
+				r.load(&l_args.package_name, &l_args.package_version)?
+					.compile()
+					.await
+			}
+			.await;
+			events.observe(&title, LifecycleAction::Start, &pkg);
+			let pkg = pkg?;
 
 			let p = pkg.clone();
 			let buckle_socket = args.buckle_socket.expect("Buckle socket is required");
@@ -157,21 +207,37 @@ async fn main() -> Result<()> {
 					tokio::time::sleep(std::time::Duration::from_secs(60)).await;
 				}
 			});
-			let command = generate_command(pkg, l_args.volume_root)?;
+			let status = run_package(pkg, l_args.volume_root).await;
+			let exit = match &status {
+				Ok(status) if status.success() => Ok(()),
+				Ok(status) => Err(format!("exited with {}", status)),
+				Err(e) => Err(e.to_string()),
+			};
+			events.observe(&title, LifecycleAction::Exit, &exit);
 
-			let status = std::process::Command::new(&command[0])
-				.args(command.iter().skip(1))
-				.status()?;
-			std::process::exit(status.code().unwrap_or(1));
+			std::process::exit(status?.code().unwrap_or(1));
 		}
 		Commands::Stop(s_args) => {
 			let r = Registry::new(args.registry_path.clone().unwrap_or(cwd.clone()));
-			stop_package(
-				r.load(&s_args.package_name, &s_args.package_version)?
-					.compile()
-					.await?,
-				s_args.volume_root,
-			)?;
+			let result = async {
+				stop_package(
+					r.load(&s_args.package_name, &s_args.package_version)?
+						.compile()
+						.await?,
+					s_args.volume_root,
+				)
+			}
+			.await;
+
+			r.event_log().observe(
+				&PackageTitle {
+					name: s_args.package_name.clone(),
+					version: s_args.package_version.clone(),
+				},
+				LifecycleAction::Stop,
+				&result,
+			);
+			result?;
 		}
 		Commands::CreateUnit(cu_args) => {
 			let r = Registry::new(args.registry_path.clone().unwrap_or(cwd.clone()));
@@ -222,6 +288,31 @@ async fn main() -> Result<()> {
 						wu_args.package_name, wu_args.package_version,
 					);
 				}
+				RemoteCommands::Status(s_args) => {
+					let mut control = client.control().await?;
+					match control
+						.installed(&s_args.package_name, &s_args.package_version)
+						.await?
+					{
+						Some(InstallStatus::Installed(status)) => println!(
+							"Installed: {:?}, {:?}, {:?}",
+							status.load_state, status.runtime_state, status.last_run_state
+						),
+						_ => println!("Not installed"),
+					}
+
+					match control
+						.vm_status(&s_args.package_name, &s_args.package_version)
+						.await
+					{
+						Ok(status) => println!("VM: {}", status),
+						// not a VM; there's nothing more to show
+						Err(e)
+							if e.downcast_ref::<tonic::Status>()
+								.is_some_and(|x| x.code() == tonic::Code::FailedPrecondition) => {}
+						Err(e) => return Err(e),
+					}
+				}
 			}
 		}
 	}
@@ -1,3 +1,4 @@
+use buckle::server::shutdown_signal;
 use charon::{Config, Server};
 
 #[tokio::main]
@@ -9,7 +10,17 @@ pub async fn main() -> Result<(), anyhow::Error> {
 			.into(),
 	)?;
 
-	if let Err(e) = Server::new(config).start()?.await {
+	if let Err(e) = config.check_buckle().await {
+		tracing::warn!(
+			"{}; operations that need it will fail until it is started",
+			e
+		);
+	}
+
+	if let Err(e) = Server::new(config)
+		.start_with_shutdown(shutdown_signal())?
+		.await
+	{
 		tracing::error!("Error while running service: {}", e.to_string());
 		return Err(e.into());
 	}
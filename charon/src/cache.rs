@@ -0,0 +1,209 @@
+use crate::{
+	CompiledPackage, GLOBAL_SUBPATH, PACKAGE_SUBPATH, RESPONSES_SUBPATH, Registry, SECRETS_SUBPATH,
+};
+use anyhow::Result;
+use std::{
+	collections::HashMap,
+	path::Path,
+	sync::{Arc, Mutex},
+	time::SystemTime,
+};
+
+// compile() only reads the package definition, its globals, its responses and its secrets. If none
+// of those files have changed since the last compile, the output hasn't either.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fingerprint(Vec<Option<(SystemTime, u64)>>);
+
+impl Fingerprint {
+	fn new(root: &Path, name: &str, version: &str) -> Self {
+		let stat = |path: &Path| {
+			std::fs::metadata(path)
+				.and_then(|x| Ok((x.modified()?, x.len())))
+				.ok()
+		};
+
+		Self(vec![
+			stat(
+				&root
+					.join(PACKAGE_SUBPATH)
+					.join(name)
+					.join(format!("{}.json", version)),
+			),
+			stat(&root.join(GLOBAL_SUBPATH).join(format!("{}.json", name))),
+			stat(&root.join(RESPONSES_SUBPATH).join(format!("{}.json", name))),
+			stat(&root.join(SECRETS_SUBPATH).join(format!("{}.sealed", name))),
+		])
+	}
+}
+
+// keyed on package name and version
+type Entries = HashMap<(String, String), (Fingerprint, CompiledPackage)>;
+
+#[derive(Debug, Clone, Default)]
+pub struct CompileCache {
+	entries: Arc<Mutex<Entries>>,
+}
+
+impl CompileCache {
+	pub async fn compile(
+		&self, registry: &Registry, name: &str, version: &str,
+	) -> Result<CompiledPackage> {
+		let key = (name.to_string(), version.to_string());
+		let fingerprint = Fingerprint::new(&registry.path(), name, version);
+
+		let cached = self
+			.entries
+			.lock()
+			.unwrap()
+			.get(&key)
+			.filter(|(x, _)| *x == fingerprint)
+			.map(|(_, pkg)| pkg.clone());
+
+		if let Some(pkg) = cached {
+			tracing::trace!("Using cached compile for package: {}", name);
+			return Ok(pkg);
+		}
+
+		let pkg = registry.load(name, version)?.compile().await?;
+		self.entries
+			.lock()
+			.unwrap()
+			.insert(key, (fingerprint, pkg.clone()));
+
+		Ok(pkg)
+	}
+
+	// for changes that file metadata may not catch, f.e. two writes inside the same mtime tick
+	pub fn invalidate(&self, name: &str) {
+		self.entries.lock().unwrap().retain(|(x, _), _| x != name);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		CompileCache, CompiledSource, Input, InputType, PackageTitle, Prompt, PromptCollection,
+		PromptResponse, PromptResponses, Registry, Source, SourcePackage,
+	};
+
+	#[tokio::test]
+	async fn invalidation() {
+		let dir = tempfile::tempdir().unwrap();
+		let source = Registry::new("testdata/registry".into());
+		let registry = Registry::new(dir.path().to_path_buf());
+		registry
+			.write(&source.load("with-prompts", "0.0.1").unwrap())
+			.unwrap();
+
+		let set = |size: u64| {
+			registry
+				.response_registry()
+				.set(
+					"with-prompts",
+					&PromptResponses(vec![
+						PromptResponse {
+							template: "private_path".into(),
+							input: Input::String("/tmp/volroot".into()),
+						},
+						PromptResponse {
+							template: "private_size".into(),
+							input: Input::Integer(size),
+						},
+						PromptResponse {
+							template: "private_recreate".into(),
+							input: Input::Boolean(false),
+						},
+					]),
+				)
+				.unwrap()
+		};
+
+		let cache = CompileCache::default();
+
+		set(1234);
+		let first = cache
+			.compile(&registry, "with-prompts", "0.0.1")
+			.await
+			.unwrap();
+		assert_eq!(first.storage.volumes[0].size, 1234);
+
+		assert_eq!(
+			cache
+				.compile(&registry, "with-prompts", "0.0.1")
+				.await
+				.unwrap(),
+			first
+		);
+
+		// a different length, so the fingerprint moves even on coarse mtime filesystems
+		set(56789);
+		let second = cache
+			.compile(&registry, "with-prompts", "0.0.1")
+			.await
+			.unwrap();
+		assert_eq!(second.storage.volumes[0].size, 56789);
+	}
+
+	#[tokio::test]
+	async fn secrets() {
+		let dir = tempfile::tempdir().unwrap();
+		let registry =
+			Registry::new(dir.path().to_path_buf()).with_secret_key(dir.path().join("secret.key"));
+		registry
+			.write(&SourcePackage {
+				title: PackageTitle {
+					name: "secretive".into(),
+					version: "0.0.1".into(),
+				},
+				source: Source::Container("registry.example/app:?token?".parse().unwrap()),
+				prompts: Some(PromptCollection(vec![Prompt {
+					template: "token".into(),
+					question: "What is your access token?".into(),
+					input_type: InputType::Secret,
+					options: Default::default(),
+					required: false,
+					default: None,
+					pattern: None,
+					min: None,
+					max: None,
+				}])),
+				..Default::default()
+			})
+			.unwrap();
+
+		let set = |token: &str| {
+			registry
+				.secret_registry()
+				.merge(
+					"secretive",
+					&PromptResponses(vec![PromptResponse {
+						template: "token".into(),
+						input: Input::Secret(token.into()),
+					}]),
+				)
+				.unwrap()
+		};
+
+		let cache = CompileCache::default();
+
+		set("hunter2");
+		assert_eq!(
+			cache
+				.compile(&registry, "secretive", "0.0.1")
+				.await
+				.unwrap()
+				.source,
+			CompiledSource::Container("registry.example/app:hunter2".into())
+		);
+
+		set("correct-horse");
+		assert_eq!(
+			cache
+				.compile(&registry, "secretive", "0.0.1")
+				.await
+				.unwrap()
+				.source,
+			CompiledSource::Container("registry.example/app:correct-horse".into())
+		);
+	}
+}
@@ -1,32 +1,196 @@
 use crate::{
-	CompiledPackage, CompiledSource,
+	CompiledPackage, CompiledSource, ProtoDownloadProgress, ProtoInstallProgress, ProtoInstallStep,
+	ProtoVmRunState, ProtoVmStatus, command_paths,
 	qmp::{client::Client, messages::GenericReturn},
 };
 use anyhow::{Result, anyhow};
 use curl::easy::Easy;
+use serde::{Deserialize, Serialize};
 use std::{io::Read, process::Stdio};
 use std::{
 	io::Write,
 	path::{Path, PathBuf},
-	sync::mpsc::channel,
+	sync::mpsc::{Sender, channel},
 };
 
 #[cfg(test)]
 mod tests;
 
-const PODMAN_COMMAND: &str = "podman";
-const QEMU_COMMAND: &str = "qemu-system-x86_64";
+const SCRATCH_IMAGE: &str = "scratch";
 const QEMU_IMAGE_FILENAME: &str = "image";
 const QEMU_MONITOR_FILENAME: &str = "qemu-monitor";
+const QEMU_VNC_FILENAME: &str = "vnc.sock";
+
+// progress is reported at most once per this many bytes, and once more when the download ends
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
 
 enum DownloadInfo {
 	Data(Vec<u8>),
 	#[expect(dead_code)]
 	ContentType(String),
+	ContentLength(u64),
+	Status(u32),
 	Close,
 }
 
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DownloadProgress {
+	pub downloaded: u64,
+	pub total: Option<u64>,
+}
+
+impl From<DownloadProgress> for ProtoDownloadProgress {
+	fn from(value: DownloadProgress) -> Self {
+		Self {
+			downloaded: value.downloaded,
+			total: value.total,
+		}
+	}
+}
+
+impl From<ProtoDownloadProgress> for DownloadProgress {
+	fn from(value: ProtoDownloadProgress) -> Self {
+		Self {
+			downloaded: value.downloaded,
+			total: value.total,
+		}
+	}
+}
+
+// the steps of an install or uninstall, in the order they happen. every operation ends with
+// Finished, whether or not it succeeded.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum InstallStep {
+	#[default]
+	Pulling,
+	Provisioning,
+	Installing,
+	WritingUnit,
+	Starting,
+	Uninstalling,
+	Deprovisioning,
+	RemovingUnit,
+	Finished,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct InstallProgress {
+	pub step: InstallStep,
+	// set on the Finished step when the operation failed
+	pub error: Option<String>,
+}
+
+impl From<InstallProgress> for ProtoInstallProgress {
+	fn from(value: InstallProgress) -> Self {
+		let step = match value.step {
+			InstallStep::Pulling => ProtoInstallStep::StepPulling,
+			InstallStep::Provisioning => ProtoInstallStep::StepProvisioning,
+			InstallStep::Installing => ProtoInstallStep::StepInstalling,
+			InstallStep::WritingUnit => ProtoInstallStep::StepWritingUnit,
+			InstallStep::Starting => ProtoInstallStep::StepStarting,
+			InstallStep::Uninstalling => ProtoInstallStep::StepUninstalling,
+			InstallStep::Deprovisioning => ProtoInstallStep::StepDeprovisioning,
+			InstallStep::RemovingUnit => ProtoInstallStep::StepRemovingUnit,
+			InstallStep::Finished => ProtoInstallStep::StepFinished,
+		};
+
+		Self {
+			step: step.into(),
+			error: value.error,
+		}
+	}
+}
+
+impl From<ProtoInstallProgress> for InstallProgress {
+	fn from(value: ProtoInstallProgress) -> Self {
+		let step = match value.step() {
+			ProtoInstallStep::StepPulling => InstallStep::Pulling,
+			ProtoInstallStep::StepProvisioning => InstallStep::Provisioning,
+			ProtoInstallStep::StepInstalling => InstallStep::Installing,
+			ProtoInstallStep::StepWritingUnit => InstallStep::WritingUnit,
+			ProtoInstallStep::StepStarting => InstallStep::Starting,
+			ProtoInstallStep::StepUninstalling => InstallStep::Uninstalling,
+			ProtoInstallStep::StepDeprovisioning => InstallStep::Deprovisioning,
+			ProtoInstallStep::StepRemovingUnit => InstallStep::RemovingUnit,
+			ProtoInstallStep::StepFinished => InstallStep::Finished,
+		};
+
+		Self {
+			step,
+			error: value.error,
+		}
+	}
+}
+
+// the run state QEMU reports for a VM. states other than these (migrations, guest panics and such)
+// are kept as QEMU names them.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum VmStatus {
+	#[default]
+	NotRunning,
+	Running,
+	Paused,
+	Shutdown,
+	Other(String),
+}
+
+impl std::fmt::Display for VmStatus {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::NotRunning => "not running",
+			Self::Running => "running",
+			Self::Paused => "paused",
+			Self::Shutdown => "shutdown",
+			Self::Other(status) => status,
+		})
+	}
+}
+
+impl From<VmStatus> for ProtoVmStatus {
+	fn from(value: VmStatus) -> Self {
+		let state = match &value {
+			VmStatus::NotRunning => ProtoVmRunState::VmNotRunning,
+			VmStatus::Running => ProtoVmRunState::VmRunning,
+			VmStatus::Paused => ProtoVmRunState::VmPaused,
+			VmStatus::Shutdown => ProtoVmRunState::VmShutdown,
+			VmStatus::Other(_) => ProtoVmRunState::VmOther,
+		};
+
+		Self {
+			state: state.into(),
+			status: value.to_string(),
+		}
+	}
+}
+
+impl From<ProtoVmStatus> for VmStatus {
+	fn from(value: ProtoVmStatus) -> Self {
+		match value.state() {
+			ProtoVmRunState::VmNotRunning => Self::NotRunning,
+			ProtoVmRunState::VmRunning => Self::Running,
+			ProtoVmRunState::VmPaused => Self::Paused,
+			ProtoVmRunState::VmShutdown => Self::Shutdown,
+			ProtoVmRunState::VmOther => Self::Other(value.status),
+		}
+	}
+}
+
+impl From<&str> for VmStatus {
+	fn from(value: &str) -> Self {
+		match value {
+			"running" => Self::Running,
+			"paused" => Self::Paused,
+			"shutdown" => Self::Shutdown,
+			x => Self::Other(x.to_string()),
+		}
+	}
+}
+
 pub fn generate_command(package: CompiledPackage, volume_root: PathBuf) -> Result<Vec<String>> {
+	// compiled packages are also read back from the cache, so don't count on compile() having
+	// checked them
+	package.validate()?;
+
 	match package.source {
 		CompiledSource::QEmu(_) => generate_vm_command(&package, &volume_root),
 		CompiledSource::Container(_) => generate_container_command(&package, &volume_root),
@@ -40,8 +204,50 @@ pub fn stop_package(package: CompiledPackage, volume_root: PathBuf) -> Result<()
 	}
 }
 
+// runs the package in the foreground until it exits. if we are asked to terminate first (f.e.
+// `systemctl kill`), the workload is shut down the same way `charon stop` would before we return.
+pub async fn run_package(
+	package: CompiledPackage, volume_root: PathBuf,
+) -> Result<std::process::ExitStatus> {
+	let command = generate_command(package.clone(), volume_root.clone())?;
+	let env_file = match package.source {
+		CompiledSource::Container(_) => write_environment_file(&package, &volume_root)?,
+		CompiledSource::QEmu(_) => None,
+	};
+
+	let result = supervise(package, volume_root, command).await;
+	if let Some(env_file) = env_file {
+		let _ = std::fs::remove_file(env_file);
+	}
+
+	result
+}
+
+async fn supervise(
+	package: CompiledPackage, volume_root: PathBuf, command: Vec<String>,
+) -> Result<std::process::ExitStatus> {
+	use tokio::signal::unix::{SignalKind, signal};
+
+	let mut child = tokio::process::Command::new(&command[0])
+		.args(command.iter().skip(1))
+		.spawn()?;
+
+	let mut sigterm = signal(SignalKind::terminate())?;
+	let mut sigint = signal(SignalKind::interrupt())?;
+
+	tokio::select! {
+		status = child.wait() => return Ok(status?),
+		_ = sigterm.recv() => {},
+		_ = sigint.recv() => {},
+	}
+
+	eprintln!("Signal received, stopping {}", package.title);
+	tokio::task::spawn_blocking(move || stop_package(package, volume_root)).await??;
+	Ok(child.wait().await?)
+}
+
 pub fn container_shutdown(package: &CompiledPackage, _: &Path) -> Result<()> {
-	std::process::Command::new(PODMAN_COMMAND)
+	std::process::Command::new(&command_paths().podman)
 		.args(vec!["rm", "-f", &package.title.to_string()])
 		.stdout(Stdio::null())
 		.stderr(Stdio::null())
@@ -50,38 +256,187 @@ pub fn container_shutdown(package: &CompiledPackage, _: &Path) -> Result<()> {
 	Ok(())
 }
 
+// pulls the package's container image so that a bad reference or an unreachable registry fails the
+// install, rather than the first start of the unit. references pinned by digest
+// (`name@sha256:...`) are checked against that digest by podman as they are pulled. nothing is
+// fetched if podman already has the image.
+pub async fn pull_container_image(package: &CompiledPackage) -> Result<()> {
+	let CompiledSource::Container(image) = &package.source else {
+		return Ok(());
+	};
+
+	// reserved for the empty image; there is nothing to pull, and registries refuse it
+	if image == SCRATCH_IMAGE {
+		return Ok(());
+	}
+
+	let exists = tokio::process::Command::new(&command_paths().podman)
+		.args(["image", "exists", image])
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.status()
+		.await?;
+
+	if exists.success() {
+		return Ok(());
+	}
+
+	let output = tokio::process::Command::new(&command_paths().podman)
+		.args(["pull", "--quiet", image])
+		.stdin(Stdio::null())
+		.output()
+		.await?;
+
+	if !output.status.success() {
+		return Err(anyhow!(
+			"could not pull image {} for {}: {}",
+			image,
+			package.title,
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+
+	Ok(())
+}
+
 pub fn download_vm_image(u: &str, target: PathBuf) -> Result<()> {
+	download_vm_image_with_progress(u, target, None, |_| {})
+}
+
+// downloads the package's VM image to where launch expects it, so the first start doesn't have to.
+// the image only appears at that path once it is complete, and matches the package's digest if it
+// has one.
+pub fn prefetch_vm_image(
+	package: &CompiledPackage, volume_root: &Path,
+	progress: impl FnMut(DownloadProgress) + Send + 'static,
+) -> Result<()> {
+	let CompiledSource::QEmu(image) = &package.source else {
+		return Err(anyhow!(
+			"{} is not a virtual machine package",
+			package.title
+		));
+	};
+
+	let target = volume_root.join(QEMU_IMAGE_FILENAME);
+	let tmp = volume_root.join(format!("{}.tmp", QEMU_IMAGE_FILENAME));
+	download_vm_image_with_progress(image.url(), tmp.clone(), image.sha256(), progress)?;
+	Ok(std::fs::rename(tmp, target)?)
+}
+
+// if sha256 is given, the download is hashed as it is written and fails if the hex digest differs.
+// an http(s) download into a file that already has something in it picks up where that left off,
+// if the server supports ranges; otherwise the file is downloaded again from the start.
+pub fn download_vm_image_with_progress(
+	u: &str, target: PathBuf, sha256: Option<&str>,
+	mut progress: impl FnMut(DownloadProgress) + Send + 'static,
+) -> Result<()> {
 	let parsed: url::Url = u.parse()?;
+	let expected = sha256.map(|x| x.to_ascii_lowercase());
+	let url = u.to_string();
+
+	let offset = if parsed.scheme() == "file" {
+		0
+	} else {
+		target.metadata().map(|x| x.len()).unwrap_or_default()
+	};
 
 	// FIXME: all this setup is to facilitate transparent decompression
 	//        which of course is not actually implemented yet
 	let (s, r) = channel();
 	let (close_s, close_r) = channel::<Result<()>>();
 	std::thread::spawn(move || {
-		let mut f = match std::fs::OpenOptions::new()
-			.create(true)
-			.truncate(true)
-			.write(true)
-			.open(&target)
-		{
-			Ok(f) => f,
-			Err(e) => {
-				close_s.send(Err(anyhow!(e))).unwrap();
-				return;
-			}
-		};
+		// opened once we know whether the download continues the file or replaces it
+		let mut f: Option<std::fs::File> = None;
+		let mut resumed = false;
+		let mut discard = false;
+
+		let mut status = DownloadProgress::default();
+		let mut reported = 0;
+		let mut digest = expected
+			.as_ref()
+			.map(|_| ring::digest::Context::new(&ring::digest::SHA256));
 
 		while let Ok(item) = r.recv() {
 			match item {
+				DownloadInfo::Status(code) => {
+					f = None;
+					resumed = offset > 0 && code == 206;
+					// the file is at least as long as what the server has; we'll be asked again
+					// for all of it, so leave the file alone until then.
+					discard = offset > 0 && code == 416;
+				}
+				DownloadInfo::Data(_) if discard => {}
 				DownloadInfo::Data(data) => {
-					if let Err(e) = f.write_all(&data) {
+					if f.is_none() {
+						match open_download(&target, resumed, &mut digest) {
+							Ok(file) => f = Some(file),
+							Err(e) => {
+								close_s.send(Err(e)).unwrap();
+								return;
+							}
+						}
+
+						status.downloaded = if resumed { offset } else { 0 };
+						reported = status.downloaded;
+					}
+
+					if let Some(f) = &mut f
+						&& let Err(e) = f.write_all(&data)
+					{
 						close_s.send(Err(anyhow!(e))).unwrap();
 						return;
 					}
+
+					if let Some(digest) = &mut digest {
+						digest.update(&data);
+					}
+
+					status.downloaded += data.len() as u64;
+					if status.downloaded - reported >= PROGRESS_INTERVAL {
+						progress(status);
+						reported = status.downloaded;
+					}
 				}
 				DownloadInfo::ContentType(_) => {}
+				// for a partial response, this is only what's left
+				DownloadInfo::ContentLength(len) => {
+					status.total = Some(if resumed { offset + len } else { len })
+				}
 				DownloadInfo::Close => {
-					close_s.send(Ok(())).unwrap();
+					// nothing was downloaded, but there should still be a file
+					if f.is_none()
+						&& let Err(e) = open_download(&target, resumed, &mut digest)
+					{
+						close_s.send(Err(e)).unwrap();
+						return;
+					}
+
+					progress(status);
+
+					let result = match (&expected, digest) {
+						(Some(expected), Some(digest)) => {
+							let found = digest
+								.finish()
+								.as_ref()
+								.iter()
+								.map(|x| format!("{:02x}", x))
+								.collect::<String>();
+
+							if *expected != found {
+								Err(anyhow!(
+									"Image {} does not match its checksum: expected sha256 {}, got {}",
+									url,
+									expected,
+									found
+								))
+							} else {
+								Ok(())
+							}
+						}
+						_ => Ok(()),
+					};
+
+					close_s.send(result).unwrap();
 					return;
 				}
 			}
@@ -98,6 +453,7 @@ pub fn download_vm_image(u: &str, target: PathBuf) -> Result<()> {
 			parsed.host().unwrap(),
 			parsed.path()
 		))?;
+		s.send(DownloadInfo::ContentLength(f.metadata()?.len()))?;
 		let mut buf: [u8; 4096] = [0u8; 4096];
 		loop {
 			let size = f.read(&mut buf)?;
@@ -106,30 +462,8 @@ pub fn download_vm_image(u: &str, target: PathBuf) -> Result<()> {
 			}
 			s.send(DownloadInfo::Data(buf[..size].to_vec())).unwrap();
 		}
-	} else {
-		let mut curl = Easy::new();
-		curl.url(u)?;
-
-		let s2 = s.clone();
-		curl.header_function(move |header| {
-			if let Ok(header) = String::from_utf8(header.into()) {
-				let split: Vec<&str> = header.splitn(2, ":").collect();
-				if split.len() == 2 && split[0].to_lowercase() == "content-type" {
-					s2.send(DownloadInfo::ContentType(split[1].trim().to_string()))
-						.unwrap();
-				}
-			}
-
-			true
-		})?;
-
-		let s2 = s.clone();
-		curl.write_function(move |data| {
-			s2.send(DownloadInfo::Data(data.to_vec())).unwrap();
-			Ok(data.len())
-		})?;
-
-		curl.perform()?;
+	} else if curl_download(u, offset, &s)? == 416 && offset > 0 {
+		curl_download(u, 0, &s)?;
 	}
 
 	s.send(DownloadInfo::Close)?;
@@ -137,6 +471,78 @@ pub fn download_vm_image(u: &str, target: PathBuf) -> Result<()> {
 	Ok(())
 }
 
+// a resumed download appends to the file, and what was already there has to be hashed too
+fn open_download(
+	target: &Path, resumed: bool, digest: &mut Option<ring::digest::Context>,
+) -> Result<std::fs::File> {
+	if !resumed {
+		return Ok(std::fs::OpenOptions::new()
+			.create(true)
+			.truncate(true)
+			.write(true)
+			.open(target)?);
+	}
+
+	let mut f = std::fs::OpenOptions::new()
+		.read(true)
+		.append(true)
+		.open(target)?;
+
+	if let Some(digest) = digest {
+		let mut buf = vec![0u8; 64 * 1024];
+		loop {
+			let size = f.read(&mut buf)?;
+			if size == 0 {
+				break;
+			}
+			digest.update(&buf[..size]);
+		}
+	}
+
+	Ok(f)
+}
+
+// requests everything from offset on, and returns the response code
+fn curl_download(u: &str, offset: u64, s: &Sender<DownloadInfo>) -> Result<u32> {
+	let mut curl = Easy::new();
+	curl.url(u)?;
+	if offset > 0 {
+		curl.range(&format!("{}-", offset))?;
+	}
+
+	let s2 = s.clone();
+	curl.header_function(move |header| {
+		if let Ok(header) = String::from_utf8(header.into()) {
+			let split: Vec<&str> = header.splitn(2, ":").collect();
+			if header.starts_with("HTTP/")
+				&& let Some(code) = header.split_whitespace().nth(1)
+				&& let Ok(code) = code.parse()
+			{
+				s2.send(DownloadInfo::Status(code)).unwrap();
+			} else if split.len() == 2 && split[0].to_lowercase() == "content-type" {
+				s2.send(DownloadInfo::ContentType(split[1].trim().to_string()))
+					.unwrap();
+			} else if split.len() == 2
+				&& split[0].to_lowercase() == "content-length"
+				&& let Ok(len) = split[1].trim().parse()
+			{
+				s2.send(DownloadInfo::ContentLength(len)).unwrap();
+			}
+		}
+
+		true
+	})?;
+
+	let s2 = s.clone();
+	curl.write_function(move |data| {
+		s2.send(DownloadInfo::Data(data.to_vec())).unwrap();
+		Ok(data.len())
+	})?;
+
+	curl.perform()?;
+	Ok(curl.response_code()?)
+}
+
 fn vm_client(package: &CompiledPackage, volume_root: &Path) -> Result<Client> {
 	match Client::new(volume_root.join(QEMU_MONITOR_FILENAME)) {
 		Ok(mut us) => {
@@ -153,6 +559,15 @@ pub fn vm_ping(package: &CompiledPackage, volume_root: &Path) -> Result<()> {
 	Ok(())
 }
 
+// a VM that can't be reached over its monitor isn't running, as far as we can tell
+pub fn vm_status(package: &CompiledPackage, volume_root: &Path) -> Result<VmStatus> {
+	let Ok(mut client) = vm_client(package, volume_root) else {
+		return Ok(VmStatus::NotRunning);
+	};
+
+	Ok(client.status()?.status.as_str().into())
+}
+
 pub fn vm_shutdown(package: &CompiledPackage, volume_root: &Path) -> Result<()> {
 	vm_client(package, volume_root)?.send_command("system_powerdown", None)
 }
@@ -162,7 +577,7 @@ pub fn vm_quit(package: &CompiledPackage, volume_root: &Path) -> Result<()> {
 }
 
 pub fn generate_vm_command(package: &CompiledPackage, volume_root: &Path) -> Result<Vec<String>> {
-	let mut cmd = vec![QEMU_COMMAND.to_string()];
+	let mut cmd = vec![command_paths().qemu.to_string_lossy().to_string()];
 
 	let mut fwdrules = String::new();
 	for (host, guest) in &package.networking.forward_ports {
@@ -185,20 +600,30 @@ pub fn generate_vm_command(package: &CompiledPackage, volume_root: &Path) -> Res
 		"-machine".into(),
 		"accel=kvm".into(),
 		"-vga".into(),
-		"none".into(), // FIXME: move to VNC
+		// without a display to show it on, there's no point emulating a graphics card
+		if package.resources.vnc.is_some() {
+			"std".into()
+		} else {
+			"none".into()
+		},
 		"-m".into(),
 		format!("{}M", package.resources.memory),
 		"-cpu".into(),
 		"max".into(),
 		"-smp".into(),
-		format!(
-			"cpus={},cores={},maxcpus={}",
-			package.resources.cpus, package.resources.cpus, package.resources.cpus
-		),
+		package.resources.smp(),
 		"-nic".into(),
 		format!("user{}", fwdrules),
 	]);
 
+	if let Some(vnc) = &package.resources.vnc {
+		cmd.push("-vnc".into());
+		cmd.push(match vnc.display {
+			Some(display) => format!("127.0.0.1:{}", display),
+			None => format!("unix:{}", volume_root.join(QEMU_VNC_FILENAME).display()),
+		});
+	}
+
 	cmd.push("-drive".into());
 	cmd.push(format!(
 		"driver=raw,if=virtio,file={},cache=none,media=disk,index={}",
@@ -207,7 +632,11 @@ pub fn generate_vm_command(package: &CompiledPackage, volume_root: &Path) -> Res
 		0,
 	));
 
-	let excluded_names = [QEMU_IMAGE_FILENAME, QEMU_MONITOR_FILENAME];
+	let excluded_names = [
+		QEMU_IMAGE_FILENAME,
+		QEMU_MONITOR_FILENAME,
+		QEMU_VNC_FILENAME,
+	];
 
 	for (x, volume) in package.storage.volumes.iter().enumerate() {
 		if excluded_names.contains(&volume.name.as_str()) {
@@ -231,10 +660,45 @@ pub fn generate_vm_command(package: &CompiledPackage, volume_root: &Path) -> Res
 	Ok(cmd)
 }
 
+// values are often secrets, so they're handed to podman in a file only we can read rather than on
+// its command line, where anyone on the host can see them.
+pub fn environment_file(package: &CompiledPackage, volume_root: &Path) -> PathBuf {
+	volume_root.join(format!("{}.env", package.title))
+}
+
+pub fn write_environment_file(
+	package: &CompiledPackage, volume_root: &Path,
+) -> Result<Option<PathBuf>> {
+	use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+	if package.system.environment.is_empty() {
+		return Ok(None);
+	}
+
+	let path = environment_file(package, volume_root);
+	let mut f = std::fs::OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.mode(0o600)
+		.open(&path)?;
+	// the mode is only applied to a new file
+	f.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+
+	for (key, value) in &package.system.environment {
+		writeln!(f, "{}={}", key, value)?;
+	}
+
+	Ok(Some(path))
+}
+
 pub fn generate_container_command(
 	package: &CompiledPackage, volume_root: &Path,
 ) -> Result<Vec<String>> {
-	let mut cmd = vec![PODMAN_COMMAND.into(), "run".into()];
+	let mut cmd = vec![
+		command_paths().podman.to_string_lossy().to_string(),
+		"run".into(),
+	];
 	let name = package.title.to_string();
 	cmd.append(&mut vec!["--rm".into(), "--name".into(), name]);
 
@@ -295,8 +759,7 @@ pub fn generate_container_command(
 		cmd.append(&mut vec!["--pid".into(), "host".into()]);
 	}
 
-	// FIXME: check for this conflict in validate
-	if package.system.host_net && package.networking.internal_network.is_none() {
+	if package.system.host_net {
 		cmd.append(&mut vec!["--network".into(), "host".into()]);
 	}
 
@@ -308,6 +771,15 @@ pub fn generate_container_command(
 		cmd.append(&mut vec!["--cap-add".into(), cap.into()]);
 	}
 
+	if !package.system.environment.is_empty() {
+		cmd.append(&mut vec![
+			"--env-file".into(),
+			environment_file(package, volume_root)
+				.to_string_lossy()
+				.to_string(),
+		]);
+	}
+
 	// TODO: cgroups
 
 	cmd.push(name.into());
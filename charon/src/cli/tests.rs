@@ -29,6 +29,20 @@ mod livetests {
 		// lower than the size of the current image in the makefile
 		assert!(md.size() > 240 * 1024 * 1024);
 
+		let last = std::sync::Arc::new(std::sync::Mutex::new(DownloadProgress::default()));
+		let inner = last.clone();
+		download_vm_image_with_progress(
+			"file://testdata/ubuntu.img",
+			path.to_path_buf(),
+			None,
+			move |x| *inner.lock().unwrap() = x,
+		)
+		.unwrap();
+		// the final report should account for the whole file
+		let last = *last.lock().unwrap();
+		assert_eq!(last.downloaded, md.size());
+		assert_eq!(last.total, Some(md.size()));
+
 		// just a file over http. this should be small and accessible.
 		download_vm_image(
 			"https://raw.githubusercontent.com/curl/curl/refs/heads/master/lib/file.c",
@@ -102,6 +116,38 @@ mod livetests {
 		assert!(status.success());
 	}
 
+	#[tokio::test]
+	async fn pull_image() {
+		let registry = Registry::new("testdata/registry".into());
+		let pkg = load(&registry, "podman-test", "0.0.3").await.unwrap();
+
+		pull_container_image(&pkg).await.unwrap();
+		let CompiledSource::Container(image) = &pkg.source else {
+			panic!("podman-test is not a container package");
+		};
+		let status = std::process::Command::new("podman")
+			.args(["image", "exists", image])
+			.status()
+			.unwrap();
+		assert!(status.success());
+
+		// already present, so this should not go to the registry at all
+		pull_container_image(&pkg).await.unwrap();
+
+		let mut bogus = pkg.clone();
+		bogus.source = CompiledSource::Container("localhost/charon-does-not-exist:none".into());
+		assert!(pull_container_image(&bogus).await.is_err());
+
+		// the default source; registries won't serve it
+		let mut scratch = pkg.clone();
+		scratch.source = CompiledSource::default();
+		pull_container_image(&scratch).await.unwrap();
+
+		// not a container, nothing to do
+		let vm = load(&registry, "plex-qemu", "0.0.2").await.unwrap();
+		pull_container_image(&vm).await.unwrap();
+	}
+
 	//
 	// #[test]
 	// fn launch_qemu() {
@@ -130,7 +176,7 @@ mod cli_generation {
 			)
 			.unwrap(),
 			string_vec(vec![
-				QEMU_COMMAND,
+				command_paths().qemu.to_str().unwrap(),
 				"-nodefaults",
 				"-chardev",
 				"socket,server=on,wait=off,id=char0,path=/volume-root/qemu-monitor",
@@ -162,7 +208,7 @@ mod cli_generation {
 			)
 			.unwrap(),
 			string_vec(vec![
-				QEMU_COMMAND,
+				command_paths().qemu.to_str().unwrap(),
 				"-nodefaults",
 				"-chardev",
 				"socket,server=on,wait=off,id=char0,path=/volume-root/qemu-monitor",
@@ -171,7 +217,7 @@ mod cli_generation {
 				"-machine",
 				"accel=kvm",
 				"-vga",
-				"none",
+				"std",
 				"-m",
 				"4096M",
 				"-cpu",
@@ -180,10 +226,76 @@ mod cli_generation {
 				"cpus=8,cores=8,maxcpus=8",
 				"-nic",
 				"user,hostfwd=tcp:0.0.0.0:1234-:5678,hostfwd=tcp:0.0.0.0:2345-:6789",
+				"-vnc",
+				"unix:/volume-root/vnc.sock",
 				"-drive",
 				"driver=raw,if=virtio,file=/volume-root/image,cache=none,media=disk,index=0"
 			]),
 		);
+
+		// a display serves VNC over TCP instead, on loopback only
+		let mut pkg = load(&registry, "plex-qemu", "0.0.1").await.unwrap();
+		pkg.resources.vnc = Some(CompiledVnc { display: Some(1) });
+		let args = generate_command(pkg.clone(), "/volume-root".into()).unwrap();
+		let vnc = args.iter().position(|x| x == "-vnc").unwrap();
+		assert_eq!(args[vnc + 1], "127.0.0.1:1");
+
+		// the highest display still has a port, one past it doesn't
+		pkg.resources.vnc = Some(CompiledVnc {
+			display: Some(59635),
+		});
+		assert!(generate_command(pkg.clone(), "/volume-root".into()).is_ok());
+		pkg.resources.vnc = Some(CompiledVnc {
+			display: Some(59636),
+		});
+		assert!(generate_command(pkg, "/volume-root".into()).is_err());
+
+		// an explicit topology has to account for every cpu
+		let mut pkg = load(&registry, "plex-qemu", "0.0.1").await.unwrap();
+		pkg.resources.sockets = Some(2);
+		pkg.resources.cores = Some(2);
+		pkg.resources.threads = Some(2);
+		let args = generate_command(pkg.clone(), "/volume-root".into()).unwrap();
+		let smp = args.iter().position(|x| x == "-smp").unwrap();
+		assert_eq!(
+			args[smp + 1],
+			"cpus=8,sockets=2,cores=2,threads=2,maxcpus=8"
+		);
+
+		// threads left out count as one
+		pkg.resources.threads = None;
+		let err = generate_command(pkg.clone(), "/volume-root".into()).unwrap_err();
+		assert!(err.to_string().contains("8 cpus"), "{}", err);
+
+		pkg.resources.cores = Some(4);
+		let args = generate_command(pkg, "/volume-root".into()).unwrap();
+		let smp = args.iter().position(|x| x == "-smp").unwrap();
+		assert_eq!(
+			args[smp + 1],
+			"cpus=8,sockets=2,cores=4,threads=1,maxcpus=8"
+		);
+	}
+
+	#[tokio::test]
+	async fn podman_cli_escape() {
+		let registry = Registry::new("testdata/registry".into());
+		let pkg = load(&registry, "podman-test", "0.0.1").await.unwrap();
+
+		for (name, mountpoint) in [
+			("../escape", "/data"),
+			("private", "../escape"),
+			("private", "/etc/passwd"),
+		] {
+			let mut pkg = pkg.clone();
+			pkg.storage.volumes[0].name = name.into();
+			pkg.storage.volumes[0].mountpoint = Some(mountpoint.into());
+			assert!(
+				generate_command(pkg, "/volume-root".into()).is_err(),
+				"{} at {}",
+				name,
+				mountpoint
+			);
+		}
 	}
 
 	#[tokio::test]
@@ -196,7 +308,7 @@ mod cli_generation {
 			)
 			.unwrap(),
 			string_vec(vec![
-				PODMAN_COMMAND,
+				command_paths().podman.to_str().unwrap(),
 				"run",
 				"--rm",
 				"--name",
@@ -211,7 +323,7 @@ mod cli_generation {
 			)
 			.unwrap(),
 			string_vec(vec![
-				PODMAN_COMMAND,
+				command_paths().podman.to_str().unwrap(),
 				"run",
 				"--rm",
 				"--name",
@@ -226,7 +338,7 @@ mod cli_generation {
 			)
 			.unwrap(),
 			string_vec(vec![
-				PODMAN_COMMAND,
+				command_paths().podman.to_str().unwrap(),
 				"run",
 				"--rm",
 				"--name",
@@ -239,11 +351,255 @@ mod cli_generation {
 				"host",
 				"--network",
 				"host",
-				"--privileged",
 				"--cap-add",
 				"SYS_ADMIN",
+				"--env-file",
+				"/volume-root/podman-test-0.0.1.env",
 				"docker://debian"
 			])
 		);
 	}
+
+	#[tokio::test]
+	async fn environment_file() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let registry = Registry::new("testdata/registry".into());
+		let dir = tempfile::tempdir().unwrap();
+
+		let pkg = load(&registry, "plex", "0.0.1").await.unwrap();
+		assert!(write_environment_file(&pkg, dir.path()).unwrap().is_none());
+
+		let pkg = load(&registry, "podman-test", "0.0.1").await.unwrap();
+		// left over from an earlier run with looser permissions
+		std::fs::write(dir.path().join("podman-test-0.0.1.env"), "stale").unwrap();
+		let path = write_environment_file(&pkg, dir.path()).unwrap().unwrap();
+		assert_eq!(path, dir.path().join("podman-test-0.0.1.env"));
+		assert_eq!(
+			std::fs::read_to_string(&path).unwrap(),
+			"TZ=UTC\nLOG_LEVEL=debug\n"
+		);
+		assert_eq!(
+			std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+			0o600
+		);
+	}
+}
+
+mod downloads {
+	use super::*;
+	use std::io::{Read, Write};
+	use tempfile::NamedTempFile;
+
+	// serves body over http on a local port, one request per connection. a `Range: bytes=n-`
+	// request gets the rest of the body from n on, unless ranges is false.
+	fn serve(body: Vec<u8>, ranges: bool) -> String {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+
+		std::thread::spawn(move || {
+			for stream in listener.incoming() {
+				let mut stream = stream.unwrap();
+				let mut request = Vec::new();
+				let mut buf = [0u8; 1024];
+				while !request.windows(4).any(|x| x == b"\r\n\r\n") {
+					let size = stream.read(&mut buf).unwrap();
+					if size == 0 {
+						break;
+					}
+					request.extend_from_slice(&buf[..size]);
+				}
+
+				let request = String::from_utf8_lossy(&request).to_lowercase();
+				let start = request.lines().find_map(|x| {
+					x.strip_prefix("range: bytes=")?
+						.strip_suffix('-')?
+						.parse::<usize>()
+						.ok()
+				});
+
+				let (status, content) = match start {
+					Some(start) if ranges && start >= body.len() => {
+						("416 Range Not Satisfiable", &body[0..0])
+					}
+					Some(start) if ranges => ("206 Partial Content", &body[start..]),
+					_ => ("200 OK", &body[..]),
+				};
+
+				write!(
+					stream,
+					"HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+					status,
+					content.len()
+				)
+				.unwrap();
+				stream.write_all(content).unwrap();
+			}
+		});
+
+		format!("http://{}/image", addr)
+	}
+
+	#[test]
+	fn download_resume() {
+		let body = (0..3 * 1024 * 1024)
+			.map(|x: u32| (x.wrapping_mul(2654435761) >> 24) as u8)
+			.collect::<Vec<u8>>();
+		let sha256 = ring::digest::digest(&ring::digest::SHA256, &body)
+			.as_ref()
+			.iter()
+			.map(|x| format!("{:02x}", x))
+			.collect::<String>();
+
+		let tf = NamedTempFile::new().unwrap();
+		let path = tf.path();
+
+		for ranges in [true, false] {
+			let url = serve(body.clone(), ranges);
+
+			// an interrupted download; with ranges only the rest is fetched, otherwise it starts
+			// over
+			std::fs::write(path, &body[..1024 * 1024]).unwrap();
+			let last = std::sync::Arc::new(std::sync::Mutex::new(DownloadProgress::default()));
+			let inner = last.clone();
+			download_vm_image_with_progress(&url, path.to_path_buf(), Some(&sha256), move |x| {
+				*inner.lock().unwrap() = x
+			})
+			.unwrap();
+			assert_eq!(std::fs::read(path).unwrap(), body);
+			let last = *last.lock().unwrap();
+			assert_eq!(last.downloaded, body.len() as u64);
+			assert_eq!(last.total, Some(body.len() as u64));
+
+			// more than the server has; the range can't be satisfied, so it is downloaded again
+			std::fs::write(path, [body.clone(), body.clone()].concat()).unwrap();
+			download_vm_image_with_progress(&url, path.to_path_buf(), Some(&sha256), |_| {})
+				.unwrap();
+			assert_eq!(std::fs::read(path).unwrap(), body);
+		}
+
+		// a resumed download still has to match as a whole
+		let url = serve(body.clone(), true);
+		let mut partial = body[..1024 * 1024].to_vec();
+		partial[0] ^= 0xff;
+		std::fs::write(path, partial).unwrap();
+		assert!(
+			download_vm_image_with_progress(&url, path.to_path_buf(), Some(&sha256), |_| {})
+				.is_err()
+		);
+	}
+
+	#[test]
+	fn download_checksum() {
+		let tf = NamedTempFile::new().unwrap();
+		let path = tf.path();
+		let source = "testdata/registry/packages/plex-qemu/0.0.2.json";
+		let url = format!("file://{}", source);
+
+		let sha256 = ring::digest::digest(&ring::digest::SHA256, &std::fs::read(source).unwrap())
+			.as_ref()
+			.iter()
+			.map(|x| format!("{:02x}", x))
+			.collect::<String>();
+
+		download_vm_image_with_progress(&url, path.to_path_buf(), Some(&sha256), |_| {}).unwrap();
+		// case shouldn't matter for hex
+		download_vm_image_with_progress(
+			&url,
+			path.to_path_buf(),
+			Some(&sha256.to_uppercase()),
+			|_| {},
+		)
+		.unwrap();
+
+		let wrong = "0".repeat(64);
+		let err = download_vm_image_with_progress(&url, path.to_path_buf(), Some(&wrong), |_| {})
+			.unwrap_err()
+			.to_string();
+		assert!(err.contains(&wrong) && err.contains(&sha256), "{}", err);
+
+		// packages declare the digest alongside the url
+		let image: VMImage<TemplatedInput<String>> =
+			serde_json::from_str(&format!(r#"{{"url": "{}", "sha256": "{}"}}"#, url, sha256))
+				.unwrap();
+		let compiled = image
+			.compile(
+				&Default::default(),
+				&Default::default(),
+				&Default::default(),
+			)
+			.unwrap();
+		assert_eq!(compiled.url(), &url);
+		assert_eq!(compiled.sha256(), Some(sha256.as_str()));
+
+		let image: VMImage<TemplatedInput<String>> =
+			serde_json::from_str(&format!(r#""{}""#, url)).unwrap();
+		assert_eq!(image.sha256(), None);
+
+		let image: VMImage<TemplatedInput<String>> =
+			serde_json::from_str(&format!(r#"{{"url": "{}", "sha256": "nope"}}"#, url)).unwrap();
+		assert!(
+			image
+				.compile(
+					&Default::default(),
+					&Default::default(),
+					&Default::default()
+				)
+				.is_err()
+		);
+	}
+}
+
+mod vm {
+	use super::*;
+	use std::io::{Read, Write};
+	use tempfile::TempDir;
+
+	// answers the QMP handshake and then a query-status with status
+	fn fake_monitor(path: PathBuf, status: &str) -> std::thread::JoinHandle<()> {
+		let listener = std::os::unix::net::UnixListener::bind(path).unwrap();
+		let status = status.to_string();
+
+		std::thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			stream.write_all(b"{\r\n  \"QMP\": {}\r\n}\r\n").unwrap();
+
+			let mut buf = [0u8; 1024];
+			for reply in [
+				"{\r\n  \"return\": {}\r\n}\r\n".to_string(),
+				format!(
+					"{{\r\n  \"return\": {{\"running\": {}, \"status\": \"{}\"}}\r\n}}\r\n",
+					status == "running",
+					status
+				),
+			] {
+				assert!(stream.read(&mut buf).unwrap() > 0);
+				stream.write_all(reply.as_bytes()).unwrap();
+			}
+		})
+	}
+
+	#[test]
+	fn vm_status_query() {
+		let dir = TempDir::new().unwrap();
+		let pkg = CompiledPackage::default();
+
+		// nothing is listening on the monitor
+		assert_eq!(vm_status(&pkg, dir.path()).unwrap(), VmStatus::NotRunning);
+
+		for (reported, status) in [
+			("running", VmStatus::Running),
+			("paused", VmStatus::Paused),
+			("shutdown", VmStatus::Shutdown),
+			("guest-panicked", VmStatus::Other("guest-panicked".into())),
+		] {
+			let monitor = dir.path().join(QEMU_MONITOR_FILENAME);
+			let _ = std::fs::remove_file(&monitor);
+			let server = fake_monitor(monitor, reported);
+			assert_eq!(vm_status(&pkg, dir.path()).unwrap(), status);
+			server.join().unwrap();
+
+			assert_eq!(VmStatus::from(ProtoVmStatus::from(status.clone())), status);
+		}
+	}
 }
@@ -1,13 +1,15 @@
 use crate::grpc::query_client::QueryClient as GRPCQueryClient;
 use crate::grpc::status_client::StatusClient as GRPCStatusClient;
 use crate::{
-	InputType, InstallStatus, PackageStatus, PackageTitle, Prompt, PromptCollection,
-	PromptResponses, ProtoPromptResponses, ProtoType, ProtoUninstallData,
+	ConsistencyReport, InstallPlan, InstallStatus, LifecycleEvent, PackageRuntimeStatus,
+	PackageStatus, PackageTitle, PortReachability, PromptCollection, PromptResponses,
+	ProtoDatasetName, ProtoDownloadProgress, ProtoEventFilter, ProtoInstallProgress,
+	ProtoPromptResponses, ProtoPromptResponsesBatch, ProtoUninstallData, VmStatus,
 };
 use crate::{ProtoPackageTitle, grpc::control_client::ControlClient as GRPCControlClient};
 use anyhow::Result;
 use std::path::PathBuf;
-use tonic::{Request, transport::Channel};
+use tonic::{Request, Streaming, transport::Channel};
 
 #[derive(Debug, Clone)]
 pub struct Client {
@@ -81,6 +83,34 @@ impl ControlClient {
 		Ok(())
 	}
 
+	// the stream always ends with a Finished step, which carries the error if the install failed
+	pub async fn install_with_progress(
+		&mut self, name: &str, version: &str,
+	) -> Result<Streaming<ProtoInstallProgress>> {
+		Ok(self
+			.client
+			.install_with_progress(Request::new(ProtoPackageTitle {
+				name: name.to_string(),
+				version: version.to_string(),
+			}))
+			.await?
+			.into_inner())
+	}
+
+	pub async fn uninstall_with_progress(
+		&mut self, name: &str, version: &str, purge: bool,
+	) -> Result<Streaming<ProtoInstallProgress>> {
+		Ok(self
+			.client
+			.uninstall_with_progress(Request::new(ProtoUninstallData {
+				name: name.to_string(),
+				version: version.to_string(),
+				purge,
+			}))
+			.await?
+			.into_inner())
+	}
+
 	pub async fn installed(&mut self, name: &str, version: &str) -> Result<Option<InstallStatus>> {
 		let reply = self
 			.client
@@ -105,6 +135,78 @@ impl ControlClient {
 		Ok(())
 	}
 
+	pub async fn prefetch_image(
+		&mut self, name: &str, version: &str,
+	) -> Result<Streaming<ProtoDownloadProgress>> {
+		Ok(self
+			.client
+			.prefetch_image(Request::new(ProtoPackageTitle {
+				name: name.to_string(),
+				version: version.to_string(),
+			}))
+			.await?
+			.into_inner())
+	}
+
+	pub async fn reconfigure(&mut self, name: &str, version: &str) -> Result<()> {
+		self.client
+			.reconfigure(Request::new(ProtoPackageTitle {
+				name: name.to_string(),
+				version: version.to_string(),
+			}))
+			.await?;
+
+		Ok(())
+	}
+
+	// makes version the installed version of the package, in place of whichever is installed now
+	pub async fn rollback(&mut self, name: &str, version: &str) -> Result<()> {
+		self.client
+			.rollback(Request::new(ProtoPackageTitle {
+				name: name.to_string(),
+				version: version.to_string(),
+			}))
+			.await?;
+
+		Ok(())
+	}
+
+	pub async fn remove_version(&mut self, name: &str, version: &str) -> Result<()> {
+		self.client
+			.remove_version(Request::new(ProtoPackageTitle {
+				name: name.to_string(),
+				version: version.to_string(),
+			}))
+			.await?;
+
+		Ok(())
+	}
+
+	pub async fn plan_install(&mut self, name: &str, version: &str) -> Result<InstallPlan> {
+		Ok(self
+			.client
+			.plan_install(Request::new(ProtoPackageTitle {
+				name: name.to_string(),
+				version: version.to_string(),
+			}))
+			.await?
+			.into_inner()
+			.into())
+	}
+
+	// fails with FailedPrecondition if the package isn't a VM
+	pub async fn vm_status(&mut self, name: &str, version: &str) -> Result<VmStatus> {
+		Ok(self
+			.client
+			.vm_status(Request::new(ProtoPackageTitle {
+				name: name.to_string(),
+				version: version.to_string(),
+			}))
+			.await?
+			.into_inner()
+			.into())
+	}
+
 	pub async fn remove_unit(&mut self, name: &str, version: &str) -> Result<()> {
 		let out = ProtoPackageTitle {
 			name: name.into(),
@@ -157,6 +259,82 @@ impl QueryClient {
 		Ok(v)
 	}
 
+	pub async fn list_with_status(&mut self) -> Result<Vec<PackageRuntimeStatus>> {
+		let list = self
+			.client
+			.list_with_status(Request::new(()))
+			.await?
+			.into_inner();
+
+		Ok(list.list.into_iter().map(Into::into).collect())
+	}
+
+	pub async fn check_reachable(
+		&mut self, name: &str, version: &str,
+	) -> Result<Vec<PortReachability>> {
+		let reply = self
+			.client
+			.check_reachable(Request::new(ProtoPackageTitle {
+				name: name.to_string(),
+				version: version.to_string(),
+			}))
+			.await?
+			.into_inner();
+
+		Ok(reply.ports.into_iter().map(Into::into).collect())
+	}
+
+	// package lifecycle events, oldest first; only those for `name` if it is given
+	pub async fn list_events(&mut self, name: Option<&str>) -> Result<Vec<LifecycleEvent>> {
+		Ok(self
+			.client
+			.list_events(Request::new(ProtoEventFilter {
+				name: name.map(ToString::to_string),
+			}))
+			.await?
+			.into_inner()
+			.list
+			.into_iter()
+			.map(Into::into)
+			.collect())
+	}
+
+	// the installed package that owns a dataset or volume, by its name in the pool
+	pub async fn dataset_owner(&mut self, dataset: &str) -> Result<Option<PackageTitle>> {
+		Ok(self
+			.client
+			.dataset_owner(Request::new(ProtoDatasetName {
+				name: dataset.to_string(),
+			}))
+			.await?
+			.into_inner()
+			.owner
+			.map(Into::into))
+	}
+
+	pub async fn list_versions(&mut self, name: &str) -> Result<Vec<String>> {
+		let title = ProtoPackageTitle {
+			name: name.into(),
+			version: String::new(),
+		};
+
+		Ok(self
+			.client
+			.list_versions(Request::new(title))
+			.await?
+			.into_inner()
+			.versions)
+	}
+
+	pub async fn check_consistency(&mut self) -> Result<ConsistencyReport> {
+		Ok(self
+			.client
+			.check_consistency(Request::new(()))
+			.await?
+			.into_inner()
+			.into())
+	}
+
 	pub async fn get_responses(&mut self, name: &str) -> Result<PromptResponses> {
 		let title = ProtoPackageTitle {
 			name: name.into(),
@@ -191,17 +369,8 @@ impl QueryClient {
 
 		let mut out = Vec::new();
 
-		for prompt in &prompts.prompts {
-			out.push(Prompt {
-				template: prompt.template.clone(),
-				question: prompt.question.clone(),
-				input_type: match prompt.input_type() {
-					ProtoType::String => InputType::String,
-					ProtoType::Integer => InputType::Integer,
-					ProtoType::SignedInteger => InputType::SignedInteger,
-					ProtoType::Boolean => InputType::Boolean,
-				},
-			});
+		for prompt in prompts.prompts {
+			out.push(prompt.into());
 		}
 
 		Ok(PromptCollection(out))
@@ -220,4 +389,21 @@ impl QueryClient {
 		self.client.set_responses(Request::new(out)).await?;
 		Ok(())
 	}
+
+	// replaces the responses of every package given, or of none of them if any can't be written.
+	pub async fn set_responses_batch(
+		&mut self, responses: Vec<(String, PromptResponses)>,
+	) -> Result<()> {
+		let mut out = ProtoPromptResponsesBatch::default();
+
+		for (name, responses) in responses {
+			out.packages.push(ProtoPromptResponses {
+				name,
+				responses: responses.0.into_iter().map(Into::into).collect(),
+			});
+		}
+
+		self.client.set_responses_batch(Request::new(out)).await?;
+		Ok(())
+	}
 }
@@ -0,0 +1,84 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::{path::PathBuf, sync::OnceLock};
+
+const GIT_DEFAULT_COMMAND: &str = "git";
+const PODMAN_DEFAULT_COMMAND: &str = "podman";
+const QEMU_DEFAULT_COMMAND: &str = "qemu-system-x86_64";
+const TAR_DEFAULT_COMMAND: &str = "tar";
+
+static COMMAND_PATHS: OnceLock<CommandPaths> = OnceLock::new();
+
+fn default_git() -> PathBuf {
+	GIT_DEFAULT_COMMAND.into()
+}
+
+fn default_podman() -> PathBuf {
+	PODMAN_DEFAULT_COMMAND.into()
+}
+
+fn default_qemu() -> PathBuf {
+	QEMU_DEFAULT_COMMAND.into()
+}
+
+fn default_tar() -> PathBuf {
+	TAR_DEFAULT_COMMAND.into()
+}
+
+// the programs charon runs. by default they're looked up on PATH, which may not be what the
+// service user has, or may find the wrong build.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct CommandPaths {
+	#[serde(default = "default_git")]
+	pub git: PathBuf,
+	#[serde(default = "default_podman")]
+	pub podman: PathBuf,
+	#[serde(default = "default_qemu")]
+	pub qemu: PathBuf,
+	#[serde(default = "default_tar")]
+	pub tar: PathBuf,
+}
+
+impl Default for CommandPaths {
+	fn default() -> Self {
+		Self {
+			git: default_git(),
+			podman: default_podman(),
+			qemu: default_qemu(),
+			tar: default_tar(),
+		}
+	}
+}
+
+// the paths in effect for this process. these are decided once: by set_command_paths() at
+// startup, or as the defaults the first time anything asks.
+pub fn command_paths() -> &'static CommandPaths {
+	COMMAND_PATHS.get_or_init(Default::default)
+}
+
+pub fn set_command_paths(paths: CommandPaths) -> Result<()> {
+	COMMAND_PATHS
+		.set(paths)
+		.map_err(|_| anyhow!("command paths were already decided for this process"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::CommandPaths;
+
+	#[test]
+	fn parse() {
+		let paths: CommandPaths =
+			serde_yaml_ng::from_str("podman: /opt/podman/bin/podman").unwrap();
+		assert_eq!(
+			paths,
+			CommandPaths {
+				podman: "/opt/podman/bin/podman".into(),
+				..Default::default()
+			}
+		);
+
+		let paths: CommandPaths = serde_yaml_ng::from_str("{}").unwrap();
+		assert_eq!(paths, CommandPaths::default());
+	}
+}
@@ -1,11 +1,13 @@
-use crate::{INSTALLED_SUBPATH, Registry, SYSTEMD_SERVICE_ROOT};
+use crate::{
+	CommandPaths, DEFAULT_SECRET_KEY_PATH, INSTALLED_SUBPATH, Registry, SYSTEMD_SERVICE_ROOT,
+	command_paths, set_command_paths,
+};
 use anyhow::{Result, anyhow};
 use serde::Deserialize;
-use std::path::PathBuf;
-use tracing::info;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
 use tracing_subscriber::FmtSubscriber;
 
-const GIT_PATH: &str = "git"; // FIXME: for now. This should be absolute or configurable, at least.
 const GIT_DEFAULT_REPOSITORY: &str = "https://github.com/trunk-os/charon-packages";
 const REGISTRY_DEFAULT_PATH: &str = "/trunk/charon/registry";
 
@@ -54,6 +56,9 @@ impl From<tracing::Level> for LogLevel {
 pub struct RegistryConfig {
 	pub path: PathBuf,
 	pub url: Option<String>,
+	// fail the sync when any package in the registry is invalid, instead of only logging them
+	#[serde(default)]
+	pub strict_sync: bool,
 }
 
 impl Default for RegistryConfig {
@@ -61,6 +66,7 @@ impl Default for RegistryConfig {
 		Self {
 			path: REGISTRY_DEFAULT_PATH.into(),
 			url: Some(GIT_DEFAULT_REPOSITORY.into()),
+			strict_sync: false,
 		}
 	}
 }
@@ -73,6 +79,38 @@ fn default_charon_path() -> Option<PathBuf> {
 	Some(DEFAULT_CHARON_BIN_PATH.into())
 }
 
+fn default_secret_key() -> Option<PathBuf> {
+	Some(DEFAULT_SECRET_KEY_PATH.into())
+}
+
+fn default_operations_concurrency() -> usize {
+	2
+}
+
+fn default_operations_wait() -> u64 {
+	5 * 60
+}
+
+// limits on the operations that create datasets, download images and touch systemd: at most
+// `concurrency` run at once and the rest queue behind them. a queued request that has not started
+// after `wait` seconds is turned away as busy. reads are never limited.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OperationsConfig {
+	#[serde(default = "default_operations_concurrency")]
+	pub concurrency: usize,
+	#[serde(default = "default_operations_wait")]
+	pub wait: u64,
+}
+
+impl Default for OperationsConfig {
+	fn default() -> Self {
+		Self {
+			concurrency: default_operations_concurrency(),
+			wait: default_operations_wait(),
+		}
+	}
+}
+
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct Config {
 	pub registry: RegistryConfig,
@@ -83,7 +121,14 @@ pub struct Config {
 	pub debug: Option<bool>,
 	#[serde(default = "default_charon_path")]
 	pub charon_path: Option<PathBuf>,
+	// the host secret that package secrets are sealed with
+	#[serde(default = "default_secret_key")]
+	pub secret_key: Option<PathBuf>,
 	pub buckle_socket: PathBuf,
+	#[serde(default)]
+	pub operations: OperationsConfig,
+	#[serde(default)]
+	pub commands: CommandPaths,
 }
 
 impl Config {
@@ -96,6 +141,7 @@ impl Config {
 			))
 			.finish();
 		tracing::subscriber::set_global_default(subscriber)?;
+		set_command_paths(this.commands.clone())?;
 		this.sync_registry()?;
 		info!("Configuration parsed successfully.");
 		Ok(this)
@@ -105,8 +151,27 @@ impl Config {
 		buckle::client::Client::new(self.buckle_socket.clone())
 	}
 
+	// anything that talks to buckle would otherwise fail with a bare transport error from deep
+	// inside the operation, which says nothing about which socket it was trying.
+	pub async fn check_buckle(&self) -> Result<()> {
+		tokio::net::UnixStream::connect(&self.buckle_socket)
+			.await
+			.map(|_| ())
+			.map_err(|e| {
+				anyhow!(
+					"buckle unavailable at {}: {}",
+					self.buckle_socket.display(),
+					e
+				)
+			})
+	}
+
 	pub fn registry(&self) -> Registry {
-		Registry::new(self.registry.path.clone())
+		let registry = Registry::new(self.registry.path.clone());
+		match &self.secret_key {
+			Some(key) => registry.with_secret_key(key.clone()),
+			None => registry,
+		}
 	}
 
 	pub fn debug(&self) -> bool {
@@ -115,23 +180,63 @@ impl Config {
 
 	pub fn sync_registry(&self) -> Result<()> {
 		if let Some(url) = &self.registry.url {
+			let git = command_paths().git.to_string_lossy().to_string();
 			// exists. here, we want to store any files we have laying around so the rebase doesn't
 			// fail. this is admittedly pretty dodgy but I don't have a better solution right now.
-			if std::fs::exists(&self.registry.path)? {
-				self.run_command(vec![GIT_PATH.into(), "add".into(), ".".into()])?;
-				self.run_command(vec![GIT_PATH.into(), "stash".into()])?;
-				self.run_command(vec![GIT_PATH.into(), "pull".into(), "--rebase".into()])?;
+			let path = &self.registry.path;
+			if std::fs::exists(path)? {
+				// in strict mode what's upstream is checked before it's pulled over the checkout
+				if self.registry.strict_sync {
+					self.run_command(path, vec![git.clone(), "fetch".into()])?;
+					let staging = tempfile::tempdir()?;
+					let archive = staging.path().join("registry.tar");
+					self.run_command(
+						path,
+						vec![
+							git.clone(),
+							"archive".into(),
+							"-o".into(),
+							archive.to_string_lossy().to_string(),
+							"@{upstream}".into(),
+						],
+					)?;
+					self.run_command(
+						staging.path(),
+						vec![
+							command_paths().tar.to_string_lossy().to_string(),
+							"-xf".into(),
+							archive.to_string_lossy().to_string(),
+						],
+					)?;
+					std::fs::remove_file(&archive)?;
+					self.check_registry(&Registry::new(staging.path().to_path_buf()))?;
+				}
+
+				self.run_command(path, vec![git.clone(), "add".into(), ".".into()])?;
+				self.run_command(path, vec![git.clone(), "stash".into()])?;
+				self.run_command(path, vec![git.clone(), "pull".into(), "--rebase".into()])?;
 				// FIXME this sucks
-				let _ = self.run_command(vec![GIT_PATH.into(), "stash".into(), "apply".into()]);
+				let _ = self.run_command(path, vec![git.clone(), "stash".into(), "apply".into()]);
 			} else {
-				std::fs::create_dir_all(&self.registry.path)?;
-				// first time, clone it
-				self.run_command(vec![
-					GIT_PATH.into(),
-					"clone".into(),
-					url.clone(),
-					self.registry.path.to_string_lossy().to_string(),
-				])?;
+				// first time, clone it. the clone is only moved into place once it's been checked,
+				// so a strict sync that fails leaves nothing behind to be picked up next time.
+				let parent = path.parent().unwrap_or(Path::new("/"));
+				std::fs::create_dir_all(parent)?;
+				let staging = tempfile::tempdir_in(parent)?;
+				let checkout = staging.path().join("registry");
+				self.run_command(
+					staging.path(),
+					vec![
+						git.clone(),
+						"clone".into(),
+						url.clone(),
+						checkout.to_string_lossy().to_string(),
+					],
+				)?;
+				if self.registry.strict_sync {
+					self.check_registry(&Registry::new(checkout.clone()))?;
+				}
+				std::fs::rename(&checkout, path)?;
 			}
 		}
 
@@ -143,15 +248,37 @@ impl Config {
 			std::fs::create_dir_all(&self.registry.path.join(INSTALLED_SUBPATH))?;
 		}
 
+		// whatever was pulled would otherwise only be checked when someone tries to install it
+		self.check_registry(&self.registry())
+	}
+
+	// logs every invalid package in registry, and fails on them if strict_sync is set
+	fn check_registry(&self, registry: &Registry) -> Result<()> {
+		let invalid = registry.invalid()?;
+		for (title, e) in &invalid {
+			warn!("Package {} in the registry is invalid: {}", title, e);
+		}
+
+		if self.registry.strict_sync && !invalid.is_empty() {
+			return Err(anyhow!(
+				"Registry contains invalid packages: {}",
+				invalid
+					.iter()
+					.map(|(title, _)| title.to_string())
+					.collect::<Vec<String>>()
+					.join(", ")
+			));
+		}
+
 		Ok(())
 	}
 
-	fn run_command(&self, command: Vec<String>) -> Result<()> {
+	fn run_command(&self, dir: &Path, command: Vec<String>) -> Result<()> {
 		let mut iter = command.iter();
 		if let Some(cmd) = iter.nth(0) {
 			let status = std::process::Command::new(cmd)
 				.args(iter.collect::<Vec<&String>>())
-				.current_dir(&self.registry.path)
+				.current_dir(dir)
 				.status()?;
 			if !status.success() {
 				return Err(anyhow!(
@@ -167,3 +294,115 @@ impl Config {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{Config, RegistryConfig};
+	use crate::PackageTitle;
+	use std::path::Path;
+
+	#[test]
+	fn sync_registry_invalid() {
+		let dir = tempfile::tempdir().unwrap();
+		let src = Path::new("testdata/registry");
+		for file in [
+			"packages/plex/0.0.1.json",
+			"variables/plex.json",
+			// has no variables, so it can't be loaded
+			"packages/no-variables/0.0.1.json",
+		] {
+			let target = dir.path().join(file);
+			std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+			std::fs::copy(src.join(file), target).unwrap();
+		}
+
+		let mut config = Config {
+			registry: RegistryConfig {
+				path: dir.path().to_path_buf(),
+				url: None,
+				strict_sync: false,
+			},
+			..Default::default()
+		};
+
+		let invalid = config.registry().invalid().unwrap();
+		assert_eq!(invalid.len(), 1);
+		assert_eq!(
+			invalid[0].0,
+			PackageTitle {
+				name: "no-variables".into(),
+				version: "0.0.1".into(),
+			}
+		);
+
+		// only reported
+		config.sync_registry().unwrap();
+
+		config.registry.strict_sync = true;
+		let err = config.sync_registry().unwrap_err().to_string();
+		assert!(err.contains("no-variables-0.0.1"), "{}", err);
+		assert!(!err.contains("plex"), "{}", err);
+
+		std::fs::remove_dir_all(dir.path().join("packages/no-variables")).unwrap();
+		config.sync_registry().unwrap();
+	}
+
+	#[test]
+	fn sync_registry_strict_pull() {
+		let git = |dir: &Path, args: &[&str]| {
+			assert!(
+				std::process::Command::new("git")
+					.args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+					.args(args)
+					.current_dir(dir)
+					.status()
+					.unwrap()
+					.success()
+			)
+		};
+
+		let copy = |root: &Path, files: &[&str]| {
+			for file in files {
+				let target = root.join(file);
+				std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+				std::fs::copy(Path::new("testdata/registry").join(file), target).unwrap();
+			}
+		};
+
+		let dir = tempfile::tempdir().unwrap();
+		let upstream = dir.path().join("upstream");
+		copy(
+			&upstream,
+			&["packages/plex/0.0.1.json", "variables/plex.json"],
+		);
+		git(&upstream, &["init", "-q"]);
+		git(&upstream, &["add", "."]);
+		git(&upstream, &["commit", "-q", "-m", "valid"]);
+
+		let mut config = Config {
+			registry: RegistryConfig {
+				path: dir.path().join("registry"),
+				url: Some(upstream.to_string_lossy().to_string()),
+				strict_sync: true,
+			},
+			..Default::default()
+		};
+		config.sync_registry().unwrap();
+
+		// has no variables, so it can't be loaded
+		copy(&upstream, &["packages/no-variables/0.0.1.json"]);
+		git(&upstream, &["add", "."]);
+		git(&upstream, &["commit", "-q", "-m", "invalid"]);
+
+		// the checkout is left as it was
+		let err = config.sync_registry().unwrap_err().to_string();
+		assert!(err.contains("no-variables-0.0.1"), "{}", err);
+		assert!(!std::fs::exists(config.registry.path.join("packages/no-variables")).unwrap());
+		assert!(std::fs::exists(config.registry.path.join("packages/plex/0.0.1.json")).unwrap());
+
+		// and a first clone isn't left behind at all
+		config.registry.path = dir.path().join("fresh");
+		assert!(config.sync_registry().is_err());
+		assert!(!std::fs::exists(&config.registry.path).unwrap());
+	}
+}
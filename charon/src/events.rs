@@ -0,0 +1,221 @@
+use crate::{PackageTitle, ProtoLifecycleAction, ProtoLifecycleEvent};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+	io::{BufRead, Write},
+	path::PathBuf,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+const EVENTS_FILENAME: &str = "events.jsonl";
+// past this many bytes the log is moved aside to `events.jsonl.1`, replacing the one before it
+const EVENTS_MAX_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleAction {
+	Install,
+	Uninstall,
+	// `charon launch` started the workload
+	Start,
+	// `charon stop` shut it down
+	Stop,
+	// the workload exited on its own, or after being stopped
+	Exit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LifecycleEvent {
+	// seconds since the epoch
+	pub time: u64,
+	pub title: PackageTitle,
+	pub action: LifecycleAction,
+	// set when the action failed
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+impl From<LifecycleEvent> for ProtoLifecycleEvent {
+	fn from(value: LifecycleEvent) -> Self {
+		Self {
+			time: value.time,
+			title: Some(value.title.into()),
+			action: match value.action {
+				LifecycleAction::Install => ProtoLifecycleAction::Install,
+				LifecycleAction::Uninstall => ProtoLifecycleAction::Uninstall,
+				LifecycleAction::Start => ProtoLifecycleAction::Start,
+				LifecycleAction::Stop => ProtoLifecycleAction::Stop,
+				LifecycleAction::Exit => ProtoLifecycleAction::Exit,
+			}
+			.into(),
+			error: value.error,
+		}
+	}
+}
+
+impl From<ProtoLifecycleEvent> for LifecycleEvent {
+	fn from(value: ProtoLifecycleEvent) -> Self {
+		Self {
+			time: value.time,
+			action: match value.action() {
+				ProtoLifecycleAction::Install => LifecycleAction::Install,
+				ProtoLifecycleAction::Uninstall => LifecycleAction::Uninstall,
+				ProtoLifecycleAction::Start => LifecycleAction::Start,
+				ProtoLifecycleAction::Stop => LifecycleAction::Stop,
+				ProtoLifecycleAction::Exit => LifecycleAction::Exit,
+			},
+			title: value.title.unwrap_or_default().into(),
+			error: value.error,
+		}
+	}
+}
+
+// what has happened to packages, oldest first. charond records installs and uninstalls; the
+// `charon launch` and `charon stop` processes systemd runs record the rest. every event is one
+// appended line of JSON, so those processes can share the file without locking it.
+#[derive(Debug, Clone)]
+pub struct EventLog {
+	root: PathBuf,
+}
+
+impl EventLog {
+	pub fn new(root: PathBuf) -> Self {
+		Self { root }
+	}
+
+	fn path(&self) -> PathBuf {
+		self.root.join(EVENTS_FILENAME)
+	}
+
+	fn rotated_path(&self) -> PathBuf {
+		self.root.join(format!("{}.1", EVENTS_FILENAME))
+	}
+
+	pub fn record(
+		&self, title: &PackageTitle, action: LifecycleAction, error: Option<String>,
+	) -> Result<()> {
+		let path = self.path();
+		if std::fs::metadata(&path).is_ok_and(|x| x.len() > EVENTS_MAX_SIZE) {
+			std::fs::rename(&path, self.rotated_path())?;
+		}
+
+		let mut line = serde_json::to_vec(&LifecycleEvent {
+			time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+			title: title.clone(),
+			action,
+			error,
+		})?;
+		line.push(b'\n');
+
+		std::fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&path)?
+			.write_all(&line)?;
+
+		Ok(())
+	}
+
+	// records how an action turned out. a log that can't be written is only complained about,
+	// since the action already happened either way.
+	pub fn observe<T, E: std::fmt::Display>(
+		&self, title: &PackageTitle, action: LifecycleAction, result: &std::result::Result<T, E>,
+	) {
+		let error = result.as_ref().err().map(ToString::to_string);
+		if let Err(e) = self.record(title, action, error) {
+			tracing::warn!("Could not record {:?} of {}: {}", action, title, e);
+		}
+	}
+
+	// the events for one package, or all of them
+	pub fn list(&self, name: Option<&str>) -> Result<Vec<LifecycleEvent>> {
+		let mut v = Vec::new();
+
+		for path in [self.rotated_path(), self.path()] {
+			let f = match std::fs::File::open(&path) {
+				Ok(f) => f,
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+				Err(e) => return Err(e.into()),
+			};
+
+			for line in std::io::BufReader::new(f).lines() {
+				let line = line?;
+				// a writer that died partway through leaves half a line behind
+				match serde_json::from_str::<LifecycleEvent>(&line) {
+					Ok(event) => {
+						if name.is_none_or(|name| event.title.name == name) {
+							v.push(event)
+						}
+					}
+					Err(e) => {
+						tracing::warn!("Skipping unreadable event in {}: {}", path.display(), e)
+					}
+				}
+			}
+		}
+
+		Ok(v)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{EventLog, LifecycleAction};
+	use crate::PackageTitle;
+
+	#[test]
+	fn record() {
+		let dir = tempfile::tempdir().unwrap();
+		let log = EventLog::new(dir.path().to_path_buf());
+		let title = |name: &str| PackageTitle {
+			name: name.into(),
+			version: "1.0.0".into(),
+		};
+
+		assert!(log.list(None).unwrap().is_empty());
+
+		log.observe(
+			&title("plex"),
+			LifecycleAction::Install,
+			&Ok::<_, String>(()),
+		);
+		log.observe(
+			&title("plex"),
+			LifecycleAction::Exit,
+			&Err::<(), _>("exited with status 1"),
+		);
+		log.observe(
+			&title("nginx"),
+			LifecycleAction::Start,
+			&Ok::<_, String>(()),
+		);
+
+		// left behind by a writer that was killed
+		std::fs::OpenOptions::new()
+			.append(true)
+			.open(log.path())
+			.and_then(|mut f| std::io::Write::write_all(&mut f, b"{\"time\":"))
+			.unwrap();
+
+		let all = log.list(None).unwrap();
+		assert_eq!(all.len(), 3);
+		assert_eq!(all[0].action, LifecycleAction::Install);
+		assert_eq!(all[0].error, None);
+
+		let plex = log.list(Some("plex")).unwrap();
+		assert_eq!(plex.len(), 2);
+		assert_eq!(plex[1].action, LifecycleAction::Exit);
+		assert_eq!(plex[1].error.as_deref(), Some("exited with status 1"));
+
+		// the rotated log is still read, ahead of the current one
+		std::fs::rename(log.path(), log.rotated_path()).unwrap();
+		log.observe(
+			&title("plex"),
+			LifecycleAction::Uninstall,
+			&Ok::<_, String>(()),
+		);
+		let plex = log.list(Some("plex")).unwrap();
+		assert_eq!(plex.len(), 3);
+		assert_eq!(plex[2].action, LifecycleAction::Uninstall);
+	}
+}
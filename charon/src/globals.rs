@@ -1,8 +1,9 @@
+use crate::validate_registry_name;
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, path::PathBuf};
 
-const GLOBAL_SUBPATH: &str = "variables";
+pub(crate) const GLOBAL_SUBPATH: &str = "variables";
 const DELIMITER: char = '@';
 
 pub type Variables = HashMap<String, String>;
@@ -86,6 +87,7 @@ impl GlobalRegistry {
 	}
 
 	pub fn remove(&self, name: &str) -> Result<()> {
+		validate_registry_name(name)?;
 		Ok(std::fs::remove_file(
 			self.root
 				.join(GLOBAL_SUBPATH)
@@ -94,6 +96,7 @@ impl GlobalRegistry {
 	}
 
 	pub fn get(&self, name: &str) -> Result<Global> {
+		validate_registry_name(name)?;
 		Ok(serde_json::from_reader(
 			std::fs::OpenOptions::new().read(true).open(
 				self.root
@@ -104,6 +107,7 @@ impl GlobalRegistry {
 	}
 
 	pub fn set(&self, global: &Global) -> Result<()> {
+		validate_registry_name(&global.name)?;
 		let pb = self.root.join(GLOBAL_SUBPATH);
 
 		std::fs::create_dir_all(&pb)?;
@@ -174,6 +178,36 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn traversal() {
+		let dir = tempfile::tempdir().unwrap();
+		let registry = GlobalRegistry {
+			root: dir.path().join("registry"),
+		};
+		let outside = dir.path().join("escape.json");
+		std::fs::write(&outside, "{}").unwrap();
+
+		for name in ["../escape", "../../escape", "/etc/passwd", "a/b", "..", ""] {
+			assert!(registry.get(name).is_err(), "{}", name);
+			assert!(registry.remove(name).is_err(), "{}", name);
+			assert!(
+				registry
+					.set(&Global {
+						name: name.into(),
+						variables: Default::default(),
+					})
+					.is_err(),
+				"{}",
+				name
+			);
+		}
+
+		// nothing was removed or written outside the registry
+		assert!(outside.exists());
+		assert!(!dir.path().join("escape.json.tmp").exists());
+		assert!(!dir.path().join("registry").exists());
+	}
+
 	#[test]
 	fn template() {
 		let mut variables = Variables::default();
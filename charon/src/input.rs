@@ -1,6 +1,10 @@
-use crate::{Global, PromptCollection, PromptParser, PromptResponses};
+use crate::{Global, Prompt, PromptCollection, PromptParser, PromptResponses};
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize, de::Visitor};
-use std::str::FromStr;
+use std::{
+	path::{Component, Path, PathBuf},
+	str::FromStr,
+};
 
 //
 // see package.rs for some important understanding about this package that I won't repeat here
@@ -30,6 +34,24 @@ impl Default for TemplatedInput<u16> {
 	}
 }
 
+impl Default for TemplatedInput<u8> {
+	fn default() -> Self {
+		TemplatedInput {
+			input: "0".into(),
+			marker: Default::default(),
+		}
+	}
+}
+
+impl Default for TemplatedInput<u32> {
+	fn default() -> Self {
+		TemplatedInput {
+			input: "0".into(),
+			marker: Default::default(),
+		}
+	}
+}
+
 impl Default for TemplatedInput<u64> {
 	fn default() -> Self {
 		TemplatedInput {
@@ -39,6 +61,15 @@ impl Default for TemplatedInput<u64> {
 	}
 }
 
+impl Default for TemplatedInput<i32> {
+	fn default() -> Self {
+		TemplatedInput {
+			input: "0".into(),
+			marker: Default::default(),
+		}
+	}
+}
+
 impl Default for TemplatedInput<i64> {
 	fn default() -> Self {
 		TemplatedInput {
@@ -75,6 +106,18 @@ impl Default for TemplatedInput<&str> {
 	}
 }
 
+impl<E> Default for TemplatedInput<Enumerated<E>>
+where
+	E: AllowedValues,
+{
+	fn default() -> Self {
+		TemplatedInput {
+			input: Enumerated::<E>::default().value,
+			marker: Default::default(),
+		}
+	}
+}
+
 impl<T> TemplatedInput<T>
 where
 	T: FromStr,
@@ -91,6 +134,11 @@ where
 			.template(globals.template(&self.input)?, responses)?
 			.parse()?)
 	}
+
+	// the prompts this input is templated with, in the order they appear
+	pub fn prompts(&self, prompts: &PromptCollection) -> anyhow::Result<Vec<Prompt>> {
+		PromptParser(prompts.clone()).prompts(self.input.clone())
+	}
 }
 
 impl<T> FromStr for TemplatedInput<T>
@@ -107,9 +155,35 @@ where
 	}
 }
 
+// what a TemplatedInput<T> should parse into, in words, for deserialization errors. this used to
+// be a match on std::any::type_name(), which never matched anything.
+pub trait InputKind {
+	const KIND: &'static str;
+}
+
+macro_rules! input_kind {
+	($kind:literal: $($t:ty),+) => {
+		$(impl InputKind for $t {
+			const KIND: &'static str = $kind;
+		})+
+	};
+}
+
+input_kind!("string": String, &str);
+input_kind!("unsigned integer": u8, u16, u32, u64);
+input_kind!("signed integer": i32, i64);
+input_kind!("boolean": bool);
+
+impl<E> InputKind for Enumerated<E>
+where
+	E: AllowedValues,
+{
+	const KIND: &'static str = "one of a fixed set of strings";
+}
+
 impl<'de, T: Default> Deserialize<'de> for TemplatedInput<T>
 where
-	T: Default,
+	T: Default + InputKind,
 {
 	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
 	where
@@ -124,19 +198,12 @@ struct TemplatedInputVisitor<T>(std::marker::PhantomData<T>);
 
 impl<'de, T> Visitor<'de> for TemplatedInputVisitor<T>
 where
-	T: Default,
+	T: Default + InputKind,
 {
 	type Value = TemplatedInput<T>;
 
 	fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-		let need = match std::any::type_name::<T>() {
-			"std::str" | "std::str::String" => "string",
-			"std::u64" | "std::u16" => "unsigned integer",
-			"std::i64" => "signed integer",
-			"std::bool" => "boolean",
-			_ => "unknown type",
-		};
-		formatter.write_str(&format!("expecting a string that parses as {}", need))
+		formatter.write_str(&format!("expecting a string that parses as {}", T::KIND))
 	}
 
 	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -150,6 +217,107 @@ where
 	}
 }
 
+// the fixed set of strings an Enumerated field accepts. the first one is the default.
+pub trait AllowedValues {
+	const ALLOWED: &'static [&'static str];
+}
+
+// a string that may only be one of E::ALLOWED. as TemplatedInput<Enumerated<E>>, it lets the
+// package author constrain a field (f.e. a log level) the same way select constrains a prompt,
+// with the check happening in output() once templating is done.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Enumerated<E> {
+	value: String,
+	marker: std::marker::PhantomData<E>,
+}
+
+impl<E> Enumerated<E> {
+	pub fn as_str(&self) -> &str {
+		&self.value
+	}
+}
+
+impl<E> std::fmt::Display for Enumerated<E> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.value)
+	}
+}
+
+impl<E> Default for Enumerated<E>
+where
+	E: AllowedValues,
+{
+	fn default() -> Self {
+		Self {
+			value: E::ALLOWED.first().copied().unwrap_or_default().to_string(),
+			marker: Default::default(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EnumeratedError {
+	pub value: String,
+	pub allowed: &'static [&'static str],
+}
+
+impl std::fmt::Display for EnumeratedError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"'{}' is not allowed here; expected one of: {}",
+			self.value,
+			self.allowed.join(", ")
+		)
+	}
+}
+
+impl std::error::Error for EnumeratedError {}
+
+impl<E> FromStr for Enumerated<E>
+where
+	E: AllowedValues,
+{
+	type Err = EnumeratedError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if E::ALLOWED.contains(&s) {
+			Ok(Self {
+				value: s.to_string(),
+				marker: Default::default(),
+			})
+		} else {
+			Err(EnumeratedError {
+				value: s.to_string(),
+				allowed: E::ALLOWED,
+			})
+		}
+	}
+}
+
+impl<E> Serialize for Enumerated<E> {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: serde::ser::Serializer,
+	{
+		serializer.serialize_str(&self.value)
+	}
+}
+
+impl<'de, E> Deserialize<'de> for Enumerated<E>
+where
+	E: AllowedValues,
+{
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		String::deserialize(deserializer)?
+			.parse()
+			.map_err(serde::de::Error::custom)
+	}
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum InputType {
 	#[serde(rename = "integer")]
@@ -160,6 +328,89 @@ pub enum InputType {
 	String,
 	#[serde(rename = "boolean")]
 	Boolean,
+	// a string that is stored encrypted and never handed back out; see SecretRegistry
+	#[serde(rename = "secret")]
+	Secret,
+	// one of the prompt's options, shown to the user by name
+	#[serde(rename = "select")]
+	Select,
+	// a string that has to pass validate_path()
+	#[serde(rename = "path")]
+	Path,
+	// a single word that passes validate_volume_name(). a volume named with one gets the package's
+	// name in front of it, so it can't collide with the names charon uses itself.
+	#[serde(rename = "volume_name")]
+	VolumeName,
+}
+
+pub fn validate_volume_name(name: &str) -> anyhow::Result<()> {
+	if name.is_empty()
+		|| !name
+			.chars()
+			.all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+	{
+		return Err(anyhow!(
+			"'{}' is not a volume name; use a single word of letters, digits, '-' and '_'",
+			name
+		));
+	}
+
+	Ok(())
+}
+
+// where a volume may not be mounted in a container: over / or the system directories, which
+// breaks the container at best and hands the package a way into the host's files at worst.
+const RESERVED_PATHS: &[&str] = &[
+	"/bin", "/boot", "/dev", "/etc", "/lib", "/lib64", "/proc", "/run", "/sbin", "/sys", "/usr",
+];
+
+// paths from packages and their users, f.e. mountpoints, have to be absolute and spelled only
+// one way: no '.' or '..', and no doubled or trailing slashes.
+pub fn validate_path(path: &str) -> anyhow::Result<()> {
+	let p = Path::new(path);
+
+	if !p.is_absolute() {
+		return Err(anyhow!("'{}' is not an absolute path", path));
+	}
+
+	if p.components().any(|x| x == Component::ParentDir) {
+		return Err(anyhow!("'{}' may not contain '..'", path));
+	}
+
+	if p.components().collect::<PathBuf>().as_os_str() != path {
+		return Err(anyhow!("'{}' is not a normalized path", path));
+	}
+
+	if p == Path::new("/") || RESERVED_PATHS.iter().any(|x| p.starts_with(x)) {
+		return Err(anyhow!("'{}' is a system directory", path));
+	}
+
+	Ok(())
+}
+
+// names that become file names under one of the registry's directories, f.e. a package name or
+// version. anything that could name a file outside of that directory is refused before it gets
+// near a join().
+pub fn validate_registry_name(name: &str) -> anyhow::Result<()> {
+	if name.is_empty() {
+		return Err(anyhow!("a name is required"));
+	}
+
+	if name.contains('/') || name.contains('\\') || name.contains('\0') {
+		return Err(anyhow!(
+			"'{}' is not a valid name; it may not contain a path separator",
+			name
+		));
+	}
+
+	if name.contains("..") || name == "." {
+		return Err(anyhow!(
+			"'{}' is not a valid name; it may not contain '..'",
+			name
+		));
+	}
+
+	Ok(())
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -178,6 +429,8 @@ pub enum Input {
 	String(String),
 	#[serde(rename = "boolean")]
 	Boolean(bool),
+	#[serde(rename = "secret")]
+	Secret(String),
 }
 
 impl std::fmt::Display for Input {
@@ -187,6 +440,145 @@ impl std::fmt::Display for Input {
 			Input::SignedInteger(x) => x.to_string(),
 			Input::String(x) => x.to_string(),
 			Input::Boolean(x) => x.to_string(),
+			Input::Secret(x) => x.to_string(),
 		})
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{AllowedValues, Enumerated, TemplatedInput};
+	use crate::{Global, Input, InputType, Prompt, PromptCollection, PromptResponse};
+
+	#[derive(Debug, Clone, Eq, PartialEq)]
+	struct LogLevel;
+
+	impl AllowedValues for LogLevel {
+		const ALLOWED: &'static [&'static str] = &["info", "debug", "trace"];
+	}
+
+	#[test]
+	fn enumerated() {
+		let globals = Global::default();
+		let prompts = PromptCollection::default();
+		let responses = Default::default();
+
+		let input: TemplatedInput<Enumerated<LogLevel>> = "debug".parse().unwrap();
+		assert_eq!(
+			input
+				.output(&globals, &prompts, &responses)
+				.unwrap()
+				.as_str(),
+			"debug"
+		);
+
+		let input: TemplatedInput<Enumerated<LogLevel>> = "loud".parse().unwrap();
+		let err = input
+			.output(&globals, &prompts, &responses)
+			.unwrap_err()
+			.to_string();
+		assert!(err.contains("loud"));
+		assert!(err.contains("info, debug, trace"));
+
+		assert_eq!(
+			TemplatedInput::<Enumerated<LogLevel>>::default()
+				.output(&globals, &prompts, &responses)
+				.unwrap()
+				.as_str(),
+			"info"
+		);
+
+		// the check applies to what the template turns into, not the template itself
+		let prompts = PromptCollection(vec![Prompt {
+			template: "level".into(),
+			question: "how much logging?".into(),
+			input_type: InputType::String,
+			options: Default::default(),
+			required: false,
+			default: None,
+			pattern: None,
+			min: None,
+			max: None,
+		}]);
+		let input: TemplatedInput<Enumerated<LogLevel>> = "?level?".parse().unwrap();
+		for (answer, ok) in [("trace", true), ("everything", false)] {
+			let responses = vec![PromptResponse {
+				template: "level".into(),
+				input: Input::String(answer.into()),
+			}]
+			.into();
+			assert_eq!(input.output(&globals, &prompts, &responses).is_ok(), ok);
+		}
+
+		assert!(serde_json::from_str::<Enumerated<LogLevel>>("\"trace\"").is_ok());
+		assert!(serde_json::from_str::<Enumerated<LogLevel>>("\"loud\"").is_err());
+	}
+
+	#[test]
+	fn integer_widths() {
+		let globals = Global::default();
+		let prompts = PromptCollection::default();
+		let responses = Default::default();
+
+		let input: TemplatedInput<u32> = serde_json::from_str("\"4000000000\"").unwrap();
+		assert_eq!(serde_json::to_string(&input).unwrap(), "\"4000000000\"");
+		assert_eq!(
+			input.output(&globals, &prompts, &responses).unwrap(),
+			4_000_000_000
+		);
+
+		let input: TemplatedInput<u32> = serde_json::from_str("\"-1\"").unwrap();
+		assert!(input.output(&globals, &prompts, &responses).is_err());
+
+		let input: TemplatedInput<i32> = serde_json::from_str("\"-1\"").unwrap();
+		assert_eq!(input.output(&globals, &prompts, &responses).unwrap(), -1);
+
+		let input: TemplatedInput<u8> = serde_json::from_str("\"256\"").unwrap();
+		assert!(input.output(&globals, &prompts, &responses).is_err());
+
+		assert_eq!(
+			TemplatedInput::<u32>::default()
+				.output(&globals, &prompts, &responses)
+				.unwrap(),
+			0
+		);
+		assert_eq!(
+			TemplatedInput::<i32>::default()
+				.output(&globals, &prompts, &responses)
+				.unwrap(),
+			0
+		);
+		assert_eq!(
+			TemplatedInput::<u8>::default()
+				.output(&globals, &prompts, &responses)
+				.unwrap(),
+			0
+		);
+	}
+
+	#[test]
+	fn expecting() {
+		let err = |r: Result<(), serde_json::Error>| r.unwrap_err().to_string();
+
+		assert!(
+			err(serde_json::from_str::<TemplatedInput<u32>>("5").map(|_| ()))
+				.contains("parses as unsigned integer")
+		);
+		assert!(
+			err(serde_json::from_str::<TemplatedInput<i64>>("5").map(|_| ()))
+				.contains("parses as signed integer")
+		);
+		assert!(
+			err(serde_json::from_str::<TemplatedInput<bool>>("true").map(|_| ()))
+				.contains("parses as boolean")
+		);
+		assert!(
+			err(serde_json::from_str::<TemplatedInput<String>>("5").map(|_| ()))
+				.contains("parses as string")
+		);
+		assert!(
+			err(serde_json::from_str::<TemplatedInput<Enumerated<LogLevel>>>("5").map(|_| ()))
+				.contains("parses as one of a fixed set of strings")
+		);
+	}
+}
@@ -1,24 +1,32 @@
+mod cache;
 mod cli;
 mod client;
+mod commands;
 mod config;
+mod events;
 mod globals;
 mod grpc;
 mod input;
 mod package;
 mod prompt;
+mod secret;
 mod server;
 mod systemd;
 
 #[expect(dead_code)]
 pub(crate) mod qmp;
 
+pub use cache::*;
 pub use cli::*;
 pub use client::*;
+pub use commands::*;
 pub use config::*;
+pub use events::*;
 pub use globals::*;
 pub use grpc::*;
 pub use input::*;
 pub use package::*;
 pub use prompt::*;
+pub use secret::*;
 pub use server::*;
 pub use systemd::*;
@@ -1,15 +1,23 @@
 use crate::{
-	Config, Global, GlobalRegistry, PromptCollection, PromptResponses, ProtoLastRunState,
-	ProtoLoadState, ProtoPackageTitle, ProtoRuntimeState, ProtoStatus, ProtoUninstallData,
-	ResponseRegistry, SystemdUnit, TemplatedInput, proto_package_installed::ProtoInstallState,
+	Config, DEFAULT_SECRET_KEY_PATH, EventLog, Global, GlobalRegistry, InputType, PromptCollection,
+	PromptParser, PromptResponses, ProtoConsistencyReport, ProtoInstallPlan, ProtoLastRunState,
+	ProtoLoadState, ProtoPackageInstalled, ProtoPackageRuntimeStatus, ProtoPackageTitle,
+	ProtoPlannedStorage, ProtoPortMapping, ProtoPortReachability, ProtoRuntimeState, ProtoStatus,
+	ProtoUninstallData, ResponseRegistry, SecretRegistry, SystemdUnit, TemplatedInput,
+	command_paths, generate_command, package_from_description,
+	proto_package_installed::ProtoInstallState, unit_files, validate_path, validate_registry_name,
 };
 use anyhow::{Result, anyhow};
 use buckle::{
-	client::{Dataset as ZfsDataset, Volume as ZfsVolume},
+	client::{Dataset as ZfsDataset, Volume as ZfsVolume, ZFSKind},
 	systemd::{LastRunState, LoadState, RuntimeState},
 };
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Component, Path, PathBuf},
+	sync::{Arc, LazyLock},
+};
 
 //
 // something really important to understand about this code is that the TemplatedInput type is only
@@ -20,6 +28,84 @@ use std::path::{Path, PathBuf};
 
 pub(crate) const PACKAGE_SUBPATH: &str = "packages";
 pub(crate) const INSTALLED_SUBPATH: &str = "installed";
+const REACHABLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+// one lock per package name. provisions of the same package wait their turn, so the second sees
+// what the first created instead of racing it to create the same datasets.
+static PROVISIONING: LazyLock<std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+	LazyLock::new(Default::default);
+
+fn provision_lock(name: &str) -> Arc<tokio::sync::Mutex<()>> {
+	PROVISIONING
+		.lock()
+		.unwrap()
+		.entry(name.to_string())
+		.or_default()
+		.clone()
+}
+
+async fn storage_exists(client: &buckle::client::Client, name: &str) -> Result<bool> {
+	match client.zfs().await?.get(name.to_string()).await {
+		Ok(_) => Ok(true),
+		Err(e) if e.code() == tonic::Code::NotFound => Ok(false),
+		Err(e) => Err(e.into()),
+	}
+}
+
+// a dataset when volume is None or has a mountpoint, a zvol otherwise. returns false if the
+// storage was already there, or turned up while it was being created (f.e. from another charon):
+// that counts as success, but it isn't this caller's to undo. buckle quietly succeeds when asked
+// to create something that exists, so that has to be checked first.
+async fn create_storage(
+	client: &buckle::client::Client, name: &str, volume: Option<&CompiledVolume>,
+) -> Result<bool> {
+	if storage_exists(client, name).await? {
+		tracing::debug!("Storage '{}' already exists, skipping", name);
+		return Ok(false);
+	}
+
+	let res = match volume {
+		Some(volume) if volume.mountpoint.is_none() => {
+			client
+				.zfs()
+				.await?
+				.create_volume(ZfsVolume {
+					name: name.to_string(),
+					size: volume.size,
+				})
+				.await
+		}
+		_ => {
+			client
+				.zfs()
+				.await?
+				.create_dataset(ZfsDataset {
+					name: name.to_string(),
+					// the package's own dataset only holds its volumes, which have their own quotas;
+					// buckle's default quota would otherwise cap all of them together
+					quota: Some(volume.map(|x| x.size).unwrap_or(0)),
+					properties: Default::default(),
+				})
+				.await
+		}
+	};
+
+	match res {
+		Ok(()) => Ok(true),
+		Err(e) => {
+			if storage_exists(client, name).await? {
+				tracing::debug!("Storage '{}' was created elsewhere, skipping", name);
+				Ok(false)
+			} else {
+				Err(e.into())
+			}
+		}
+	}
+}
+
+// the only files a package archive may contain; see Registry::import_archive
+const ARCHIVE_PACKAGE: &str = "package.json";
+const ARCHIVE_VARIABLES: &str = "variables.json";
 
 #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SourcePackage {
@@ -40,6 +126,9 @@ pub struct SourcePackage {
 	pub prompts: Option<PromptCollection>,
 	#[serde(skip)]
 	pub root: Option<std::path::PathBuf>,
+	// where the host secret for this package's secrets lives, if not DEFAULT_SECRET_KEY_PATH
+	#[serde(skip)]
+	pub secret_key: Option<std::path::PathBuf>,
 }
 
 impl PartialOrd for SourcePackage {
@@ -64,6 +153,9 @@ impl SourcePackage {
 			version
 		);
 
+		validate_registry_name(name)?;
+		validate_registry_name(version)?;
+
 		let pb = root
 			.join(PACKAGE_SUBPATH)
 			.join(name)
@@ -118,9 +210,28 @@ impl SourcePackage {
 	}
 
 	#[inline]
+	pub fn secret_registry(&self) -> Result<SecretRegistry> {
+		if self.root.is_none() {
+			return Err(anyhow!(
+				"source package does not contain registry information, cannot find secrets"
+			));
+		}
+
+		Ok(SecretRegistry::new(
+			self.root.clone().unwrap(),
+			self.secret_key
+				.clone()
+				.unwrap_or(DEFAULT_SECRET_KEY_PATH.into()),
+		))
+	}
+
 	pub fn set_responses(&self, responses: &PromptResponses) -> Result<()> {
 		tracing::debug!("Setting responses for package: {}", self.title.name,);
-		self.response_registry()?.set(&self.title.name, responses)
+		let (plain, secrets) = responses.clone().split_secrets();
+		if !secrets.0.is_empty() {
+			self.secret_registry()?.merge(&self.title.name, &secrets)?;
+		}
+		self.response_registry()?.set(&self.title.name, &plain)
 	}
 
 	#[inline]
@@ -133,9 +244,29 @@ impl SourcePackage {
 
 		let globals = self.globals().unwrap_or_default();
 		let prompts = self.prompts.clone().unwrap_or_default();
-		let responses = self.responses().unwrap_or_default();
+		let mut responses = self.responses().unwrap_or_default();
+		// only packages that ask for secrets ever touch the host secret
+		if prompts.0.iter().any(|x| x.input_type == InputType::Secret) {
+			responses
+				.0
+				.extend(self.secret_registry()?.get(&self.title.name)?.0);
+		}
 
-		Ok(CompiledPackage {
+		let missing = prompts
+			.0
+			.iter()
+			.filter(|x| x.required && !responses.0.iter().any(|r| r.template == x.template))
+			.map(|x| x.template.as_str())
+			.collect::<Vec<&str>>();
+		if !missing.is_empty() {
+			return Err(anyhow!(
+				"Package {} needs responses to: {}",
+				self.title,
+				missing.join(", ")
+			));
+		}
+
+		let package = CompiledPackage {
 			root: self.root.clone().unwrap_or_default(),
 			title: self.title.clone(),
 			description: self.description.clone(),
@@ -146,11 +277,12 @@ impl SourcePackage {
 				.clone()
 				.unwrap_or_default()
 				.compile(&globals, &prompts, &responses)?,
-			storage: self
-				.storage
-				.clone()
-				.unwrap_or_default()
-				.compile(&globals, &prompts, &responses)?,
+			storage: self.storage.clone().unwrap_or_default().compile(
+				&self.title.name,
+				&globals,
+				&prompts,
+				&responses,
+			)?,
 			system: self
 				.system
 				.clone()
@@ -161,7 +293,10 @@ impl SourcePackage {
 				.clone()
 				.unwrap_or_default()
 				.compile(&globals, &prompts, &responses)?,
-		})
+		};
+
+		package.validate()?;
+		Ok(package)
 	}
 
 	pub fn dependencies(&self) -> Result<Vec<SourcePackage>> {
@@ -172,13 +307,16 @@ impl SourcePackage {
 			));
 		}
 
+		let mut registry = Registry::new(self.root.clone().unwrap());
+		if let Some(key) = &self.secret_key {
+			registry = registry.with_secret_key(key.clone());
+		}
 		let mut v = Vec::new();
 
 		for item in self.dependencies.clone().unwrap_or_default() {
-			v.push(Self::from_file(
-				self.root.clone().unwrap().as_path(),
+			v.push(registry.load(
 				&item.name,
-				&item.version,
+				&registry.resolve_version(&item.name, &item.version)?,
 			)?)
 		}
 
@@ -186,6 +324,14 @@ impl SourcePackage {
 	}
 }
 
+// what storage is being provisioned for. reconfiguring or rolling back a package repairs its
+// storage, and must not throw away the data in its recreate volumes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ProvisionMode {
+	Install,
+	Repair,
+}
+
 #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct CompiledPackage {
 	pub title: PackageTitle,
@@ -200,6 +346,30 @@ pub struct CompiledPackage {
 	root: PathBuf,
 }
 
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PortReachability {
+	pub port: u16,
+	pub reachable: bool,
+}
+
+impl From<PortReachability> for ProtoPortReachability {
+	fn from(value: PortReachability) -> Self {
+		Self {
+			port: value.port.into(),
+			reachable: value.reachable,
+		}
+	}
+}
+
+impl From<ProtoPortReachability> for PortReachability {
+	fn from(value: ProtoPortReachability) -> Self {
+		Self {
+			port: value.port as u16,
+			reachable: value.reachable,
+		}
+	}
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum InstallStatus {
 	Installed(buckle::systemd::Status),
@@ -286,10 +456,64 @@ impl CompiledPackage {
 	}
 
 	fn installed_path(&self) -> PathBuf {
-		self.root
-			.join(INSTALLED_SUBPATH)
-			.join(&self.title.name)
-			.join(&self.title.version)
+		self.title.installed_path(&self.root)
+	}
+
+	// settings that podman would quietly let one win over the other
+	pub fn validate(&self) -> Result<()> {
+		self.storage.validate()?;
+		self.resources.validate()?;
+
+		if self.system.host_net
+			&& let Some(network) = &self.networking.internal_network
+		{
+			return Err(anyhow!(
+				"Package {} uses the host's network, so it can't also join internal network '{}'",
+				self.title,
+				network
+			));
+		}
+
+		if self.system.privileged && !self.system.capabilities.is_empty() {
+			return Err(anyhow!(
+				"Package {} is privileged, which already grants the capabilities it adds: {}",
+				self.title,
+				self.system.capabilities.join(", ")
+			));
+		}
+
+		// qemu won't start with two hostfwd rules for one port, and podman won't either
+		let mut ports = HashSet::new();
+		if let Some(port) = self.host_ports().find(|x| !ports.insert(*x)) {
+			return Err(anyhow!(
+				"Package {} maps host port {} more than once",
+				self.title,
+				port
+			));
+		}
+
+		Ok(())
+	}
+
+	// forwarded and exposed alike, these are bound on the host. so is a VM's VNC display.
+	pub fn host_ports(&self) -> impl Iterator<Item = u16> + '_ {
+		let vnc = match self.source {
+			CompiledSource::QEmu(_) => self.resources.vnc.as_ref().and_then(|x| x.port()),
+			CompiledSource::Container(_) => None,
+		};
+
+		self.networking
+			.forward_ports
+			.iter()
+			.chain(&self.networking.expose_ports)
+			.map(|(host, _)| *host)
+			.chain(vnc)
+	}
+
+	// the first host port both packages would bind, if any
+	pub fn conflicting_port(&self, other: &Self) -> Option<u16> {
+		self.host_ports()
+			.find(|x| other.host_ports().any(|y| y == *x))
 	}
 
 	pub async fn install(&self) -> Result<()> {
@@ -314,54 +538,258 @@ impl CompiledPackage {
 	}
 
 	pub async fn installed(&self) -> Result<InstallStatus> {
-		if std::fs::exists(self.installed_path())? {
-			let client = buckle::systemd::Systemd::new_system().await?;
-			let path = client.get_unit(format!("{}.service", self.title)).await?;
-			let status = client.status(path).await?;
-			Ok(InstallStatus::Installed(status))
-		} else {
-			Ok(InstallStatus::NotInstalled)
+		self.title.installed(&self.root).await
+	}
+
+	// tries a TCP connection to every host port the package forwards or exposes. a port that
+	// doesn't answer within REACHABLE_TIMEOUT is reported as unreachable.
+	pub async fn check_reachable(&self) -> Vec<PortReachability> {
+		let mut ports = self
+			.networking
+			.forward_ports
+			.iter()
+			.chain(self.networking.expose_ports.iter())
+			.map(|(host, _)| *host)
+			.collect::<Vec<u16>>();
+		ports.sort();
+		ports.dedup();
+
+		let mut v = Vec::with_capacity(ports.len());
+		for port in ports {
+			let reachable = matches!(
+				tokio::time::timeout(
+					REACHABLE_TIMEOUT,
+					tokio::net::TcpStream::connect(("127.0.0.1", port))
+				)
+				.await,
+				Ok(Ok(_))
+			);
+
+			v.push(PortReachability { port, reachable });
 		}
+
+		v
 	}
 
-	pub async fn provision(&self, buckle_socket: &Path) -> Result<()> {
+	// provisioning can be re-run to repair a package: storage that already exists in a compatible
+	// form is left alone and only what is missing gets created. on an install, volumes marked
+	// recreate are destroyed and created empty again instead. the names of what didn't exist before
+	// are returned, so that a failed install can undo just that with unprovision(); recreated
+	// storage isn't among them, since other versions may be using it.
+	pub async fn provision(
+		&self, buckle_socket: &Path, mode: ProvisionMode,
+	) -> Result<Vec<String>> {
 		tracing::debug!("Provisioning package: {}", self.title.name);
+		let lock = provision_lock(&self.title.name);
+		let _guard = lock.lock().await;
 		let client = buckle::client::Client::new(buckle_socket.to_path_buf())?;
 
-		client
+		let existing = client
 			.zfs()
 			.await?
-			.create_dataset(ZfsDataset {
-				name: self.title.name.clone(),
-				quota: None,
-			})
+			.list(Some(self.title.name.clone()))
 			.await?;
+		let find = |name: &str| existing.iter().find(|x| x.name == name);
+
+		let mut missing = Vec::new();
+		let mut recreate = Vec::new();
 
 		for volume in &self.storage.volumes {
-			if volume.mountpoint.is_some() {
-				client
-					.zfs()
-					.await?
-					.create_dataset(ZfsDataset {
-						name: format!("{}/{}", self.title.name, volume.name),
-						quota: Some(volume.size),
-					})
-					.await?;
+			let name = format!("{}/{}", self.title.name, volume.name);
+			let kind = if volume.mountpoint.is_some() {
+				ZFSKind::Dataset
 			} else {
-				client
-					.zfs()
-					.await?
-					.create_volume(ZfsVolume {
-						name: format!("{}/{}", self.title.name, volume.name),
-						size: volume.size,
-					})
-					.await?;
+				ZFSKind::Volume
+			};
+
+			match find(&name) {
+				// whatever shape it has now doesn't matter, it's replaced
+				Some(_) if volume.recreate && mode == ProvisionMode::Install => {
+					recreate.push(volume)
+				}
+				Some(stat) if stat.kind != kind || stat.size < volume.size => {
+					return Err(anyhow!(
+						"Storage '{}' for package {} already exists, but does not match the package (expected {:?} of {} bytes, found {:?} of {} bytes)",
+						name,
+						self.title,
+						kind,
+						volume.size,
+						stat.kind,
+						stat.size
+					));
+				}
+				Some(_) => {
+					tracing::debug!("Storage '{}' already exists, skipping", name);
+				}
+				None => missing.push(volume),
+			}
+		}
+
+		// check up front, otherwise running out of space fails halfway through with some
+		// datasets already created.
+		let required = missing.iter().map(|x| x.size).sum::<u64>();
+		let pool = client.zfs().await?.pool_status().await?;
+		if required > pool.avail {
+			return Err(anyhow!(
+				"Package {} requires {} bytes of storage, but pool '{}' only has {} bytes free",
+				self.title,
+				required,
+				pool.name,
+				pool.avail
+			));
+		}
+
+		if !recreate.is_empty() {
+			self.check_not_running(&client).await?;
+		}
+
+		let mut created = Vec::new();
+		let result: Result<()> = async {
+			if find(&self.title.name).is_none()
+				&& create_storage(&client, &self.title.name, None).await?
+			{
+				created.push(self.title.name.clone());
+			}
+
+			for volume in recreate {
+				let name = format!("{}/{}", self.title.name, volume.name);
+				tracing::info!("Recreating storage '{}' for package {}", name, self.title);
+				client.zfs().await?.destroy(name.clone()).await?;
+				// by now the old data is gone, and a failed install won't clean this up since it
+				// isn't in created, so the error has to say so
+				create_storage(&client, &name, Some(volume))
+					.await
+					.map_err(|e| {
+						anyhow!(
+							"Storage '{}' of package {} was destroyed to be recreated, but could not be created again; its old data is gone and the package has no such volume now: {}",
+							name,
+							self.title,
+							e
+						)
+					})?;
+			}
+
+			for volume in missing {
+				let name = format!("{}/{}", self.title.name, volume.name);
+				if create_storage(&client, &name, Some(volume)).await? {
+					created.push(name);
+				}
+			}
+
+			Ok(())
+		}
+		.await;
+
+		// don't leave some of the package's storage behind
+		if let Err(e) = result {
+			if let Err(e) = self.unprovision(buckle_socket, &created).await {
+				tracing::error!("Could not remove storage for {}: {}", self.title, e);
 			}
+			return Err(e);
+		}
+
+		Ok(created)
+	}
+
+	// storage is shared between the package's versions, so a unit for any of them could be using
+	// it.
+	async fn check_not_running(&self, client: &buckle::client::Client) -> Result<()> {
+		let prefix = format!("{}-", self.title.name);
+		let running = client
+			.systemd()
+			.await?
+			.list(Some(prefix.clone()))
+			.await?
+			.into_iter()
+			// the version keeps other packages whose names start with this one's out
+			.filter(|x| {
+				x.name.strip_prefix(&prefix).is_some_and(|rest| {
+					rest.starts_with(|c: char| c.is_ascii_digit()) && rest.ends_with(".service")
+				})
+			})
+			.filter(|x| {
+				!matches!(
+					x.status.last_run_state,
+					LastRunState::Dead | LastRunState::Failed | LastRunState::Exited
+				)
+			})
+			.map(|x| x.name)
+			.collect::<Vec<String>>();
+
+		if !running.is_empty() {
+			return Err(anyhow!(
+				"Storage for package {} can't be recreated while {} is running",
+				self.title,
+				running.join(", ")
+			));
 		}
 
 		Ok(())
 	}
 
+	// destroys what provision() created, newest first so volumes go before the dataset they are in
+	pub async fn unprovision(&self, buckle_socket: &Path, created: &[String]) -> Result<()> {
+		tracing::debug!(
+			"Removing provisioned storage for package: {}",
+			self.title.name
+		);
+		let client = buckle::client::Client::new(buckle_socket.to_path_buf())?;
+		for name in created.iter().rev() {
+			client.zfs().await?.destroy(name.clone()).await?;
+		}
+		Ok(())
+	}
+
+	// what install would do with this package, for someone to look over first. buckle is only
+	// asked which storage already exists.
+	pub async fn plan(
+		&self, buckle_socket: &Path, volume_root: &Path, unit_file: PathBuf,
+	) -> Result<InstallPlan> {
+		let client = buckle::client::Client::new(buckle_socket.to_path_buf())?;
+		let existing = client
+			.zfs()
+			.await?
+			.list(Some(self.title.name.clone()))
+			.await?;
+
+		let storage = self
+			.storage
+			.volumes
+			.iter()
+			.map(|volume| {
+				let name = format!("{}/{}", self.title.name, volume.name);
+				PlannedStorage {
+					exists: existing.iter().any(|x| x.name == name),
+					name,
+					dataset: volume.mountpoint.is_some(),
+					size: volume.size,
+					mountpoint: volume.mountpoint.clone(),
+				}
+			})
+			.collect();
+
+		let (image, virtual_machine) = match &self.source {
+			CompiledSource::Container(image) => (image.clone(), false),
+			CompiledSource::QEmu(image) => (image.url().clone(), true),
+		};
+
+		Ok(InstallPlan {
+			title: self.title.clone(),
+			dependencies: self.dependencies.clone(),
+			image,
+			virtual_machine,
+			storage,
+			forward_ports: self.networking.forward_ports.clone(),
+			expose_ports: self.networking.expose_ports.clone(),
+			host_net: self.system.host_net,
+			host_pid: self.system.host_pid,
+			privileged: self.system.privileged,
+			capabilities: self.system.capabilities.clone(),
+			unit_file,
+			command: generate_command(self.clone(), volume_root.to_path_buf())?,
+		})
+	}
+
 	async fn destroy_volumes(&self, buckle_socket: &Path) -> Result<()> {
 		tracing::debug!("Destroying volumes for package: {}", self.title.name);
 		let client = buckle::client::Client::new(buckle_socket.to_path_buf())?;
@@ -376,6 +804,25 @@ impl CompiledPackage {
 		Ok(())
 	}
 
+	// the router mappings buckle made for this package, whatever ports the package declares now
+	async fn remove_ports(&self, buckle_socket: &Path) -> Result<()> {
+		let client = buckle::client::Client::new(buckle_socket.to_path_buf())?;
+		let mut network = client.network().await?;
+		let name = self.title.to_string();
+
+		for forward in network.list_ports().await? {
+			if forward.name == name {
+				tracing::debug!(
+					"Removing port {} for package: {}",
+					forward.port,
+					self.title.name
+				);
+				network.remove_port(forward.port, forward.protocol).await?;
+			}
+		}
+		Ok(())
+	}
+
 	pub async fn deprovision(&self, buckle_socket: &Path) -> Result<()> {
 		tracing::debug!("Deprovisioning package: {}", self.title.name);
 		let client = buckle::client::Client::new(buckle_socket.to_path_buf())?;
@@ -385,6 +832,7 @@ impl CompiledPackage {
 		match client.systemd().await?.unit_info(unit_name.clone()).await {
 			Ok(status) => match status.status.last_run_state {
 				LastRunState::Dead | LastRunState::Failed | LastRunState::Exited => {
+					self.remove_ports(buckle_socket).await?;
 					self.destroy_volumes(buckle_socket).await?;
 				}
 				_ => {
@@ -396,32 +844,29 @@ impl CompiledPackage {
 					tokio::spawn(async move {
 						tokio::time::sleep(std::time::Duration::from_secs(5)).await;
 
-						let client = buckle::client::Client::new(socket.clone()).unwrap();
-
-						for (exposed, _) in &s.networking.expose_ports {
-							client
-								.network()
-								.await
-								.unwrap()
-								.unexpose_port(
-									*exposed,
-									buckle::upnp::Protocol::TCP,
-									s.title.to_string(),
-								)
-								.await
-								.unwrap();
-						}
-
+						s.remove_ports(&socket).await.unwrap();
 						s.destroy_volumes(&socket).await.unwrap();
 					});
 				}
 			},
-			_ => self.destroy_volumes(buckle_socket).await?,
+			_ => {
+				self.remove_ports(buckle_socket).await?;
+				self.destroy_volumes(buckle_socket).await?
+			}
 		}
 		Ok(())
 	}
 }
 
+impl From<PackageTitle> for ProtoPackageTitle {
+	fn from(value: PackageTitle) -> Self {
+		Self {
+			name: value.name,
+			version: value.version,
+		}
+	}
+}
+
 impl From<ProtoPackageTitle> for PackageTitle {
 	fn from(value: ProtoPackageTitle) -> Self {
 		Self {
@@ -431,6 +876,53 @@ impl From<ProtoPackageTitle> for PackageTitle {
 	}
 }
 
+// which of the installed packages a dataset or volume belongs to. a package's storage all lives
+// under a dataset named after it (`{name}` and `{name}/{volume}`), so only the first part of the
+// name is looked at. if several versions are installed, the newest is reported.
+pub fn dataset_owner(installed: &[PackageTitle], dataset: &str) -> Option<PackageTitle> {
+	let name = dataset.split('/').next()?;
+
+	installed
+		.iter()
+		.filter(|x| x.name == name)
+		.max_by(|a, b| compare_versions(&a.version, &b.version))
+		.cloned()
+}
+
+// orders versions the way people read them: dotted parts that are numbers compare as numbers, so
+// 1.10.0 comes after 1.9.0, and a pre-release (1.0.0-rc1) comes before its release.
+pub fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+	use std::cmp::Ordering;
+
+	let (a_core, a_pre) = a.split_once('-').map_or((a, None), |(x, y)| (x, Some(y)));
+	let (b_core, b_pre) = b.split_once('-').map_or((b, None), |(x, y)| (x, Some(y)));
+
+	let mut a_parts = a_core.split('.');
+	let mut b_parts = b_core.split('.');
+	loop {
+		let ord = match (a_parts.next(), b_parts.next()) {
+			(None, None) => break,
+			(Some(_), None) => Ordering::Greater,
+			(None, Some(_)) => Ordering::Less,
+			(Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+				(Ok(x), Ok(y)) => x.cmp(&y),
+				_ => x.cmp(y),
+			},
+		};
+
+		if ord != Ordering::Equal {
+			return ord;
+		}
+	}
+
+	match (a_pre, b_pre) {
+		(None, None) => Ordering::Equal,
+		(Some(_), None) => Ordering::Less,
+		(None, Some(_)) => Ordering::Greater,
+		(Some(x), Some(y)) => x.cmp(y),
+	}
+}
+
 #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct PackageTitle {
 	pub name: String,
@@ -441,6 +933,33 @@ impl PackageTitle {
 	pub fn format_volume(&self, root: &Path) -> PathBuf {
 		root.join(&self.name)
 	}
+
+	// both the name and the version end up in paths under the registry
+	pub fn validate(&self) -> Result<()> {
+		validate_registry_name(&self.name)?;
+		validate_registry_name(&self.version)
+	}
+
+	pub(crate) fn installed_path(&self, root: &Path) -> PathBuf {
+		root.join(INSTALLED_SUBPATH)
+			.join(&self.name)
+			.join(&self.version)
+	}
+
+	// status only needs the installed marker and the unit name, both of which come from the title,
+	// so this can be answered without loading or compiling the package.
+	pub async fn installed(&self, root: &Path) -> Result<InstallStatus> {
+		self.validate()?;
+
+		if std::fs::exists(self.installed_path(root))? {
+			let client = buckle::systemd::Systemd::new_system().await?;
+			let path = client.get_unit(format!("{}.service", self)).await?;
+			let status = client.status(path).await?;
+			Ok(InstallStatus::Installed(status))
+		} else {
+			Ok(InstallStatus::NotInstalled)
+		}
+	}
 }
 
 impl std::fmt::Display for PackageTitle {
@@ -473,52 +992,309 @@ pub struct PackageStatus {
 	pub installed: bool,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
-pub enum Source {
-	#[serde(rename = "qemu")]
-	QEmu(TemplatedInput<String>),
-	#[serde(rename = "container")]
-	Container(TemplatedInput<String>),
+// disagreements between the installed markers, the unit files charon wrote, and the units systemd
+// has loaded. an empty report means all three agree.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+	// installed, but the unit file is gone
+	pub missing_unit_files: Vec<PackageTitle>,
+	// installed, but systemd doesn't have the unit loaded
+	pub missing_systemd_units: Vec<PackageTitle>,
+	// unit files for packages that aren't installed
+	pub orphaned_unit_files: Vec<PackageTitle>,
+	// units systemd has loaded for packages that aren't installed
+	pub orphaned_systemd_units: Vec<PackageTitle>,
 }
 
-impl Default for Source {
-	#[inline]
-	fn default() -> Self {
-		Self::Container("scratch".parse().unwrap())
+impl ConsistencyReport {
+	pub fn new(
+		installed: &[PackageTitle], unit_files: &[PackageTitle], systemd_units: &[PackageTitle],
+	) -> Self {
+		let missing = |from: &[PackageTitle], within: &[PackageTitle]| {
+			let mut v = from
+				.iter()
+				.filter(|x| !within.contains(x))
+				.cloned()
+				.collect::<Vec<PackageTitle>>();
+			v.sort();
+			v.dedup();
+			v
+		};
+
+		Self {
+			missing_unit_files: missing(installed, unit_files),
+			missing_systemd_units: missing(installed, systemd_units),
+			orphaned_unit_files: missing(unit_files, installed),
+			orphaned_systemd_units: missing(systemd_units, installed),
+		}
 	}
-}
 
-impl Source {
-	pub fn compile(
-		&self, globals: &Global, prompts: &PromptCollection, responses: &PromptResponses,
-	) -> Result<CompiledSource> {
-		tracing::debug!("Compiling package source subsection");
-		Ok(match self {
-			Self::QEmu(x) => CompiledSource::QEmu(x.output(globals, prompts, responses)?),
-			Self::Container(x) => CompiledSource::Container(x.output(globals, prompts, responses)?),
-		})
+	pub fn is_consistent(&self) -> bool {
+		self == &Self::default()
 	}
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
-pub enum CompiledSource {
-	#[serde(rename = "qemu")]
-	QEmu(String),
-	#[serde(rename = "container")]
-	Container(String),
+impl From<ConsistencyReport> for ProtoConsistencyReport {
+	fn from(value: ConsistencyReport) -> Self {
+		let titles = |v: Vec<PackageTitle>| v.into_iter().map(Into::into).collect();
+
+		Self {
+			missing_unit_files: titles(value.missing_unit_files),
+			missing_systemd_units: titles(value.missing_systemd_units),
+			orphaned_unit_files: titles(value.orphaned_unit_files),
+			orphaned_systemd_units: titles(value.orphaned_systemd_units),
+		}
+	}
 }
 
-impl Default for CompiledSource {
-	#[inline]
-	fn default() -> Self {
-		Self::Container("scratch".parse().unwrap())
+impl From<ProtoConsistencyReport> for ConsistencyReport {
+	fn from(value: ProtoConsistencyReport) -> Self {
+		let titles = |v: Vec<ProtoPackageTitle>| v.into_iter().map(Into::into).collect();
+
+		Self {
+			missing_unit_files: titles(value.missing_unit_files),
+			missing_systemd_units: titles(value.missing_systemd_units),
+			orphaned_unit_files: titles(value.orphaned_unit_files),
+			orphaned_systemd_units: titles(value.orphaned_systemd_units),
+		}
 	}
 }
 
+// everything installing a package would do, worked out without doing any of it. see
+// CompiledPackage::plan.
 #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
-pub struct Networking {
+pub struct InstallPlan {
+	pub title: PackageTitle,
+	pub dependencies: Vec<PackageTitle>,
+	// the container image or VM image URL
+	pub image: String,
+	pub virtual_machine: bool,
+	pub storage: Vec<PlannedStorage>,
+	pub forward_ports: Vec<(u16, u16)>,
+	// these are also opened on the router with UPnP
+	pub expose_ports: Vec<(u16, u16)>,
+	pub host_net: bool,
+	pub host_pid: bool,
+	pub privileged: bool,
+	pub capabilities: Vec<String>,
+	pub unit_file: PathBuf,
+	pub command: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PlannedStorage {
+	pub name: String,
+	// a dataset when mounted, otherwise a block volume
+	pub dataset: bool,
+	pub size: u64,
 	#[serde(skip_serializing_if = "Option::is_none")]
-	pub forward_ports: Option<Vec<(TemplatedInput<u16>, TemplatedInput<u16>)>>,
+	pub mountpoint: Option<String>,
+	// already there, so install will leave it alone
+	pub exists: bool,
+}
+
+impl From<InstallPlan> for ProtoInstallPlan {
+	fn from(value: InstallPlan) -> Self {
+		let ports = |v: Vec<(u16, u16)>| {
+			v.into_iter()
+				.map(|(host, local)| ProtoPortMapping {
+					host: host.into(),
+					local: local.into(),
+				})
+				.collect()
+		};
+
+		Self {
+			title: Some(value.title.into()),
+			dependencies: value.dependencies.into_iter().map(Into::into).collect(),
+			image: value.image,
+			virtual_machine: value.virtual_machine,
+			storage: value
+				.storage
+				.into_iter()
+				.map(|x| ProtoPlannedStorage {
+					name: x.name,
+					dataset: x.dataset,
+					size: x.size,
+					mountpoint: x.mountpoint,
+					exists: x.exists,
+				})
+				.collect(),
+			forward_ports: ports(value.forward_ports),
+			expose_ports: ports(value.expose_ports),
+			host_net: value.host_net,
+			host_pid: value.host_pid,
+			privileged: value.privileged,
+			capabilities: value.capabilities,
+			unit_file: value.unit_file.to_string_lossy().to_string(),
+			command: value.command,
+		}
+	}
+}
+
+impl From<ProtoInstallPlan> for InstallPlan {
+	fn from(value: ProtoInstallPlan) -> Self {
+		let ports = |v: Vec<ProtoPortMapping>| {
+			v.into_iter()
+				.map(|x| (x.host as u16, x.local as u16))
+				.collect()
+		};
+
+		Self {
+			title: value.title.unwrap_or_default().into(),
+			dependencies: value.dependencies.into_iter().map(Into::into).collect(),
+			image: value.image,
+			virtual_machine: value.virtual_machine,
+			storage: value
+				.storage
+				.into_iter()
+				.map(|x| PlannedStorage {
+					name: x.name,
+					dataset: x.dataset,
+					size: x.size,
+					mountpoint: x.mountpoint,
+					exists: x.exists,
+				})
+				.collect(),
+			forward_ports: ports(value.forward_ports),
+			expose_ports: ports(value.expose_ports),
+			host_net: value.host_net,
+			host_pid: value.host_pid,
+			privileged: value.privileged,
+			capabilities: value.capabilities,
+			unit_file: value.unit_file.into(),
+			command: value.command,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PackageRuntimeStatus {
+	pub title: PackageTitle,
+	pub status: InstallStatus,
+}
+
+impl From<PackageRuntimeStatus> for ProtoPackageRuntimeStatus {
+	fn from(value: PackageRuntimeStatus) -> Self {
+		Self {
+			title: Some(ProtoPackageTitle {
+				name: value.title.name,
+				version: value.title.version,
+			}),
+			status: Some(ProtoPackageInstalled {
+				proto_install_state: Some(value.status.into()),
+			}),
+		}
+	}
+}
+
+impl From<ProtoPackageRuntimeStatus> for PackageRuntimeStatus {
+	fn from(value: ProtoPackageRuntimeStatus) -> Self {
+		Self {
+			title: value.title.unwrap_or_default().into(),
+			status: value
+				.status
+				.and_then(|x| x.proto_install_state)
+				.map(|x| x.into())
+				.unwrap_or(InstallStatus::NotInstalled),
+		}
+	}
+}
+
+// a VM image is usually just its url, but it can also carry the sha256 digest the download has to
+// match. the digest is written as hex.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VMImage<T> {
+	Url(T),
+	Verified { url: T, sha256: String },
+}
+
+impl<T> VMImage<T> {
+	pub fn url(&self) -> &T {
+		match self {
+			Self::Url(url) | Self::Verified { url, .. } => url,
+		}
+	}
+
+	pub fn sha256(&self) -> Option<&str> {
+		match self {
+			Self::Url(_) => None,
+			Self::Verified { sha256, .. } => Some(sha256),
+		}
+	}
+}
+
+impl VMImage<TemplatedInput<String>> {
+	pub fn compile(
+		&self, globals: &Global, prompts: &PromptCollection, responses: &PromptResponses,
+	) -> Result<VMImage<String>> {
+		let url = self.url().output(globals, prompts, responses)?;
+		Ok(match self.sha256() {
+			None => VMImage::Url(url),
+			Some(sha256) => {
+				if sha256.len() != 64 || !sha256.chars().all(|x| x.is_ascii_hexdigit()) {
+					return Err(anyhow!(
+						"sha256 for image {} is not a hex encoded sha256 digest",
+						url
+					));
+				}
+
+				VMImage::Verified {
+					url,
+					sha256: sha256.to_ascii_lowercase(),
+				}
+			}
+		})
+	}
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Source {
+	#[serde(rename = "qemu")]
+	QEmu(VMImage<TemplatedInput<String>>),
+	#[serde(rename = "container")]
+	Container(TemplatedInput<String>),
+}
+
+impl Default for Source {
+	#[inline]
+	fn default() -> Self {
+		Self::Container("scratch".parse().unwrap())
+	}
+}
+
+impl Source {
+	pub fn compile(
+		&self, globals: &Global, prompts: &PromptCollection, responses: &PromptResponses,
+	) -> Result<CompiledSource> {
+		tracing::debug!("Compiling package source subsection");
+		Ok(match self {
+			Self::QEmu(x) => CompiledSource::QEmu(x.compile(globals, prompts, responses)?),
+			Self::Container(x) => CompiledSource::Container(x.output(globals, prompts, responses)?),
+		})
+	}
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CompiledSource {
+	#[serde(rename = "qemu")]
+	QEmu(VMImage<String>),
+	#[serde(rename = "container")]
+	Container(String),
+}
+
+impl Default for CompiledSource {
+	#[inline]
+	fn default() -> Self {
+		Self::Container("scratch".parse().unwrap())
+	}
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Networking {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub forward_ports: Option<Vec<(TemplatedInput<u16>, TemplatedInput<u16>)>>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub expose_ports: Option<Vec<(TemplatedInput<u16>, TemplatedInput<u16>)>>,
 	#[serde(skip_serializing_if = "Option::is_none")]
@@ -604,15 +1380,18 @@ pub struct Storage {
 
 impl Storage {
 	pub fn compile(
-		&self, globals: &Global, prompts: &PromptCollection, responses: &PromptResponses,
+		&self, package: &str, globals: &Global, prompts: &PromptCollection,
+		responses: &PromptResponses,
 	) -> Result<CompiledStorage> {
 		tracing::debug!("Compiling package storage subsection");
 		let mut v = Vec::new();
 		for volume in &self.volumes {
-			v.push(volume.compile(globals, prompts, responses)?);
+			v.push(volume.compile(package, globals, prompts, responses)?);
 		}
 
-		Ok(CompiledStorage { volumes: v })
+		let storage = CompiledStorage { volumes: v };
+		storage.validate()?;
+		Ok(storage)
 	}
 }
 
@@ -621,6 +1400,51 @@ pub struct CompiledStorage {
 	pub volumes: Vec<CompiledVolume>,
 }
 
+impl CompiledStorage {
+	// mountpoints become bind mount targets inside the container; podman only complains about
+	// these at launch, so catch them here instead. names become the source of the mount, under
+	// the package's volume root, and may not leave it.
+	pub fn validate(&self) -> Result<()> {
+		let mut seen: HashMap<&Path, &str> = HashMap::default();
+
+		for volume in &self.volumes {
+			if volume.name.is_empty()
+				|| !Path::new(&volume.name)
+					.components()
+					.all(|x| matches!(x, Component::Normal(_)))
+			{
+				return Err(anyhow!(
+					"Volume name '{}' does not stay inside the package's volumes",
+					volume.name
+				));
+			}
+
+			if let Some(mountpoint) = &volume.mountpoint {
+				let path = Path::new(mountpoint);
+
+				if let Err(e) = validate_path(mountpoint) {
+					return Err(anyhow!(
+						"Mountpoint for volume '{}' is invalid: {}",
+						volume.name,
+						e
+					));
+				}
+
+				if let Some(other) = seen.insert(path, &volume.name) {
+					return Err(anyhow!(
+						"Volumes '{}' and '{}' have the same mountpoint '{}'",
+						other,
+						volume.name,
+						mountpoint
+					));
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
 #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Volume {
 	pub name: TemplatedInput<String>,
@@ -632,7 +1456,8 @@ pub struct Volume {
 
 impl Volume {
 	pub fn compile(
-		&self, globals: &Global, prompts: &PromptCollection, responses: &PromptResponses,
+		&self, package: &str, globals: &Global, prompts: &PromptCollection,
+		responses: &PromptResponses,
 	) -> Result<CompiledVolume> {
 		tracing::debug!(
 			"Compiling package storage subsection, volume: {}",
@@ -652,8 +1477,18 @@ impl Volume {
 			None
 		};
 
+		let mut name = self.name.output(globals, prompts, responses)?;
+		if self
+			.name
+			.prompts(prompts)?
+			.iter()
+			.any(|x| x.input_type == InputType::VolumeName)
+		{
+			name = format!("{}-{}", package, name);
+		}
+
 		Ok(CompiledVolume {
-			name: self.name.output(globals, prompts, responses)?,
+			name,
 			size: self.size.output(globals, prompts, responses)?,
 			mountpoint,
 			recreate: self.recreate.output(globals, prompts, responses)?,
@@ -679,6 +1514,9 @@ pub struct System {
 	pub host_net: TemplatedInput<bool>,
 	pub capabilities: Vec<TemplatedInput<String>>,
 	pub privileged: TemplatedInput<bool>,
+	// -e KEY=VALUE, containers only
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub environment: Option<Vec<(TemplatedInput<String>, TemplatedInput<String>)>>,
 }
 
 impl System {
@@ -693,11 +1531,36 @@ impl System {
 			capabilities.push(cap.output(globals, prompts, responses)?);
 		}
 
+		let mut environment = Vec::new();
+		if let Some(env) = &self.environment {
+			for (key, value) in env {
+				let key = key.output(globals, prompts, responses)?;
+				if key.is_empty() || key.contains('=') || key.contains('\n') {
+					return Err(anyhow!(
+						"Environment variable name '{}' is empty or contains '=' or a newline",
+						key
+					));
+				}
+
+				// the environment file podman reads it from has one variable per line
+				let value = value.output(globals, prompts, responses)?;
+				if value.contains('\n') {
+					return Err(anyhow!(
+						"Value of environment variable '{}' contains a newline",
+						key
+					));
+				}
+
+				environment.push((key, value));
+			}
+		}
+
 		Ok(CompiledSystem {
 			host_pid: self.host_pid.output(globals, prompts, responses)?,
 			host_net: self.host_net.output(globals, prompts, responses)?,
 			capabilities,
 			privileged: self.privileged.output(globals, prompts, responses)?,
+			environment,
 		})
 	}
 }
@@ -710,23 +1573,81 @@ pub struct CompiledSystem {
 	pub host_net: bool,
 	pub capabilities: Vec<String>,
 	pub privileged: bool,
+	#[serde(default)]
+	pub environment: Vec<(String, String)>,
 }
 
 #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Resources {
 	pub cpus: TemplatedInput<u64>,
+	// how a VM's cpus are laid out. any left out count as 1, and together they have to multiply
+	// out to cpus. with none of them, every cpu is a core of a single socket.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub sockets: Option<TemplatedInput<u64>>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cores: Option<TemplatedInput<u64>>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub threads: Option<TemplatedInput<u64>>,
 	pub memory: TemplatedInput<u64>,
+	// a console for VMs; containers ignore it
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub vnc: Option<Vnc>,
 	// probably something to bring in PCI devices to appease the crypto folks
 }
 
+// VNC displays count up from this TCP port
+pub const VNC_BASE_PORT: u16 = 5900;
+
+// without a display, VNC is only served on a socket in the package's volume root. a display
+// listens on TCP port 5900 + display, like QEMU's own -vnc :N, but only on the loopback
+// interface: there is no password on it.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Vnc {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub display: Option<TemplatedInput<u16>>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CompiledVnc {
+	pub display: Option<u16>,
+}
+
+impl CompiledVnc {
+	// the TCP port the display listens on, if it has one that fits
+	pub fn port(&self) -> Option<u16> {
+		self.display.and_then(|x| VNC_BASE_PORT.checked_add(x))
+	}
+}
+
 impl Resources {
 	pub fn compile(
 		&self, globals: &Global, prompts: &PromptCollection, responses: &PromptResponses,
 	) -> Result<CompiledResources> {
 		tracing::debug!("Compiling package resources subsection");
+		let vnc = match &self.vnc {
+			Some(vnc) => Some(CompiledVnc {
+				display: match &vnc.display {
+					Some(display) => Some(display.output(globals, prompts, responses)?),
+					None => None,
+				},
+			}),
+			None => None,
+		};
+
+		let output = |x: &Option<TemplatedInput<u64>>| -> Result<Option<u64>> {
+			Ok(match x {
+				Some(x) => Some(x.output(globals, prompts, responses)?),
+				None => None,
+			})
+		};
+
 		Ok(CompiledResources {
 			cpus: self.cpus.output(globals, prompts, responses)?,
+			sockets: output(&self.sockets)?,
+			cores: output(&self.cores)?,
+			threads: output(&self.threads)?,
 			memory: self.memory.output(globals, prompts, responses)?,
+			vnc,
 		})
 	}
 }
@@ -734,17 +1655,102 @@ impl Resources {
 #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct CompiledResources {
 	pub cpus: u64,
+	#[serde(default)]
+	pub sockets: Option<u64>,
+	#[serde(default)]
+	pub cores: Option<u64>,
+	#[serde(default)]
+	pub threads: Option<u64>,
 	pub memory: u64,
+	#[serde(default)]
+	pub vnc: Option<CompiledVnc>,
 	// probably something to bring in PCI devices to appease the crypto folks
 }
 
+impl CompiledResources {
+	fn has_topology(&self) -> bool {
+		self.sockets.is_some() || self.cores.is_some() || self.threads.is_some()
+	}
+
+	pub fn validate(&self) -> Result<()> {
+		if let Some(vnc) = &self.vnc
+			&& let Some(display) = vnc.display
+			&& vnc.port().is_none()
+		{
+			return Err(anyhow!(
+				"VNC display {} is out of range; the highest display is {}",
+				display,
+				u16::MAX - VNC_BASE_PORT
+			));
+		}
+
+		if !self.has_topology() {
+			return Ok(());
+		}
+
+		let (sockets, cores, threads) = (
+			self.sockets.unwrap_or(1),
+			self.cores.unwrap_or(1),
+			self.threads.unwrap_or(1),
+		);
+
+		if sockets == 0 || cores == 0 || threads == 0 {
+			return Err(anyhow!("Sockets, cores and threads must all be at least 1"));
+		}
+
+		if sockets
+			.checked_mul(cores)
+			.and_then(|x| x.checked_mul(threads))
+			!= Some(self.cpus)
+		{
+			return Err(anyhow!(
+				"{} sockets of {} cores with {} threads each is not the {} cpus asked for",
+				sockets,
+				cores,
+				threads,
+				self.cpus
+			));
+		}
+
+		Ok(())
+	}
+
+	// the argument to qemu's -smp
+	pub fn smp(&self) -> String {
+		if !self.has_topology() {
+			return format!(
+				"cpus={},cores={},maxcpus={}",
+				self.cpus, self.cpus, self.cpus
+			);
+		}
+
+		format!(
+			"cpus={},sockets={},cores={},threads={},maxcpus={}",
+			self.cpus,
+			self.sockets.unwrap_or(1),
+			self.cores.unwrap_or(1),
+			self.threads.unwrap_or(1),
+			self.cpus
+		)
+	}
+}
+
 pub struct Registry {
 	root: PathBuf,
+	secret_key: PathBuf,
 }
 
 impl Registry {
 	pub fn new(root: PathBuf) -> Self {
-		Self { root }
+		Self {
+			root,
+			secret_key: DEFAULT_SECRET_KEY_PATH.into(),
+		}
+	}
+
+	pub fn with_secret_key(mut self, secret_key: PathBuf) -> Self {
+		self.secret_key = secret_key;
+		self
 	}
 
 	pub fn path(&self) -> PathBuf {
@@ -808,6 +1814,116 @@ impl Registry {
 		Ok(v)
 	}
 
+	// every version of one package in the registry, newest first.
+	pub fn list_versions(&self, name: &str) -> Result<Vec<String>> {
+		validate_registry_name(name)?;
+
+		let mut v = Vec::new();
+
+		for item in std::fs::read_dir(self.root.join(PACKAGE_SUBPATH).join(name))? {
+			let path = item?.path();
+			if path.extension().is_some_and(|x| x == "json")
+				&& let Some(version) = path.file_stem().and_then(|x| x.to_str())
+			{
+				v.push(version.to_string());
+			}
+		}
+
+		v.sort_by(|a, b| compare_versions(b, a));
+		Ok(v)
+	}
+
+	// a dependency names either an exact version, or a semver requirement (`^0.0.1`,
+	// `>=1.2, <2.0`) that resolves to the highest version in the registry satisfying it. versions
+	// that are in the registry, or that are valid semver themselves, are taken as they are.
+	pub fn resolve_version(&self, name: &str, version: &str) -> Result<String> {
+		if self
+			.root
+			.join(PACKAGE_SUBPATH)
+			.join(name)
+			.join(format!("{}.json", version))
+			.exists() || semver::Version::parse(version).is_ok()
+		{
+			return Ok(version.to_string());
+		}
+
+		let Ok(req) = semver::VersionReq::parse(version) else {
+			return Ok(version.to_string());
+		};
+
+		self.list_versions(name)
+			.unwrap_or_default()
+			.into_iter()
+			.filter_map(|x| Some((semver::Version::parse(&x).ok()?, x)))
+			.filter(|(parsed, _)| req.matches(parsed))
+			.max_by(|a, b| a.0.cmp(&b.0))
+			.map(|(_, x)| x)
+			.ok_or_else(|| {
+				anyhow!(
+					"No version of {} in the registry satisfies '{}'",
+					name,
+					version
+				)
+			})
+	}
+
+	// like list(), but with the systemd status of anything installed. systemd's unit list is
+	// fetched once and matched by unit name, instead of asking about each package in turn.
+	pub async fn list_with_status(&self) -> Result<Vec<PackageRuntimeStatus>> {
+		let list = self.list()?;
+
+		let mut units = HashMap::new();
+		if list.iter().any(|x| x.installed) {
+			let client = buckle::systemd::Systemd::new_system().await?;
+			for unit in client.list(Some(".service".into())).await? {
+				units.insert(unit.name, unit.status);
+			}
+		}
+
+		Ok(list
+			.into_iter()
+			.map(|item| {
+				let status = if item.installed {
+					// list_units only reports units systemd has in memory; an installed package
+					// whose unit isn't loaded is simply not running.
+					InstallStatus::Installed(
+						units.remove(&format!("{}.service", item.title)).unwrap_or(
+							buckle::systemd::Status {
+								load_state: LoadState::Unloaded,
+								runtime_state: RuntimeState::Stopped,
+								last_run_state: LastRunState::Dead,
+							},
+						),
+					)
+				} else {
+					InstallStatus::NotInstalled
+				};
+
+				PackageRuntimeStatus {
+					title: item.title,
+					status,
+				}
+			})
+			.collect())
+	}
+
+	// cross-references what is marked installed with the unit files in systemd_root and the units
+	// systemd has loaded. see ConsistencyReport.
+	pub async fn check_consistency(&self, systemd_root: &Path) -> Result<ConsistencyReport> {
+		let installed = self.installed()?;
+		let files = unit_files(systemd_root)?;
+
+		let client = buckle::systemd::Systemd::new_system().await?;
+		let units = client
+			.list(Some(".service".into()))
+			.await?
+			.into_iter()
+			.filter_map(|x| package_from_description(&x.description))
+			.collect::<Vec<PackageTitle>>();
+
+		Ok(ConsistencyReport::new(&installed, &files, &units))
+	}
+
 	pub fn installed(&self) -> Result<Vec<PackageTitle>> {
 		let installed_path = self.root.join(INSTALLED_SUBPATH);
 		match std::fs::read_dir(&installed_path) {
@@ -857,11 +1973,61 @@ impl Registry {
 		}
 	}
 
+	pub fn dataset_owner(&self, dataset: &str) -> Result<Option<PackageTitle>> {
+		Ok(dataset_owner(&self.installed()?, dataset))
+	}
+
+	pub fn event_log(&self) -> EventLog {
+		EventLog::new(self.root.clone())
+	}
+
 	pub fn response_registry(&self) -> ResponseRegistry {
 		ResponseRegistry::new(self.root.clone())
 	}
 
+	pub fn secret_registry(&self) -> SecretRegistry {
+		SecretRegistry::new(self.root.clone(), self.secret_key.clone())
+	}
+
 	pub fn validate(&self, name: &str, version: &str) -> Result<()> {
+		self.validate_dependent(name, version, &mut Vec::new())
+	}
+
+	// every version in the registry that fails validate(), with the reason
+	pub fn invalid(&self) -> Result<Vec<(PackageTitle, anyhow::Error)>> {
+		Ok(self
+			.list()?
+			.into_iter()
+			.filter_map(|x| {
+				self.validate(&x.title.name, &x.title.version)
+					.err()
+					.map(|e| (x.title, e))
+			})
+			.collect())
+	}
+
+	// chain is the packages that led here through their dependencies, so a package depending on
+	// itself can be caught instead of recursing forever.
+	fn validate_dependent(
+		&self, name: &str, version: &str, chain: &mut Vec<PackageTitle>,
+	) -> Result<()> {
+		let title = PackageTitle {
+			name: name.to_string(),
+			version: version.to_string(),
+		};
+
+		if let Some(pos) = chain.iter().position(|x| *x == title) {
+			return Err(anyhow!(
+				"Dependency cycle: {} -> {}",
+				chain[pos..]
+					.iter()
+					.map(ToString::to_string)
+					.collect::<Vec<String>>()
+					.join(" -> "),
+				title
+			));
+		}
+
 		let package = self.load(name, version)?;
 
 		if package.title.name != name || package.title.version != version {
@@ -871,33 +2037,94 @@ impl Registry {
 		// validate we can load globals, but we don't need them
 		let _ = package.globals()?;
 
+		// catch defaults and responses that would only fail once the package is compiled to
+		// launch it
+		let prompts = package.prompts.clone().unwrap_or_default();
+		for prompt in &prompts.0 {
+			prompt.validate_pattern()?;
+			prompt.validate_default()?;
+		}
+
+		let parser = PromptParser(prompts);
+		for response in &package.responses().unwrap_or_default().0 {
+			parser.check_response(response)?;
+		}
+
 		let dependencies = package.dependencies.clone().unwrap_or_default();
 
 		// validate package dependencies exist
+		chain.push(title);
 		for item in &dependencies {
-			self.validate(&item.name, &item.version)?;
+			self.validate_dependent(
+				&item.name,
+				&self.resolve_version(&item.name, &item.version)?,
+				chain,
+			)?;
 		}
+		chain.pop();
 
 		Ok(())
 	}
 
-	pub fn remove(&self, name: &str) -> Result<()> {
-		Ok(std::fs::remove_dir_all(
-			self.root.join(PACKAGE_SUBPATH).join(name),
-		)?)
-	}
+	// removes one version of a package, along with the package's directory if no versions are
+	// left. an installed version is refused.
+	pub fn remove_version(&self, name: &str, version: &str) -> Result<()> {
+		validate_registry_name(name)?;
+		validate_registry_name(version)?;
 
-	pub fn load(&self, name: &str, version: &str) -> Result<SourcePackage> {
-		SourcePackage::from_file(&self.root, name, version)
-	}
+		let installed = self
+			.installed()?
+			.into_iter()
+			.filter(|x| x.name == name && x.version == version)
+			.collect::<Vec<PackageTitle>>();
 
-	pub fn write(&self, package: &SourcePackage) -> Result<()> {
-		let pb = self.root.join(PACKAGE_SUBPATH).join(&package.title.name);
-		std::fs::create_dir_all(&pb)?;
+		if !installed.is_empty() {
+			return Err(PackageInstalledError(installed).into());
+		}
 
-		let name = pb.join(format!("{}.json.tmp", package.title.version));
-		let f = std::fs::OpenOptions::new()
-			.create(true)
+		let dir = self.root.join(PACKAGE_SUBPATH).join(name);
+		std::fs::remove_file(dir.join(format!("{}.json", version)))?;
+
+		if std::fs::read_dir(&dir)?.next().is_none() {
+			std::fs::remove_dir(&dir)?;
+		}
+
+		Ok(())
+	}
+
+	// removes every version of a package. if any of them are installed this is refused, since their
+	// units launch from these definitions, unless force is set.
+	pub fn remove(&self, name: &str, force: bool) -> Result<()> {
+		validate_registry_name(name)?;
+
+		let installed = self
+			.installed()?
+			.into_iter()
+			.filter(|x| x.name == name)
+			.collect::<Vec<PackageTitle>>();
+
+		if !installed.is_empty() && !force {
+			return Err(PackageInstalledError(installed).into());
+		}
+
+		Ok(std::fs::remove_dir_all(
+			self.root.join(PACKAGE_SUBPATH).join(name),
+		)?)
+	}
+
+	pub fn load(&self, name: &str, version: &str) -> Result<SourcePackage> {
+		let mut pkg = SourcePackage::from_file(&self.root, name, version)?;
+		pkg.secret_key = Some(self.secret_key.clone());
+		Ok(pkg)
+	}
+
+	pub fn write(&self, package: &SourcePackage) -> Result<()> {
+		let pb = self.root.join(PACKAGE_SUBPATH).join(&package.title.name);
+		std::fs::create_dir_all(&pb)?;
+
+		let name = pb.join(format!("{}.json.tmp", package.title.version));
+		let f = std::fs::OpenOptions::new()
+			.create(true)
 			.truncate(true)
 			.write(true)
 			.open(&name)?;
@@ -914,8 +2141,161 @@ impl Registry {
 	pub fn globals(&self, package: &SourcePackage) -> Result<Global> {
 		package.globals()
 	}
+
+	// adds a package from a single tar archive holding package.json (the definition) and
+	// optionally variables.json (its globals), so a package can be handed around as one file.
+	// everything is checked before anything lands in the registry, and a version that is already
+	// there is refused rather than replaced.
+	pub fn import_archive(&self, archive: &Path) -> Result<PackageTitle> {
+		// look before extracting anything; tar writes wherever the member names point
+		let listing = std::process::Command::new(&command_paths().tar)
+			.arg("-tf")
+			.arg(archive)
+			.output()?;
+		if !listing.status.success() {
+			return Err(anyhow!(
+				"could not read archive {}: {}",
+				archive.display(),
+				String::from_utf8_lossy(&listing.stderr).trim()
+			));
+		}
+
+		let mut has_package = false;
+		for member in String::from_utf8_lossy(&listing.stdout).lines() {
+			match member.trim_start_matches("./") {
+				"" => {}
+				ARCHIVE_PACKAGE => has_package = true,
+				ARCHIVE_VARIABLES => {}
+				other => {
+					return Err(anyhow!(
+						"archive {} contains '{}'; only {} and {} are allowed",
+						archive.display(),
+						other,
+						ARCHIVE_PACKAGE,
+						ARCHIVE_VARIABLES
+					));
+				}
+			}
+		}
+
+		if !has_package {
+			return Err(anyhow!(
+				"archive {} has no {}",
+				archive.display(),
+				ARCHIVE_PACKAGE
+			));
+		}
+
+		// inside the registry, so the files can be renamed into place
+		std::fs::create_dir_all(&self.root)?;
+		let staging = tempfile::Builder::new()
+			.prefix(".import-")
+			.tempdir_in(&self.root)?;
+		let extract = std::process::Command::new(&command_paths().tar)
+			.arg("-xf")
+			.arg(archive)
+			.arg("-C")
+			.arg(staging.path())
+			.output()?;
+		if !extract.status.success() {
+			return Err(anyhow!(
+				"could not extract archive {}: {}",
+				archive.display(),
+				String::from_utf8_lossy(&extract.stderr).trim()
+			));
+		}
+
+		let package: SourcePackage = serde_json::from_reader(
+			std::fs::OpenOptions::new()
+				.read(true)
+				.open(staging.path().join(ARCHIVE_PACKAGE))?,
+		)
+		.map_err(|e| anyhow!("Error parsing JSON in {}: {}", ARCHIVE_PACKAGE, e))?;
+		let title = package.title.clone();
+
+		validate_registry_name(&title.name)?;
+		validate_registry_name(&title.version)?;
+
+		if self.load(&title.name, &title.version).is_ok() {
+			return Err(anyhow!("{} is already in the registry", title));
+		}
+
+		for item in package.dependencies.clone().unwrap_or_default() {
+			self.resolve_version(&item.name, &item.version)
+				.and_then(|version| self.validate(&item.name, &version))
+				.map_err(|e| anyhow!("{} depends on {}, which is unusable: {}", title, item, e))?;
+		}
+
+		let global = match std::fs::OpenOptions::new()
+			.read(true)
+			.open(staging.path().join(ARCHIVE_VARIABLES))
+		{
+			Ok(f) => {
+				let global: Global = serde_json::from_reader(f)
+					.map_err(|e| anyhow!("Error parsing JSON in {}: {}", ARCHIVE_VARIABLES, e))?;
+				if global.name != title.name {
+					return Err(anyhow!(
+						"{} is for '{}', not '{}'",
+						ARCHIVE_VARIABLES,
+						global.name,
+						title.name
+					));
+				}
+				Some(global)
+			}
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+			Err(e) => return Err(e.into()),
+		};
+
+		// globals go first, since a package can't be loaded without them. if the package can't be
+		// written afterwards, the globals that were there before are put back.
+		let globals = GlobalRegistry::new(self.root.clone());
+		let previous = globals.get(&title.name).ok();
+		match &global {
+			Some(global) => globals.set(global)?,
+			None if previous.is_none() => globals.set(&Global {
+				name: title.name.clone(),
+				..Default::default()
+			})?,
+			None => {}
+		}
+
+		if let Err(e) = self.write(&package) {
+			match &previous {
+				Some(previous) => {
+					let _ = globals.set(previous);
+				}
+				None => {
+					let _ = globals.remove(&title.name);
+				}
+			}
+			return Err(e);
+		}
+
+		Ok(title)
+	}
+}
+
+// returned when removing something from the registry would orphan these installed packages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageInstalledError(pub Vec<PackageTitle>);
+
+impl std::fmt::Display for PackageInstalledError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"uninstall these first: {}",
+			self.0
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<String>>()
+				.join(", ")
+		)
+	}
 }
 
+impl std::error::Error for PackageInstalledError {}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UninstallData {
 	pub name: String,
@@ -936,9 +2316,46 @@ impl From<ProtoUninstallData> for UninstallData {
 #[cfg(test)]
 mod tests {
 	use crate::{
-		CompiledPackage, Global, GlobalRegistry, PackageTitle, Registry, SourcePackage, Variables,
+		CompiledNetworking, CompiledPackage, CompiledResources, CompiledSource, CompiledStorage,
+		CompiledVnc, CompiledVolume, ConsistencyReport, Global, GlobalRegistry, Input, InputType,
+		InstallStatus, PackageInstalledError, PackageTitle, PortReachability, Prompt,
+		PromptCollection, PromptResponse, PromptResponses, Registry, Source, SourcePackage,
+		Storage, VMImage, Variables, Volume, compare_versions,
 	};
 
+	#[tokio::test]
+	async fn check_reachable() {
+		let open = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let open_port = open.local_addr().unwrap().port();
+		let closed_port = {
+			let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+			closed.local_addr().unwrap().port()
+		};
+
+		let pkg = CompiledPackage {
+			networking: CompiledNetworking {
+				forward_ports: vec![(open_port, 80)],
+				expose_ports: vec![(closed_port, 81), (open_port, 80)],
+				..Default::default()
+			},
+			..Default::default()
+		};
+
+		let mut expected = vec![
+			PortReachability {
+				port: open_port,
+				reachable: true,
+			},
+			PortReachability {
+				port: closed_port,
+				reachable: false,
+			},
+		];
+		expected.sort_by_key(|x| x.port);
+
+		assert_eq!(pkg.check_reachable().await, expected);
+	}
+
 	#[test]
 	fn dependencies() {
 		let registry = Registry::new("testdata/registry".into());
@@ -947,6 +2364,63 @@ mod tests {
 		let deps = pkg.dependencies().unwrap();
 
 		assert_eq!(deps, vec![plex]);
+
+		// a requirement picks the highest version that satisfies it
+		let pkg = registry.load("with-dependencies", "0.0.2").unwrap();
+		let podman = registry.load("podman-test", "0.0.2").unwrap();
+		assert_eq!(pkg.dependencies().unwrap(), vec![podman]);
+	}
+
+	#[test]
+	fn from_file_traversal() {
+		let root = std::path::Path::new("testdata/registry");
+		assert!(SourcePackage::from_file(root, "plex", "0.0.1").is_ok());
+
+		// each of these would otherwise find a real package definition
+		for (name, version) in [
+			("../packages/plex", "0.0.1"),
+			("plex/../plex", "0.0.1"),
+			("plex", "../plex/0.0.1"),
+			("/etc/passwd", "0.0.1"),
+			("plex", "/etc/passwd"),
+		] {
+			let err = SourcePackage::from_file(root, name, version).unwrap_err();
+			assert!(err.to_string().contains("not a valid name"), "{}", err);
+		}
+	}
+
+	#[test]
+	fn resolve_version() {
+		let registry = Registry::new("testdata/registry".into());
+
+		let table = [
+			("plex", "0.0.2", "0.0.2"),
+			// exact, even if it isn't there; loading it is what fails
+			("plex", "0.0.3", "0.0.3"),
+			("podman-test", "*", "0.0.3"),
+			("podman-test", ">=0.0.1", "0.0.3"),
+			("podman-test", ">=0.0.1, <0.0.3", "0.0.2"),
+			// carets on 0.0.x only match that version
+			("podman-test", "^0.0.1", "0.0.1"),
+			("podman-test", "=0.0.2", "0.0.2"),
+		];
+
+		for (name, version, resolved) in table {
+			assert_eq!(
+				registry.resolve_version(name, version).unwrap(),
+				resolved,
+				"{} {}",
+				name,
+				version
+			);
+		}
+
+		let err = registry
+			.resolve_version("plex", "^1.0")
+			.unwrap_err()
+			.to_string();
+		assert!(err.contains("plex") && err.contains("^1.0"), "{}", err);
+		assert!(registry.resolve_version("missing", "^1.0").is_err());
 	}
 
 	#[test]
@@ -961,6 +2435,8 @@ mod tests {
 		assert!(registry.validate("no-variables", "0.0.1").is_err());
 
 		assert!(registry.validate("with-dependencies", "0.0.1").is_ok());
+		// depends on a range of podman-test versions
+		assert!(registry.validate("with-dependencies", "0.0.2").is_ok());
 
 		// depends on a non-existent version of plex
 		assert!(registry.validate("bad-dependencies", "0.0.1").is_err());
@@ -968,12 +2444,252 @@ mod tests {
 		assert!(registry.validate("bad-dependencies", "0.0.2").is_err());
 		// depends on a bad package
 		assert!(registry.validate("bad-dependencies", "0.0.3").is_err());
+		// no version of plex satisfies the requirement
+		assert!(registry.validate("bad-dependencies", "0.0.4").is_err());
+		// cycle-a and cycle-b depend on each other
+		let err = registry
+			.validate("cycle-a", "0.0.1")
+			.unwrap_err()
+			.to_string();
+		assert!(
+			err.contains("cycle-a-0.0.1 -> cycle-b-0.0.1 -> cycle-a-0.0.1"),
+			"{}",
+			err
+		);
 		// invalid name, valid version
 		assert!(registry.validate("bad-name-version", "0.0.1").is_err());
 		// invalid version, valid name
 		assert!(registry.validate("bad-name-version", "0.0.2").is_err());
 	}
 
+	#[test]
+	fn storage_validate() {
+		let volume = |name: &str, mountpoint: Option<&str>| CompiledVolume {
+			name: name.into(),
+			mountpoint: mountpoint.map(Into::into),
+			..Default::default()
+		};
+
+		let table = &[
+			(vec![], true),
+			(vec![volume("a", None), volume("b", None)], true),
+			(vec![volume("a", Some("/a")), volume("b", Some("/b"))], true),
+			(vec![volume("a", Some("relative"))], false),
+			(vec![volume("a", Some("../escape"))], false),
+			(vec![volume("a", Some("/data/../etc"))], false),
+			(vec![volume("a", Some("/etc/passwd"))], false),
+			(vec![volume("a", Some("/"))], false),
+			(vec![volume("a", Some("/data"))], true),
+			(vec![volume("../escape", Some("/data"))], false),
+			(vec![volume("/escape", None)], false),
+			(vec![volume("", None)], false),
+			(
+				vec![volume("a", Some("/data")), volume("b", Some("/data"))],
+				false,
+			),
+			// same path, spelled differently
+			(
+				vec![volume("a", Some("/data")), volume("b", Some("/data/"))],
+				false,
+			),
+		];
+
+		for (volumes, ok) in table {
+			let storage = CompiledStorage {
+				volumes: volumes.clone(),
+			};
+			assert_eq!(storage.validate().is_ok(), *ok, "{:?}", volumes);
+		}
+
+		let err = CompiledStorage {
+			volumes: vec![volume("a", Some("/data")), volume("b", Some("/data"))],
+		}
+		.validate()
+		.unwrap_err()
+		.to_string();
+		assert!(err.contains("'a'") && err.contains("'b'"));
+	}
+
+	#[tokio::test]
+	async fn installed_without_marker() {
+		let dir = tempfile::tempdir().unwrap();
+		let title = PackageTitle {
+			name: "plex".into(),
+			version: "1.2.3".into(),
+		};
+
+		assert_eq!(
+			title.installed(dir.path()).await.unwrap(),
+			InstallStatus::NotInstalled
+		);
+	}
+
+	#[test]
+	fn consistency() {
+		let title = |name: &str| PackageTitle {
+			name: name.into(),
+			version: "1.0.0".into(),
+		};
+
+		let report = ConsistencyReport::new(
+			&[title("a"), title("b"), title("c")],
+			&[title("a"), title("b"), title("d")],
+			&[title("a"), title("c"), title("d"), title("e")],
+		);
+
+		assert_eq!(
+			report,
+			ConsistencyReport {
+				missing_unit_files: vec![title("c")],
+				missing_systemd_units: vec![title("b")],
+				orphaned_unit_files: vec![title("d")],
+				orphaned_systemd_units: vec![title("d"), title("e")],
+			}
+		);
+		assert!(!report.is_consistent());
+
+		assert!(
+			ConsistencyReport::new(&[title("a")], &[title("a")], &[title("a")]).is_consistent()
+		);
+	}
+
+	#[test]
+	fn remove_version() {
+		let dir = tempfile::tempdir().unwrap();
+		let pr = Registry::new(dir.path().to_path_buf());
+
+		let titles = ["1.2.3", "1.2.4"].map(|version| PackageTitle {
+			name: "plex".into(),
+			version: version.into(),
+		});
+
+		for title in &titles {
+			pr.write(&SourcePackage {
+				title: title.clone(),
+				root: Some(dir.path().to_path_buf()),
+				..Default::default()
+			})
+			.unwrap();
+		}
+
+		let marker = titles[0].installed_path(dir.path());
+		std::fs::create_dir_all(marker.parent().unwrap()).unwrap();
+		std::fs::write(&marker, "").unwrap();
+
+		let err = pr.remove_version("plex", "1.2.3").unwrap_err();
+		assert_eq!(
+			err.downcast_ref::<PackageInstalledError>(),
+			Some(&PackageInstalledError(vec![titles[0].clone()]))
+		);
+		assert!(pr.load("plex", "1.2.3").is_ok());
+
+		pr.remove_version("plex", "1.2.4").unwrap();
+		assert!(pr.load("plex", "1.2.4").is_err());
+		assert_eq!(pr.list().unwrap().len(), 1);
+
+		// a version that isn't there is an error, not a no-op
+		assert!(pr.remove_version("plex", "1.2.4").is_err());
+
+		std::fs::remove_file(&marker).unwrap();
+		pr.remove_version("plex", "1.2.3").unwrap();
+		assert!(pr.list().unwrap().is_empty());
+		assert!(
+			!dir.path()
+				.join(crate::PACKAGE_SUBPATH)
+				.join("plex")
+				.exists()
+		);
+
+		// names that would step outside of the registry are refused before touching the disk
+		std::fs::write(dir.path().join("x.json"), "").unwrap();
+		assert!(pr.remove_version("../x", "1.2.3").is_err());
+		assert!(pr.remove_version("..", "x").is_err());
+		assert!(pr.remove_version("plex", "../../x").is_err());
+		assert!(dir.path().join("x.json").exists());
+	}
+
+	#[test]
+	fn dataset_owner() {
+		let dir = tempfile::tempdir().unwrap();
+		let pr = Registry::new(dir.path().to_path_buf());
+
+		let titles = ["1.9.0", "1.10.0"].map(|version| PackageTitle {
+			name: "plex".into(),
+			version: version.into(),
+		});
+
+		for title in &titles {
+			pr.write(&SourcePackage {
+				title: title.clone(),
+				root: Some(dir.path().to_path_buf()),
+				..Default::default()
+			})
+			.unwrap();
+		}
+
+		assert_eq!(pr.dataset_owner("plex").unwrap(), None);
+
+		for title in &titles {
+			let marker = title.installed_path(dir.path());
+			std::fs::create_dir_all(marker.parent().unwrap()).unwrap();
+			std::fs::write(&marker, "").unwrap();
+		}
+
+		assert_eq!(pr.dataset_owner("plex").unwrap(), Some(titles[1].clone()));
+		assert_eq!(
+			pr.dataset_owner("plex/config").unwrap(),
+			Some(titles[1].clone())
+		);
+		assert_eq!(pr.dataset_owner("plexmediaserver").unwrap(), None);
+		assert_eq!(pr.dataset_owner("other/plex").unwrap(), None);
+		assert_eq!(pr.dataset_owner("").unwrap(), None);
+	}
+
+	#[test]
+	fn remove_installed() {
+		let dir = tempfile::tempdir().unwrap();
+		let pr = Registry::new(dir.path().to_path_buf());
+
+		let titles = ["1.2.3", "1.2.4"].map(|version| PackageTitle {
+			name: "plex".into(),
+			version: version.into(),
+		});
+
+		for title in &titles {
+			pr.write(&SourcePackage {
+				title: title.clone(),
+				root: Some(dir.path().to_path_buf()),
+				..Default::default()
+			})
+			.unwrap();
+		}
+
+		let marker = titles[1].installed_path(dir.path());
+		std::fs::create_dir_all(marker.parent().unwrap()).unwrap();
+		std::fs::write(&marker, "").unwrap();
+
+		let err = pr.remove("plex", false).unwrap_err();
+		assert_eq!(
+			err.downcast_ref::<PackageInstalledError>(),
+			Some(&PackageInstalledError(vec![titles[1].clone()]))
+		);
+		assert_eq!(pr.list().unwrap().len(), 2);
+
+		pr.remove("plex", true).unwrap();
+		assert!(pr.list().unwrap().is_empty());
+		assert!(
+			!dir.path()
+				.join(crate::PACKAGE_SUBPATH)
+				.join("plex")
+				.exists()
+		);
+
+		// the registry itself isn't a package
+		assert!(pr.remove("..", true).is_err());
+		assert!(pr.remove("plex/..", true).is_err());
+		assert!(dir.path().join(crate::PACKAGE_SUBPATH).exists());
+	}
+
 	#[test]
 	fn io() {
 		let dir = tempfile::tempdir().unwrap();
@@ -983,12 +2699,11 @@ mod tests {
 				version: "1.2.3".into(),
 			},
 			root: Some(dir.path().to_path_buf()),
+			secret_key: Some(crate::DEFAULT_SECRET_KEY_PATH.into()),
 			..Default::default()
 		}];
 
-		let pr = Registry {
-			root: dir.path().to_path_buf(),
-		};
+		let pr = Registry::new(dir.path().to_path_buf());
 
 		for item in table {
 			assert!(pr.write(item).is_ok());
@@ -1009,9 +2724,7 @@ mod tests {
 			..Default::default()
 		}];
 
-		let pr = Registry {
-			root: dir.path().to_path_buf(),
-		};
+		let pr = Registry::new(dir.path().to_path_buf());
 
 		for item in packages {
 			pr.write(item).unwrap();
@@ -1049,9 +2762,7 @@ mod tests {
 			..Default::default()
 		}];
 
-		let pr = Registry {
-			root: dir.path().to_path_buf(),
-		};
+		let pr = Registry::new(dir.path().to_path_buf());
 
 		for item in packages {
 			pr.write(item).unwrap();
@@ -1089,4 +2800,449 @@ mod tests {
 			}
 		);
 	}
+
+	#[test]
+	fn port_conflicts() {
+		let package = |forward: Vec<(u16, u16)>, expose: Vec<(u16, u16)>| CompiledPackage {
+			networking: CompiledNetworking {
+				forward_ports: forward,
+				expose_ports: expose,
+				..Default::default()
+			},
+			..Default::default()
+		};
+
+		let a = package(vec![(8080, 80)], vec![(2345, 22)]);
+		assert!(a.validate().is_ok());
+		assert_eq!(
+			a.conflicting_port(&package(vec![(2345, 80)], vec![])),
+			Some(2345)
+		);
+		assert_eq!(
+			a.conflicting_port(&package(vec![], vec![(8080, 8080)])),
+			Some(8080)
+		);
+		// only the host side has to be distinct
+		assert_eq!(
+			a.conflicting_port(&package(vec![(8081, 80)], vec![(22, 22)])),
+			None
+		);
+
+		let err = package(vec![(8080, 80)], vec![(8080, 81)])
+			.validate()
+			.unwrap_err()
+			.to_string();
+		assert!(err.contains("port 8080"), "{}", err);
+
+		// a VM's VNC display is bound on the host too; a container's is ignored
+		let vm = |display: u16, forward: Vec<(u16, u16)>| CompiledPackage {
+			source: CompiledSource::QEmu(VMImage::Url("http://localhost/image".into())),
+			resources: CompiledResources {
+				vnc: Some(CompiledVnc {
+					display: Some(display),
+				}),
+				..Default::default()
+			},
+			..package(forward, vec![])
+		};
+
+		assert_eq!(vm(1, vec![]).conflicting_port(&vm(1, vec![])), Some(5901));
+		assert_eq!(vm(1, vec![]).conflicting_port(&vm(2, vec![])), None);
+		assert_eq!(
+			vm(2, vec![]).conflicting_port(&package(vec![(5902, 80)], vec![])),
+			Some(5902)
+		);
+		assert!(vm(1, vec![(5901, 80)]).validate().is_err());
+
+		let container = CompiledPackage {
+			resources: vm(1, vec![]).resources,
+			..package(vec![(5901, 80)], vec![])
+		};
+		assert!(container.validate().is_ok());
+	}
+
+	#[tokio::test]
+	async fn system_conflicts() {
+		let registry = Registry::new("testdata/registry".into());
+
+		let err = registry
+			.load("bad-system", "0.0.1")
+			.unwrap()
+			.compile()
+			.await
+			.unwrap_err()
+			.to_string();
+		assert!(err.contains("internal network 'bad-system'"), "{}", err);
+
+		let err = registry
+			.load("bad-system", "0.0.2")
+			.unwrap()
+			.compile()
+			.await
+			.unwrap_err()
+			.to_string();
+		assert!(err.contains("SYS_ADMIN, NET_ADMIN"), "{}", err);
+
+		// host networking and capabilities alone are fine
+		assert!(
+			registry
+				.load("podman-test", "0.0.1")
+				.unwrap()
+				.compile()
+				.await
+				.is_ok()
+		);
+	}
+
+	#[tokio::test]
+	async fn required_prompts() {
+		let dir = tempfile::tempdir().unwrap();
+		let source = Registry::new("testdata/registry".into());
+		let registry = Registry::new(dir.path().to_path_buf());
+		registry
+			.write(&source.load("with-required-prompts", "0.0.1").unwrap())
+			.unwrap();
+		let pkg = registry.load("with-required-prompts", "0.0.1").unwrap();
+
+		let err = pkg.compile().await.unwrap_err().to_string();
+		assert!(err.contains("release, hostname"), "{}", err);
+
+		pkg.set_responses(
+			&vec![PromptResponse {
+				template: "release".into(),
+				input: Input::String("trixie".into()),
+			}]
+			.into(),
+		)
+		.unwrap();
+		let err = pkg.compile().await.unwrap_err().to_string();
+		assert!(
+			err.contains("hostname") && !err.contains("release"),
+			"{}",
+			err
+		);
+
+		pkg.set_responses(
+			&vec![
+				PromptResponse {
+					template: "release".into(),
+					input: Input::String("trixie".into()),
+				},
+				PromptResponse {
+					template: "hostname".into(),
+					input: Input::String("debian".into()),
+				},
+			]
+			.into(),
+		)
+		.unwrap();
+		let out = pkg.compile().await.unwrap();
+		assert_eq!(
+			out.source,
+			CompiledSource::Container("docker://debian:trixie".into())
+		);
+		assert_eq!(out.networking.hostname.as_deref(), Some("debian"));
+	}
+
+	#[tokio::test]
+	async fn volume_names() {
+		let dir = tempfile::tempdir().unwrap();
+		let pr = Registry::new(dir.path().to_path_buf());
+		pr.write(&SourcePackage {
+			title: PackageTitle {
+				name: "plex".into(),
+				version: "1.2.3".into(),
+			},
+			storage: Some(Storage {
+				volumes: vec![Volume {
+					name: "?backup?".parse().unwrap(),
+					..Default::default()
+				}],
+			}),
+			prompts: Some(PromptCollection(vec![Prompt {
+				template: "backup".into(),
+				question: "What should the backup volume be called?".into(),
+				input_type: InputType::VolumeName,
+				options: Default::default(),
+				required: false,
+				default: None,
+				pattern: None,
+				min: None,
+				max: None,
+			}])),
+			root: Some(dir.path().to_path_buf()),
+			..Default::default()
+		})
+		.unwrap();
+		GlobalRegistry {
+			root: dir.path().to_path_buf(),
+		}
+		.set(&Global {
+			name: "plex".into(),
+			variables: Default::default(),
+		})
+		.unwrap();
+
+		let pkg = pr.load("plex", "1.2.3").unwrap();
+		let respond = |answer: &str| -> PromptResponses {
+			vec![PromptResponse {
+				template: "backup".into(),
+				input: Input::String(answer.into()),
+			}]
+			.into()
+		};
+
+		pkg.set_responses(&respond("my volume!")).unwrap();
+		assert!(pr.validate("plex", "1.2.3").is_err());
+		assert!(pkg.compile().await.is_err());
+
+		pkg.set_responses(&respond("backups")).unwrap();
+		assert!(pr.validate("plex", "1.2.3").is_ok());
+		assert_eq!(
+			pkg.compile().await.unwrap().storage.volumes[0].name,
+			"plex-backups"
+		);
+	}
+
+	#[test]
+	fn versions() {
+		use std::cmp::Ordering;
+
+		assert_eq!(compare_versions("1.10.0", "1.9.0"), Ordering::Greater);
+		assert_eq!(compare_versions("1.0.0", "1.0.0"), Ordering::Equal);
+		assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+		assert_eq!(compare_versions("1.0.0-rc1", "1.0.0"), Ordering::Less);
+		assert_eq!(
+			compare_versions("1.0.0-rc2", "1.0.0-rc1"),
+			Ordering::Greater
+		);
+		assert_eq!(compare_versions("1.0.b", "1.0.a"), Ordering::Greater);
+
+		let dir = tempfile::tempdir().unwrap();
+		let pr = Registry::new(dir.path().to_path_buf());
+		for version in ["1.9.0", "1.10.0", "1.10.0-rc1", "0.1.0"] {
+			pr.write(&SourcePackage {
+				title: PackageTitle {
+					name: "plex".into(),
+					version: version.into(),
+				},
+				root: Some(dir.path().to_path_buf()),
+				..Default::default()
+			})
+			.unwrap();
+		}
+
+		assert_eq!(
+			pr.list_versions("plex").unwrap(),
+			vec!["1.10.0", "1.10.0-rc1", "1.9.0", "0.1.0"]
+		);
+		assert!(pr.list_versions("missing").is_err());
+
+		// nothing outside of the registry's packages is listed
+		std::fs::write(dir.path().join("x.json"), "").unwrap();
+		assert!(pr.list_versions("..").is_err());
+		assert!(pr.list_versions("plex/../..").is_err());
+	}
+
+	#[tokio::test]
+	async fn installed_outside_registry() {
+		let dir = tempfile::tempdir().unwrap();
+		std::fs::write(dir.path().join("marker"), "").unwrap();
+
+		// installed/../../marker would be there, but it's not a package
+		for (name, version) in [("..", "marker"), ("../..", "marker"), ("x", "../../marker")] {
+			let title = PackageTitle {
+				name: name.into(),
+				version: version.into(),
+			};
+			assert!(title.installed(&dir.path().join("registry")).await.is_err());
+		}
+	}
+
+	#[tokio::test]
+	async fn compile_secrets() {
+		let dir = tempfile::tempdir().unwrap();
+		let pkg = SourcePackage {
+			title: PackageTitle {
+				name: "secretive".into(),
+				version: "0.0.1".into(),
+			},
+			source: Source::Container("registry.example/app:?token?".parse().unwrap()),
+			prompts: Some(PromptCollection(vec![Prompt {
+				template: "token".into(),
+				question: "What is your access token?".into(),
+				input_type: InputType::Secret,
+				options: Default::default(),
+				required: false,
+				default: None,
+				pattern: None,
+				min: None,
+				max: None,
+			}])),
+			root: Some(dir.path().to_path_buf()),
+			secret_key: Some(dir.path().join("secret.key")),
+			..Default::default()
+		};
+
+		pkg.set_responses(
+			&vec![PromptResponse {
+				template: "token".into(),
+				input: Input::Secret("hunter2".into()),
+			}]
+			.into(),
+		)
+		.unwrap();
+
+		// the plain responses know nothing about it
+		assert_eq!(pkg.responses().unwrap(), PromptResponses::default());
+		assert_eq!(
+			pkg.compile().await.unwrap().source,
+			CompiledSource::Container("registry.example/app:hunter2".into())
+		);
+	}
+
+	#[test]
+	fn import_archive() {
+		fn archive(
+			dir: &std::path::Path, name: &str, files: &[(&str, String)],
+		) -> std::path::PathBuf {
+			let src = dir.join(format!("{}-src", name));
+			std::fs::create_dir_all(&src).unwrap();
+			for (file, contents) in files {
+				std::fs::write(src.join(file), contents).unwrap();
+			}
+
+			let out = dir.join(format!("{}.tar", name));
+			assert!(
+				std::process::Command::new("tar")
+					.arg("-cf")
+					.arg(&out)
+					.arg("-C")
+					.arg(&src)
+					.args(files.iter().map(|(file, _)| file))
+					.status()
+					.unwrap()
+					.success()
+			);
+			out
+		}
+
+		let dir = tempfile::tempdir().unwrap();
+		let registry = dir.path().join("registry");
+		let pr = Registry::new(registry.clone());
+		let gr = GlobalRegistry::new(registry.clone());
+
+		let package = SourcePackage {
+			title: PackageTitle {
+				name: "imported".into(),
+				version: "1.0.0".into(),
+			},
+			description: "from an archive".into(),
+			..Default::default()
+		};
+		let mut variables = Variables::default();
+		variables.insert("greeting".into(), "hello".into());
+		let global = Global {
+			name: "imported".into(),
+			variables,
+		};
+
+		let good = archive(
+			dir.path(),
+			"good",
+			&[
+				("package.json", serde_json::to_string(&package).unwrap()),
+				("variables.json", serde_json::to_string(&global).unwrap()),
+			],
+		);
+
+		assert_eq!(pr.import_archive(&good).unwrap(), package.title);
+		pr.validate("imported", "1.0.0").unwrap();
+		assert_eq!(
+			pr.load("imported", "1.0.0").unwrap().description,
+			"from an archive"
+		);
+		assert_eq!(gr.get("imported").unwrap(), global);
+
+		// already there
+		assert!(pr.import_archive(&good).is_err());
+
+		// anything besides the package and its variables is refused outright
+		let extra = archive(
+			dir.path(),
+			"extra",
+			&[
+				("package.json", serde_json::to_string(&package).unwrap()),
+				("evil.json", "{}".into()),
+			],
+		);
+		assert!(pr.import_archive(&extra).is_err());
+
+		// a missing dependency leaves nothing behind
+		let dependent = SourcePackage {
+			title: PackageTitle {
+				name: "dependent".into(),
+				version: "1.0.0".into(),
+			},
+			dependencies: Some(vec![PackageTitle {
+				name: "missing".into(),
+				version: "1.0.0".into(),
+			}]),
+			..Default::default()
+		};
+		let broken = archive(
+			dir.path(),
+			"broken",
+			&[("package.json", serde_json::to_string(&dependent).unwrap())],
+		);
+		assert!(pr.import_archive(&broken).is_err());
+		assert!(pr.load("dependent", "1.0.0").is_err());
+		assert!(gr.get("dependent").is_err());
+
+		// without variables, an empty set is created so the package can load
+		let plain = SourcePackage {
+			title: PackageTitle {
+				name: "plain".into(),
+				version: "0.1.0".into(),
+			},
+			..Default::default()
+		};
+		let plain_archive = archive(
+			dir.path(),
+			"plain",
+			&[("package.json", serde_json::to_string(&plain).unwrap())],
+		);
+		pr.import_archive(&plain_archive).unwrap();
+		pr.validate("plain", "0.1.0").unwrap();
+
+		// a title that the registry couldn't load back is refused
+		let unloadable = SourcePackage {
+			title: PackageTitle {
+				name: "unloadable".into(),
+				version: "1..2".into(),
+			},
+			..Default::default()
+		};
+		let unloadable_archive = archive(
+			dir.path(),
+			"unloadable",
+			&[("package.json", serde_json::to_string(&unloadable).unwrap())],
+		);
+		assert!(pr.import_archive(&unloadable_archive).is_err());
+		assert!(
+			!registry
+				.join(crate::PACKAGE_SUBPATH)
+				.join("unloadable")
+				.exists()
+		);
+
+		// no temporary directories are left in the registry
+		assert!(
+			std::fs::read_dir(&registry).unwrap().all(|x| !x
+				.unwrap()
+				.file_name()
+				.to_string_lossy()
+				.starts_with('.'))
+		);
+	}
 }
@@ -1,11 +1,37 @@
-use std::path::PathBuf;
+use std::{
+	collections::HashMap,
+	path::PathBuf,
+	sync::{LazyLock, Mutex},
+};
 
-use crate::{Input, InputType, ProtoPromptResponse, ProtoType};
+use crate::{
+	Input, InputType, ProtoPrompt, ProtoPromptResponse, ProtoSelectOption, ProtoType, SelectOption,
+	validate_path, validate_registry_name, validate_volume_name,
+};
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 
 pub const RESPONSES_SUBPATH: &str = "responses";
 const DELIMITER: char = '?';
+// patterns come from packages, so bound how big one may compile to
+const PATTERN_SIZE_LIMIT: usize = 1024 * 1024;
+
+// compiled prompt patterns, by pattern
+static PATTERNS: LazyLock<Mutex<HashMap<String, regex::Regex>>> = LazyLock::new(Default::default);
+
+// the pattern has to match the whole response, not just some part of it
+fn compile_pattern(pattern: &str) -> Result<regex::Regex> {
+	let mut patterns = PATTERNS.lock().unwrap();
+	if let Some(regex) = patterns.get(pattern) {
+		return Ok(regex.clone());
+	}
+
+	let regex = regex::RegexBuilder::new(&format!("^(?:{})$", pattern))
+		.size_limit(PATTERN_SIZE_LIMIT)
+		.build()?;
+	patterns.insert(pattern.to_string(), regex.clone());
+	Ok(regex)
+}
 
 pub struct ResponseRegistry {
 	pub root: PathBuf,
@@ -17,6 +43,7 @@ impl ResponseRegistry {
 	}
 
 	pub fn remove(&self, name: &str) -> Result<()> {
+		validate_registry_name(name)?;
 		Ok(std::fs::remove_file(
 			self.root
 				.join(RESPONSES_SUBPATH)
@@ -25,6 +52,7 @@ impl ResponseRegistry {
 	}
 
 	pub fn get(&self, name: &str) -> Result<PromptResponses> {
+		validate_registry_name(name)?;
 		Ok(serde_json::from_reader(
 			std::fs::OpenOptions::new().read(true).open(
 				self.root
@@ -35,6 +63,15 @@ impl ResponseRegistry {
 	}
 
 	pub fn set(&self, name: &str, responses: &PromptResponses) -> Result<()> {
+		validate_registry_name(name)?;
+
+		if responses.has_secrets() {
+			return Err(anyhow!(
+				"secret responses for {} can't be stored in plain text",
+				name
+			));
+		}
+
 		let pb = self.root.join(RESPONSES_SUBPATH);
 
 		std::fs::create_dir_all(&pb)?;
@@ -53,6 +90,91 @@ impl ResponseRegistry {
 			pb.join(format!("{}.json", name)),
 		)?)
 	}
+
+	// like set(), but for several packages at once: either every one of them is replaced or none
+	// are. everything is written aside first, and if swapping the files in fails partway, the
+	// ones already swapped are restored from their backups.
+	pub fn set_many(&self, responses: &[(String, PromptResponses)]) -> Result<()> {
+		let mut seen = std::collections::BTreeSet::new();
+		for (name, responses) in responses {
+			validate_registry_name(name)?;
+
+			if !seen.insert(name) {
+				return Err(anyhow!("responses for {} were given more than once", name));
+			}
+
+			if responses.has_secrets() {
+				return Err(anyhow!(
+					"secret responses for {} can't be stored in plain text",
+					name
+				));
+			}
+		}
+
+		let pb = self.root.join(RESPONSES_SUBPATH);
+		std::fs::create_dir_all(&pb)?;
+		let path = |name: &str, ext: &str| pb.join(format!("{}.{}", name, ext));
+
+		let mut written = Vec::new();
+		let res = (|| -> Result<()> {
+			for (name, responses) in responses {
+				let tmpname = path(name, "json.tmp");
+				serde_json::to_writer_pretty(
+					std::fs::OpenOptions::new()
+						.create(true)
+						.truncate(true)
+						.write(true)
+						.open(&tmpname)?,
+					responses,
+				)?;
+				written.push(tmpname);
+			}
+			Ok(())
+		})();
+
+		if let Err(e) = res {
+			for tmpname in written {
+				let _ = std::fs::remove_file(tmpname);
+			}
+			return Err(e);
+		}
+
+		// (name, whether it had responses before)
+		let mut swapped = Vec::new();
+		let res = (|| -> Result<()> {
+			for (name, _) in responses {
+				let target = path(name, "json");
+				let existed = target.exists();
+				if existed {
+					std::fs::rename(&target, path(name, "json.bak"))?;
+				}
+				swapped.push((name, existed));
+				std::fs::rename(path(name, "json.tmp"), &target)?;
+			}
+			Ok(())
+		})();
+
+		if let Err(e) = res {
+			for (name, existed) in swapped.into_iter().rev() {
+				let _ = std::fs::remove_file(path(name, "json"));
+				if existed {
+					let _ = std::fs::rename(path(name, "json.bak"), path(name, "json"));
+				}
+			}
+			for (name, _) in responses {
+				let _ = std::fs::remove_file(path(name, "json.tmp"));
+			}
+			return Err(e);
+		}
+
+		for (name, existed) in swapped {
+			if existed {
+				let _ = std::fs::remove_file(path(name, "json.bak"));
+			}
+		}
+
+		Ok(())
+	}
 }
 
 #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
@@ -118,12 +240,25 @@ impl PromptParser {
 				let mut matched = false;
 				for response in &responses.0 {
 					if response.template == tmp {
+						self.check_response(response)?;
 						out += &response.to_string();
 						matched = true;
 						break;
 					}
 				}
 
+				if !matched
+					&& let Some(default) = self
+						.0
+						.0
+						.iter()
+						.find(|x| x.template == tmp)
+						.and_then(|x| x.default.as_ref())
+				{
+					out += &default.to_string();
+					matched = true;
+				}
+
 				if !matched {
 					return Err(anyhow!("No response matches prompt '{}'", tmp));
 				}
@@ -145,6 +280,66 @@ impl PromptParser {
 
 		Ok(out)
 	}
+
+	// a response to a select prompt has to be one of its options, and ones to path and volume name
+	// prompts have to pass validate_path() and validate_volume_name(). any prompt's pattern, min
+	// and max apply on top of that.
+	pub fn check_response(&self, response: &PromptResponse) -> Result<()> {
+		let value = response.to_string();
+
+		for prompt in &self.0.0 {
+			if prompt.template != response.template {
+				continue;
+			}
+
+			if prompt.input_type == InputType::Path {
+				validate_path(&value)
+					.map_err(|e| anyhow!("Response to prompt '{}': {}", prompt.template, e))?;
+			}
+
+			if let Some(pattern) = &prompt.pattern
+				&& !prompt.compile_pattern()?.is_match(&value)
+			{
+				return Err(anyhow!(
+					"'{}' is not a valid response to prompt '{}'; expected something matching: {}",
+					value,
+					prompt.template,
+					pattern
+				));
+			}
+
+			if matches!(
+				prompt.input_type,
+				InputType::Integer | InputType::SignedInteger
+			) && (prompt.min.is_some() || prompt.max.is_some())
+			{
+				prompt.check_bounds(&value)?;
+			}
+
+			if prompt.input_type == InputType::VolumeName {
+				validate_volume_name(&value)
+					.map_err(|e| anyhow!("Response to prompt '{}': {}", prompt.template, e))?;
+			}
+
+			if prompt.input_type == InputType::Select
+				&& !prompt.options.iter().any(|x| x.value.to_string() == value)
+			{
+				return Err(anyhow!(
+					"'{}' is not an option for prompt '{}'; expected one of: {}",
+					value,
+					prompt.template,
+					prompt
+						.options
+						.iter()
+						.map(|x| x.value.to_string())
+						.collect::<Vec<_>>()
+						.join(", ")
+				));
+			}
+		}
+
+		Ok(())
+	}
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -152,6 +347,204 @@ pub struct Prompt {
 	pub template: String,
 	pub question: String,
 	pub input_type: InputType,
+	// the choices for a select prompt
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub options: Vec<SelectOption>,
+	// the package won't compile without a response to it
+	#[serde(default)]
+	pub required: bool,
+	// used when there is no response to it
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub default: Option<Input>,
+	// a regex the whole response has to match
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub pattern: Option<String>,
+	// inclusive bounds on responses to integer and signed integer prompts
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub min: Option<i64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max: Option<i64>,
+}
+
+impl Prompt {
+	fn compile_pattern(&self) -> Result<regex::Regex> {
+		let pattern = self.pattern.as_deref().unwrap_or_default();
+		compile_pattern(pattern)
+			.map_err(|e| anyhow!("Pattern for prompt '{}' is invalid: {}", self.template, e))
+	}
+
+	// i128 holds every u64 and i64, so either kind of integer compares against the bounds
+	fn check_bounds(&self, value: &str) -> Result<()> {
+		let number: i128 = value.parse().map_err(|_| {
+			anyhow!(
+				"'{}' is not a valid response to prompt '{}'; expected a number",
+				value,
+				self.template
+			)
+		})?;
+
+		if let Some(min) = self.min
+			&& number < min as i128
+		{
+			return Err(anyhow!(
+				"{} is below the minimum of {} for prompt '{}'",
+				number,
+				min,
+				self.template
+			));
+		}
+
+		if let Some(max) = self.max
+			&& number > max as i128
+		{
+			return Err(anyhow!(
+				"{} is above the maximum of {} for prompt '{}'",
+				number,
+				max,
+				self.template
+			));
+		}
+
+		Ok(())
+	}
+
+	pub fn validate_pattern(&self) -> Result<()> {
+		if self.pattern.is_some() {
+			self.compile_pattern()?;
+		}
+
+		Ok(())
+	}
+
+	// a default has to be the kind of input the prompt asks for, and pass the same checks a
+	// response would
+	pub fn validate_default(&self) -> Result<()> {
+		let Some(default) = &self.default else {
+			return Ok(());
+		};
+
+		let matches = match self.input_type {
+			InputType::Integer => matches!(default, Input::Integer(_)),
+			InputType::SignedInteger => matches!(default, Input::SignedInteger(_)),
+			InputType::Boolean => matches!(default, Input::Boolean(_)),
+			InputType::String | InputType::Path | InputType::VolumeName => {
+				matches!(default, Input::String(_))
+			}
+			// packages are plain text, so a secret in one wouldn't be
+			InputType::Secret => false,
+			InputType::Select => true,
+		};
+
+		if !matches {
+			return Err(anyhow!(
+				"Default for prompt '{}' is not a valid {:?} input",
+				self.template,
+				self.input_type
+			));
+		}
+
+		PromptParser(PromptCollection(vec![self.clone()])).check_response(&PromptResponse {
+			template: self.template.clone(),
+			input: default.clone(),
+		})
+	}
+}
+
+impl From<Prompt> for ProtoPrompt {
+	fn from(value: Prompt) -> Self {
+		Self {
+			template: value.template,
+			question: value.question,
+			input_type: match value.input_type {
+				InputType::String => ProtoType::String,
+				InputType::Integer => ProtoType::Integer,
+				InputType::SignedInteger => ProtoType::SignedInteger,
+				InputType::Boolean => ProtoType::Boolean,
+				InputType::Secret => ProtoType::Secret,
+				InputType::Select => ProtoType::Select,
+				InputType::Path => ProtoType::Path,
+				InputType::VolumeName => ProtoType::VolumeName,
+			}
+			.into(),
+			options: value.options.into_iter().map(Into::into).collect(),
+			required: value.required,
+			default_type: value.default.as_ref().map(|x| proto_type(x).into()),
+			default: value.default.map(|x| x.to_string()),
+			pattern: value.pattern,
+			min: value.min,
+			max: value.max,
+		}
+	}
+}
+
+impl From<ProtoPrompt> for Prompt {
+	fn from(value: ProtoPrompt) -> Self {
+		let default_type = value.default_type();
+
+		Self {
+			input_type: match value.input_type() {
+				ProtoType::String => InputType::String,
+				ProtoType::Integer => InputType::Integer,
+				ProtoType::SignedInteger => InputType::SignedInteger,
+				ProtoType::Boolean => InputType::Boolean,
+				ProtoType::Secret => InputType::Secret,
+				ProtoType::Select => InputType::Select,
+				ProtoType::Path => InputType::Path,
+				ProtoType::VolumeName => InputType::VolumeName,
+			},
+			template: value.template,
+			question: value.question,
+			options: value.options.into_iter().map(Into::into).collect(),
+			required: value.required,
+			default: value.default.map(|x| proto_input(default_type, x)),
+			pattern: value.pattern,
+			min: value.min,
+			max: value.max,
+		}
+	}
+}
+
+impl From<SelectOption> for ProtoSelectOption {
+	fn from(value: SelectOption) -> Self {
+		Self {
+			name: value.name,
+			value: value.value.to_string(),
+			value_type: proto_type(&value.value).into(),
+		}
+	}
+}
+
+impl From<ProtoSelectOption> for SelectOption {
+	fn from(value: ProtoSelectOption) -> Self {
+		Self {
+			value: proto_input(value.value_type(), value.value),
+			name: value.name,
+		}
+	}
+}
+
+// the type an input travels with over gRPC
+fn proto_type(input: &Input) -> ProtoType {
+	match input {
+		Input::Integer(_) => ProtoType::Integer,
+		Input::SignedInteger(_) => ProtoType::SignedInteger,
+		Input::Boolean(_) => ProtoType::Boolean,
+		Input::String(_) => ProtoType::String,
+		Input::Secret(_) => ProtoType::Secret,
+	}
+}
+
+// selections are sent as the string form of the option's value
+fn proto_input(input_type: ProtoType, value: String) -> Input {
+	match input_type {
+		ProtoType::Integer => Input::Integer(value.parse().unwrap()),
+		ProtoType::SignedInteger => Input::SignedInteger(value.parse().unwrap()),
+		ProtoType::Boolean => Input::Boolean(value.parse().unwrap()),
+		ProtoType::String | ProtoType::Select | ProtoType::Path | ProtoType::VolumeName => {
+			Input::String(value)
+		}
+		ProtoType::Secret => Input::Secret(value),
+	}
 }
 
 #[derive(Debug, Clone, Eq, Default, PartialEq, Serialize, Deserialize)]
@@ -181,13 +574,7 @@ impl From<PromptResponse> for ProtoPromptResponse {
 			template: value.template.clone(),
 			response: value.input.to_string(),
 			// tag the type separate of the input, probably the only way this is going to work
-			input_type: match value.input {
-				Input::Integer(_) => ProtoType::Integer,
-				Input::SignedInteger(_) => ProtoType::SignedInteger,
-				Input::Boolean(_) => ProtoType::Boolean,
-				Input::String(_) => ProtoType::String,
-			}
-			.into(),
+			input_type: proto_type(&value.input).into(),
 		}
 	}
 }
@@ -196,12 +583,7 @@ impl From<ProtoPromptResponse> for PromptResponse {
 	fn from(value: ProtoPromptResponse) -> Self {
 		Self {
 			template: value.template.clone(),
-			input: match value.input_type() {
-				ProtoType::Integer => Input::Integer(value.response.parse().unwrap()),
-				ProtoType::SignedInteger => Input::SignedInteger(value.response.parse().unwrap()),
-				ProtoType::Boolean => Input::Boolean(value.response.parse().unwrap()),
-				ProtoType::String => Input::String(value.response),
-			},
+			input: proto_input(value.input_type(), value.response),
 		}
 	}
 }
@@ -210,7 +592,10 @@ impl From<ProtoPromptResponse> for PromptResponse {
 mod tests {
 	use crate::PromptResponse;
 
-	use super::{Input, InputType, Prompt, PromptCollection, PromptParser};
+	use super::{
+		Input, InputType, Prompt, PromptCollection, PromptParser, PromptResponses, ProtoPrompt,
+		RESPONSES_SUBPATH, ResponseRegistry, SelectOption,
+	};
 	use lazy_static::lazy_static;
 
 	lazy_static! {
@@ -219,16 +604,34 @@ mod tests {
 				template: "greeting".into(),
 				question: "how do we greet each other in computers?".into(),
 				input_type: InputType::String,
+				options: Default::default(),
+				required: false,
+				default: None,
+				pattern: None,
+				min: None,
+				max: None,
 			},
 			Prompt {
 				template: "shoesize".into(),
 				question: "what is your shoe size?".into(),
 				input_type: InputType::Integer,
+				options: Default::default(),
+				required: false,
+				default: None,
+				pattern: None,
+				min: None,
+				max: None,
 			},
 			Prompt {
 				template: "file".into(),
 				question: "Give me the name of your favorite file".into(),
 				input_type: InputType::String,
+				options: Default::default(),
+				required: false,
+				default: None,
+				pattern: None,
+				min: None,
+				max: None,
 			},
 		]
 		.to_vec();
@@ -355,6 +758,283 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn prompt_select() {
+		let prompt = Prompt {
+			template: "level".into(),
+			question: "how much logging?".into(),
+			input_type: InputType::Select,
+			options: vec![
+				SelectOption {
+					name: "Quiet".into(),
+					value: Input::String("warn".into()),
+				},
+				SelectOption {
+					name: "Chatty".into(),
+					value: Input::String("debug".into()),
+				},
+			],
+			required: false,
+			default: None,
+			pattern: None,
+			min: None,
+			max: None,
+		};
+		let parser = PromptParser(PromptCollection(vec![prompt.clone()]));
+		let respond = |answer: &str| -> PromptResponses {
+			vec![PromptResponse {
+				template: "level".into(),
+				input: Input::String(answer.into()),
+			}]
+			.into()
+		};
+
+		assert_eq!(
+			parser
+				.template("--log=?level?".into(), &respond("debug"))
+				.unwrap(),
+			"--log=debug"
+		);
+		let err = parser
+			.template("--log=?level?".into(), &respond("Chatty"))
+			.unwrap_err()
+			.to_string();
+		assert!(err.contains("warn, debug"), "{}", err);
+
+		// options survive the trip to gild and back
+		assert_eq!(Prompt::from(ProtoPrompt::from(prompt.clone())), prompt);
+	}
+
+	#[test]
+	fn prompt_default() {
+		let prompt = |input_type: InputType, default: Input| Prompt {
+			template: "port".into(),
+			question: "which port?".into(),
+			input_type,
+			options: Default::default(),
+			required: false,
+			default: Some(default),
+			pattern: None,
+			min: None,
+			max: None,
+		};
+
+		let parser = PromptParser(PromptCollection(vec![prompt(
+			InputType::Integer,
+			Input::Integer(8080),
+		)]));
+		assert_eq!(
+			parser
+				.template("-p ?port?".into(), &Default::default())
+				.unwrap(),
+			"-p 8080"
+		);
+		// a response still wins
+		assert_eq!(
+			parser
+				.template(
+					"-p ?port?".into(),
+					&vec![PromptResponse {
+						template: "port".into(),
+						input: Input::Integer(9090),
+					}]
+					.into()
+				)
+				.unwrap(),
+			"-p 9090"
+		);
+
+		assert!(
+			prompt(InputType::Integer, Input::Integer(8080))
+				.validate_default()
+				.is_ok()
+		);
+		assert!(
+			prompt(InputType::Integer, Input::String("8080".into()))
+				.validate_default()
+				.is_err()
+		);
+		assert!(
+			prompt(InputType::Secret, Input::Secret("hunter2".into()))
+				.validate_default()
+				.is_err()
+		);
+		assert!(
+			prompt(InputType::Path, Input::String("../escape".into()))
+				.validate_default()
+				.is_err()
+		);
+
+		// gild gets it back the way it was
+		let with_default = prompt(InputType::SignedInteger, Input::SignedInteger(-1));
+		assert_eq!(
+			Prompt::from(ProtoPrompt::from(with_default.clone())),
+			with_default
+		);
+	}
+
+	#[test]
+	fn prompt_pattern() {
+		let prompt = |pattern: &str| Prompt {
+			template: "host".into(),
+			question: "what is this machine called?".into(),
+			input_type: InputType::String,
+			options: Default::default(),
+			required: false,
+			default: None,
+			pattern: Some(pattern.into()),
+			min: None,
+			max: None,
+		};
+		let respond = |answer: &str| -> PromptResponses {
+			vec![PromptResponse {
+				template: "host".into(),
+				input: Input::String(answer.into()),
+			}]
+			.into()
+		};
+
+		let parser = PromptParser(PromptCollection(vec![prompt("[a-z][a-z0-9-]*")]));
+		assert_eq!(
+			parser.template("?host?".into(), &respond("nas-1")).unwrap(),
+			"nas-1"
+		);
+		// the whole response has to match, not just its start
+		let err = parser
+			.template("?host?".into(), &respond("nas 1"))
+			.unwrap_err()
+			.to_string();
+		assert!(
+			err.contains("'host'") && err.contains("[a-z][a-z0-9-]*"),
+			"{}",
+			err
+		);
+
+		assert!(prompt("[a-z]+").validate_pattern().is_ok());
+		assert!(prompt("[a-z").validate_pattern().is_err());
+		assert!(
+			PromptParser(PromptCollection(vec![prompt("[a-z")]))
+				.template("?host?".into(), &respond("nas"))
+				.is_err()
+		);
+
+		let with_pattern = prompt("[a-z]+");
+		assert_eq!(
+			Prompt::from(ProtoPrompt::from(with_pattern.clone())),
+			with_pattern
+		);
+	}
+
+	#[test]
+	fn prompt_bounds() {
+		let prompt = |input_type, min, max| Prompt {
+			template: "port".into(),
+			question: "which port should it listen on?".into(),
+			input_type,
+			options: Default::default(),
+			required: false,
+			default: None,
+			pattern: None,
+			min,
+			max,
+		};
+		let respond = |input: Input| -> PromptResponses {
+			vec![PromptResponse {
+				template: "port".into(),
+				input,
+			}]
+			.into()
+		};
+
+		let parser = PromptParser(PromptCollection(vec![prompt(
+			InputType::Integer,
+			Some(1024),
+			Some(65535),
+		)]));
+		assert_eq!(
+			parser
+				.template("?port?".into(), &respond(Input::Integer(8080)))
+				.unwrap(),
+			"8080"
+		);
+		for (port, bound) in [(80, "minimum of 1024"), (70000, "maximum of 65535")] {
+			let err = parser
+				.template("?port?".into(), &respond(Input::Integer(port)))
+				.unwrap_err()
+				.to_string();
+			assert!(err.contains(bound), "{}", err);
+		}
+		// the bounds themselves are allowed
+		for port in [1024, 65535] {
+			assert!(
+				parser
+					.template("?port?".into(), &respond(Input::Integer(port)))
+					.is_ok()
+			);
+		}
+
+		let parser = PromptParser(PromptCollection(vec![prompt(
+			InputType::SignedInteger,
+			Some(-10),
+			None,
+		)]));
+		assert!(
+			parser
+				.template("?port?".into(), &respond(Input::SignedInteger(-5)))
+				.is_ok()
+		);
+		assert!(
+			parser
+				.template("?port?".into(), &respond(Input::SignedInteger(-11)))
+				.is_err()
+		);
+		// larger than any i64, but still has to compare correctly
+		assert!(
+			parser
+				.template("?port?".into(), &respond(Input::Integer(u64::MAX)))
+				.is_ok()
+		);
+
+		let bounded = prompt(InputType::Integer, Some(1), Some(10));
+		assert_eq!(Prompt::from(ProtoPrompt::from(bounded.clone())), bounded);
+	}
+
+	#[test]
+	fn prompt_path() {
+		let parser = PromptParser(PromptCollection(vec![Prompt {
+			template: "mount".into(),
+			question: "where should it go?".into(),
+			input_type: InputType::Path,
+			options: Default::default(),
+			required: false,
+			default: None,
+			pattern: None,
+			min: None,
+			max: None,
+		}]));
+		let respond = |answer: &str| -> PromptResponses {
+			vec![PromptResponse {
+				template: "mount".into(),
+				input: Input::String(answer.into()),
+			}]
+			.into()
+		};
+
+		assert_eq!(
+			parser
+				.template("?mount?".into(), &respond("/data"))
+				.unwrap(),
+			"/data"
+		);
+		for bad in ["../escape", "/data/../etc", "/data/", "/etc/passwd"] {
+			assert!(
+				parser.template("?mount?".into(), &respond(bad)).is_err(),
+				"{}",
+				bad
+			);
+		}
+	}
+
 	#[test]
 	fn prompt_gathering() {
 		let parser = PromptParser(PromptCollection(PROMPTS.clone()));
@@ -403,4 +1083,103 @@ mod tests {
 			Input::String("hello, world!".into()).to_string()
 		);
 	}
+
+	#[test]
+	fn traversal() {
+		let dir = tempfile::tempdir().unwrap();
+		let registry = ResponseRegistry::new(dir.path().join("registry"));
+		let outside = dir.path().join("escape.json");
+		std::fs::write(&outside, "[]").unwrap();
+		let responses: PromptResponses = vec![PromptResponse {
+			template: "shoesize".into(),
+			input: Input::Integer(1),
+		}]
+		.into();
+
+		// escape.json sits right where ../escape would land
+		for name in ["../escape", "/etc/passwd", "a/b", ".."] {
+			assert!(registry.get(name).is_err(), "{}", name);
+			assert!(registry.remove(name).is_err(), "{}", name);
+			assert!(registry.set(name, &responses).is_err(), "{}", name);
+			assert!(
+				registry
+					.set_many(&[(name.into(), responses.clone())])
+					.is_err(),
+				"{}",
+				name
+			);
+		}
+
+		assert_eq!(std::fs::read_to_string(&outside).unwrap(), "[]");
+		assert!(!dir.path().join("registry").exists());
+	}
+
+	#[test]
+	fn set_many() {
+		let dir = tempfile::tempdir().unwrap();
+		let registry = ResponseRegistry::new(dir.path().into());
+		let responses = |x: u64| -> PromptResponses {
+			vec![PromptResponse {
+				template: "shoesize".into(),
+				input: Input::Integer(x),
+			}]
+			.into()
+		};
+
+		registry.set("first", &responses(1)).unwrap();
+		registry
+			.set_many(&[
+				("first".into(), responses(2)),
+				("second".into(), responses(2)),
+			])
+			.unwrap();
+		assert_eq!(registry.get("first").unwrap(), responses(2));
+		assert_eq!(registry.get("second").unwrap(), responses(2));
+
+		assert!(
+			registry
+				.set_many(&[
+					("first".into(), responses(3)),
+					("first".into(), responses(4)),
+				])
+				.is_err()
+		);
+		assert_eq!(registry.get("first").unwrap(), responses(2));
+
+		// nowhere to write the second one, so the first must not change either
+		let blocker = dir.path().join(RESPONSES_SUBPATH).join("third.json.tmp");
+		std::fs::create_dir(&blocker).unwrap();
+		assert!(
+			registry
+				.set_many(&[
+					("first".into(), responses(3)),
+					("third".into(), responses(3)),
+				])
+				.is_err()
+		);
+		assert_eq!(registry.get("first").unwrap(), responses(2));
+		std::fs::remove_dir(&blocker).unwrap();
+
+		// the second one can't be moved aside, which fails after the first was already swapped in
+		let blocker = dir.path().join(RESPONSES_SUBPATH).join("second.json.bak");
+		std::fs::create_dir(&blocker).unwrap();
+		std::fs::write(blocker.join("file"), "").unwrap();
+		assert!(
+			registry
+				.set_many(&[
+					("first".into(), responses(3)),
+					("second".into(), responses(3)),
+				])
+				.is_err()
+		);
+		assert_eq!(registry.get("first").unwrap(), responses(2));
+		assert_eq!(registry.get("second").unwrap(), responses(2));
+
+		let leftovers = std::fs::read_dir(dir.path().join(RESPONSES_SUBPATH))
+			.unwrap()
+			.map(|x| x.unwrap().file_name().to_string_lossy().to_string())
+			.filter(|x| x.ends_with(".tmp"))
+			.count();
+		assert_eq!(leftovers, 0);
+	}
 }
@@ -1,4 +1,6 @@
-use super::messages::{ErrorReturn, Event, GenericReturn, JobInfo, QueryBlock, QueryJobs};
+use super::messages::{
+	ErrorReturn, Event, GenericReturn, JobInfo, QueryBlock, QueryJobs, QueryStatus, StatusInfo,
+};
 use anyhow::{Result, anyhow};
 use serde_json::{Value, json};
 use std::{
@@ -92,6 +94,12 @@ impl Client {
 		self.send_command("query-block", None)
 	}
 
+	pub fn status(&mut self) -> Result<StatusInfo> {
+		Ok(self
+			.send_command::<QueryStatus>("query-status", None)?
+			.result)
+	}
+
 	pub fn jobs(&mut self) -> Result<QueryJobs> {
 		self.send_command("query-jobs", None)
 	}
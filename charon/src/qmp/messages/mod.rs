@@ -39,6 +39,20 @@ pub struct QueryJobs {
 	pub result: Vec<JobInfo>,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct QueryStatus {
+	#[serde(rename = "return")]
+	pub result: StatusInfo,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatusInfo {
+	pub running: bool,
+	pub status: String,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct JobInfo {
@@ -0,0 +1,296 @@
+use crate::{Input, PromptResponse, PromptResponses, validate_registry_name};
+use anyhow::{Result, anyhow};
+use ring::{
+	aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+	hkdf::{HKDF_SHA256, Salt},
+	rand::{SecureRandom, SystemRandom},
+};
+use std::{
+	io::Write,
+	os::unix::fs::OpenOptionsExt,
+	path::{Path, PathBuf},
+};
+
+pub const SECRETS_SUBPATH: &str = "secrets";
+// outside the registry on purpose: the registry is a git checkout, and the key has no business
+// sitting next to what it encrypts.
+pub const DEFAULT_SECRET_KEY_PATH: &str = "/etc/charon/secret.key";
+
+pub const REDACTED: &str = "<redacted>";
+
+const HOST_SECRET_LEN: usize = 32;
+const KEY_SALT: &[u8] = b"charon package secrets";
+
+impl PromptResponses {
+	// separates the responses to secret prompts from the rest, as (plain, secret).
+	pub fn split_secrets(self) -> (Self, Self) {
+		let (secret, plain): (Vec<_>, Vec<_>) = self
+			.0
+			.into_iter()
+			.partition(|x| matches!(x.input, Input::Secret(_)));
+
+		(Self(plain), Self(secret))
+	}
+
+	pub fn has_secrets(&self) -> bool {
+		self.0.iter().any(|x| matches!(x.input, Input::Secret(_)))
+	}
+
+	// the same responses with every secret value blanked out, for anything that gets recorded
+	pub fn redact_secrets(self) -> Self {
+		Self(
+			self.0
+				.into_iter()
+				.map(|x| match x.input {
+					Input::Secret(_) => PromptResponse {
+						input: Input::Secret(REDACTED.into()),
+						..x
+					},
+					_ => x,
+				})
+				.collect(),
+		)
+	}
+}
+
+// responses to secret prompts, kept apart from the other responses and encrypted at rest. each
+// package's file is sealed with its own key, derived from the host secret and the package name,
+// so a file moved over to another package won't open either. the host secret is created the
+// first time something is stored.
+//
+// nothing here hands secrets back out over the API; they're only read when a package is compiled.
+#[derive(Debug, Clone)]
+pub struct SecretRegistry {
+	root: PathBuf,
+	key: PathBuf,
+}
+
+impl SecretRegistry {
+	pub fn new(root: PathBuf, key: PathBuf) -> Self {
+		Self { root, key }
+	}
+
+	fn path(&self, name: &str) -> PathBuf {
+		self.root
+			.join(SECRETS_SUBPATH)
+			.join(format!("{}.sealed", name))
+	}
+
+	pub fn get(&self, name: &str) -> Result<PromptResponses> {
+		validate_registry_name(name)?;
+
+		let mut data = match std::fs::read(self.path(name)) {
+			Ok(data) => data,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+			Err(e) => return Err(e.into()),
+		};
+
+		if data.len() < NONCE_LEN {
+			return Err(anyhow!("secrets for {} are truncated", name));
+		}
+
+		let (nonce, sealed) = data.split_at_mut(NONCE_LEN);
+		let nonce = Nonce::try_assume_unique_for_key(nonce)
+			.map_err(|_| anyhow!("secrets for {} are corrupt", name))?;
+		let plain = self
+			.sealing_key(name)?
+			.open_in_place(nonce, Aad::from(name.as_bytes()), sealed)
+			.map_err(|_| anyhow!("secrets for {} could not be decrypted", name))?;
+
+		Ok(serde_json::from_slice(plain)?)
+	}
+
+	pub fn set(&self, name: &str, secrets: &PromptResponses) -> Result<()> {
+		validate_registry_name(name)?;
+
+		let mut nonce = [0u8; NONCE_LEN];
+		SystemRandom::new()
+			.fill(&mut nonce)
+			.map_err(|_| anyhow!("could not generate a nonce"))?;
+
+		let mut sealed = serde_json::to_vec(secrets)?;
+		self.sealing_key(name)?
+			.seal_in_place_append_tag(
+				Nonce::assume_unique_for_key(nonce),
+				Aad::from(name.as_bytes()),
+				&mut sealed,
+			)
+			.map_err(|_| anyhow!("could not encrypt secrets for {}", name))?;
+
+		let pb = self.root.join(SECRETS_SUBPATH);
+		std::fs::create_dir_all(&pb)?;
+		let tmpname = pb.join(format!("{}.sealed.tmp", name));
+		let mut f = std::fs::OpenOptions::new()
+			.create(true)
+			.truncate(true)
+			.write(true)
+			.mode(0o600)
+			.open(&tmpname)?;
+		f.write_all(&nonce)?;
+		f.write_all(&sealed)?;
+
+		Ok(std::fs::rename(&tmpname, self.path(name))?)
+	}
+
+	// stores the given secrets over any with the same template, keeping the others. secrets can't
+	// be read back, so a caller resubmitting a package's responses can't be expected to include
+	// them all again.
+	pub fn merge(&self, name: &str, secrets: &PromptResponses) -> Result<()> {
+		validate_registry_name(name)?;
+
+		let mut all = self.get(name)?;
+
+		for secret in &secrets.0 {
+			match all.0.iter_mut().find(|x| x.template == secret.template) {
+				Some(existing) => *existing = secret.clone(),
+				None => all.0.push(secret.clone()),
+			}
+		}
+
+		self.set(name, &all)
+	}
+
+	pub fn remove(&self, name: &str) -> Result<()> {
+		validate_registry_name(name)?;
+		match std::fs::remove_file(self.path(name)) {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(()),
+		}
+	}
+
+	fn sealing_key(&self, name: &str) -> Result<LessSafeKey> {
+		let secret = host_secret(&self.key)?;
+		let prk = Salt::new(HKDF_SHA256, KEY_SALT).extract(&secret);
+		let info = [name.as_bytes()];
+		let okm = prk
+			.expand(&info, &CHACHA20_POLY1305)
+			.map_err(|_| anyhow!("could not derive a key for {}", name))?;
+
+		Ok(LessSafeKey::new(UnboundKey::from(okm)))
+	}
+}
+
+fn host_secret(path: &Path) -> Result<Vec<u8>> {
+	match std::fs::read(path) {
+		Ok(secret) if secret.len() >= HOST_SECRET_LEN => return Ok(secret),
+		Ok(_) => return Err(anyhow!("host secret at {} is too short", path.display())),
+		Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+		Err(_) => {}
+	}
+
+	let mut secret = vec![0u8; HOST_SECRET_LEN];
+	SystemRandom::new()
+		.fill(&mut secret)
+		.map_err(|_| anyhow!("could not generate a host secret"))?;
+
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	match std::fs::OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.mode(0o600)
+		.open(path)
+	{
+		Ok(mut f) => {
+			f.write_all(&secret)?;
+			Ok(secret)
+		}
+		// someone else got there first; theirs is the one in use now
+		Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(std::fs::read(path)?),
+		Err(e) => Err(e.into()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{SECRETS_SUBPATH, SecretRegistry};
+	use crate::{Input, PromptResponse, PromptResponses};
+	use std::os::unix::fs::PermissionsExt;
+
+	fn secret(template: &str, value: &str) -> PromptResponse {
+		PromptResponse {
+			template: template.into(),
+			input: Input::Secret(value.into()),
+		}
+	}
+
+	#[test]
+	fn io() {
+		let dir = tempfile::tempdir().unwrap();
+		let key = dir.path().join("keys/secret.key");
+		let registry = SecretRegistry::new(dir.path().into(), key.clone());
+
+		// nothing stored yet, and no reason to create the host secret for that
+		assert_eq!(registry.get("test").unwrap(), PromptResponses::default());
+		assert!(!key.exists());
+
+		let secrets: PromptResponses = vec![secret("token", "hunter2")].into();
+		registry.set("test", &secrets).unwrap();
+		assert_eq!(registry.get("test").unwrap(), secrets);
+		assert_eq!(
+			std::fs::metadata(&key).unwrap().permissions().mode() & 0o777,
+			0o600
+		);
+
+		let sealed = std::fs::read(dir.path().join(SECRETS_SUBPATH).join("test.sealed")).unwrap();
+		assert!(!String::from_utf8_lossy(&sealed).contains("hunter2"));
+
+		registry
+			.merge("test", &vec![secret("password", "swordfish")].into())
+			.unwrap();
+		registry
+			.merge("test", &vec![secret("token", "hunter3")].into())
+			.unwrap();
+		assert_eq!(
+			registry.get("test").unwrap(),
+			PromptResponses::from(vec![
+				secret("token", "hunter3"),
+				secret("password", "swordfish")
+			])
+		);
+
+		// sealed for "test", so it must not open as anything else
+		std::fs::copy(
+			dir.path().join(SECRETS_SUBPATH).join("test.sealed"),
+			dir.path().join(SECRETS_SUBPATH).join("other.sealed"),
+		)
+		.unwrap();
+		assert!(registry.get("other").is_err());
+
+		// nor with another host's secret
+		let elsewhere = SecretRegistry::new(dir.path().into(), dir.path().join("other.key"));
+		assert!(elsewhere.get("test").is_err());
+
+		registry.remove("test").unwrap();
+		registry.remove("test").unwrap();
+		assert_eq!(registry.get("test").unwrap(), PromptResponses::default());
+
+		// names that would land outside of the registry are refused
+		for name in ["../x", "..", "a/b"] {
+			assert!(registry.set(name, &secrets).is_err());
+			assert!(registry.merge(name, &secrets).is_err());
+			assert!(registry.get(name).is_err());
+			assert!(registry.remove(name).is_err());
+		}
+		assert!(!dir.path().join("x.sealed").exists());
+		assert!(!dir.path().join("x.sealed.tmp").exists());
+	}
+
+	#[test]
+	fn split() {
+		let plain = PromptResponse {
+			template: "greeting".into(),
+			input: Input::String("hello".into()),
+		};
+
+		let responses: PromptResponses = vec![plain.clone(), secret("token", "hunter2")].into();
+		assert!(responses.has_secrets());
+
+		let (p, s) = responses.split_secrets();
+		assert_eq!(p, PromptResponses::from(vec![plain]));
+		assert_eq!(s, PromptResponses::from(vec![secret("token", "hunter2")]));
+		assert!(!p.has_secrets());
+	}
+}
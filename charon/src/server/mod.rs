@@ -1,32 +1,138 @@
 use crate::{
-	Config, InputType, PromptResponses, ProtoPackageInstalled, ProtoPackageStatus,
-	ProtoPackageStatusList, ProtoPackageTitle, ProtoPackageTitleList, ProtoPrompt,
-	ProtoPromptResponses, ProtoPrompts, ProtoType, ProtoUninstallData, ResponseRegistry,
-	SystemdUnit,
+	CompileCache, CompiledPackage, CompiledSource, Config, InstallProgress, InstallStep,
+	LifecycleAction, PackageInstalledError, PackageTitle, PromptResponses, ProtoConsistencyReport,
+	ProtoDatasetName, ProtoDatasetOwner, ProtoDownloadProgress, ProtoEventFilter, ProtoInstallPlan,
+	ProtoInstallProgress, ProtoLifecycleEventList, ProtoPackageInstalled,
+	ProtoPackageRuntimeStatusList, ProtoPackageStatus, ProtoPackageStatusList, ProtoPackageTitle,
+	ProtoPackageTitleList, ProtoPackageVersions, ProtoPromptResponses, ProtoPromptResponsesBatch,
+	ProtoPrompts, ProtoReachability, ProtoUninstallData, ProtoVmStatus, ProvisionMode,
+	ResponseRegistry, SYSTEMD_SERVICE_ROOT, SystemdUnit,
 	control_server::{Control, ControlServer},
+	prefetch_vm_image, pull_container_image,
 	query_server::{Query, QueryServer},
 	status_server::{Status, StatusServer},
+	validate_registry_name, vm_status,
 };
-use std::{fs::Permissions, os::unix::fs::PermissionsExt, path::Path};
+use std::{
+	fs::Permissions,
+	os::unix::fs::PermissionsExt,
+	path::Path,
+	pin::Pin,
+	sync::{
+		Arc,
+		atomic::{AtomicUsize, Ordering},
+	},
+	time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
 use tonic::{Result, body::Body, transport::Server as TransportServer};
 use tonic_middleware::{Middleware, MiddlewareLayer, ServiceBound};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[cfg(test)]
 pub(crate) mod tests;
 
+// admission for the heavy operations; see OperationsConfig. clones share the same permits.
+#[derive(Debug, Clone)]
+pub(crate) struct OperationLimit {
+	permits: Arc<Semaphore>,
+	waiting: Arc<AtomicUsize>,
+	wait: Duration,
+}
+
+impl OperationLimit {
+	pub(crate) fn new(concurrency: usize, wait: Duration) -> Self {
+		Self {
+			// no permits at all would queue everything forever
+			permits: Arc::new(Semaphore::new(concurrency.max(1))),
+			waiting: Default::default(),
+			wait,
+		}
+	}
+
+	// the operation runs for as long as the permit is held
+	pub(crate) async fn acquire(&self, what: &str) -> Result<OwnedSemaphorePermit> {
+		if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+			return Ok(permit);
+		}
+
+		let position = self.waiting.fetch_add(1, Ordering::SeqCst) + 1;
+		info!("{} is queued at position {}", what, position);
+		let permit = tokio::time::timeout(self.wait, self.permits.clone().acquire_owned()).await;
+		self.waiting.fetch_sub(1, Ordering::SeqCst);
+
+		match permit {
+			Ok(Ok(permit)) => Ok(permit),
+			Ok(Err(e)) => Err(tonic::Status::new(tonic::Code::Internal, e.to_string())),
+			Err(_) => Err(tonic::Status::new(
+				tonic::Code::ResourceExhausted,
+				format!(
+					"charon is busy: {} was queued at position {} and did not start within {}s; try again later",
+					what,
+					position,
+					self.wait.as_secs()
+				),
+			)),
+		}
+	}
+}
+
+// where install and uninstall report their steps. the unary calls have nowhere to send them; the
+// streaming ones forward them to the caller, who may have gone away, which doesn't stop anything.
+#[derive(Debug, Clone, Default)]
+struct Progress(Option<mpsc::Sender<Result<ProtoInstallProgress>>>);
+
+impl Progress {
+	async fn step(&self, step: InstallStep) {
+		self.send(InstallProgress { step, error: None }).await
+	}
+
+	async fn finish(&self, result: &Result<()>) {
+		self.send(InstallProgress {
+			step: InstallStep::Finished,
+			error: result.as_ref().err().map(|e| e.message().to_string()),
+		})
+		.await
+	}
+
+	async fn send(&self, progress: InstallProgress) {
+		if let Some(tx) = &self.0 {
+			let _ = tx.send(Ok(progress.into())).await;
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct Server {
 	config: Config,
+	cache: CompileCache,
+	operations: OperationLimit,
 }
 
 impl Server {
 	pub fn new(config: Config) -> Self {
-		Self { config }
+		let operations = OperationLimit::new(
+			config.operations.concurrency,
+			Duration::from_secs(config.operations.wait),
+		);
+
+		Self {
+			config,
+			cache: Default::default(),
+			operations,
+		}
 	}
 
 	pub fn start(
 		&self,
+	) -> anyhow::Result<impl std::future::Future<Output = Result<(), tonic::transport::Error>>> {
+		self.start_with_shutdown(std::future::pending())
+	}
+
+	// stops accepting connections when signal resolves, then waits for in-flight calls to finish.
+	pub fn start_with_shutdown(
+		&self, signal: impl std::future::Future<Output = ()>,
 	) -> anyhow::Result<impl std::future::Future<Output = Result<(), tonic::transport::Error>>> {
 		info!("Starting service.");
 
@@ -48,114 +154,237 @@ impl Server {
 			.add_service(StatusServer::new(self.clone()))
 			.add_service(ControlServer::new(self.clone()))
 			.add_service(QueryServer::new(self.clone()))
-			.serve_with_incoming(uds_stream))
+			.serve_with_incoming_shutdown(uds_stream, signal))
 	}
-}
 
-#[tonic::async_trait]
-impl Status for Server {
-	async fn ping(&self, _: tonic::Request<()>) -> Result<tonic::Response<()>> {
-		Ok(tonic::Response::new(()))
-	}
-}
+	async fn install_package(&self, title: ProtoPackageTitle, progress: &Progress) -> Result<()> {
+		self.require_buckle().await?;
 
-#[tonic::async_trait]
-impl Control for Server {
-	async fn installed(
-		&self, title: tonic::Request<ProtoPackageTitle>,
-	) -> Result<tonic::Response<ProtoPackageInstalled>> {
 		let r = self.config.registry();
-		let title = title.into_inner();
+		let _permit = self
+			.operations
+			.acquire(&format!("install of {}-{}", title.name, title.version))
+			.await?;
 
-		let pkg = r
-			.load(&title.name, &title.version)
+		// otherwise provisioning recreates the installed copy's storage, and then only marking it
+		// installed fails
+		let package_title: PackageTitle = title.clone().into();
+		package_title
+			.validate()
+			.map_err(|e| tonic::Status::new(tonic::Code::InvalidArgument, e.to_string()))?;
+		if std::fs::exists(package_title.installed_path(&r.path()))
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
-			.compile()
+		{
+			return Err(tonic::Status::new(
+				tonic::Code::FailedPrecondition,
+				format!("{} is already installed", package_title),
+			));
+		}
+
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
-		Ok(tonic::Response::new(ProtoPackageInstalled {
-			proto_install_state: Some(
-				pkg.installed()
-					.await
-					.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
-					.into(),
-			),
-		}))
-	}
-
-	async fn install(
-		&self, title: tonic::Request<ProtoPackageTitle>,
-	) -> Result<tonic::Response<()>> {
-		let r = self.config.registry();
-		let title = title.into_inner();
+		self.check_ports(&pkg, &[]).await?;
 
-		let pkg = r
-			.load(&title.name, &title.version)
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
-			.compile()
+		// before anything is created, so a bad image leaves nothing behind to clean up
+		progress.step(InstallStep::Pulling).await;
+		pull_container_image(&pkg)
 			.await
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+			.map_err(|e| tonic::Status::new(tonic::Code::Unavailable, e.to_string()))?;
 
-		pkg.provision(&self.config.buckle_socket)
+		progress.step(InstallStep::Provisioning).await;
+		let created = pkg
+			.provision(&self.config.buckle_socket, ProvisionMode::Install)
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
-		pkg.install()
-			.await
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		// if a later step fails, what was done up to there is undone so that the package isn't
+		// left half installed
+		let mut installed = false;
+		let result: Result<()> = async {
+			progress.step(InstallStep::Installing).await;
+			pkg.install()
+				.await
+				.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+			installed = true;
 
-		self.write_unit(tonic::Request::new(ProtoPackageTitle {
-			name: title.name,
-			version: title.version,
-		}))
-		.await?;
+			self.write_package_unit(title.clone(), progress).await
+		}
+		.await;
 
-		Ok(tonic::Response::new(()))
+		if let Err(e) = result {
+			// the unit may have been written before starting it failed
+			if installed && let Err(e) = self.remove_unit(tonic::Request::new(title)).await {
+				error!("Could not remove unit of {}: {}", pkg.title, e.message());
+			}
+
+			if installed && let Err(e) = pkg.uninstall().await {
+				error!("Could not roll back install of {}: {}", pkg.title, e);
+			}
+
+			if let Err(e) = pkg.unprovision(&self.config.buckle_socket, &created).await {
+				error!("Could not remove storage for {}: {}", pkg.title, e);
+			}
+
+			return Err(e);
+		}
+
+		Ok(())
 	}
 
-	async fn uninstall(
-		&self, title: tonic::Request<ProtoUninstallData>,
-	) -> Result<tonic::Response<()>> {
+	async fn uninstall_package(
+		&self, title: ProtoUninstallData, progress: &Progress,
+	) -> Result<()> {
+		self.require_buckle().await?;
+
 		let r = self.config.registry();
-		let title = title.into_inner();
 
-		let pkg = r
-			.load(&title.name, &title.version)
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
-			.compile()
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
+		progress.step(InstallStep::Uninstalling).await;
 		pkg.uninstall()
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
 		if title.purge {
+			progress.step(InstallStep::Deprovisioning).await;
 			pkg.deprovision(&self.config.buckle_socket)
 				.await
 				.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 		}
 
+		progress.step(InstallStep::RemovingUnit).await;
 		self.remove_unit(tonic::Request::new(ProtoPackageTitle {
 			name: title.name.clone(),
 			version: title.version.clone(),
 		}))
 		.await?;
 
-		Ok(tonic::Response::new(()))
+		Ok(())
 	}
 
-	async fn write_unit(
-		&self, title: tonic::Request<ProtoPackageTitle>,
-	) -> Result<tonic::Response<()>> {
+	// undoes a rollback to target that failed partway: target is taken down again and the versions
+	// it was replacing are marked installed and get their units back. only logs what it can't
+	// undo, since the caller already has the error that got it here.
+	async fn restore_versions(&self, target: &CompiledPackage, previous: &[PackageTitle]) {
+		if let Err(e) = self
+			.remove_unit(tonic::Request::new(target.title.clone().into()))
+			.await
+		{
+			error!("Could not remove unit of {}: {}", target.title, e.message());
+		}
+
+		if let Err(e) = target.uninstall().await {
+			error!("Could not unmark {} as installed: {}", target.title, e);
+		}
+
 		let r = self.config.registry();
-		let title = title.into_inner();
+		for old in previous {
+			if !std::fs::exists(old.installed_path(&r.path())).unwrap_or_default() {
+				let restored = match self.cache.compile(&r, &old.name, &old.version).await {
+					Ok(pkg) => pkg.install().await,
+					Err(e) => Err(e),
+				};
 
-		let pkg = r
-			.load(&title.name, &title.version)
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
-			.compile()
+				if let Err(e) = restored {
+					error!("Could not mark {} as installed again: {}", old, e);
+					continue;
+				}
+			}
+
+			if let Err(e) = self
+				.write_unit(tonic::Request::new(old.clone().into()))
+				.await
+			{
+				error!("Could not restore unit of {}: {}", old, e.message());
+			}
+		}
+	}
+
+	// podman and qemu only notice a port that's already taken once the unit is started, long after
+	// the install has reported success. installed versions that pkg is about to replace don't
+	// count, since they'll be stopped first.
+	async fn check_ports(&self, pkg: &CompiledPackage, replacing: &[PackageTitle]) -> Result<()> {
+		let r = self.config.registry();
+
+		let installed = r
+			.installed()
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		for title in installed {
+			if title == pkg.title || replacing.contains(&title) {
+				continue;
+			}
+
+			// an installed package that no longer compiles can't be launched to hold its ports
+			let other = match self.cache.compile(&r, &title.name, &title.version).await {
+				Ok(other) => other,
+				Err(e) => {
+					warn!("Skipping port check against {}: {}", title, e);
+					continue;
+				}
+			};
+
+			if let Some(port) = pkg.conflicting_port(&other) {
+				return Err(tonic::Status::new(
+					tonic::Code::FailedPrecondition,
+					format!(
+						"Host port {} of {} is already used by installed package {}",
+						port, pkg.title, other.title
+					),
+				));
+			}
+		}
+
+		Ok(())
+	}
+
+	// both install calls go through here, so the event log sees every install
+	async fn install_observed(&self, title: ProtoPackageTitle, progress: &Progress) -> Result<()> {
+		let result = self.install_package(title.clone(), progress).await;
+		self.config.registry().event_log().observe(
+			&title.into(),
+			LifecycleAction::Install,
+			&result.as_ref().map_err(|e| e.message()),
+		);
+
+		result
+	}
+
+	async fn uninstall_observed(
+		&self, title: ProtoUninstallData, progress: &Progress,
+	) -> Result<()> {
+		let result = self.uninstall_package(title.clone(), progress).await;
+		let package_title = PackageTitle {
+			name: title.name,
+			version: title.version,
+		};
+		self.config.registry().event_log().observe(
+			&package_title,
+			LifecycleAction::Uninstall,
+			&result.as_ref().map_err(|e| e.message()),
+		);
+
+		result
+	}
+
+	async fn write_package_unit(
+		&self, title: ProtoPackageTitle, progress: &Progress,
+	) -> Result<()> {
+		self.require_buckle().await?;
+
+		let r = self.config.registry();
+
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
@@ -175,9 +404,10 @@ impl Control for Server {
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
-		unit.create_unit(
+		progress.step(InstallStep::WritingUnit).await;
+		unit.write_unit(
 			&self.config.registry.path,
-			&Into::<crate::PackageTitle>::into(title).format_volume(&Path::new(
+			&Into::<crate::PackageTitle>::into(title).format_volume(Path::new(
 				&zfs_client
 					.root_path()
 					.await
@@ -189,19 +419,145 @@ impl Control for Server {
 
 		info!("Wrote unit to {}", unit.filename().display());
 
+		progress.step(InstallStep::Starting).await;
+		unit.start()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(())
+	}
+
+	// runs op on its own task so that it finishes even if the caller goes away, streaming its steps
+	// and then a final Finished message with the outcome.
+	fn with_progress<F>(
+		&self, op: impl FnOnce(Self, Progress) -> F + Send + 'static,
+	) -> ProgressStream
+	where
+		F: std::future::Future<Output = Result<()>> + Send + 'static,
+	{
+		let (tx, rx) = mpsc::channel(10);
+		let this = self.clone();
+		tokio::spawn(async move {
+			let progress = Progress(Some(tx));
+			let result = op(this, progress.clone()).await;
+			progress.finish(&result).await;
+		});
+
+		Box::pin(ReceiverStream::new(rx))
+	}
+
+	async fn require_buckle(&self) -> Result<()> {
+		self.config
+			.check_buckle()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Unavailable, e.to_string()))
+	}
+}
+
+#[tonic::async_trait]
+impl Status for Server {
+	async fn ping(&self, _: tonic::Request<()>) -> Result<tonic::Response<()>> {
 		Ok(tonic::Response::new(()))
 	}
+}
+
+type ProgressStream = Pin<Box<dyn Stream<Item = Result<ProtoInstallProgress>> + Send>>;
+
+// registry errors that the caller can do something about get their own codes
+fn registry_status(e: anyhow::Error) -> tonic::Status {
+	let code = if e.is::<PackageInstalledError>() {
+		tonic::Code::FailedPrecondition
+	} else if e
+		.downcast_ref::<std::io::Error>()
+		.is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+	{
+		tonic::Code::NotFound
+	} else {
+		tonic::Code::Internal
+	};
+
+	tonic::Status::new(code, e.to_string())
+}
+
+#[tonic::async_trait]
+impl Control for Server {
+	type PrefetchImageStream = Pin<Box<dyn Stream<Item = Result<ProtoDownloadProgress>> + Send>>;
+	type InstallWithProgressStream = ProgressStream;
+	type UninstallWithProgressStream = ProgressStream;
+
+	async fn installed(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<ProtoPackageInstalled>> {
+		let r = self.config.registry();
+		let title: PackageTitle = title.into_inner().into();
+		title
+			.validate()
+			.map_err(|e| tonic::Status::new(tonic::Code::InvalidArgument, e.to_string()))?;
+
+		Ok(tonic::Response::new(ProtoPackageInstalled {
+			proto_install_state: Some(
+				title
+					.installed(&r.path())
+					.await
+					.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+					.into(),
+			),
+		}))
+	}
+
+	async fn install(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<()>> {
+		self.install_observed(title.into_inner(), &Progress::default())
+			.await
+			.map(tonic::Response::new)
+	}
+
+	async fn install_with_progress(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<Self::InstallWithProgressStream>> {
+		let title = title.into_inner();
+		Ok(tonic::Response::new(self.with_progress(
+			move |this, progress| async move { this.install_observed(title, &progress).await },
+		)))
+	}
+
+	async fn uninstall(
+		&self, title: tonic::Request<ProtoUninstallData>,
+	) -> Result<tonic::Response<()>> {
+		self.uninstall_observed(title.into_inner(), &Progress::default())
+			.await
+			.map(tonic::Response::new)
+	}
+
+	async fn uninstall_with_progress(
+		&self, title: tonic::Request<ProtoUninstallData>,
+	) -> Result<tonic::Response<Self::UninstallWithProgressStream>> {
+		let title = title.into_inner();
+		Ok(tonic::Response::new(self.with_progress(
+			move |this, progress| async move { this.uninstall_observed(title, &progress).await },
+		)))
+	}
+
+	async fn write_unit(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<()>> {
+		self.write_package_unit(title.into_inner(), &Progress::default())
+			.await
+			.map(tonic::Response::new)
+	}
 
 	async fn remove_unit(
 		&self, title: tonic::Request<ProtoPackageTitle>,
 	) -> Result<tonic::Response<()>> {
+		self.require_buckle().await?;
+
 		let r = self.config.registry();
 		let title = title.into_inner();
 
-		let pkg = r
-			.load(&title.name, &title.version)
-			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
-			.compile()
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
 			.await
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
 
@@ -219,6 +575,315 @@ impl Control for Server {
 
 		Ok(tonic::Response::new(()))
 	}
+
+	async fn prefetch_image(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<Self::PrefetchImageStream>> {
+		self.require_buckle().await?;
+
+		let r = self.config.registry();
+		let title = title.into_inner();
+
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		let root = self
+			.config
+			.buckle()
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.zfs()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.root_path()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		let volume_root = Into::<PackageTitle>::into(title).format_volume(Path::new(&root));
+
+		if !std::fs::exists(&volume_root)
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+		{
+			return Err(tonic::Status::new(
+				tonic::Code::FailedPrecondition,
+				format!("Package {} has not been provisioned", pkg.title),
+			));
+		}
+
+		let permit = self
+			.operations
+			.acquire(&format!("image download for {}", pkg.title))
+			.await?;
+
+		let (tx, rx) = tokio::sync::mpsc::channel(10);
+		tokio::task::spawn_blocking(move || {
+			let _permit = permit;
+			let progress = tx.clone();
+			if let Err(e) = prefetch_vm_image(&pkg, &volume_root, move |x| {
+				// a client that went away shouldn't stop the download, it is still useful
+				let _ = progress.blocking_send(Ok(x.into()));
+			}) {
+				error!("Error prefetching image for {}: {}", pkg.title, e);
+				let _ = tx.blocking_send(Err(tonic::Status::new(
+					tonic::Code::Internal,
+					e.to_string(),
+				)));
+			}
+		});
+
+		Ok(tonic::Response::new(
+			Box::pin(ReceiverStream::new(rx)) as Self::PrefetchImageStream
+		))
+	}
+
+	// drops the version from the cache too, so nothing compiles it from what's left in memory
+	async fn remove_version(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<()>> {
+		let title: PackageTitle = title.into_inner().into();
+
+		self.config
+			.registry()
+			.remove_version(&title.name, &title.version)
+			.map_err(registry_status)?;
+		self.cache.invalidate(&title.name);
+		info!("Removed package {} from the registry", title);
+
+		Ok(tonic::Response::new(()))
+	}
+
+	async fn plan_install(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<ProtoInstallPlan>> {
+		self.require_buckle().await?;
+
+		let r = self.config.registry();
+		let title = title.into_inner();
+
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		let root = self
+			.config
+			.buckle()
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.zfs()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.root_path()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		let volume_root = Into::<PackageTitle>::into(title).format_volume(Path::new(&root));
+
+		let unit_file = SystemdUnit::new(
+			self.config.buckle_socket.clone(),
+			pkg.clone(),
+			self.config.systemd_root.clone(),
+			self.config.charon_path.clone(),
+		)
+		.filename();
+
+		let plan = pkg
+			.plan(&self.config.buckle_socket, &volume_root, unit_file)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(tonic::Response::new(plan.into()))
+	}
+
+	async fn vm_status(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<ProtoVmStatus>> {
+		self.require_buckle().await?;
+
+		let r = self.config.registry();
+		let title = title.into_inner();
+
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		// containers have their state in the unit, which installed() already reports
+		if !matches!(pkg.source, CompiledSource::QEmu(_)) {
+			return Err(tonic::Status::new(
+				tonic::Code::FailedPrecondition,
+				format!("{} is not a virtual machine package", pkg.title),
+			));
+		}
+
+		let root = self
+			.config
+			.buckle()
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.zfs()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.root_path()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		let volume_root = Into::<PackageTitle>::into(title).format_volume(Path::new(&root));
+
+		let status = tokio::task::spawn_blocking(move || vm_status(&pkg, &volume_root))
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(tonic::Response::new(status.into()))
+	}
+
+	// picks up changed responses for an installed package. storage is only ever added by
+	// provisioning, so existing data is left alone.
+	async fn reconfigure(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<()>> {
+		let r = self.config.registry();
+		let title = title.into_inner();
+		let package_title: PackageTitle = title.clone().into();
+		package_title
+			.validate()
+			.map_err(|e| tonic::Status::new(tonic::Code::InvalidArgument, e.to_string()))?;
+
+		if !std::fs::exists(package_title.installed_path(&r.path()))
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+		{
+			return Err(tonic::Status::new(
+				tonic::Code::FailedPrecondition,
+				format!("Package {} is not installed", package_title),
+			));
+		}
+
+		self.require_buckle().await?;
+		let _permit = self
+			.operations
+			.acquire(&format!("reconfiguration of {}", package_title))
+			.await?;
+
+		self.cache.invalidate(&title.name);
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		// the new responses may have moved its ports onto another package's
+		self.check_ports(&pkg, &[]).await?;
+
+		pkg.provision(&self.config.buckle_socket, ProvisionMode::Repair)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		// write_unit only starts the service, which does nothing if it is already running
+		self.config
+			.buckle()
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.systemd()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.stop_unit(format!("{}.service", package_title))
+			.await?;
+
+		self.write_unit(tonic::Request::new(title)).await?;
+		info!("Reconfigured package {}", package_title);
+
+		Ok(tonic::Response::new(()))
+	}
+
+	// switches an installed package over to another version from the registry, usually an older
+	// one after an upgrade went wrong. storage belongs to the package rather than a version, so it
+	// is kept; only the unit and the installed marker move to the new version.
+	async fn rollback(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<()>> {
+		let r = self.config.registry();
+		let title = title.into_inner();
+		let target: PackageTitle = title.clone().into();
+
+		let current = r
+			.installed()
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+			.into_iter()
+			.filter(|x| x.name == target.name)
+			.collect::<Vec<PackageTitle>>();
+
+		if current.is_empty() {
+			return Err(tonic::Status::new(
+				tonic::Code::FailedPrecondition,
+				format!("Package {} is not installed", target.name),
+			));
+		}
+
+		if current.contains(&target) {
+			return Err(tonic::Status::new(
+				tonic::Code::FailedPrecondition,
+				format!("{} is already installed", target),
+			));
+		}
+
+		self.require_buckle().await?;
+		let _permit = self
+			.operations
+			.acquire(&format!("rollback of {}", target))
+			.await?;
+
+		// compiled before anything is stopped, so a version that can't be used changes nothing
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		// nor may it declare ports the current version doesn't
+		self.check_ports(&pkg, &current).await?;
+
+		// the target may have volumes the current version doesn't
+		pkg.provision(&self.config.buckle_socket, ProvisionMode::Repair)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		// the target is marked installed before anything of the current version is taken down,
+		// and if it can't be brought up the current version is put back, so a failed rollback
+		// doesn't leave the package with no version installed.
+		pkg.install()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		let result: Result<()> = async {
+			for old in &current {
+				self.config
+					.buckle()
+					.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+					.systemd()
+					.await
+					.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?
+					.stop_unit(format!("{}.service", old))
+					.await?;
+
+				self.remove_unit(tonic::Request::new(old.clone().into()))
+					.await?;
+				std::fs::remove_file(old.installed_path(&r.path()))
+					.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+			}
+
+			self.write_unit(tonic::Request::new(title)).await?;
+			Ok(())
+		}
+		.await;
+
+		if let Err(e) = result {
+			self.restore_versions(&pkg, &current).await;
+			return Err(e);
+		}
+
+		info!("Rolled package {} back to {}", target.name, target.version);
+
+		Ok(tonic::Response::new(()))
+	}
 }
 
 #[tonic::async_trait]
@@ -268,6 +933,103 @@ impl Query for Server {
 		Ok(tonic::Response::new(ProtoPackageStatusList { list: v }))
 	}
 
+	async fn list_with_status(
+		&self, _empty: tonic::Request<()>,
+	) -> Result<tonic::Response<ProtoPackageRuntimeStatusList>> {
+		let r = self.config.registry();
+
+		let list = r
+			.list_with_status()
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(tonic::Response::new(ProtoPackageRuntimeStatusList {
+			list: list.into_iter().map(Into::into).collect(),
+		}))
+	}
+
+	async fn check_reachable(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<ProtoReachability>> {
+		let r = self.config.registry();
+		let title = title.into_inner();
+
+		let pkg = self
+			.cache
+			.compile(&r, &title.name, &title.version)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(tonic::Response::new(ProtoReachability {
+			ports: pkg
+				.check_reachable()
+				.await
+				.into_iter()
+				.map(Into::into)
+				.collect(),
+		}))
+	}
+
+	async fn list_events(
+		&self, filter: tonic::Request<ProtoEventFilter>,
+	) -> Result<tonic::Response<ProtoLifecycleEventList>> {
+		let events = self
+			.config
+			.registry()
+			.event_log()
+			.list(filter.into_inner().name.as_deref())
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(tonic::Response::new(ProtoLifecycleEventList {
+			list: events.into_iter().map(Into::into).collect(),
+		}))
+	}
+
+	async fn dataset_owner(
+		&self, dataset: tonic::Request<ProtoDatasetName>,
+	) -> Result<tonic::Response<ProtoDatasetOwner>> {
+		let owner = self
+			.config
+			.registry()
+			.dataset_owner(&dataset.into_inner().name)
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(tonic::Response::new(ProtoDatasetOwner {
+			owner: owner.map(Into::into),
+		}))
+	}
+
+	async fn list_versions(
+		&self, title: tonic::Request<ProtoPackageTitle>,
+	) -> Result<tonic::Response<ProtoPackageVersions>> {
+		let versions = self
+			.config
+			.registry()
+			.list_versions(&title.into_inner().name)
+			.map_err(registry_status)?;
+
+		Ok(tonic::Response::new(ProtoPackageVersions { versions }))
+	}
+
+	async fn check_consistency(
+		&self, _: tonic::Request<()>,
+	) -> Result<tonic::Response<ProtoConsistencyReport>> {
+		let systemd_root = self
+			.config
+			.systemd_root
+			.clone()
+			.unwrap_or(SYSTEMD_SERVICE_ROOT.into());
+
+		let report = self
+			.config
+			.registry()
+			.check_consistency(&systemd_root)
+			.await
+			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+
+		Ok(tonic::Response::new(report.into()))
+	}
+
 	async fn get_responses(
 		&self, title: tonic::Request<ProtoPackageTitle>,
 	) -> Result<tonic::Response<ProtoPromptResponses>> {
@@ -299,19 +1061,8 @@ impl Query for Server {
 
 		let mut out = ProtoPrompts::default();
 
-		for prompt in &prompts.to_vec() {
-			// FIXME: do a From trait
-			out.prompts.push(ProtoPrompt {
-				template: prompt.template.clone(),
-				question: prompt.question.clone(),
-				input_type: match prompt.input_type {
-					InputType::String => ProtoType::String,
-					InputType::Integer => ProtoType::Integer,
-					InputType::SignedInteger => ProtoType::SignedInteger,
-					InputType::Boolean => ProtoType::Boolean,
-				}
-				.into(),
-			})
+		for prompt in prompts.to_vec() {
+			out.prompts.push(prompt.into())
 		}
 
 		Ok(tonic::Response::new(out))
@@ -323,18 +1074,93 @@ impl Query for Server {
 		let r = self.config.registry();
 		let responses = responses.into_inner();
 
+		// secrets are written before the plain responses, so the name has to be checked before
+		// either of them is
+		validate_registry_name(&responses.name)
+			.map_err(|e| tonic::Status::new(tonic::Code::InvalidArgument, e.to_string()))?;
+
 		let mut pr = Vec::new();
 		for response in responses.responses {
 			pr.push(response.into());
 		}
 
+		let (plain, secrets) = PromptResponses(pr).split_secrets();
+		if !secrets.0.is_empty() {
+			r.secret_registry()
+				.merge(&responses.name, &secrets)
+				.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		}
+
 		r.response_registry()
-			.set(&responses.name, &PromptResponses(pr))
+			.set(&responses.name, &plain)
 			.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?;
+		self.cache.invalidate(&responses.name);
 		info!("Wrote responses for package {}", responses.name);
 
 		Ok(tonic::Response::new(()))
 	}
+
+	async fn set_responses_batch(
+		&self, batch: tonic::Request<ProtoPromptResponsesBatch>,
+	) -> Result<tonic::Response<()>> {
+		let r = self.config.registry();
+		let secret_registry = r.secret_registry();
+
+		let mut all = Vec::new();
+		let mut secrets = Vec::new();
+		for responses in batch.into_inner().packages {
+			validate_registry_name(&responses.name)
+				.map_err(|e| tonic::Status::new(tonic::Code::InvalidArgument, e.to_string()))?;
+
+			let (plain, secret) =
+				PromptResponses(responses.responses.into_iter().map(Into::into).collect())
+					.split_secrets();
+			if !secret.0.is_empty() {
+				secrets.push((responses.name.clone(), secret));
+			}
+			all.push((responses.name, plain));
+		}
+
+		// secrets live in their own registry and are stored first. if the plain responses can't
+		// be, every package's secrets are put back the way they were.
+		let mut previous = Vec::new();
+		for (name, _) in &secrets {
+			previous.push((
+				name,
+				secret_registry
+					.get(name)
+					.map_err(|e| tonic::Status::new(tonic::Code::Internal, e.to_string()))?,
+			));
+		}
+
+		let res = secrets
+			.iter()
+			.try_for_each(|(name, secret)| secret_registry.merge(name, secret))
+			.and_then(|_| r.response_registry().set_many(&all));
+
+		if let Err(e) = res {
+			for (name, old) in previous.into_iter().rev() {
+				let restored = if old.0.is_empty() {
+					secret_registry.remove(name)
+				} else {
+					secret_registry.set(name, &old)
+				};
+
+				if let Err(e) = restored {
+					error!("Could not restore secrets for package {}: {}", name, e);
+				}
+			}
+
+			return Err(tonic::Status::new(tonic::Code::Internal, e.to_string()));
+		}
+
+		for (name, _) in &all {
+			self.cache.invalidate(name);
+			info!("Wrote responses for package {}", name);
+		}
+
+		Ok(tonic::Response::new(()))
+	}
 }
 
 #[derive(Default, Clone)]
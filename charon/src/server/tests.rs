@@ -1,6 +1,6 @@
 use crate::{
 	Client, Config, Input, InputType, PackageStatus, PackageTitle, Prompt, PromptCollection,
-	PromptResponse, PromptResponses, RegistryConfig, Server,
+	PromptResponse, PromptResponses, ProvisionMode, RegistryConfig, Server,
 };
 use std::path::PathBuf;
 use tempfile::{NamedTempFile, tempdir};
@@ -26,8 +26,12 @@ pub async fn start_server(
 			socket: "".into(), // ovewrites socket on create, not sure why
 			zfs: buckle::config::ZFSConfig {
 				pool: zpool.clone(),
+				timeout: buckle::config::DEFAULT_ZFS_TIMEOUT,
+				default_quota: None,
 			},
 			log_level: buckle::config::LogLevel::Debug,
+			commands: Default::default(),
+			port_state: "".into(), // also overwritten
 		}))
 		.await
 		.unwrap();
@@ -55,10 +59,14 @@ pub async fn start_server(
 		registry: RegistryConfig {
 			path: "testdata/registry".into(),
 			url: None,
+			strict_sync: false,
 		},
 		systemd_root: inner,
 		charon_path: Some(crate::DEFAULT_CHARON_BIN_PATH.into()),
+		secret_key: Some(pb.with_extension("key")),
 		buckle_socket: bi.map(|x| x.0).unwrap_or("/tmp/buckled.sock".into()),
+		operations: Default::default(),
+		commands: Default::default(),
 	};
 	let inner_config = config.clone();
 
@@ -74,6 +82,64 @@ async fn test_ping() {
 	client.status().await.unwrap().ping().await.unwrap();
 }
 
+#[tokio::test]
+async fn shutdown() {
+	let (config, ..) = start_server(true, None).await;
+	let (_, socket) = NamedTempFile::new().unwrap().keep().unwrap();
+	let config = Config {
+		socket: socket.clone(),
+		..config
+	};
+
+	let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+	let handle = tokio::spawn(async move {
+		Server::new(config)
+			.start_with_shutdown(async move {
+				let _ = rx.await;
+			})
+			.unwrap()
+			.await
+	});
+	tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+	let client = Client::new(socket).unwrap();
+	client.status().await.unwrap().ping().await.unwrap();
+
+	tx.send(()).unwrap();
+	tokio::time::timeout(std::time::Duration::from_secs(5), handle)
+		.await
+		.expect("server did not stop")
+		.unwrap()
+		.unwrap();
+
+	// nothing is listening any more
+	assert!(client.status().await.is_err());
+}
+
+#[tokio::test]
+async fn buckle_unavailable() {
+	let (config, socket, _, _) = start_server(true, None).await;
+	assert!(config.check_buckle().await.is_err());
+
+	let err = Client::new(socket)
+		.unwrap()
+		.control()
+		.await
+		.unwrap()
+		.write_unit("plex", "0.0.2")
+		.await
+		.unwrap_err();
+
+	assert!(
+		err.to_string().contains(&format!(
+			"buckle unavailable at {}",
+			config.buckle_socket.display()
+		)),
+		"{}",
+		err
+	);
+}
+
 #[tokio::test]
 async fn test_write_unit_real() {
 	// real mode. validate written. this test also reloads systemd (which doesn't pick up anything
@@ -188,16 +254,34 @@ async fn test_get_prompts() {
 			template: "private_path".into(),
 			question: "Where do you want this mounted?".into(),
 			input_type: InputType::String,
+			options: Default::default(),
+			required: false,
+			default: None,
+			pattern: None,
+			min: None,
+			max: None,
 		},
 		Prompt {
 			template: "private_size".into(),
 			question: "How big should it be?".into(),
 			input_type: InputType::Integer,
+			options: Default::default(),
+			required: false,
+			default: None,
+			pattern: None,
+			min: None,
+			max: None,
 		},
 		Prompt {
 			template: "private_recreate".into(),
 			question: "Should we recreate this volume if it already exists?".into(),
 			input_type: InputType::Boolean,
+			options: Default::default(),
+			required: false,
+			default: None,
+			pattern: None,
+			min: None,
+			max: None,
 		},
 	]);
 
@@ -241,19 +325,126 @@ async fn set_get_responses() {
 	assert_eq!(responses, responses2);
 }
 
+#[tokio::test]
+async fn set_responses_batch_with_secrets() {
+	let (config, path, _, _) = start_server(true, None).await;
+	let secrets = config.registry().secret_registry();
+	let names = ["batch-secrets-a", "batch-secrets-b"];
+
+	// anything left over from an earlier run was sealed with another key, and won't open
+	for name in names {
+		secrets.remove(name).unwrap();
+	}
+
+	let plain = PromptResponse {
+		input: Input::String("hello".into()),
+		template: "greeting".into(),
+	};
+	let secret = |value: &str| PromptResponse {
+		input: Input::Secret(value.into()),
+		template: "token".into(),
+	};
+
+	let client = Client::new(path).unwrap();
+	client
+		.query()
+		.await
+		.unwrap()
+		.set_responses_batch(
+			names
+				.iter()
+				.map(|name| {
+					(
+						name.to_string(),
+						PromptResponses(vec![plain.clone(), secret("hunter2")]),
+					)
+				})
+				.collect(),
+		)
+		.await
+		.unwrap();
+
+	for name in names {
+		assert_eq!(
+			client
+				.query()
+				.await
+				.unwrap()
+				.get_responses(name)
+				.await
+				.unwrap(),
+			PromptResponses(vec![plain.clone()])
+		);
+		assert_eq!(
+			secrets.get(name).unwrap(),
+			PromptResponses(vec![secret("hunter2")])
+		);
+	}
+
+	// the same package twice is refused as a whole, and the secrets stay as they were
+	assert!(
+		client
+			.query()
+			.await
+			.unwrap()
+			.set_responses_batch(vec![
+				(names[0].into(), PromptResponses(vec![secret("swordfish")])),
+				(names[0].into(), PromptResponses(vec![secret("swordfish")])),
+			])
+			.await
+			.is_err()
+	);
+	assert_eq!(
+		secrets.get(names[0]).unwrap(),
+		PromptResponses(vec![secret("hunter2")])
+	);
+
+	// a name that can't be stored is the caller's mistake
+	let err = client
+		.query()
+		.await
+		.unwrap()
+		.set_responses_batch(vec![(
+			"../x".into(),
+			PromptResponses(vec![secret("swordfish")]),
+		)])
+		.await
+		.unwrap_err();
+	assert_eq!(
+		err.downcast_ref::<tonic::Status>().unwrap().code(),
+		tonic::Code::InvalidArgument
+	);
+	let err = client
+		.query()
+		.await
+		.unwrap()
+		.set_responses("../x", PromptResponses(vec![secret("swordfish")]))
+		.await
+		.unwrap_err();
+	assert_eq!(
+		err.downcast_ref::<tonic::Status>().unwrap().code(),
+		tonic::Code::InvalidArgument
+	);
+}
+
 #[tokio::test]
 async fn list() {
 	// NOTE: this table must be updated anytime testdata's registry is.
 	// Packages are sorted by name first, then in reverse order by version.
 	let table = vec![
-		("bad-dependencies", vec!["0.0.3", "0.0.2", "0.0.1"]),
+		("bad-dependencies", vec!["0.0.4", "0.0.3", "0.0.2", "0.0.1"]),
 		("bad-name-version", vec!["0.0.2", "0.0.1"]),
+		("bad-system", vec!["0.0.2", "0.0.1"]),
+		("cycle-a", vec!["0.0.1"]),
+		("cycle-b", vec!["0.0.1"]),
 		("no-variables", vec!["0.0.1"]),
 		("plex", vec!["0.0.2", "0.0.1"]),
 		("plex-qemu", vec!["0.0.2", "0.0.1"]),
 		("podman-test", vec!["0.0.3", "0.0.2", "0.0.1"]),
-		("with-dependencies", vec!["0.0.1"]),
+		("shared-port", vec!["0.0.1"]),
+		("with-dependencies", vec!["0.0.2", "0.0.1"]),
 		("with-prompts", vec!["0.0.1"]),
+		("with-required-prompts", vec!["0.0.1"]),
 	];
 
 	let mut v = Vec::new();
@@ -276,6 +467,115 @@ async fn list() {
 	assert_eq!(list, v)
 }
 
+#[tokio::test]
+async fn check_reachable() {
+	let client = Client::new(start_server(true, None).await.1.to_path_buf()).unwrap();
+
+	let ports = client
+		.query()
+		.await
+		.unwrap()
+		.check_reachable("podman-test", "0.0.3")
+		.await
+		.unwrap();
+	assert_eq!(ports.len(), 1);
+	assert_eq!(ports[0].port, 8000);
+}
+
+#[tokio::test]
+async fn list_with_status() {
+	use crate::{InstallStatus, PackageRuntimeStatus};
+
+	let client = Client::new(start_server(true, None).await.1.to_path_buf()).unwrap();
+
+	let list = client.query().await.unwrap().list().await.unwrap();
+	let with_status = client
+		.query()
+		.await
+		.unwrap()
+		.list_with_status()
+		.await
+		.unwrap();
+
+	assert_eq!(
+		with_status,
+		list.into_iter()
+			.map(|x| PackageRuntimeStatus {
+				title: x.title,
+				status: InstallStatus::NotInstalled,
+			})
+			.collect::<Vec<_>>()
+	);
+}
+
+#[tokio::test]
+async fn reconfigure_not_installed() {
+	let client = Client::new(start_server(true, None).await.1.to_path_buf()).unwrap();
+
+	let err = client
+		.control()
+		.await
+		.unwrap()
+		.reconfigure("plex", "0.0.2")
+		.await
+		.unwrap_err();
+	assert_eq!(
+		err.downcast_ref::<tonic::Status>().unwrap().code(),
+		tonic::Code::FailedPrecondition
+	);
+}
+
+#[tokio::test]
+async fn rollback_not_installed() {
+	let client = Client::new(start_server(true, None).await.1.to_path_buf()).unwrap();
+
+	let err = client
+		.control()
+		.await
+		.unwrap()
+		.rollback("plex", "0.0.1")
+		.await
+		.unwrap_err();
+	assert_eq!(
+		err.downcast_ref::<tonic::Status>().unwrap().code(),
+		tonic::Code::FailedPrecondition
+	);
+}
+
+#[tokio::test]
+async fn remove_version_missing() {
+	let client = Client::new(start_server(true, None).await.1.to_path_buf()).unwrap();
+
+	let err = client
+		.control()
+		.await
+		.unwrap()
+		.remove_version("plex", "9.9.9")
+		.await
+		.unwrap_err();
+	assert_eq!(
+		err.downcast_ref::<tonic::Status>().unwrap().code(),
+		tonic::Code::NotFound
+	);
+}
+
+#[tokio::test]
+async fn list_versions() {
+	let client = Client::new(start_server(true, None).await.1.to_path_buf()).unwrap();
+	let mut query = client.query().await.unwrap();
+
+	assert_eq!(
+		query.list_versions("podman-test").await.unwrap(),
+		vec!["0.0.3", "0.0.2", "0.0.1"]
+	);
+
+	let err = query.list_versions("missing").await.unwrap_err();
+	assert_eq!(
+		err.downcast_ref::<tonic::Status>().unwrap().code(),
+		tonic::Code::NotFound
+	);
+}
+
 #[tokio::test]
 async fn installer() {
 	use crate::{InstallStatus, PackageTitle};
@@ -289,6 +589,33 @@ async fn installer() {
 			.to_path_buf(),
 	)
 	.unwrap();
+
+	// planning mustn't do any of what it describes
+	let plan = client
+		.control()
+		.await
+		.unwrap()
+		.plan_install("plex", "0.0.2")
+		.await
+		.unwrap();
+	assert_eq!(plan.image, "scratch");
+	assert!(!plan.virtual_machine);
+	assert!(plan.storage.is_empty());
+	assert_eq!(plan.command[..2], ["podman", "run"]);
+	assert!(plan.command.contains(&"scratch".to_string()));
+	assert!(plan.unit_file.ends_with("plex-0.0.2.service"));
+	assert!(matches!(
+		client
+			.control()
+			.await
+			.unwrap()
+			.installed("plex", "0.0.2")
+			.await
+			.unwrap()
+			.unwrap(),
+		InstallStatus::NotInstalled,
+	));
+
 	client
 		.control()
 		.await
@@ -329,7 +656,48 @@ async fn installer() {
 		.control()
 		.await
 		.unwrap()
-		.uninstall("plex", "0.0.2", true)
+		.reconfigure("plex", "0.0.2")
+		.await
+		.unwrap();
+
+	// already the installed version
+	assert!(
+		client
+			.control()
+			.await
+			.unwrap()
+			.rollback("plex", "0.0.2")
+			.await
+			.is_err()
+	);
+
+	client
+		.control()
+		.await
+		.unwrap()
+		.rollback("plex", "0.0.1")
+		.await
+		.unwrap();
+
+	assert_eq!(
+		client
+			.query()
+			.await
+			.unwrap()
+			.list_installed()
+			.await
+			.unwrap(),
+		vec![PackageTitle {
+			name: "plex".into(),
+			version: "0.0.1".into()
+		}]
+	);
+
+	client
+		.control()
+		.await
+		.unwrap()
+		.uninstall("plex", "0.0.1", true)
 		.await
 		.unwrap();
 
@@ -358,3 +726,506 @@ async fn installer() {
 
 	let _ = buckle::testutil::destroy_zpool("test-installer", None);
 }
+
+#[tokio::test]
+async fn install_progress() {
+	use crate::{InstallProgress, InstallStep};
+
+	let _ = buckle::testutil::destroy_zpool("test-install-progress", None);
+
+	let client = Client::new(
+		start_server(true, Some("test-install-progress".into()))
+			.await
+			.1
+			.to_path_buf(),
+	)
+	.unwrap();
+
+	let mut steps = Vec::new();
+	let mut stream = client
+		.control()
+		.await
+		.unwrap()
+		.install_with_progress("plex", "0.0.2")
+		.await
+		.unwrap();
+	while let Some(msg) = stream.message().await.unwrap() {
+		steps.push(InstallProgress::from(msg));
+	}
+
+	assert_eq!(
+		steps.iter().map(|x| x.step).collect::<Vec<_>>(),
+		vec![
+			InstallStep::Pulling,
+			InstallStep::Provisioning,
+			InstallStep::Installing,
+			InstallStep::WritingUnit,
+			InstallStep::Starting,
+			InstallStep::Finished,
+		]
+	);
+	assert!(steps.iter().all(|x| x.error.is_none()));
+
+	let mut steps = Vec::new();
+	let mut stream = client
+		.control()
+		.await
+		.unwrap()
+		.uninstall_with_progress("plex", "0.0.2", true)
+		.await
+		.unwrap();
+	while let Some(msg) = stream.message().await.unwrap() {
+		steps.push(InstallProgress::from(msg));
+	}
+
+	assert_eq!(
+		steps.iter().map(|x| x.step).collect::<Vec<_>>(),
+		vec![
+			InstallStep::Uninstalling,
+			InstallStep::Deprovisioning,
+			InstallStep::RemovingUnit,
+			InstallStep::Finished,
+		]
+	);
+	assert!(steps.iter().all(|x| x.error.is_none()));
+
+	// failures still end the stream with Finished, rather than with an error status
+	let mut steps = Vec::new();
+	let mut stream = client
+		.control()
+		.await
+		.unwrap()
+		.install_with_progress("missing", "0.0.1")
+		.await
+		.unwrap();
+	while let Some(msg) = stream.message().await.unwrap() {
+		steps.push(InstallProgress::from(msg));
+	}
+
+	assert_eq!(steps.len(), 1);
+	assert_eq!(steps[0].step, InstallStep::Finished);
+	assert!(steps[0].error.is_some());
+
+	let _ = buckle::testutil::destroy_zpool("test-install-progress", None);
+}
+
+#[tokio::test]
+async fn install_port_conflict() {
+	use crate::InstallStatus;
+
+	let _ = buckle::testutil::destroy_zpool("test-port-conflict", None);
+
+	let (_, socket, _, buckle_info) = start_server(true, Some("test-port-conflict".into())).await;
+	let client = Client::new(socket.to_path_buf()).unwrap();
+
+	client
+		.control()
+		.await
+		.unwrap()
+		.install("plex-qemu", "0.0.1")
+		.await
+		.unwrap();
+
+	let err = client
+		.control()
+		.await
+		.unwrap()
+		.install("shared-port", "0.0.1")
+		.await
+		.unwrap_err()
+		.to_string();
+	assert!(err.contains("2345"), "{}", err);
+	assert!(err.contains("plex-qemu-0.0.1"), "{}", err);
+
+	assert!(matches!(
+		client
+			.control()
+			.await
+			.unwrap()
+			.installed("shared-port", "0.0.1")
+			.await
+			.unwrap()
+			.unwrap(),
+		InstallStatus::NotInstalled,
+	));
+
+	// nothing is holding the port once plex-qemu is gone
+	client
+		.control()
+		.await
+		.unwrap()
+		.uninstall("plex-qemu", "0.0.1", false)
+		.await
+		.unwrap();
+	client
+		.control()
+		.await
+		.unwrap()
+		.install("shared-port", "0.0.1")
+		.await
+		.unwrap();
+
+	// rolling back to a version that wants the port is refused too, and leaves the current one
+	client
+		.control()
+		.await
+		.unwrap()
+		.install("plex-qemu", "0.0.2")
+		.await
+		.unwrap();
+	let err = client
+		.control()
+		.await
+		.unwrap()
+		.rollback("plex-qemu", "0.0.1")
+		.await
+		.unwrap_err()
+		.to_string();
+	assert!(err.contains("2345"), "{}", err);
+	assert!(err.contains("shared-port-0.0.1"), "{}", err);
+
+	assert!(matches!(
+		client
+			.control()
+			.await
+			.unwrap()
+			.installed("plex-qemu", "0.0.2")
+			.await
+			.unwrap()
+			.unwrap(),
+		InstallStatus::Installed(_),
+	));
+
+	buckle::testutil::destroy_zpool("test-port-conflict", Some(&buckle_info.unwrap().2)).unwrap();
+}
+
+#[tokio::test]
+async fn install_rollback() {
+	use crate::InstallStatus;
+
+	let _ = buckle::testutil::destroy_zpool("test-install-rollback", None);
+
+	let (config, _, _, buckle_info) =
+		start_server(true, Some("test-install-rollback".into())).await;
+	let (buckle_socket, _, file) = buckle_info.unwrap();
+
+	// units can't be written beneath a file, so the install fails after its storage has been
+	// provisioned and it has been marked installed
+	let not_a_dir = NamedTempFile::new().unwrap();
+	let (_, socket) = NamedTempFile::new().unwrap().keep().unwrap();
+	let config = Config {
+		socket: socket.clone(),
+		systemd_root: Some(not_a_dir.path().join("units")),
+		..config
+	};
+	tokio::spawn(async move { Server::new(config).start().unwrap().await.unwrap() });
+	tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+	let client = Client::new(socket).unwrap();
+	assert!(
+		client
+			.control()
+			.await
+			.unwrap()
+			.install("plex-qemu", "0.0.2")
+			.await
+			.is_err()
+	);
+
+	assert!(matches!(
+		client
+			.control()
+			.await
+			.unwrap()
+			.installed("plex-qemu", "0.0.2")
+			.await
+			.unwrap()
+			.unwrap(),
+		InstallStatus::NotInstalled,
+	));
+
+	let list = buckle::client::Client::new(buckle_socket)
+		.unwrap()
+		.zfs()
+		.await
+		.unwrap()
+		.list(None)
+		.await
+		.unwrap();
+	assert!(list.is_empty(), "{:?}", list);
+
+	buckle::testutil::destroy_zpool("test-install-rollback", Some(&file)).unwrap();
+}
+
+#[tokio::test]
+async fn install_installed() {
+	let _ = buckle::testutil::destroy_zpool("test-install-installed", None);
+
+	let (config, socket, _, buckle_info) =
+		start_server(true, Some("test-install-installed".into())).await;
+	let (buckle_socket, _, file) = buckle_info.unwrap();
+
+	let title = PackageTitle {
+		name: "with-prompts".into(),
+		version: "0.0.1".into(),
+	};
+	let marker = title.installed_path(&config.registry().path());
+	std::fs::create_dir_all(marker.parent().unwrap()).unwrap();
+	std::fs::write(&marker, "").unwrap();
+
+	let client = Client::new(socket.to_path_buf()).unwrap();
+	let err = client
+		.control()
+		.await
+		.unwrap()
+		.install("with-prompts", "0.0.1")
+		.await
+		.unwrap_err();
+	std::fs::remove_file(&marker).unwrap();
+	assert!(err.to_string().contains("already installed"), "{}", err);
+
+	// refused before anything was provisioned
+	let list = buckle::client::Client::new(buckle_socket)
+		.unwrap()
+		.zfs()
+		.await
+		.unwrap()
+		.list(None)
+		.await
+		.unwrap();
+	assert!(list.is_empty(), "{:?}", list);
+
+	buckle::testutil::destroy_zpool("test-install-installed", Some(&file)).unwrap();
+}
+
+#[tokio::test]
+async fn provision_space_check() {
+	use crate::Registry;
+
+	let _ = buckle::testutil::destroy_zpool("test-provision-space", None);
+
+	let (_, _, _, buckle_info) = start_server(true, Some("test-provision-space".into())).await;
+	let (buckle_socket, _, file) = buckle_info.unwrap();
+
+	let registry = Registry::new("testdata/registry".into());
+	let mut package = registry
+		.load("podman-test", "0.0.1")
+		.unwrap()
+		.compile()
+		.await
+		.unwrap();
+
+	// the test pool is only 5G
+	package.storage.volumes[0].size = 100 * 1024 * 1024 * 1024;
+
+	let err = package
+		.provision(&buckle_socket, ProvisionMode::Install)
+		.await
+		.unwrap_err();
+	assert!(err.to_string().contains("requires"), "{}", err);
+
+	// nothing should have been created before bailing out
+	let list = buckle::client::Client::new(buckle_socket)
+		.unwrap()
+		.zfs()
+		.await
+		.unwrap()
+		.list(None)
+		.await
+		.unwrap();
+	assert!(list.is_empty());
+
+	buckle::testutil::destroy_zpool("test-provision-space", Some(&file)).unwrap();
+}
+
+#[tokio::test]
+async fn provision_twice() {
+	use crate::Registry;
+
+	let _ = buckle::testutil::destroy_zpool("test-provision-twice", None);
+
+	let (_, _, _, buckle_info) = start_server(true, Some("test-provision-twice".into())).await;
+	let (buckle_socket, _, file) = buckle_info.unwrap();
+	let client = buckle::client::Client::new(buckle_socket.clone()).unwrap();
+
+	let registry = Registry::new("testdata/registry".into());
+	let mut package = registry
+		.load("podman-test", "0.0.1")
+		.unwrap()
+		.compile()
+		.await
+		.unwrap();
+
+	let summarize = |list: Vec<buckle::client::ZFSStat>| {
+		let mut list = list
+			.into_iter()
+			.map(|x| (x.name, x.size))
+			.collect::<Vec<(String, u64)>>();
+		list.sort();
+		list
+	};
+
+	package
+		.provision(&buckle_socket, ProvisionMode::Install)
+		.await
+		.unwrap();
+	let first = summarize(client.zfs().await.unwrap().list(None).await.unwrap());
+	assert_eq!(first.len(), 3);
+
+	package
+		.provision(&buckle_socket, ProvisionMode::Install)
+		.await
+		.unwrap();
+	let second = summarize(client.zfs().await.unwrap().list(None).await.unwrap());
+	assert_eq!(first, second);
+
+	// existing storage that is too small for the package is an error, not silently reused
+	package.storage.volumes[0].size *= 2;
+	assert!(
+		package
+			.provision(&buckle_socket, ProvisionMode::Install)
+			.await
+			.is_err()
+	);
+
+	buckle::testutil::destroy_zpool("test-provision-twice", Some(&file)).unwrap();
+}
+
+#[tokio::test]
+async fn provision_recreate() {
+	use crate::Registry;
+
+	let _ = buckle::testutil::destroy_zpool("test-provision-recreate", None);
+
+	let (_, _, _, buckle_info) = start_server(true, Some("test-provision-recreate".into())).await;
+	let (buckle_socket, _, file) = buckle_info.unwrap();
+	let client = buckle::client::Client::new(buckle_socket.clone()).unwrap();
+
+	let registry = Registry::new("testdata/registry".into());
+	let mut package = registry
+		.load("podman-test", "0.0.1")
+		.unwrap()
+		.compile()
+		.await
+		.unwrap();
+
+	package
+		.provision(&buckle_socket, ProvisionMode::Install)
+		.await
+		.unwrap();
+
+	// a file in each volume shows whether it is the same one afterwards
+	let mut markers = Vec::new();
+	for name in ["podman-test/private", "podman-test/shared"] {
+		let stat = client
+			.zfs()
+			.await
+			.unwrap()
+			.list(Some(name.into()))
+			.await
+			.unwrap()
+			.into_iter()
+			.find(|x| x.name == name)
+			.unwrap();
+		let marker = PathBuf::from(stat.mountpoint.unwrap()).join("marker");
+		std::fs::write(&marker, "").unwrap();
+		markers.push(marker);
+	}
+
+	assert!(
+		package
+			.provision(&buckle_socket, ProvisionMode::Install)
+			.await
+			.unwrap()
+			.is_empty()
+	);
+	assert!(markers.iter().all(|x| x.exists()));
+
+	package.storage.volumes[0].recreate = true;
+	// reconfigure and rollback only repair storage; the data stays
+	assert!(
+		package
+			.provision(&buckle_socket, ProvisionMode::Repair)
+			.await
+			.unwrap()
+			.is_empty()
+	);
+	assert!(markers.iter().all(|x| x.exists()));
+
+	// recreated storage isn't reported as created; undoing the install mustn't remove it
+	assert!(
+		package
+			.provision(&buckle_socket, ProvisionMode::Install)
+			.await
+			.unwrap()
+			.is_empty()
+	);
+	assert!(!markers[0].exists());
+	assert!(markers[1].exists());
+
+	buckle::testutil::destroy_zpool("test-provision-recreate", Some(&file)).unwrap();
+}
+
+#[tokio::test]
+async fn provision_concurrently() {
+	use crate::Registry;
+
+	let _ = buckle::testutil::destroy_zpool("test-provision-concurrent", None);
+
+	let (_, _, _, buckle_info) = start_server(true, Some("test-provision-concurrent".into())).await;
+	let (buckle_socket, _, file) = buckle_info.unwrap();
+
+	let package = Registry::new("testdata/registry".into())
+		.load("podman-test", "0.0.1")
+		.unwrap()
+		.compile()
+		.await
+		.unwrap();
+	let (first, second) = tokio::join!(
+		package.provision(&buckle_socket, ProvisionMode::Install),
+		package.provision(&buckle_socket, ProvisionMode::Install)
+	);
+	let (first, second) = (first.unwrap(), second.unwrap());
+
+	// everything was created exactly once, by whichever went first
+	assert_eq!(first.len() + second.len(), 3);
+	assert!(first.is_empty() || second.is_empty());
+
+	let list = buckle::client::Client::new(buckle_socket)
+		.unwrap()
+		.zfs()
+		.await
+		.unwrap()
+		.list(None)
+		.await
+		.unwrap();
+	assert_eq!(list.len(), 3);
+
+	buckle::testutil::destroy_zpool("test-provision-concurrent", Some(&file)).unwrap();
+}
+
+#[tokio::test]
+async fn operation_limit() {
+	let limit = super::OperationLimit::new(2, std::time::Duration::from_millis(200));
+
+	let first = limit.acquire("first").await.unwrap();
+	let _second = limit.acquire("second").await.unwrap();
+
+	let err = limit.acquire("third").await.unwrap_err();
+	assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+	assert!(err.message().contains("position 1"));
+
+	// a queued operation starts as soon as one finishes
+	let queued = {
+		let limit = limit.clone();
+		tokio::spawn(async move { limit.acquire("fourth").await.map(|_| ()) })
+	};
+	tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+	drop(first);
+	queued.await.unwrap().unwrap();
+
+	// zero is taken as one rather than refusing everything
+	let single = super::OperationLimit::new(0, std::time::Duration::from_millis(50));
+	let _only = single.acquire("only").await.unwrap();
+	assert!(single.acquire("another").await.is_err());
+}
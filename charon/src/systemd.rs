@@ -1,4 +1,4 @@
-use crate::{CompiledPackage, DEFAULT_CHARON_BIN_PATH};
+use crate::{CompiledPackage, DEFAULT_CHARON_BIN_PATH, PackageTitle, command_paths};
 use anyhow::{Result, anyhow};
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -10,8 +10,8 @@ const UNIT_TEMPLATE: &str = r#"
 Description=Charon launcher for @PACKAGE_NAME@, version @PACKAGE_VERSION@
 
 [Service]
-ExecStart=@CHARON_PATH@ -b @BUCKLE_SOCKET@ -r @REGISTRY_PATH@ launch @PACKAGE_NAME@ @PACKAGE_VERSION@ @VOLUME_ROOT@
-ExecStop=@CHARON_PATH@ -b @BUCKLE_SOCKET@ -r @REGISTRY_PATH@ stop @PACKAGE_NAME@ @PACKAGE_VERSION@ @VOLUME_ROOT@
+ExecStart=@CHARON_PATH@ -b @BUCKLE_SOCKET@ -r @REGISTRY_PATH@ --podman @PODMAN_PATH@ --qemu @QEMU_PATH@ launch @PACKAGE_NAME@ @PACKAGE_VERSION@ @VOLUME_ROOT@
+ExecStop=@CHARON_PATH@ -b @BUCKLE_SOCKET@ -r @REGISTRY_PATH@ --podman @PODMAN_PATH@ --qemu @QEMU_PATH@ stop @PACKAGE_NAME@ @PACKAGE_VERSION@ @VOLUME_ROOT@
 Restart=always
 TimeoutSec=300
 
@@ -19,6 +19,49 @@ TimeoutSec=300
 Alias=@PACKAGE_FILENAME@.service
 "#;
 
+const UNIT_DESCRIPTION_PREFIX: &str = "Charon launcher for ";
+
+// recovers the package from the description charon writes into its units (see UNIT_TEMPLATE), so
+// charon's units can be told apart from everything else systemd knows about.
+pub fn package_from_description(description: &str) -> Option<PackageTitle> {
+	let (name, version) = description
+		.strip_prefix(UNIT_DESCRIPTION_PREFIX)?
+		.split_once(", version ")?;
+
+	Some(PackageTitle {
+		name: name.to_string(),
+		version: version.to_string(),
+	})
+}
+
+// the packages of every unit file charon has written to systemd_root
+pub fn unit_files(systemd_root: &Path) -> Result<Vec<PackageTitle>> {
+	let mut v = Vec::new();
+
+	for item in std::fs::read_dir(systemd_root)? {
+		let path = item?.path();
+		if path.extension().is_none_or(|x| x != "service") || !path.is_file() {
+			continue;
+		}
+
+		// units that can't be read aren't ours to worry about
+		let Ok(contents) = std::fs::read_to_string(&path) else {
+			continue;
+		};
+
+		if let Some(title) = contents
+			.lines()
+			.find_map(|x| x.strip_prefix("Description="))
+			.and_then(package_from_description)
+		{
+			v.push(title)
+		}
+	}
+
+	v.sort();
+	Ok(v)
+}
+
 #[derive(Debug, Clone)]
 pub struct SystemdUnit {
 	buckle_socket: PathBuf,
@@ -75,6 +118,9 @@ impl SystemdUnit {
 						"REGISTRY_PATH" => out.push_str(&registry_path.to_string_lossy()),
 						"BUCKLE_SOCKET" => out.push_str(&self.buckle_socket.to_string_lossy()),
 						"VOLUME_ROOT" => out.push_str(&volume_root.to_string_lossy()),
+						// the launcher doesn't read our config, so it is told which to use
+						"PODMAN_PATH" => out.push_str(&command_paths().podman.to_string_lossy()),
+						"QEMU_PATH" => out.push_str(&command_paths().qemu.to_string_lossy()),
 						"CHARON_PATH" => {
 							out.push_str(
 								self.charon_path
@@ -105,6 +151,12 @@ impl SystemdUnit {
 	}
 
 	pub async fn create_unit(&self, registry_path: &Path, volume_root: &Path) -> Result<()> {
+		self.write_unit(registry_path, volume_root).await?;
+		self.start().await
+	}
+
+	// writes the unit file and has systemd pick it up, without starting it
+	pub async fn write_unit(&self, registry_path: &Path, volume_root: &Path) -> Result<()> {
 		let mut f = std::fs::OpenOptions::new()
 			.create(true)
 			.truncate(true)
@@ -137,13 +189,16 @@ impl SystemdUnit {
 			)
 		})?;
 
-		let buckle = self.buckle()?;
+		self.buckle()?.systemd().await?.reload().await?;
 
-		buckle.systemd().await?.reload().await?;
-		buckle
+		Ok(())
+	}
+
+	pub async fn start(&self) -> Result<()> {
+		self.buckle()?
 			.systemd()
 			.await?
-			.start_unit(format!("{}.service", self.package.title))
+			.start_unit(self.service_name())
 			.await?;
 
 		Ok(())
@@ -175,11 +230,51 @@ impl SystemdUnit {
 mod tests {
 	use std::path::PathBuf;
 
-	use super::SystemdUnit;
+	use super::{SystemdUnit, package_from_description, unit_files};
 	use crate::server::tests::start_server;
-	use crate::{CompiledPackage, Registry, SYSTEMD_SERVICE_ROOT};
+	use crate::{CompiledPackage, PackageTitle, Registry, SYSTEMD_SERVICE_ROOT};
 	use anyhow::Result;
 
+	#[test]
+	fn descriptions() {
+		assert_eq!(
+			package_from_description("Charon launcher for podman-test, version 0.0.2"),
+			Some(PackageTitle {
+				name: "podman-test".into(),
+				version: "0.0.2".into(),
+			})
+		);
+		assert_eq!(package_from_description("OpenSSH server daemon"), None);
+	}
+
+	#[test]
+	fn unit_file_scan() {
+		let dir = tempfile::tempdir().unwrap();
+		std::fs::write(
+			dir.path().join("plex-1.2.3.service"),
+			"[Unit]\nDescription=Charon launcher for plex, version 1.2.3\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("sshd.service"),
+			"[Unit]\nDescription=OpenSSH server daemon\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("plex-1.2.3.timer"),
+			"[Unit]\nDescription=Charon launcher for plex, version 1.2.3\n",
+		)
+		.unwrap();
+
+		assert_eq!(
+			unit_files(dir.path()).unwrap(),
+			vec![PackageTitle {
+				name: "plex".into(),
+				version: "1.2.3".into(),
+			}]
+		);
+	}
+
 	async fn load(registry: &Registry, name: &str, version: &str) -> Result<CompiledPackage> {
 		registry.load(name, version)?.compile().await
 	}
@@ -229,8 +324,8 @@ mod tests {
 Description=Charon launcher for podman-test, version 0.0.2
 
 [Service]
-ExecStart=/usr/bin/charon -b @BUCKLE_SOCKET@ -r testdata/registry launch podman-test 0.0.2 /tmp/volroot
-ExecStop=/usr/bin/charon -b @BUCKLE_SOCKET@ -r testdata/registry stop podman-test 0.0.2 /tmp/volroot
+ExecStart=/usr/bin/charon -b @BUCKLE_SOCKET@ -r testdata/registry --podman podman --qemu qemu-system-x86_64 launch podman-test 0.0.2 /tmp/volroot
+ExecStop=/usr/bin/charon -b @BUCKLE_SOCKET@ -r testdata/registry --podman podman --qemu qemu-system-x86_64 stop podman-test 0.0.2 /tmp/volroot
 Restart=always
 TimeoutSec=300
 
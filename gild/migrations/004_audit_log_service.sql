@@ -0,0 +1,5 @@
+alter table audit_log add column service varchar;
+alter table audit_log add column method varchar;
+alter table audit_log add column code varchar;
+
+create index audit_log_service_idx on audit_log (service);
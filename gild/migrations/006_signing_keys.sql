@@ -0,0 +1,5 @@
+create table signing_keys (
+  signing_key_id integer primary key autoincrement,
+  signing_key blob not null,
+  created_at timestamp not null
+);
@@ -0,0 +1,10 @@
+create table api_tokens (
+  api_token_id integer primary key autoincrement,
+  name varchar not null,
+  token_hash varchar not null,
+  user_id integer not null,
+  created_at timestamp not null,
+  expires timestamp,
+  last_used_at timestamp,
+  UNIQUE(token_hash)
+);
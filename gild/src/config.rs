@@ -49,6 +49,154 @@ impl Default for SocketConfig {
 	}
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+	pub cert: std::path::PathBuf,
+	pub key: std::path::PathBuf,
+	// when set, clients must present a certificate signed by one of the CAs in this file
+	pub client_ca: Option<std::path::PathBuf>,
+}
+
+fn default_cors_methods() -> Vec<String> {
+	["GET", "POST", "DELETE", "PUT", "PATCH", "HEAD", "OPTIONS"]
+		.into_iter()
+		.map(Into::into)
+		.collect()
+}
+
+fn default_cors_headers() -> Vec<String> {
+	["content-type", "accept", "authorization"]
+		.into_iter()
+		.map(Into::into)
+		.collect()
+}
+
+// with no origins listed, browsers will only allow the UI to talk to gild from the same origin.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CorsConfig {
+	#[serde(default)]
+	pub origins: Vec<String>,
+	#[serde(default = "default_cors_methods")]
+	pub methods: Vec<String>,
+	#[serde(default = "default_cors_headers")]
+	pub headers: Vec<String>,
+}
+
+impl CorsConfig {
+	// credentials are always allowed, and browsers won't honor a wildcard alongside them, so
+	// everything has to be listed out.
+	pub fn validate(&self) -> Result<()> {
+		for (field, values) in [
+			("origins", &self.origins),
+			("methods", &self.methods),
+			("headers", &self.headers),
+		] {
+			if values.iter().any(|x| x.trim() == "*") {
+				return Err(anyhow!(
+					"cors {} may not contain '*'; list each one explicitly",
+					field
+				));
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl Default for CorsConfig {
+	fn default() -> Self {
+		Self {
+			origins: Default::default(),
+			methods: default_cors_methods(),
+			headers: default_cors_headers(),
+		}
+	}
+}
+
+fn default_memory_cost() -> u32 {
+	argon2::Params::DEFAULT_M_COST
+}
+
+fn default_time_cost() -> u32 {
+	argon2::Params::DEFAULT_T_COST
+}
+
+fn default_parallelism() -> u32 {
+	argon2::Params::DEFAULT_P_COST
+}
+
+// argon2id cost for stored passwords. hashes made at a different cost are replaced the next time
+// their user logs in successfully.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct PasswordConfig {
+	// in KiB
+	#[serde(default = "default_memory_cost")]
+	pub memory_cost: u32,
+	#[serde(default = "default_time_cost")]
+	pub time_cost: u32,
+	#[serde(default = "default_parallelism")]
+	pub parallelism: u32,
+}
+
+impl Default for PasswordConfig {
+	fn default() -> Self {
+		Self {
+			memory_cost: default_memory_cost(),
+			time_cost: default_time_cost(),
+			parallelism: default_parallelism(),
+		}
+	}
+}
+
+impl PasswordConfig {
+	pub(crate) fn params(&self) -> Result<argon2::Params> {
+		argon2::Params::new(self.memory_cost, self.time_cost, self.parallelism, None)
+			.map_err(|e| anyhow!(e.to_string()))
+	}
+
+	pub(crate) fn hasher(&self) -> Result<argon2::Argon2<'static>> {
+		Ok(argon2::Argon2::new(
+			argon2::Algorithm::Argon2id,
+			argon2::Version::V0x13,
+			self.params()?,
+		))
+	}
+}
+
+fn default_lockout_attempts() -> u32 {
+	5
+}
+
+fn default_lockout_window() -> u64 {
+	15 * 60
+}
+
+fn default_lockout_cooldown() -> u64 {
+	15 * 60
+}
+
+// an account that fails to log in `attempts` times within `window` seconds is refused logins for
+// `cooldown` seconds, or until an administrator unlocks it. zero attempts turns this off.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct LockoutConfig {
+	#[serde(default = "default_lockout_attempts")]
+	pub attempts: u32,
+	#[serde(default = "default_lockout_window")]
+	pub window: u64,
+	#[serde(default = "default_lockout_cooldown")]
+	pub cooldown: u64,
+}
+
+impl Default for LockoutConfig {
+	fn default() -> Self {
+		Self {
+			attempts: default_lockout_attempts(),
+			window: default_lockout_window(),
+			cooldown: default_lockout_cooldown(),
+		}
+	}
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
 	#[serde(default = "default_listen")]
@@ -61,6 +209,14 @@ pub struct Config {
 	#[serde(default = "default_random")]
 	pub signing_key_salt: Vec<u8>,
 	pub log_level: buckle::config::LogLevel,
+	#[serde(default)]
+	pub tls: Option<TlsConfig>,
+	#[serde(default)]
+	pub cors: CorsConfig,
+	#[serde(default)]
+	pub password: PasswordConfig,
+	#[serde(default)]
+	pub lockout: LockoutConfig,
 }
 
 impl Default for Config {
@@ -72,6 +228,10 @@ impl Default for Config {
 			signing_key: default_random(),
 			signing_key_salt: default_random(),
 			log_level: buckle::config::LogLevel::Info,
+			tls: None,
+			cors: Default::default(),
+			password: Default::default(),
+			lockout: Default::default(),
 		};
 		this.start_tracing().unwrap();
 		this.convert_signing_key().unwrap();
@@ -93,6 +253,9 @@ impl Config {
 	pub fn from_file(file: std::path::PathBuf) -> Result<Self> {
 		let file = std::fs::OpenOptions::new().read(true).open(file)?;
 		let mut this: Self = serde_yaml_ng::from_reader(file)?;
+		// catch bad costs at startup instead of at the first login
+		this.password.params()?;
+		this.cors.validate()?;
 		this.start_tracing()?;
 		this.convert_signing_key()?;
 		Ok(this)
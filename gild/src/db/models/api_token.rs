@@ -0,0 +1,125 @@
+use super::{super::DB, User};
+use anyhow::{Result, anyhow};
+use rand::Fill;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use welds::{WeldsModel, state::DbState};
+
+// every token starts with this, which is how the Account extractor tells them apart from a JWT.
+pub(crate) const API_TOKEN_PREFIX: &str = "gild_";
+
+// long-lived credentials for scripts. only a hash of the token is kept, so it can be shown to the
+// user exactly once, when it is minted.
+#[derive(
+	Debug, Clone, Eq, PartialEq, Ord, PartialOrd, WeldsModel, Default, Serialize, Deserialize,
+)]
+#[welds(table = "api_tokens")]
+#[welds(BelongsTo(user, User, "user_id"))]
+pub(crate) struct ApiToken {
+	#[welds(primary_key)]
+	#[welds(rename = "api_token_id")]
+	pub id: u32,
+	pub name: String,
+	#[serde(skip)]
+	pub(crate) token_hash: String,
+	pub user_id: u32,
+	pub created_at: chrono::DateTime<chrono::Local>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub expires: Option<chrono::DateTime<chrono::Local>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_used_at: Option<chrono::DateTime<chrono::Local>>,
+}
+
+fn hash_token(token: &str) -> String {
+	sha2::Sha256::digest(token.as_bytes())
+		.iter()
+		.map(|b| format!("{:02x}", b))
+		.collect()
+}
+
+impl ApiToken {
+	// returns the saved row and the token itself, which is not stored anywhere.
+	pub(crate) async fn mint(
+		db: &DB, user: &User, name: String, expires: Option<chrono::DateTime<chrono::Local>>,
+	) -> Result<(DbState<Self>, String)> {
+		let mut secret: [u8; 32] = [0u8; 32];
+		secret.fill(&mut rand::rng());
+		let token = format!(
+			"{}{}",
+			API_TOKEN_PREFIX,
+			secret
+				.iter()
+				.map(|b| format!("{:02x}", b))
+				.collect::<String>()
+		);
+
+		let mut row = DbState::new_uncreated(Self {
+			name,
+			token_hash: hash_token(&token),
+			user_id: user.id,
+			created_at: chrono::Local::now(),
+			expires,
+			..Default::default()
+		});
+		row.save(db.handle()).await?;
+
+		Ok((row, token))
+	}
+
+	pub(crate) async fn from_token(db: &DB, token: &str) -> Result<DbState<Self>> {
+		let hash = hash_token(token);
+		let mut list = Self::all()
+			.where_col(|c| c.token_hash.equal(hash.clone()))
+			.run(db.handle())
+			.await?;
+
+		let row = match list.pop() {
+			Some(row) => row,
+			None => return Err(anyhow!("invalid api token")),
+		};
+
+		if let Some(expires) = row.expires
+			&& expires < chrono::Local::now()
+		{
+			return Err(anyhow!("api token is expired"));
+		}
+
+		Ok(row)
+	}
+
+	pub(crate) async fn for_user(db: &DB, user_id: u32) -> Result<Vec<Self>> {
+		Ok(Self::all()
+			.where_col(|c| c.user_id.equal(user_id))
+			.order_by_asc(|c| c.id)
+			.run(db.handle())
+			.await?
+			.into_iter()
+			.map(|t| t.into_inner())
+			.collect())
+	}
+
+	// scoped to user_id like Session::revoke; returns false if there was nothing to remove.
+	pub(crate) async fn revoke(db: &DB, user_id: u32, id: u32) -> Result<bool> {
+		let mut list = Self::all()
+			.where_col(|c| c.id.equal(id))
+			.where_col(|c| c.user_id.equal(user_id))
+			.run(db.handle())
+			.await?;
+
+		match list.first_mut() {
+			Some(token) => {
+				token.delete(db.handle()).await?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	pub(crate) async fn revoke_all(db: &DB, user_id: u32) -> Result<()> {
+		Self::all()
+			.where_col(|c| c.user_id.equal(user_id))
+			.delete(db.handle())
+			.await?;
+		Ok(())
+	}
+}
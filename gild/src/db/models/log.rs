@@ -5,7 +5,10 @@ use serde::{Deserialize, Serialize};
 use validator::Validate;
 use welds::{WeldsModel, state::DbState};
 
-use crate::db::models::User;
+use crate::{
+	db::models::User,
+	server::{ServiceError, messages::AuditLogFilter},
+};
 
 #[derive(
 	Debug,
@@ -31,6 +34,9 @@ pub struct AuditLog {
 	pub endpoint: String,
 	pub data: String,
 	pub error: Option<String>,
+	pub service: Option<String>,
+	pub method: Option<String>,
+	pub code: Option<String>,
 
 	#[welds(ignore)]
 	pub user: Option<User>,
@@ -59,6 +65,13 @@ impl AuditLog {
 		self
 	}
 
+	pub fn with_service_error(&mut self, error: &ServiceError) -> &mut Self {
+		self.service = Some(error.service.to_string());
+		self.method = Some(error.method.to_string());
+		self.code = Some(format!("{:?}", error.code));
+		self
+	}
+
 	pub fn with_entry(&mut self, entry: &str) -> &mut Self {
 		self.entry = entry.to_string();
 		self
@@ -72,6 +85,41 @@ impl AuditLog {
 		Ok(self)
 	}
 
+	// newest first, one page at a time
+	pub async fn search(db: &super::super::DB, filter: &AuditLogFilter) -> Result<Vec<Self>> {
+		let per_page: i64 = filter.per_page.unwrap_or(20).into();
+		let page: i64 = filter.page.unwrap_or(0).into();
+
+		let mut query = Self::all();
+
+		if let Some(user_id) = filter.user_id {
+			query = query.where_col(|c| c.user_id.equal(Some(user_id)));
+		}
+
+		if let Some(endpoint) = &filter.endpoint {
+			let pattern = format!("%{}%", endpoint);
+			query = query.where_col(|c| c.endpoint.like(pattern.clone()));
+		}
+
+		if let Some(since) = filter.since {
+			query = query.where_col(|c| c.time.gte(since));
+		}
+
+		if let Some(until) = filter.until {
+			query = query.where_col(|c| c.time.lte(until));
+		}
+
+		Ok(query
+			.order_by_desc(|x| x.id)
+			.limit(per_page)
+			.offset(page * per_page)
+			.run(db.handle())
+			.await?
+			.into_iter()
+			.map(|x| x.into_inner())
+			.collect())
+	}
+
 	pub async fn complete(&mut self, db: &super::super::DB) -> Result<()> {
 		let mut this = self.clone();
 		this.time = chrono::Local::now();
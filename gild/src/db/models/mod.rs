@@ -1,7 +1,9 @@
+mod api_token;
 mod log;
 mod session;
+mod signing_key;
 #[cfg(test)]
 mod tests;
 mod user;
 
-pub use self::{log::*, session::*, user::*};
+pub use self::{api_token::*, log::*, session::*, signing_key::*, user::*};
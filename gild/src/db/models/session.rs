@@ -46,10 +46,51 @@ impl Session {
 
 	pub async fn prune(db: &DB) -> Result<()> {
 		Self::all()
-			.where_col(|c| {
-				c.expires
-					.lt(chrono::Local::now() - chrono::Duration::days(DEFAULT_EXPIRATION))
-			})
+			.where_col(|c| c.expires.lt(Self::cutoff()))
+			.delete(db.handle())
+			.await?;
+		Ok(())
+	}
+
+	// anything older than this has been, or is about to be, removed by prune()
+	fn cutoff() -> chrono::DateTime<chrono::Local> {
+		chrono::Local::now() - chrono::Duration::days(DEFAULT_EXPIRATION)
+	}
+
+	pub(crate) async fn for_user(db: &DB, user_id: u32) -> Result<Vec<Self>> {
+		Ok(Self::all()
+			.where_col(|c| c.user_id.equal(user_id))
+			.where_col(|c| c.expires.gte(Self::cutoff()))
+			.order_by_asc(|c| c.id)
+			.run(db.handle())
+			.await?
+			.into_iter()
+			.map(|s| s.into_inner())
+			.collect())
+	}
+
+	// only sessions belonging to user_id can be removed this way; returns false if there was
+	// nothing to remove.
+	pub(crate) async fn revoke(db: &DB, user_id: u32, id: u32) -> Result<bool> {
+		let mut list = Self::all()
+			.where_col(|c| c.id.equal(id))
+			.where_col(|c| c.user_id.equal(user_id))
+			.run(db.handle())
+			.await?;
+
+		match list.first_mut() {
+			Some(session) => {
+				session.delete(db.handle()).await?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	pub(crate) async fn revoke_all_except(db: &DB, user_id: u32, keep: u32) -> Result<()> {
+		Self::all()
+			.where_col(|c| c.user_id.equal(user_id))
+			.where_col(|c| c.id.not_equal(keep))
 			.delete(db.handle())
 			.await?;
 		Ok(())
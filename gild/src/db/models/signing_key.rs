@@ -0,0 +1,46 @@
+use super::{super::DB, Session};
+use anyhow::Result;
+use rand::Fill;
+use welds::{WeldsModel, state::DbState};
+
+// keys made by rotate(). once there is one, it replaces the key derived from the configuration, so
+// a rotation survives restarts.
+#[derive(Debug, Clone, Eq, PartialEq, WeldsModel, Default)]
+#[welds(table = "signing_keys")]
+pub(crate) struct SigningKey {
+	#[welds(primary_key)]
+	#[welds(rename = "signing_key_id")]
+	pub id: u32,
+	pub signing_key: Vec<u8>,
+	pub created_at: chrono::DateTime<chrono::Local>,
+}
+
+impl SigningKey {
+	pub(crate) async fn current(db: &DB) -> Result<Option<Vec<u8>>> {
+		Ok(Self::all()
+			.order_by_desc(|x| x.id)
+			.limit(1)
+			.run(db.handle())
+			.await?
+			.into_iter()
+			.next()
+			.map(|x| x.into_inner().signing_key))
+	}
+
+	// stores a new random key and removes every session, so no token issued before this call is
+	// accepted again.
+	pub(crate) async fn rotate(db: &DB) -> Result<Vec<u8>> {
+		let mut key: [u8; 64] = [0u8; 64];
+		key.fill(&mut rand::rng());
+
+		let mut row = DbState::new_uncreated(Self {
+			signing_key: key.to_vec(),
+			created_at: chrono::Local::now(),
+			..Default::default()
+		});
+		row.save(db.handle()).await?;
+
+		Session::all().delete(db.handle()).await?;
+		Ok(key.to_vec())
+	}
+}
@@ -4,8 +4,12 @@ use welds::state::DbState;
 
 use super::User;
 use crate::{
-	db::models::{AuditLog, JWT_SESSION_ID_KEY, Session},
-	server::messages::Authentication,
+	config::{LockoutConfig, PasswordConfig},
+	db::models::{API_TOKEN_PREFIX, ApiToken, AuditLog, JWT_SESSION_ID_KEY, Session, SigningKey},
+	server::{
+		Service, ServiceContext,
+		messages::{AuditLogFilter, Authentication},
+	},
 	testutil::*,
 };
 
@@ -46,6 +50,131 @@ async fn audit_log() {
 	}
 }
 
+#[tokio::test]
+async fn audit_log_filter() {
+	let db = make_config(None, None)
+		.await
+		.unwrap()
+		.get_db()
+		.await
+		.unwrap();
+
+	let now = chrono::Local::now();
+	let rows = [
+		(Some(1), "/packages/install", 10),
+		(Some(1), "/packages/uninstall", 8),
+		(Some(2), "/packages/install", 6),
+		(None, "/session/login", 3),
+		(Some(2), "/zfs/create_dataset", 1),
+	];
+
+	for (user_id, endpoint, days_ago) in rows {
+		DbState::new_uncreated(AuditLog {
+			user_id,
+			endpoint: format!("http://localhost{}", endpoint),
+			time: now - chrono::Duration::days(days_ago),
+			..Default::default()
+		})
+		.save(db.handle())
+		.await
+		.unwrap();
+	}
+
+	let search = async |filter: AuditLogFilter| {
+		AuditLog::search(&db, &filter)
+			.await
+			.unwrap()
+			.into_iter()
+			.map(|x| x.id)
+			.collect::<Vec<_>>()
+	};
+
+	// newest first
+	assert_eq!(search(AuditLogFilter::default()).await, vec![5, 4, 3, 2, 1]);
+	assert_eq!(
+		search(AuditLogFilter {
+			user_id: Some(2),
+			..Default::default()
+		})
+		.await,
+		vec![5, 3]
+	);
+	assert_eq!(
+		search(AuditLogFilter {
+			endpoint: Some("/packages/".into()),
+			..Default::default()
+		})
+		.await,
+		vec![3, 2, 1]
+	);
+	assert_eq!(
+		search(AuditLogFilter {
+			since: Some(now - chrono::Duration::days(7)),
+			..Default::default()
+		})
+		.await,
+		vec![5, 4, 3]
+	);
+	assert_eq!(
+		search(AuditLogFilter {
+			until: Some(now - chrono::Duration::days(7)),
+			..Default::default()
+		})
+		.await,
+		vec![2, 1]
+	);
+
+	// filters narrow together, and paging applies to what is left
+	let installs = AuditLogFilter {
+		endpoint: Some("install".into()),
+		since: Some(now - chrono::Duration::days(9)),
+		until: Some(now - chrono::Duration::days(2)),
+		..Default::default()
+	};
+	assert_eq!(search(installs.clone()).await, vec![3, 2]);
+	assert_eq!(
+		search(AuditLogFilter {
+			per_page: Some(1),
+			page: Some(1),
+			..installs
+		})
+		.await,
+		vec![2]
+	);
+}
+
+#[tokio::test]
+async fn audit_log_service_error() {
+	let db = make_config(None, None)
+		.await
+		.unwrap()
+		.get_db()
+		.await
+		.unwrap();
+
+	let err = Err::<(), _>(tonic::Status::resource_exhausted("out of space"))
+		.service(Service::Charon, "install")
+		.unwrap_err();
+
+	AuditLog::builder()
+		.with_entry("Install package")
+		.with_service_error(&err)
+		.complete(&db)
+		.await
+		.unwrap();
+
+	let unavailable = Err::<(), _>(anyhow::anyhow!("connection refused"))
+		.service(Service::Buckle, "destroy")
+		.unwrap_err();
+	assert_eq!(unavailable.code, tonic::Code::Unavailable);
+
+	let records = AuditLog::all().run(db.handle()).await.unwrap();
+	assert_eq!(records.len(), 1);
+	assert_eq!(records[0].service.as_deref(), Some("charon"));
+	assert_eq!(records[0].method.as_deref(), Some("install"));
+	assert_eq!(records[0].code.as_deref(), Some("ResourceExhausted"));
+}
+
 #[tokio::test]
 async fn session_jwt() {
 	let db = make_config(None, None)
@@ -57,7 +186,10 @@ async fn session_jwt() {
 
 	let mut user = User::new();
 	user.username = "erikh".into();
-	assert!(user.set_password("horlclax".into()).is_ok());
+	assert!(
+		user.set_password("horlclax".into(), &Default::default())
+			.is_ok()
+	);
 	user.save(db.handle()).await.unwrap();
 	let mut session = Session::new_assigned(user.deref());
 	session.save(db.handle()).await.unwrap();
@@ -82,7 +214,10 @@ async fn user_password() {
 
 	let mut user = User::new();
 	user.username = "erikh".into();
-	assert!(user.set_password("horlclax".into()).is_ok());
+	assert!(
+		user.set_password("horlclax".into(), &Default::default())
+			.is_ok()
+	);
 	assert_ne!(user.password, "horlclax".to_string());
 	assert!(user.save(&db.handle).await.is_ok());
 
@@ -95,6 +230,83 @@ async fn user_password() {
 
 	assert!(user.login("test".into()).is_err());
 	assert!(user.login("horlclax".into()).is_ok());
+	assert!(!user.needs_rehash(&Default::default()).unwrap());
+}
+
+#[test]
+fn user_lockout() {
+	let config = LockoutConfig {
+		attempts: 3,
+		window: 60,
+		cooldown: 60,
+	};
+
+	let mut user = User::new();
+	assert!(!user.record_failed_login(&config));
+	assert!(!user.record_failed_login(&config));
+	assert!(!user.locked());
+
+	// a successful login starts the count over
+	assert!(user.clear_failed_logins());
+	assert!(!user.clear_failed_logins());
+	assert!(!user.record_failed_login(&config));
+	assert!(!user.record_failed_login(&config));
+	assert!(user.record_failed_login(&config));
+	assert!(user.locked());
+
+	assert!(user.clear_failed_logins());
+	assert!(!user.locked());
+
+	// failures outside of the window don't add up
+	assert!(!user.record_failed_login(&config));
+	assert!(!user.record_failed_login(&config));
+	user.failed_login_at = Some(chrono::Local::now() - chrono::Duration::seconds(120));
+	assert!(!user.record_failed_login(&config));
+	assert_eq!(user.failed_logins, 1);
+
+	// and the lock runs out on its own
+	user.locked_until = Some(chrono::Local::now() - chrono::Duration::seconds(1));
+	assert!(!user.locked());
+
+	let off = LockoutConfig {
+		attempts: 0,
+		..config
+	};
+	for _ in 0..10 {
+		assert!(!user.record_failed_login(&off));
+	}
+}
+
+#[tokio::test]
+async fn user_password_rehash() {
+	let weak = PasswordConfig {
+		memory_cost: 1024,
+		time_cost: 1,
+		parallelism: 1,
+	};
+	let strong = PasswordConfig {
+		memory_cost: 2048,
+		time_cost: 2,
+		parallelism: 1,
+	};
+
+	let mut user = User::new();
+	user.username = "erikh".into();
+	user.set_password("horlclax".into(), &weak).unwrap();
+	assert!(!user.needs_rehash(&weak).unwrap());
+	assert!(user.needs_rehash(&strong).unwrap());
+
+	// a hash at any cost still verifies, so raising the cost doesn't lock anyone out
+	assert!(user.login("horlclax".into()).is_ok());
+
+	let old = user.password.clone();
+	user.set_password("horlclax".into(), &strong).unwrap();
+	assert_ne!(user.password, old);
+	assert!(!user.needs_rehash(&strong).unwrap());
+	assert!(user.login("horlclax".into()).is_ok());
+
+	// the cost is carried in the hash itself
+	assert!(user.password.contains("m=2048,t=2,p=1"));
 }
 
 #[tokio::test]
@@ -116,6 +328,7 @@ async fn user_basic() {
 			password: "".into(),
 			plaintext_password: Some("horlclax".into()),
 			deleted_at: None,
+			..Default::default()
 		}),
 		DbState::new_uncreated(User {
 			id: 0,
@@ -126,6 +339,7 @@ async fn user_basic() {
 			password: "".into(),
 			plaintext_password: Some("foobar".into()),
 			deleted_at: None,
+			..Default::default()
 		}),
 		DbState::new_uncreated(User {
 			id: 0,
@@ -136,6 +350,7 @@ async fn user_basic() {
 			password: "".into(),
 			plaintext_password: Some("pooprocket".into()),
 			deleted_at: None,
+			..Default::default()
 		}),
 		DbState::new_uncreated(User {
 			id: 0,
@@ -146,6 +361,7 @@ async fn user_basic() {
 			password: "".into(),
 			plaintext_password: Some("mmph".into()),
 			deleted_at: None,
+			..Default::default()
 		}),
 		DbState::new_uncreated(User {
 			id: 0,
@@ -156,12 +372,13 @@ async fn user_basic() {
 			password: "".into(),
 			plaintext_password: Some("meh".into()),
 			deleted_at: None,
+			..Default::default()
 		}),
 	];
 
 	for item in table.into_iter() {
 		let pw = item.plaintext_password.clone().unwrap();
-		item.set_password(pw).unwrap();
+		item.set_password(pw, &Default::default()).unwrap();
 		assert_ne!(item.password.len(), 0);
 		assert_ne!(item.password, item.plaintext_password.clone().unwrap(),);
 		assert!(item.save(&db.handle).await.is_ok());
@@ -190,3 +407,80 @@ async fn user_basic() {
 
 	assert_eq!(User::all().count(&db.handle).await.unwrap(), 0);
 }
+
+#[tokio::test]
+async fn signing_key_rotation() {
+	let db = make_config(None, None)
+		.await
+		.unwrap()
+		.get_db()
+		.await
+		.unwrap();
+
+	assert!(SigningKey::current(&db).await.unwrap().is_none());
+
+	let mut user = User::new();
+	user.username = "erikh".into();
+	user.set_password("horlclax".into(), &Default::default())
+		.unwrap();
+	user.save(db.handle()).await.unwrap();
+	let mut session = Session::new_assigned(user.deref());
+	session.save(db.handle()).await.unwrap();
+
+	let key = SigningKey::rotate(&db).await.unwrap();
+	assert_eq!(key.len(), 64);
+	assert_eq!(SigningKey::current(&db).await.unwrap(), Some(key.clone()));
+	assert_eq!(Session::all().count(db.handle()).await.unwrap(), 0);
+	assert!(Session::from_jwt(&db, session.to_jwt()).await.is_err());
+
+	let key2 = SigningKey::rotate(&db).await.unwrap();
+	assert_ne!(key, key2);
+	assert_eq!(SigningKey::current(&db).await.unwrap(), Some(key2));
+}
+
+#[tokio::test]
+async fn api_tokens() {
+	let db = make_config(None, None)
+		.await
+		.unwrap()
+		.get_db()
+		.await
+		.unwrap();
+
+	let mut user = User::new();
+	user.username = "erikh".into();
+	user.set_password("horlclax".into(), &Default::default())
+		.unwrap();
+	user.save(db.handle()).await.unwrap();
+
+	let (row, token) = ApiToken::mint(&db, user.deref(), "ci".into(), None)
+		.await
+		.unwrap();
+	assert!(token.starts_with(API_TOKEN_PREFIX));
+	// only the hash is kept
+	assert_ne!(row.token_hash, token);
+	assert_eq!(ApiToken::from_token(&db, &token).await.unwrap().id, row.id);
+	assert!(
+		ApiToken::from_token(&db, &format!("{}0", token))
+			.await
+			.is_err()
+	);
+
+	let (_, expired) = ApiToken::mint(
+		&db,
+		user.deref(),
+		"old".into(),
+		Some(chrono::Local::now() - chrono::Duration::hours(1)),
+	)
+	.await
+	.unwrap();
+	assert!(ApiToken::from_token(&db, &expired).await.is_err());
+	assert_eq!(ApiToken::for_user(&db, user.id).await.unwrap().len(), 2);
+
+	assert!(!ApiToken::revoke(&db, user.id + 1, row.id).await.unwrap());
+	assert!(ApiToken::revoke(&db, user.id, row.id).await.unwrap());
+	assert!(ApiToken::from_token(&db, &token).await.is_err());
+
+	ApiToken::revoke_all(&db, user.id).await.unwrap();
+	assert!(ApiToken::for_user(&db, user.id).await.unwrap().is_empty());
+}
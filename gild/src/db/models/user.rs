@@ -1,5 +1,5 @@
 use argon2::{
-	Argon2,
+	Algorithm, Argon2, Params,
 	password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
 
@@ -8,7 +8,10 @@ use serde::{Deserialize, Serialize};
 use validator::Validate;
 use welds::WeldsModel;
 
-use crate::db::DB;
+use crate::{
+	config::{LockoutConfig, PasswordConfig},
+	db::DB,
+};
 
 #[derive(
 	Debug,
@@ -25,6 +28,7 @@ use crate::db::DB;
 )]
 #[welds(table = "users")]
 #[welds(HasMany(sessions, super::Session, "user_id"))]
+#[welds(HasMany(api_tokens, super::ApiToken, "user_id"))]
 pub(crate) struct User {
 	#[welds(primary_key)]
 	#[welds(rename = "user_id")]
@@ -59,6 +63,30 @@ pub(crate) struct User {
 
 	#[serde(skip)]
 	pub(crate) password: String,
+
+	// lockout bookkeeping; see LockoutConfig. these are only changed by logging in and unlocking,
+	// never by a client.
+	#[serde(skip)]
+	pub(crate) failed_logins: u32,
+	#[serde(skip)]
+	pub(crate) failed_login_at: Option<chrono::DateTime<chrono::Local>>,
+	#[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+	pub locked_until: Option<chrono::DateTime<chrono::Local>>,
+
+	// only read when updating a user: a field left null keeps what's stored, so emptying one has
+	// to be asked for here.
+	#[welds(ignore)]
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub clear_fields: Vec<UserField>,
+}
+
+// the optional fields of a user that an update can clear
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum UserField {
+	Realname,
+	Phone,
+	Email,
 }
 
 impl User {
@@ -74,8 +102,21 @@ impl User {
 			.map_err(|e| anyhow!(e.to_string()))
 	}
 
-	pub(crate) fn set_password(&mut self, password: String) -> Result<()> {
-		let crypt = Argon2::default();
+	// true when the stored hash wasn't made with the configured algorithm and cost. only meaningful
+	// after a successful login(), since that is the only time the plaintext is around to rehash.
+	pub(crate) fn needs_rehash(&self, config: &PasswordConfig) -> Result<bool> {
+		let parsed = PasswordHash::new(&self.password).map_err(|e| anyhow!(e.to_string()))?;
+		let params = Params::try_from(&parsed).map_err(|e| anyhow!(e.to_string()))?;
+		let wanted = config.params()?;
+
+		Ok(parsed.algorithm != Algorithm::Argon2id.ident()
+			|| params.m_cost() != wanted.m_cost()
+			|| params.t_cost() != wanted.t_cost()
+			|| params.p_cost() != wanted.p_cost())
+	}
+
+	pub(crate) fn set_password(&mut self, password: String, config: &PasswordConfig) -> Result<()> {
+		let crypt = config.hasher()?;
 		let salt = SaltString::generate(&mut OsRng);
 		self.password = crypt
 			.hash_password(password.as_bytes(), &salt)
@@ -84,6 +125,48 @@ impl User {
 		Ok(())
 	}
 
+	pub(crate) fn locked(&self) -> bool {
+		self.locked_until
+			.is_some_and(|until| until > chrono::Local::now())
+	}
+
+	// counts a failed login against the account, locking it once there have been too many within
+	// the window. returns true if this failure locked it.
+	pub(crate) fn record_failed_login(&mut self, config: &LockoutConfig) -> bool {
+		if config.attempts == 0 {
+			return false;
+		}
+
+		let now = chrono::Local::now();
+		let window = chrono::Duration::seconds(config.window as i64);
+
+		match self.failed_login_at {
+			Some(first) if now - first < window => self.failed_logins += 1,
+			_ => {
+				self.failed_logins = 1;
+				self.failed_login_at = Some(now);
+			}
+		}
+
+		if self.failed_logins >= config.attempts {
+			self.locked_until = Some(now + chrono::Duration::seconds(config.cooldown as i64));
+			self.failed_logins = 0;
+			self.failed_login_at = None;
+			true
+		} else {
+			false
+		}
+	}
+
+	// returns true if there was anything to clear
+	pub(crate) fn clear_failed_logins(&mut self) -> bool {
+		let dirty = self.failed_logins != 0 || self.locked_until.is_some();
+		self.failed_logins = 0;
+		self.failed_login_at = None;
+		self.locked_until = None;
+		dirty
+	}
+
 	pub async fn first_time_setup(db: &DB) -> Result<bool> {
 		let count = User::all()
 			.where_col(|c| c.deleted_at.equal(None))
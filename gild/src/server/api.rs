@@ -0,0 +1,277 @@
+use anyhow::Result;
+use buckle::{
+	client::{
+		CloneSnapshot, Dataset, Info, ModifyDataset, ModifyVolume, Volume, ZFSDetail, ZFSStat,
+	},
+	systemd::{LogDirection, LogMessage, LogPriority, PowerAction, Unit, UnitFilter, UnitSettings},
+};
+use charon::{
+	ConsistencyReport, DownloadProgress, InstallPlan, InstallProgress, InstallStatus,
+	PackageRuntimeStatus, PackageStatus, PackageTitle, PortReachability, PromptCollection,
+	PromptResponses,
+};
+use std::pin::Pin;
+use tokio_stream::{Stream, StreamExt};
+
+pub type ApiStream<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send>>;
+
+// the calls gild makes into buckle. handlers only see this trait, so tests can stand in for
+// buckle without running it; the real implementation is the buckle client.
+#[tonic::async_trait]
+pub trait BuckleApi: std::fmt::Debug + Send + Sync {
+	async fn ping(&self) -> Result<Info>;
+
+	async fn zfs_list(&self, filter: Option<String>) -> Result<Vec<ZFSStat>>;
+	async fn zfs_get(&self, name: String) -> Result<ZFSDetail>;
+	async fn create_dataset(&self, dataset: Dataset) -> Result<()>;
+	async fn modify_dataset(&self, dataset: ModifyDataset) -> Result<()>;
+	async fn create_volume(&self, volume: Volume) -> Result<()>;
+	async fn modify_volume(&self, volume: ModifyVolume) -> Result<()>;
+	async fn destroy(&self, name: String) -> Result<()>;
+	async fn clone_snapshot(&self, info: CloneSnapshot) -> Result<()>;
+	async fn promote(&self, name: String) -> Result<()>;
+	async fn mount(&self, name: String) -> Result<()>;
+	async fn unmount(&self, name: String) -> Result<()>;
+
+	async fn list_units(&self, filter: UnitFilter) -> Result<Vec<Unit>>;
+	async fn set_unit(&self, settings: UnitSettings) -> Result<()>;
+	async fn unit_log(
+		&self, name: &str, count: usize, cursor: Option<String>, direction: Option<LogDirection>,
+		priority: Option<LogPriority>, follow: bool,
+	) -> Result<ApiStream<LogMessage>>;
+
+	async fn power(&self, action: PowerAction) -> Result<()>;
+}
+
+// the calls gild makes into charon; see BuckleApi.
+#[tonic::async_trait]
+pub trait CharonApi: std::fmt::Debug + Send + Sync {
+	async fn ping(&self) -> Result<()>;
+
+	async fn get_prompts(&self, name: &str, version: &str) -> Result<PromptCollection>;
+	async fn get_responses(&self, name: &str) -> Result<PromptResponses>;
+	async fn set_responses(&self, name: &str, responses: PromptResponses) -> Result<()>;
+	async fn set_responses_batch(&self, responses: Vec<(String, PromptResponses)>) -> Result<()>;
+
+	async fn list_installed(&self) -> Result<Vec<PackageTitle>>;
+	async fn list(&self) -> Result<Vec<PackageStatus>>;
+	async fn list_versions(&self, name: &str) -> Result<Vec<String>>;
+	async fn list_with_status(&self) -> Result<Vec<PackageRuntimeStatus>>;
+	async fn installed(&self, name: &str, version: &str) -> Result<Option<InstallStatus>>;
+	async fn check_reachable(&self, name: &str, version: &str) -> Result<Vec<PortReachability>>;
+	async fn check_consistency(&self) -> Result<ConsistencyReport>;
+
+	async fn plan_install(&self, name: &str, version: &str) -> Result<InstallPlan>;
+	async fn install(&self, name: &str, version: &str) -> Result<()>;
+	async fn reconfigure(&self, name: &str, version: &str) -> Result<()>;
+	async fn rollback(&self, name: &str, version: &str) -> Result<()>;
+	async fn uninstall(&self, name: &str, version: &str, purge: bool) -> Result<()>;
+	async fn install_with_progress(
+		&self, name: &str, version: &str,
+	) -> Result<ApiStream<InstallProgress>>;
+	async fn uninstall_with_progress(
+		&self, name: &str, version: &str, purge: bool,
+	) -> Result<ApiStream<InstallProgress>>;
+	async fn remove_version(&self, name: &str, version: &str) -> Result<()>;
+	async fn prefetch_image(
+		&self, name: &str, version: &str,
+	) -> Result<ApiStream<DownloadProgress>>;
+}
+
+#[tonic::async_trait]
+impl BuckleApi for buckle::client::Client {
+	async fn ping(&self) -> Result<Info> {
+		Ok(self
+			.status()
+			.await?
+			.ping()
+			.await?
+			.info
+			.unwrap_or_default()
+			.into())
+	}
+
+	async fn zfs_list(&self, filter: Option<String>) -> Result<Vec<ZFSStat>> {
+		Ok(self.zfs().await?.list(filter).await?)
+	}
+
+	async fn zfs_get(&self, name: String) -> Result<ZFSDetail> {
+		Ok(self.zfs().await?.get(name).await?)
+	}
+
+	async fn create_dataset(&self, dataset: Dataset) -> Result<()> {
+		Ok(self.zfs().await?.create_dataset(dataset).await?)
+	}
+
+	async fn modify_dataset(&self, dataset: ModifyDataset) -> Result<()> {
+		Ok(self.zfs().await?.modify_dataset(dataset).await?)
+	}
+
+	async fn create_volume(&self, volume: Volume) -> Result<()> {
+		Ok(self.zfs().await?.create_volume(volume).await?)
+	}
+
+	async fn modify_volume(&self, volume: ModifyVolume) -> Result<()> {
+		Ok(self.zfs().await?.modify_volume(volume).await?)
+	}
+
+	async fn destroy(&self, name: String) -> Result<()> {
+		Ok(self.zfs().await?.destroy(name).await?)
+	}
+
+	async fn clone_snapshot(&self, info: CloneSnapshot) -> Result<()> {
+		Ok(self.zfs().await?.clone_snapshot(info).await?)
+	}
+
+	async fn promote(&self, name: String) -> Result<()> {
+		Ok(self.zfs().await?.promote(name).await?)
+	}
+
+	async fn mount(&self, name: String) -> Result<()> {
+		Ok(self.zfs().await?.mount(name).await?)
+	}
+
+	async fn unmount(&self, name: String) -> Result<()> {
+		Ok(self.zfs().await?.unmount(name).await?)
+	}
+
+	async fn list_units(&self, filter: UnitFilter) -> Result<Vec<Unit>> {
+		Ok(self.systemd().await?.list_filtered(filter).await?)
+	}
+
+	async fn set_unit(&self, settings: UnitSettings) -> Result<()> {
+		Ok(self.systemd().await?.set_unit(settings).await?)
+	}
+
+	async fn unit_log(
+		&self, name: &str, count: usize, cursor: Option<String>, direction: Option<LogDirection>,
+		priority: Option<LogPriority>, follow: bool,
+	) -> Result<ApiStream<LogMessage>> {
+		let stream = self
+			.systemd()
+			.await?
+			.unit_log(name, count, cursor, direction, priority, follow)
+			.await?;
+
+		Ok(Box::pin(
+			stream.map(|x| x.map(Into::into).map_err(Into::into)),
+		))
+	}
+
+	async fn power(&self, action: PowerAction) -> Result<()> {
+		Ok(self.power().await?.power(action).await?)
+	}
+}
+
+#[tonic::async_trait]
+impl CharonApi for charon::Client {
+	async fn ping(&self) -> Result<()> {
+		self.status().await?.ping().await
+	}
+
+	async fn get_prompts(&self, name: &str, version: &str) -> Result<PromptCollection> {
+		self.query().await?.get_prompts(name, version).await
+	}
+
+	async fn get_responses(&self, name: &str) -> Result<PromptResponses> {
+		self.query().await?.get_responses(name).await
+	}
+
+	async fn set_responses(&self, name: &str, responses: PromptResponses) -> Result<()> {
+		self.query().await?.set_responses(name, responses).await
+	}
+
+	async fn set_responses_batch(&self, responses: Vec<(String, PromptResponses)>) -> Result<()> {
+		self.query().await?.set_responses_batch(responses).await
+	}
+
+	async fn list_installed(&self) -> Result<Vec<PackageTitle>> {
+		self.query().await?.list_installed().await
+	}
+
+	async fn list(&self) -> Result<Vec<PackageStatus>> {
+		self.query().await?.list().await
+	}
+
+	async fn list_versions(&self, name: &str) -> Result<Vec<String>> {
+		self.query().await?.list_versions(name).await
+	}
+
+	async fn list_with_status(&self) -> Result<Vec<PackageRuntimeStatus>> {
+		self.query().await?.list_with_status().await
+	}
+
+	async fn installed(&self, name: &str, version: &str) -> Result<Option<InstallStatus>> {
+		self.control().await?.installed(name, version).await
+	}
+
+	async fn check_reachable(&self, name: &str, version: &str) -> Result<Vec<PortReachability>> {
+		self.query().await?.check_reachable(name, version).await
+	}
+
+	async fn check_consistency(&self) -> Result<ConsistencyReport> {
+		self.query().await?.check_consistency().await
+	}
+
+	async fn plan_install(&self, name: &str, version: &str) -> Result<InstallPlan> {
+		self.control().await?.plan_install(name, version).await
+	}
+
+	async fn install(&self, name: &str, version: &str) -> Result<()> {
+		self.control().await?.install(name, version).await
+	}
+
+	async fn reconfigure(&self, name: &str, version: &str) -> Result<()> {
+		self.control().await?.reconfigure(name, version).await
+	}
+
+	async fn rollback(&self, name: &str, version: &str) -> Result<()> {
+		self.control().await?.rollback(name, version).await
+	}
+
+	async fn uninstall(&self, name: &str, version: &str, purge: bool) -> Result<()> {
+		self.control().await?.uninstall(name, version, purge).await
+	}
+
+	async fn install_with_progress(
+		&self, name: &str, version: &str,
+	) -> Result<ApiStream<InstallProgress>> {
+		let stream = self
+			.control()
+			.await?
+			.install_with_progress(name, version)
+			.await?;
+
+		Ok(Box::pin(
+			stream.map(|x| x.map(Into::into).map_err(Into::into)),
+		))
+	}
+
+	async fn uninstall_with_progress(
+		&self, name: &str, version: &str, purge: bool,
+	) -> Result<ApiStream<InstallProgress>> {
+		let stream = self
+			.control()
+			.await?
+			.uninstall_with_progress(name, version, purge)
+			.await?;
+
+		Ok(Box::pin(
+			stream.map(|x| x.map(Into::into).map_err(Into::into)),
+		))
+	}
+
+	async fn remove_version(&self, name: &str, version: &str) -> Result<()> {
+		self.control().await?.remove_version(name, version).await
+	}
+
+	async fn prefetch_image(
+		&self, name: &str, version: &str,
+	) -> Result<ApiStream<DownloadProgress>> {
+		let stream = self.control().await?.prefetch_image(name, version).await?;
+
+		Ok(Box::pin(
+			stream.map(|x| x.map(Into::into).map_err(Into::into)),
+		))
+	}
+}
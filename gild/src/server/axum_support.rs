@@ -1,21 +1,27 @@
 use super::ServerState;
 use crate::{
-	db::models::{AuditLog, JWTClaims, Session, User},
-	server::HandlerError,
+	db::models::{API_TOKEN_PREFIX, ApiToken, AuditLog, JWTClaims, Session, User},
+	server::{HandlerError, ServiceError},
 };
 use anyhow::anyhow;
 use axum::{
-	extract::{FromRequest, FromRequestParts, Path},
+	extract::{FromRequest, FromRequestParts, Path, Request, State},
 	http::{StatusCode, request::Parts},
+	middleware::Next,
 	response::{IntoResponse, Response},
 };
 use axum_serde::Cbor;
 use hmac::{Hmac, Mac};
 use jwt::{Header, Token, Verified, VerifyWithKey};
 use problem_details::ProblemDetails;
-use std::{borrow::Cow, collections::HashMap, sync::Arc};
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	sync::{Arc, atomic::Ordering},
+};
 use tracing::error;
 use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+use welds::state::DbState;
 
 pub(crate) type Result<T> = core::result::Result<T, AppError>;
 
@@ -104,8 +110,73 @@ fn human_validation_error(value: &HashMap<Cow<'static, str>, ValidationErrorsKin
 	msg.join(", ")
 }
 
+// follows the usual gRPC to HTTP mapping, so errors from buckle and charon keep their meaning when
+// they reach the client.
+fn grpc_http_status(code: tonic::Code) -> StatusCode {
+	match code {
+		tonic::Code::Ok => StatusCode::OK,
+		tonic::Code::InvalidArgument
+		| tonic::Code::FailedPrecondition
+		| tonic::Code::OutOfRange => StatusCode::BAD_REQUEST,
+		tonic::Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+		tonic::Code::PermissionDenied => StatusCode::FORBIDDEN,
+		tonic::Code::NotFound => StatusCode::NOT_FOUND,
+		tonic::Code::AlreadyExists | tonic::Code::Aborted => StatusCode::CONFLICT,
+		tonic::Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+		tonic::Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+		tonic::Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+		tonic::Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+		tonic::Code::Cancelled
+		| tonic::Code::Unknown
+		| tonic::Code::Internal
+		| tonic::Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+	}
+}
+
+// the `type` member of every problem gild returns; clients should branch on this, not the title
+// or detail, which are for people.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum ProblemKind {
+	Validation,
+	Auth,
+	Upstream,
+	Request,
+	Maintenance,
+	Uncategorized,
+}
+
+impl ProblemKind {
+	pub(crate) fn code(&self) -> &'static str {
+		match self {
+			Self::Validation => "/problems/validation",
+			Self::Auth => "/problems/auth",
+			Self::Upstream => "/problems/upstream",
+			Self::Request => "/problems/request",
+			Self::Maintenance => "/problems/maintenance",
+			Self::Uncategorized => "/problems/uncategorized",
+		}
+	}
+
+	pub(crate) fn problem(&self) -> ProblemDetails {
+		ProblemDetails::new().with_type(http::Uri::from_static(self.code()))
+	}
+}
+
+fn handler_http_status(err: &HandlerError) -> StatusCode {
+	match err {
+		HandlerError::LoginError(_) => StatusCode::UNAUTHORIZED,
+		HandlerError::AccountLocked(_) => StatusCode::FORBIDDEN,
+		HandlerError::UserManagementError(_) | HandlerError::SessionError(_) => {
+			StatusCode::BAD_REQUEST
+		}
+		HandlerError::Unknown | HandlerError::UnknownWithMessage(_) => {
+			StatusCode::INTERNAL_SERVER_ERROR
+		}
+	}
+}
+
 #[derive(Debug, Clone, Default)]
-pub(crate) struct AppError(pub ProblemDetails);
+pub(crate) struct AppError(pub ProblemDetails, pub Option<ServiceError>);
 
 impl<E> From<E> for AppError
 where
@@ -117,20 +188,42 @@ where
 		// these blocks are here to catch types that match the Into<anyhow::Error> and shouldn't be
 		// converted to an "Unknown Error"
 
+		if value.is::<ServiceError>() {
+			let value = value.downcast::<ServiceError>().unwrap();
+			return Self(
+				ProblemKind::Upstream
+					.problem()
+					.with_detail(value.message.clone())
+					.with_status(grpc_http_status(value.code))
+					.with_title("API sub-services error"),
+				Some(value),
+			);
+		}
+
 		if value.is::<tonic::Status>() {
 			let value = value.downcast_ref::<tonic::Status>().unwrap();
 			return Self(
-				ProblemDetails::new()
+				ProblemKind::Upstream
+					.problem()
 					.with_detail(value.message())
+					.with_status(grpc_http_status(value.code()))
 					.with_title("API sub-services error"),
+				None,
 			);
 		}
 
 		if value.is::<HandlerError>() {
+			let value = value.downcast_ref::<HandlerError>().unwrap();
+			let kind = match value {
+				HandlerError::LoginError(_) | HandlerError::AccountLocked(_) => ProblemKind::Auth,
+				_ => ProblemKind::Request,
+			};
 			return Self(
-				ProblemDetails::new()
+				kind.problem()
+					.with_status(handler_http_status(value))
 					.with_title("API Error")
 					.with_detail(value.to_string()),
+				None,
 			);
 		}
 
@@ -138,21 +231,27 @@ where
 			let value = value.downcast_ref::<ValidationErrors>().unwrap();
 
 			return Self(
-				ProblemDetails::new()
+				ProblemKind::Validation
+					.problem()
 					.with_detail(human_validation_error(value.errors()))
+					.with_status(StatusCode::UNPROCESSABLE_ENTITY)
 					.with_title("Validation Error"),
+				None,
 			);
 		}
 
 		if value.is::<ProblemDetails>() {
 			let value = value.downcast::<ProblemDetails>().unwrap();
-			return Self(value);
+			return Self(value, None);
 		}
 
 		Self(
-			ProblemDetails::new()
+			ProblemKind::Uncategorized
+				.problem()
 				.with_detail(value.to_string())
+				.with_status(StatusCode::INTERNAL_SERVER_ERROR)
 				.with_title("Uncategorized Error"),
+			None,
 		)
 	}
 }
@@ -163,6 +262,26 @@ impl IntoResponse for AppError {
 	}
 }
 
+// layered onto the routes that change state; reads are left alone so the UI keeps working while
+// maintenance is underway.
+pub(crate) async fn maintenance_guard(
+	State(state): State<Arc<ServerState>>, req: Request, next: Next,
+) -> Response {
+	if state.maintenance.load(Ordering::SeqCst) {
+		return AppError(
+			ProblemKind::Maintenance
+				.problem()
+				.with_detail("The control plane is in maintenance mode; only reads are allowed")
+				.with_status(StatusCode::SERVICE_UNAVAILABLE)
+				.with_title("Maintenance Mode"),
+			None,
+		)
+		.into_response();
+	}
+
+	next.run(req).await
+}
+
 #[derive(Debug, Clone, Default)]
 pub(crate) struct CborOut<T>(pub T);
 
@@ -226,14 +345,35 @@ where
 
 pub(crate) struct Account<T>(pub T);
 
-async fn read_jwt(parts: &mut Parts, state: &Arc<ServerState>) -> Result<Option<User>> {
+// the session the caller's token was issued for. a token whose session row is gone (pruned,
+// revoked, or wiped by a key rotation) is refused here, regardless of its signature.
+pub(crate) struct CurrentSession(pub Session);
+
+// what the bearer token in a request turned out to be.
+enum Credential {
+	Session(Session),
+	ApiToken(ApiToken),
+}
+
+impl Credential {
+	fn user_id(&self) -> u32 {
+		match self {
+			Self::Session(session) => session.user_id,
+			Self::ApiToken(token) => token.user_id,
+		}
+	}
+}
+
+async fn read_credential(parts: &mut Parts, state: &Arc<ServerState>) -> Result<Credential> {
 	// FIXME: we want to hide the error from the end user to avoid giving them information about this
 	// process. We should, however, log the errors for debugging purposes, which isn't done yet.
 	let err = AppError(
-		ProblemDetails::new()
+		ProblemKind::Auth
+			.problem()
 			.with_detail("Please enter correct credentials")
 			.with_status(http::StatusCode::UNAUTHORIZED)
 			.with_title("Invalid Login"),
+		None,
 	);
 
 	let token = parts
@@ -246,8 +386,19 @@ async fn read_jwt(parts: &mut Parts, state: &Arc<ServerState>) -> Result<Option<
 		.map_err(|_| err.clone())?
 		.strip_prefix("Bearer ")
 		.unwrap();
+
+	if token.starts_with(API_TOKEN_PREFIX) {
+		return match ApiToken::from_token(&state.db, token).await {
+			Ok(x) => Ok(Credential::ApiToken(x.into_inner())),
+			Err(e) => {
+				error!("Error locating API token: {}", e);
+				Err(err)
+			}
+		};
+	}
+
 	let signing_key: Hmac<sha2::Sha384> =
-		Hmac::new_from_slice(&state.config.signing_key).map_err(|_| err.clone())?;
+		Hmac::new_from_slice(&state.signing_key()).map_err(|_| err.clone())?;
 
 	let token: Token<Header, JWTClaims, Verified> = match token.verify_with_key(&signing_key) {
 		Ok(x) => x,
@@ -257,33 +408,38 @@ async fn read_jwt(parts: &mut Parts, state: &Arc<ServerState>) -> Result<Option<
 		}
 	};
 
-	let session = match Session::from_jwt(&state.db, token.claims().clone()).await {
-		Ok(x) => x,
+	match Session::from_jwt(&state.db, token.claims().clone()).await {
+		Ok(x) => Ok(Credential::Session(x.into_inner())),
 		Err(e) => {
 			error!("Error locating session from JWT: {}", e);
-			return Err(err);
+			Err(err)
 		}
-	};
+	}
+}
+
+async fn read_jwt(parts: &mut Parts, state: &Arc<ServerState>) -> Result<Option<User>> {
+	let credential = read_credential(parts, state).await?;
+	Ok(read_user(state, credential.user_id()).await)
+}
 
-	match User::find_by_id(state.db.handle(), session.user_id).await {
+// a deleted user's sessions and tokens are refused here.
+async fn read_user(state: &Arc<ServerState>, user_id: u32) -> Option<User> {
+	match User::find_by_id(state.db.handle(), user_id).await {
 		Ok(Some(user)) => {
 			if user.deleted_at.is_none() {
-				Ok(Some(user.into_inner()))
+				Some(user.into_inner())
 			} else {
 				error!("User was deleted at {}", user.deleted_at.unwrap());
-				Ok(None)
+				None
 			}
 		}
 		Ok(None) => {
-			error!(
-				"User authenticated but not found: User ID: {}",
-				session.user_id
-			);
-			Ok(None)
+			error!("User authenticated but not found: User ID: {}", user_id);
+			None
 		}
 		Err(e) => {
 			error!("Error finding user: {}", e);
-			Ok(None)
+			None
 		}
 	}
 }
@@ -295,11 +451,23 @@ impl FromRequestParts<Arc<ServerState>> for Account<User> {
 		parts: &mut Parts, state: &Arc<ServerState>,
 	) -> core::result::Result<Self, Self::Rejection> {
 		Session::prune(&state.db).await?; // prune sessions before trying to read them
-		if let Some(user) = read_jwt(parts, state).await? {
-			Ok(Account(user))
-		} else {
-			Err(AppError::from(anyhow!("user is not logged in")))
+		let credential = read_credential(parts, state).await?;
+		let Some(user) = read_user(state, credential.user_id()).await else {
+			return Err(AppError(
+				ProblemKind::Auth
+					.problem()
+					.with_detail("user is not logged in")
+					.with_status(StatusCode::UNAUTHORIZED)
+					.with_title("Invalid Login"),
+				None,
+			));
+		};
+
+		if let Credential::ApiToken(token) = credential {
+			record_token_use(state, &parts.uri, &user, token).await;
 		}
+
+		Ok(Account(user))
 	}
 }
 
@@ -313,6 +481,49 @@ impl FromRequestParts<Arc<ServerState>> for Account<Option<User>> {
 	}
 }
 
+impl FromRequestParts<Arc<ServerState>> for CurrentSession {
+	type Rejection = AppError;
+
+	async fn from_request_parts(
+		parts: &mut Parts, state: &Arc<ServerState>,
+	) -> core::result::Result<Self, Self::Rejection> {
+		Session::prune(&state.db).await?;
+		match read_credential(parts, state).await? {
+			Credential::Session(session) => Ok(CurrentSession(session)),
+			Credential::ApiToken(_) => Err(AppError(
+				ProblemKind::Auth
+					.problem()
+					.with_detail("this request needs a login session, not an api token")
+					.with_status(StatusCode::FORBIDDEN)
+					.with_title("Invalid Login"),
+				None,
+			)),
+		}
+	}
+}
+
+// scripts don't log in, so this is the only trace of which token did what. neither write is allowed
+// to fail the request.
+async fn record_token_use(state: &Arc<ServerState>, uri: &http::Uri, user: &User, token: ApiToken) {
+	let mut map: HashMap<&str, String> = HashMap::default();
+	map.insert("token_id", token.id.to_string());
+	map.insert("name", token.name.clone());
+
+	let mut log = AuditLog::builder().from_uri(uri.clone()).clone();
+	log.from_user(user).with_entry("API token used");
+	if let Err(e) = log.with_data(&map) {
+		error!("Could not record API token use: {}", e);
+	} else if let Err(e) = log.complete(&state.db).await {
+		error!("Could not record API token use: {}", e);
+	}
+
+	let mut row = DbState::db_loaded(token);
+	row.last_used_at = Some(chrono::Local::now());
+	if let Err(e) = row.save(state.db.handle()).await {
+		error!("Could not update API token {}: {}", row.id, e);
+	}
+}
+
 #[derive(Debug, Clone, Default)]
 pub(crate) struct Log(pub(crate) AuditLog);
 
@@ -360,6 +571,10 @@ where
 		let mut log = self.1;
 		if let Err(ref e) = self.0 {
 			log.with_error(&e.0);
+
+			if let Some(service) = &e.1 {
+				log.with_service_error(service);
+			}
 		}
 
 		let db = self.2.db.clone();
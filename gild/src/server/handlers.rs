@@ -4,19 +4,33 @@ use super::{
 	messages::*,
 };
 use crate::{
-	db::models::{AuditLog, Session, User},
-	server::HandlerError,
+	db::models::{ApiToken, AuditLog, Session, SigningKey, User, UserField},
+	server::{HandlerError, Service, ServiceContext, ServiceError, api::ApiStream},
+};
+use axum::{
+	extract::State,
+	response::sse::{Event, KeepAlive, KeepAliveStream, Sse},
+};
+use buckle::client::{ZFSDetail, ZFSStat};
+use charon::{
+	ConsistencyReport, InstallPlan, InstallProgress, InstallStatus, InstallStep,
+	PackageRuntimeStatus, PackageStatus, PackageTitle, PortReachability, UninstallData,
 };
-use axum::extract::State;
-use buckle::client::ZFSStat;
-use charon::{InstallStatus, PackageStatus, PackageTitle, UninstallData};
 use hmac::{Hmac, Mac};
 use jwt::SignWithKey;
-use std::{collections::HashMap, ops::Deref, sync::Arc};
-use tokio_stream::StreamExt;
+use std::{
+	collections::HashMap,
+	convert::Infallible,
+	sync::{Arc, atomic::Ordering},
+};
+use tokio_stream::{StreamExt, wrappers::ReceiverStream};
 use validator::Validate;
 use welds::{exts::VecStateExt, state::DbState};
 
+// what the server-sent event handlers answer with: whatever is sent on the channel, with keep-alives
+// in between
+type EventStream = Sse<KeepAliveStream<ReceiverStream<std::result::Result<Event, Infallible>>>>;
+
 // this macro is used in handlers that return WithLog<T>; it will trap audit log information with
 // the result of the inner handler. Look at other calls for examples.
 #[macro_export]
@@ -50,12 +64,44 @@ macro_rules! run_with_log {
 // status handlers
 //
 
+pub(crate) async fn get_maintenance(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
+) -> Result<CborOut<Maintenance>> {
+	Ok(CborOut(Maintenance {
+		enabled: state.maintenance.load(Ordering::SeqCst),
+	}))
+}
+
+pub(crate) async fn set_maintenance(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	Cbor(maintenance): Cbor<Maintenance>,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			log.from_user(&user)
+				.with_entry(if maintenance.enabled {
+					"Enter maintenance mode"
+				} else {
+					"Leave maintenance mode"
+				})
+				.with_data(&maintenance)?;
+
+			state
+				.maintenance
+				.store(maintenance.enabled, Ordering::SeqCst);
+			Ok(CborOut(()))
+		}
+	)
+}
+
 pub(crate) async fn ping(
 	State(state): State<Arc<ServerState>>, Account(user): Account<Option<User>>,
 ) -> Result<CborOut<PingResult>> {
 	Ok(CborOut(if user.is_some() {
 		let start = std::time::Instant::now();
-		let buckle = state.buckle.status().await?.ping().await;
+		let buckle = state.buckle.ping().await;
 		let buckle_latency = (std::time::Instant::now() - start).as_millis() as u64;
 
 		let mut buckle_error = None;
@@ -63,12 +109,12 @@ pub(crate) async fn ping(
 		let mut info = None;
 
 		match buckle {
-			Ok(result) => info = Some(result.info.unwrap_or_default().into()),
+			Ok(result) => info = Some(result),
 			Err(e) => buckle_error = Some(e.to_string()),
 		}
 
 		let start = std::time::Instant::now();
-		if let Err(e) = state.charon.status().await?.ping().await {
+		if let Err(e) = state.charon.ping().await {
 			charon_error = Some(e.to_string())
 		}
 		let charon_latency = (std::time::Instant::now() - start).as_millis() as u64;
@@ -93,19 +139,12 @@ pub(crate) async fn ping(
 
 pub(crate) async fn log(
 	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
-	Cbor(pagination): Cbor<Pagination>,
+	Cbor(filter): Cbor<AuditLogFilter>,
 ) -> Result<CborOut<Vec<AuditLog>>> {
-	let per_page: i64 = pagination.per_page.unwrap_or(20).into();
-	let page: i64 = pagination.page.unwrap_or(0).into();
 	let user_query = User::all().run(state.db.handle()).await?;
-
-	let query = AuditLog::all()
-		.order_by_desc(|x| x.id)
-		.limit(per_page)
-		.offset(page * per_page);
 	let mut log = Vec::new();
 
-	for mut entry in query.run(state.db.handle()).await?.into_inners() {
+	for mut entry in AuditLog::search(&state.db, &filter).await? {
 		if let Some(user_id) = entry.user_id {
 			entry.user = user_query
 				.iter()
@@ -127,7 +166,36 @@ pub(crate) async fn zfs_list(
 	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
 	Cbor(filter): Cbor<Option<String>>,
 ) -> Result<CborOut<Vec<ZFSStat>>> {
-	Ok(CborOut(state.buckle.zfs().await?.list(filter).await?))
+	Ok(CborOut(state.buckle.zfs_list(filter).await?))
+}
+
+pub(crate) async fn zfs_list_owned(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
+	Cbor(filter): Cbor<Option<String>>,
+) -> Result<CborOut<Vec<OwnedZFSStat>>> {
+	let list = state.buckle.zfs_list(filter).await?;
+	let installed = state.charon.list_installed().await?;
+
+	Ok(CborOut(
+		list.into_iter()
+			.map(|stat| OwnedZFSStat {
+				owner: charon::dataset_owner(&installed, &stat.name),
+				stat,
+			})
+			.collect(),
+	))
+}
+
+pub(crate) async fn zfs_get(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>, Cbor(name): Cbor<String>,
+) -> Result<CborOut<ZFSDetail>> {
+	Ok(CborOut(
+		state
+			.buckle
+			.zfs_get(name)
+			.await
+			.service(Service::Buckle, "get")?,
+	))
 }
 
 pub(crate) async fn zfs_create_dataset(
@@ -141,7 +209,11 @@ pub(crate) async fn zfs_create_dataset(
 		async move |state: Arc<ServerState>, log: &mut AuditLog| {
 			let dataset = dataset.lock().await.clone();
 			log.with_entry("Creating dataset").with_data(&dataset)?;
-			state.buckle.zfs().await?.create_dataset(dataset).await?;
+			state
+				.buckle
+				.create_dataset(dataset)
+				.await
+				.service(Service::Buckle, "create_dataset")?;
 			Ok(())
 		}
 	)
@@ -158,7 +230,11 @@ pub(crate) async fn zfs_modify_dataset(
 		async move |state: Arc<ServerState>, log: &mut AuditLog| {
 			let dataset = dataset.lock().await.clone();
 			log.with_entry("Modifying dataset").with_data(&dataset)?;
-			state.buckle.zfs().await?.modify_dataset(dataset).await?;
+			state
+				.buckle
+				.modify_dataset(dataset)
+				.await
+				.service(Service::Buckle, "modify_dataset")?;
 			Ok(())
 		}
 	)
@@ -175,7 +251,11 @@ pub(crate) async fn zfs_create_volume(
 		async move |state: Arc<ServerState>, log: &mut AuditLog| {
 			let volume = volume.lock().await.clone();
 			log.with_entry("Creating volume").with_data(&volume)?;
-			state.buckle.zfs().await?.create_volume(volume).await?;
+			state
+				.buckle
+				.create_volume(volume)
+				.await
+				.service(Service::Buckle, "create_volume")?;
 			Ok(())
 		}
 	)
@@ -192,7 +272,11 @@ pub(crate) async fn zfs_modify_volume(
 		async move |state: Arc<ServerState>, log: &mut AuditLog| {
 			let volume = volume.lock().await.clone();
 			log.with_entry("Modifying volume").with_data(&volume)?;
-			state.buckle.zfs().await?.modify_volume(volume).await?;
+			state
+				.buckle
+				.modify_volume(volume)
+				.await
+				.service(Service::Buckle, "modify_volume")?;
 			Ok(())
 		}
 	)
@@ -214,7 +298,107 @@ pub(crate) async fn zfs_destroy(
 			log.with_entry("Destroy volume or dataset")
 				.with_data(&map)?;
 
-			state.buckle.zfs().await?.destroy(name).await?;
+			state
+				.buckle
+				.destroy(name)
+				.await
+				.service(Service::Buckle, "destroy")?;
+			Ok(())
+		}
+	)
+}
+
+pub(crate) async fn zfs_clone(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>, Log(log): Log,
+	Cbor(info): Cbor<buckle::client::CloneSnapshot>,
+) -> Result<WithLog<()>> {
+	run_with_log!(
+		state,
+		log,
+		(info),
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			let info = info.lock().await.clone();
+			log.with_entry("Cloning snapshot").with_data(&info)?;
+			state
+				.buckle
+				.clone_snapshot(info)
+				.await
+				.service(Service::Buckle, "clone_snapshot")?;
+			Ok(())
+		}
+	)
+}
+
+pub(crate) async fn zfs_promote(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>, Log(log): Log,
+	Cbor(name): Cbor<String>,
+) -> Result<WithLog<()>> {
+	run_with_log!(
+		state,
+		log,
+		(name),
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			let name = name.lock().await.clone();
+			let mut map: HashMap<&str, &str> = HashMap::default();
+			map.insert("name", &name);
+
+			log.with_entry("Promote clone").with_data(&map)?;
+
+			state
+				.buckle
+				.promote(name)
+				.await
+				.service(Service::Buckle, "promote")?;
+			Ok(())
+		}
+	)
+}
+
+pub(crate) async fn zfs_mount(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>, Log(log): Log,
+	Cbor(name): Cbor<String>,
+) -> Result<WithLog<()>> {
+	run_with_log!(
+		state,
+		log,
+		(name),
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			let name = name.lock().await.clone();
+			let mut map: HashMap<&str, &str> = HashMap::default();
+			map.insert("name", &name);
+
+			log.with_entry("Mount dataset").with_data(&map)?;
+
+			state
+				.buckle
+				.mount(name)
+				.await
+				.service(Service::Buckle, "mount")?;
+			Ok(())
+		}
+	)
+}
+
+pub(crate) async fn zfs_unmount(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>, Log(log): Log,
+	Cbor(name): Cbor<String>,
+) -> Result<WithLog<()>> {
+	run_with_log!(
+		state,
+		log,
+		(name),
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			let name = name.lock().await.clone();
+			let mut map: HashMap<&str, &str> = HashMap::default();
+			map.insert("name", &name);
+
+			log.with_entry("Unmount dataset").with_data(&map)?;
+
+			state
+				.buckle
+				.unmount(name)
+				.await
+				.service(Service::Buckle, "unmount")?;
 			Ok(())
 		}
 	)
@@ -224,6 +408,16 @@ pub(crate) async fn zfs_destroy(
 // User accounts
 //
 
+// answered to anyone, so the UI can tell a fresh install apart before there's an account to log in
+// with. nothing but the one flag is given out.
+pub(crate) async fn setup_status(
+	State(state): State<Arc<ServerState>>,
+) -> Result<CborOut<SetupStatus>> {
+	Ok(CborOut(SetupStatus {
+		needed: User::first_time_setup(&state.db).await?,
+	}))
+}
+
 pub(crate) async fn create_user(
 	State(state): State<Arc<ServerState>>, Account(login): Account<Option<User>>, Log(log): Log,
 	Cbor(user): Cbor<User>,
@@ -244,7 +438,7 @@ pub(crate) async fn create_user(
 			// crypt the plaintext password if it is set, otherwise return error (passwords are required at
 			// this step)
 			if let Some(password) = user.plaintext_password.clone() {
-				user.set_password(password)?;
+				user.set_password(password, &state.config.password)?;
 			} else {
 				return Err(
 					HandlerError::UserManagementError("password is required".into()).into(),
@@ -283,6 +477,28 @@ pub(crate) async fn reactivate_user(
 	)
 }
 
+// any account can unlock any other until there are roles to reserve this for admins; until then it
+// at least takes a login session, not just an api token.
+pub(crate) async fn unlock_user(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>, _: CurrentSession,
+	Log(log): Log, Path(id): Path<u32>,
+) -> Result<WithLog<()>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			if let Some(user) = &mut User::find_by_id(state.db.handle(), id).await? {
+				user.clear_failed_logins();
+				log.with_entry("Unlocking user").with_data(user.clone())?;
+				user.save(state.db.handle()).await?;
+				Ok(())
+			} else {
+				Err(HandlerError::UserManagementError("invalid user".into()).into())
+			}
+		}
+	)
+}
+
 pub(crate) async fn remove_user(
 	State(state): State<Arc<ServerState>>, Account(_): Account<User>, Log(log): Log,
 	Path(id): Path<u32>,
@@ -296,6 +512,8 @@ pub(crate) async fn remove_user(
 				log.with_entry("Deactivating user")
 					.with_data(user.clone())?;
 				user.save(state.db.handle()).await?;
+				// reactivating the account should not bring these back
+				ApiToken::revoke_all(&state.db, id).await?;
 				Ok(())
 			} else {
 				Err(HandlerError::UserManagementError("invalid user".into()).into())
@@ -358,30 +576,41 @@ pub(crate) async fn update_user(
 
 				// crypt the plaintext password if it is set
 				if let Some(password) = &user.plaintext_password {
-					user.set_password(password.clone())?;
+					user.set_password(password.clone(), &state.config.password)?;
 				} else {
 					user.password = orig.password.clone()
 				}
 
 				user.plaintext_password = None; // NOTE: so it doesn't appear in the logging that follows
 
-				// NOTE: the unfortunate situation here is that you can't just "clear" a field right now. I'll
-				// have to get to that later. Setting any of these fields to null will just get the original
-				// merged in. Use reactivate_user and remove_user for toggling deleted_at status anyway.
+				// a null field gets the original merged in, unless it's named in clear_fields. use
+				// reactivate_user and remove_user for toggling deleted_at status.
+				let clear = std::mem::take(&mut user.clear_fields);
 
 				if user.deleted_at.is_none() {
 					user.deleted_at = orig.deleted_at
 				}
 
-				if user.realname.is_none() {
+				// use unlock_user for these
+				user.failed_logins = orig.failed_logins;
+				user.failed_login_at = orig.failed_login_at;
+				user.locked_until = orig.locked_until;
+
+				if clear.contains(&UserField::Realname) {
+					user.realname = None
+				} else if user.realname.is_none() {
 					user.realname = orig.realname.clone()
 				}
 
-				if user.phone.is_none() {
+				if clear.contains(&UserField::Phone) {
+					user.phone = None
+				} else if user.phone.is_none() {
 					user.phone = orig.phone.clone()
 				}
 
-				if user.email.is_none() {
+				if clear.contains(&UserField::Email) {
+					user.email = None
+				} else if user.email.is_none() {
 					user.email = orig.email.clone()
 				}
 
@@ -432,8 +661,16 @@ pub(crate) async fn login(
 			map.insert("username", &form.username);
 			log.with_data(&map)?;
 
-			let user = match users.first() {
-				Some(user) => user.deref(),
+			let Some(id) = users.first().map(|x| x.id) else {
+				log.with_entry("Login: Invalid Username");
+				return Err(HandlerError::LoginError("Invalid Login".into()).into());
+			};
+
+			// read again once it's our turn, so the failure count is the last attempt's
+			let lock = state.login_lock(id);
+			let _guard = lock.lock().await;
+			let user = &mut match User::find_by_id(state.db.handle(), id).await? {
+				Some(user) => user,
 				None => {
 					log.with_entry("Login: Invalid Username");
 					return Err(HandlerError::LoginError("Invalid Login".into()).into());
@@ -442,15 +679,44 @@ pub(crate) async fn login(
 
 			log.from_user(user);
 
-			if user.login(form.password).is_err() {
+			// checked before the password so a locked account can't be used to keep guessing
+			if user.locked() {
+				log.with_entry("Login: Account Locked");
+				return Err(HandlerError::AccountLocked(
+					"too many failed logins; try again later".into(),
+				)
+				.into());
+			}
+
+			if user.login(form.password.clone()).is_err() {
 				log.with_entry("Login: Invalid Username");
+				if user.record_failed_login(&state.config.lockout) {
+					log.with_entry("Login: Account Locked");
+				}
+				user.save(state.db.handle()).await?;
 				return Err(HandlerError::LoginError("Invalid Login".into()).into());
 			}
 
+			let mut dirty = user.clear_failed_logins();
+
+			// the configured cost has changed since this hash was made; this is the only chance to
+			// bring it up to date without asking the user for a new password. failing to do so
+			// shouldn't stop them from logging in.
+			if user.needs_rehash(&state.config.password).unwrap_or(true) {
+				match user.set_password(form.password, &state.config.password) {
+					Ok(()) => dirty = true,
+					Err(e) => tracing::warn!("Could not rehash password: {}", e),
+				}
+			}
+
+			if dirty && let Err(e) = user.save(state.db.handle()).await {
+				tracing::warn!("Could not update {} after login: {}", form.username, e);
+			}
+
 			let mut session = Session::new_assigned(user);
 			session.save(state.db.handle()).await?;
 
-			let key: Hmac<sha2::Sha384> = Hmac::new_from_slice(&state.config.signing_key)?;
+			let key: Hmac<sha2::Sha384> = Hmac::new_from_slice(&state.signing_key())?;
 			let header = jwt::Header {
 				algorithm: jwt::AlgorithmType::Hs384,
 				..Default::default()
@@ -464,6 +730,128 @@ pub(crate) async fn login(
 	)
 }
 
+// logs everyone out, including the caller. an api token survives the rotation, so one that leaked
+// mustn't be able to do it.
+pub(crate) async fn rotate_signing_key(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	_: CurrentSession,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			log.from_user(&user).with_entry("Rotate signing key");
+			state.set_signing_key(SigningKey::rotate(&state.db).await?);
+			Ok(CborOut(()))
+		}
+	)
+}
+
+pub(crate) async fn list_sessions(
+	State(state): State<Arc<ServerState>>, Account(user): Account<User>,
+) -> Result<CborOut<Vec<Session>>> {
+	Ok(CborOut(Session::for_user(&state.db, user.id).await?))
+}
+
+pub(crate) async fn revoke_session(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	Path(id): Path<u32>,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			let mut map: HashMap<&str, u32> = HashMap::default();
+			map.insert("session_id", id);
+			log.from_user(&user)
+				.with_entry("Revoke session")
+				.with_data(&map)?;
+
+			// someone else's session looks the same as one that doesn't exist
+			if !Session::revoke(&state.db, user.id, id).await? {
+				return Err(HandlerError::SessionError("invalid session".into()).into());
+			}
+
+			Ok(CborOut(()))
+		}
+	)
+}
+
+// the caller stays logged in; everywhere else they are logged in is not.
+pub(crate) async fn revoke_all_sessions(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	CurrentSession(session): CurrentSession,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			log.from_user(&user).with_entry("Revoke all other sessions");
+			Session::revoke_all_except(&state.db, user.id, session.id).await?;
+			Ok(CborOut(()))
+		}
+	)
+}
+
+pub(crate) async fn list_api_tokens(
+	State(state): State<Arc<ServerState>>, Account(user): Account<User>,
+) -> Result<CborOut<Vec<ApiToken>>> {
+	Ok(CborOut(ApiToken::for_user(&state.db, user.id).await?))
+}
+
+pub(crate) async fn create_api_token(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	Cbor(form): Cbor<CreateApiToken>,
+) -> Result<WithLog<CborOut<NewApiToken>>> {
+	run_with_log!(
+		state,
+		log,
+		(form),
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			let form = form.lock().await.clone();
+			log.from_user(&user)
+				.with_entry("Create API token")
+				.with_data(&form)?;
+
+			form.validate()?;
+
+			if let Some(expires) = form.expires
+				&& expires <= chrono::Local::now()
+			{
+				return Err(
+					HandlerError::SessionError("expiry must be in the future".into()).into(),
+				);
+			}
+
+			let (row, token) = ApiToken::mint(&state.db, &user, form.name, form.expires).await?;
+			Ok(CborOut(NewApiToken { id: row.id, token }))
+		}
+	)
+}
+
+pub(crate) async fn revoke_api_token(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	Path(id): Path<u32>,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			let mut map: HashMap<&str, u32> = HashMap::default();
+			map.insert("token_id", id);
+			log.from_user(&user)
+				.with_entry("Revoke API token")
+				.with_data(&map)?;
+
+			if !ApiToken::revoke(&state.db, user.id, id).await? {
+				return Err(HandlerError::SessionError("invalid api token".into()).into());
+			}
+
+			Ok(CborOut(()))
+		}
+	)
+}
+
 pub(crate) async fn me(
 	State(_): State<Arc<ServerState>>, Account(user): Account<Option<User>>,
 ) -> Result<CborOut<Option<User>>> {
@@ -476,9 +864,14 @@ pub(crate) async fn me(
 
 pub(crate) async fn list_units(
 	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
-	Cbor(filter): Cbor<Option<String>>,
+	Cbor(filter): Cbor<Option<UnitListFilter>>,
 ) -> Result<CborOut<Vec<buckle::systemd::Unit>>> {
-	Ok(CborOut(state.buckle.systemd().await?.list(filter).await?))
+	Ok(CborOut(
+		state
+			.buckle
+			.list_units(filter.map(Into::into).unwrap_or_default())
+			.await?,
+	))
 }
 
 pub(crate) async fn set_unit(
@@ -495,7 +888,35 @@ pub(crate) async fn set_unit(
 			log.from_user(&user)
 				.with_entry("Update systemd unit")
 				.with_data(&settings)?;
-			state.buckle.systemd().await?.set_unit(settings).await?;
+			state
+				.buckle
+				.set_unit(settings)
+				.await
+				.service(Service::Buckle, "set_unit")?;
+			Ok(CborOut(()))
+		}
+	)
+}
+
+// there are no roles to keep this from anyone with an account, so at least a script holding an api
+// token can't do it; someone has to be logged in.
+pub(crate) async fn power(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	_: CurrentSession, Cbor(action): Cbor<buckle::systemd::PowerAction>,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			log.from_user(&user).with_entry(match action {
+				buckle::systemd::PowerAction::Reboot => "Reboot host",
+				buckle::systemd::PowerAction::Poweroff => "Power off host",
+			});
+			state
+				.buckle
+				.power(action)
+				.await
+				.service(Service::Buckle, "power")?;
 			Ok(CborOut(()))
 		}
 	)
@@ -519,19 +940,23 @@ pub(crate) async fn unit_log(
 
 			let mut unit_log = state
 				.buckle
-				.systemd()
+				.unit_log(
+					&params.name,
+					params.count,
+					params.cursor,
+					params.direction,
+					params.priority,
+					false,
+				)
 				.await
-				.unwrap()
-				.unit_log(&params.name, params.count, params.cursor, params.direction)
-				.await
-				.unwrap();
+				.service(Service::Buckle, "unit_log")?;
 
 			// NOTE: this value can get very large and potentially cause a lot of memory usage if the count
 			// is too high.
 			let mut v = Vec::with_capacity(params.count);
 
 			while let Some(Ok(entry)) = unit_log.next().await {
-				v.push(entry.into())
+				v.push(entry)
 			}
 
 			Ok(CborOut(v))
@@ -539,6 +964,66 @@ pub(crate) async fn unit_log(
 	)
 }
 
+// streams the unit's log as server-sent events, starting at the current end of the journal. each
+// event's id is the journal cursor of the entry.
+pub(crate) async fn unit_log_follow(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>, Path(name): Path<String>,
+) -> Result<EventStream> {
+	let (tx, rx) = tokio::sync::mpsc::channel(100);
+	tokio::spawn(follow_unit_log(state, name, tx));
+	Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
+
+// runs until the SSE client disconnects. if the stream from buckle ends or buckle can't be
+// reached (f.e. it is restarting), it reconnects and resumes from the last cursor sent, so the
+// client neither misses nor repeats lines.
+async fn follow_unit_log(
+	state: Arc<ServerState>, name: String,
+	tx: tokio::sync::mpsc::Sender<std::result::Result<Event, Infallible>>,
+) {
+	let mut cursor: Option<String> = None;
+
+	while !tx.is_closed() {
+		match state
+			.buckle
+			.unit_log(&name, 0, cursor.clone(), None, None, true)
+			.await
+		{
+			// a quiet unit may not produce another entry for a long time, so the client going
+			// away is watched for too. dropping the stream is what stops buckle's follower.
+			Ok(mut stream) => loop {
+				let entry = tokio::select! {
+					_ = tx.closed() => return,
+					next = stream.next() => match next {
+						Some(Ok(entry)) => entry,
+						_ => break,
+					},
+				};
+
+				let event = match Event::default().id(entry.cursor()).json_data(&entry) {
+					Ok(event) => event,
+					Err(e) => {
+						tracing::error!("Error encoding log entry for {}: {}", name, e);
+						continue;
+					}
+				};
+
+				cursor = Some(entry.cursor().to_string());
+
+				if tx.send(Ok(event)).await.is_err() {
+					return;
+				}
+			},
+			Err(e) => tracing::warn!("Lost log stream for {}, reconnecting: {}", name, e),
+		}
+
+		tokio::select! {
+			_ = tx.closed() => return,
+			_ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {}
+		}
+	}
+}
+
 //
 // Package handlers
 //
@@ -548,12 +1033,7 @@ pub(crate) async fn get_prompts(
 	Cbor(pkg): Cbor<charon::PackageTitle>,
 ) -> Result<CborOut<charon::PromptCollection>> {
 	Ok(CborOut(
-		state
-			.charon
-			.query()
-			.await?
-			.get_prompts(&pkg.name, &pkg.version)
-			.await?,
+		state.charon.get_prompts(&pkg.name, &pkg.version).await?,
 	))
 }
 
@@ -569,14 +1049,43 @@ pub(crate) async fn set_responses(
 			let responses = responses.lock().await.clone();
 			log.from_user(&user)
 				.with_entry("Set package responses")
-				.with_data(&responses)?;
+				.with_data(&responses.redacted())?;
 
 			state
 				.charon
-				.query()
-				.await?
 				.set_responses(&responses.name, responses.responses)
-				.await?;
+				.await
+				.service(Service::Charon, "set_responses")?;
+			Ok(CborOut(()))
+		}
+	)
+}
+
+// for restoring a configuration: charon applies all of these or none.
+pub(crate) async fn set_responses_batch(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	Cbor(batch): Cbor<Vec<PromptResponsesWithName>>,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		(batch),
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			let batch = batch.lock().await.clone();
+			log.from_user(&user)
+				.with_entry("Set package responses in batch")
+				.with_data(
+					&batch
+						.iter()
+						.map(PromptResponsesWithName::redacted)
+						.collect::<Vec<_>>(),
+				)?;
+
+			state
+				.charon
+				.set_responses_batch(batch.into_iter().map(|x| (x.name, x.responses)).collect())
+				.await
+				.service(Service::Charon, "set_responses_batch")?;
 			Ok(CborOut(()))
 		}
 	)
@@ -601,10 +1110,9 @@ pub(crate) async fn get_responses(
 			Ok(CborOut(
 				state
 					.charon
-					.query()
-					.await?
 					.get_responses(&title.name)
-					.await?,
+					.await
+					.service(Service::Charon, "get_responses")?,
 			))
 		}
 	)
@@ -613,31 +1121,114 @@ pub(crate) async fn get_responses(
 pub(crate) async fn list_installed(
 	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
 ) -> Result<CborOut<Vec<PackageTitle>>> {
-	Ok(CborOut(state.charon.query().await?.list_installed().await?))
+	Ok(CborOut(state.charon.list_installed().await?))
 }
 
 pub(crate) async fn list_packages(
 	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
 ) -> Result<CborOut<Vec<PackageStatus>>> {
-	Ok(CborOut(state.charon.query().await?.list().await?))
+	Ok(CborOut(state.charon.list().await?))
+}
+
+// charon has no notion of paging, so the whole list is fetched and narrowed here.
+pub(crate) async fn search_packages(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
+	Cbor(filter): Cbor<Option<PackageFilter>>,
+) -> Result<CborOut<PackageList>> {
+	let filter = filter.unwrap_or_default();
+
+	let packages: Vec<PackageStatus> = state
+		.charon
+		.list()
+		.await?
+		.into_iter()
+		.filter(|x| !filter.installed_only || x.installed)
+		.filter(|x| {
+			filter
+				.name_filter
+				.as_ref()
+				.is_none_or(|name| x.title.name.contains(name.as_str()))
+		})
+		.collect();
+
+	let total = packages.len();
+
+	let packages = if filter.per_page.is_some() || filter.page.is_some() {
+		let per_page: usize = filter.per_page.unwrap_or(20).into();
+		let page: usize = filter.page.unwrap_or(0).into();
+
+		packages
+			.into_iter()
+			.skip(page * per_page)
+			.take(per_page)
+			.collect()
+	} else {
+		packages
+	};
+
+	Ok(CborOut(PackageList { total, packages }))
+}
+
+pub(crate) async fn list_package_versions(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>, Path(name): Path<String>,
+) -> Result<CborOut<Vec<String>>> {
+	Ok(CborOut(
+		state
+			.charon
+			.list_versions(&name)
+			.await
+			.service(Service::Charon, "list_versions")?,
+	))
+}
+
+pub(crate) async fn list_packages_with_status(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
+) -> Result<CborOut<Vec<PackageRuntimeStatus>>> {
+	Ok(CborOut(state.charon.list_with_status().await?))
 }
 
 pub(crate) async fn installed(
 	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
 	Cbor(pkg): Cbor<charon::PackageTitle>,
 ) -> Result<CborOut<bool>> {
-	match state
-		.charon
-		.control()
-		.await?
-		.installed(&pkg.name, &pkg.version)
-		.await?
-	{
+	match state.charon.installed(&pkg.name, &pkg.version).await? {
 		Some(InstallStatus::Installed(_)) => Ok(CborOut(true)),
 		_ => Ok(CborOut(false)),
 	}
 }
 
+pub(crate) async fn check_reachable(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
+	Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<CborOut<Vec<PortReachability>>> {
+	Ok(CborOut(
+		state
+			.charon
+			.check_reachable(&pkg.name, &pkg.version)
+			.await?,
+	))
+}
+
+pub(crate) async fn check_consistency(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
+) -> Result<CborOut<ConsistencyReport>> {
+	Ok(CborOut(state.charon.check_consistency().await?))
+}
+
+// what installing would do, without doing it: storage, ports, privileges and the launch command.
+pub(crate) async fn plan_install(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
+	Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<CborOut<InstallPlan>> {
+	Ok(CborOut(
+		state
+			.charon
+			.plan_install(&pkg.name, &pkg.version)
+			.await
+			.service(Service::Charon, "plan_install")?,
+	))
+}
+
 pub(crate) async fn install_package(
 	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
 	Cbor(pkg): Cbor<charon::PackageTitle>,
@@ -652,15 +1243,113 @@ pub(crate) async fn install_package(
 
 			state
 				.charon
-				.control()
-				.await?
 				.install(&pkg.name, &pkg.version)
-				.await?;
+				.await
+				.service(Service::Charon, "install")?;
 			Ok(CborOut(()))
 		}
 	)
 }
 
+// install_package, but streaming charon's steps as server-sent events; see relay_progress.
+pub(crate) async fn install_package_progress(
+	State(state): State<Arc<ServerState>>, Log(mut log): Log, Account(user): Account<User>,
+	Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<EventStream> {
+	log.from_user(&user)
+		.with_entry("Install package")
+		.with_data(&pkg)?;
+
+	let stream = state
+		.charon
+		.install_with_progress(&pkg.name, &pkg.version)
+		.await
+		.service(Service::Charon, "install_with_progress");
+
+	relay_progress(state, log, stream).await
+}
+
+pub(crate) async fn reconfigure_package(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			log.from_user(&user)
+				.with_entry("Reconfigure package")
+				.with_data(&pkg)?;
+
+			state
+				.charon
+				.reconfigure(&pkg.name, &pkg.version)
+				.await
+				.service(Service::Charon, "reconfigure")?;
+			Ok(CborOut(()))
+		}
+	)
+}
+
+// puts an installed package back on another version, keeping its storage
+pub(crate) async fn rollback_package(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			log.from_user(&user)
+				.with_entry("Roll back package")
+				.with_data(&pkg)?;
+
+			state
+				.charon
+				.rollback(&pkg.name, &pkg.version)
+				.await
+				.service(Service::Charon, "rollback")?;
+			Ok(CborOut(()))
+		}
+	)
+}
+
+// downloads the package's VM image ahead of its first start, streaming byte progress as
+// server-sent events. the stream ends once the image is in place or the download fails.
+pub(crate) async fn prefetch_image(
+	State(state): State<Arc<ServerState>>, Account(_): Account<User>,
+	Path((name, version)): Path<(String, String)>,
+) -> Result<EventStream> {
+	let mut stream = state.charon.prefetch_image(&name, &version).await?;
+
+	let (tx, rx) = tokio::sync::mpsc::channel(100);
+	tokio::spawn(async move {
+		while let Some(msg) = stream.next().await {
+			let progress = match msg {
+				Ok(progress) => progress,
+				Err(e) => {
+					tracing::error!("Error prefetching image for {}-{}: {}", name, version, e);
+					return;
+				}
+			};
+
+			let event = match Event::default().json_data(progress) {
+				Ok(event) => event,
+				Err(e) => {
+					tracing::error!("Error encoding download progress: {}", e);
+					continue;
+				}
+			};
+
+			if tx.send(Ok(event)).await.is_err() {
+				return;
+			}
+		}
+	});
+
+	Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
+
 pub(crate) async fn uninstall_package(
 	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
 	Cbor(pkg): Cbor<UninstallData>,
@@ -674,10 +1363,122 @@ pub(crate) async fn uninstall_package(
 				.with_data(&pkg)?;
 			state
 				.charon
-				.control()
-				.await?
 				.uninstall(&pkg.name, &pkg.version, pkg.purge)
-				.await?;
+				.await
+				.service(Service::Charon, "uninstall")?;
+			Ok(CborOut(()))
+		}
+	)
+}
+
+pub(crate) async fn uninstall_package_progress(
+	State(state): State<Arc<ServerState>>, Log(mut log): Log, Account(user): Account<User>,
+	Cbor(pkg): Cbor<UninstallData>,
+) -> Result<EventStream> {
+	log.from_user(&user)
+		.with_entry("Uninstall package")
+		.with_data(&pkg)?;
+
+	let stream = state
+		.charon
+		.uninstall_with_progress(&pkg.name, &pkg.version, pkg.purge)
+		.await
+		.service(Service::Charon, "uninstall_with_progress");
+
+	relay_progress(state, log, stream).await
+}
+
+// charon keeps going when the client disconnects, so the stream is drained regardless and the audit
+// log entry is written with the outcome. the client always gets a final Finished event, even when
+// charon goes away before sending one.
+async fn relay_progress(
+	state: Arc<ServerState>, mut log: AuditLog,
+	stream: std::result::Result<ApiStream<InstallProgress>, ServiceError>,
+) -> Result<EventStream> {
+	let mut stream = match stream {
+		Ok(stream) => stream,
+		Err(e) => {
+			let e = AppError::from(e);
+			log.with_error(&e.0);
+			if let Some(service) = &e.1 {
+				log.with_service_error(service);
+			}
+			log.complete(&state.db).await?;
+			return Err(e);
+		}
+	};
+
+	let (tx, rx) = tokio::sync::mpsc::channel(100);
+	tokio::spawn(async move {
+		let mut finished = None;
+
+		while let Some(msg) = stream.next().await {
+			let progress = match msg {
+				Ok(progress) => progress,
+				Err(e) => {
+					finished = Some(InstallProgress {
+						step: InstallStep::Finished,
+						error: Some(e.to_string()),
+					});
+					break;
+				}
+			};
+
+			if progress.step == InstallStep::Finished {
+				finished = Some(progress);
+				break;
+			}
+
+			if let Ok(event) = Event::default().json_data(&progress) {
+				let _ = tx.send(Ok(event)).await;
+			}
+		}
+
+		let finished = finished.unwrap_or(InstallProgress {
+			step: InstallStep::Finished,
+			error: Some("charon ended the operation without reporting its outcome".into()),
+		});
+
+		if let Some(error) = &finished.error {
+			log.with_error(
+				&ProblemKind::Upstream
+					.problem()
+					.with_detail(error.clone())
+					.with_title("API sub-services error"),
+			);
+		}
+
+		if let Err(e) = log.complete(&state.db).await {
+			tracing::error!("Could not write audit log: {}", e);
+		}
+
+		match Event::default().json_data(&finished) {
+			Ok(event) => {
+				let _ = tx.send(Ok(event)).await;
+			}
+			Err(e) => tracing::error!("Error encoding install progress: {}", e),
+		}
+	});
+
+	Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
+
+pub(crate) async fn remove_package_version(
+	State(state): State<Arc<ServerState>>, Log(log): Log, Account(user): Account<User>,
+	Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<WithLog<CborOut<()>>> {
+	run_with_log!(
+		state,
+		log,
+		async move |state: Arc<ServerState>, log: &mut AuditLog| {
+			log.from_user(&user)
+				.with_entry("Remove package version")
+				.with_data(&pkg)?;
+			state
+				.charon
+				.remove_version(&pkg.name, &pkg.version)
+				.await
+				.service(Service::Charon, "remove_version")?;
 			Ok(CborOut(()))
 		}
 	)
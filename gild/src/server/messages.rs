@@ -1,4 +1,7 @@
-use buckle::client::Info;
+use buckle::{
+	client::{Info, ZFSStat},
+	systemd::UnitFilter,
+};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
@@ -12,12 +15,55 @@ pub struct Pagination {
 	pub page: Option<u8>,
 }
 
+// every filter is optional and they narrow together; the paging fields match Pagination, so a
+// plain Pagination is still accepted.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AuditLogFilter {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub user_id: Option<u32>,
+	// matched anywhere in the endpoint
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub endpoint: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub since: Option<chrono::DateTime<chrono::Local>>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub until: Option<chrono::DateTime<chrono::Local>>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub per_page: Option<u8>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub page: Option<u8>,
+}
+
+// like AuditLogFilter, the filters narrow together and the paging fields match Pagination.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PackageFilter {
+	// matched anywhere in the package name
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub name_filter: Option<String>,
+	#[serde(default)]
+	pub installed_only: bool,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub per_page: Option<u8>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub page: Option<u8>,
+}
+
+// total counts every package that matched the filter, not just this page.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PackageList {
+	pub total: usize,
+	pub packages: Vec<charon::PackageStatus>,
+}
+
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct LogParameters {
 	pub name: String,
 	pub count: usize,
 	pub cursor: Option<String>,
 	pub direction: Option<buckle::systemd::LogDirection>,
+	// minimum severity to return; unset returns everything
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub priority: Option<buckle::systemd::LogPriority>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -25,6 +71,22 @@ pub struct Token {
 	pub(crate) token: String,
 }
 
+#[derive(Debug, Clone, Default, Validate, Serialize, Deserialize)]
+pub struct CreateApiToken {
+	#[validate(length(min = 1, max = 50))]
+	pub name: String,
+	// never expires if unset
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub expires: Option<chrono::DateTime<chrono::Local>>,
+}
+
+// the only time the token itself is handed out; afterwards it can only be listed by id and name.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct NewApiToken {
+	pub(crate) id: u32,
+	pub(crate) token: String,
+}
+
 #[derive(Debug, Clone, Default, Validate, Serialize, Deserialize)]
 pub struct Authentication {
 	#[validate(length(min = 3, max = 30))]
@@ -41,6 +103,17 @@ pub struct PingResult {
 	pub info: Option<Info>,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Maintenance {
+	pub enabled: bool,
+}
+
+// whether the first account can still be created without logging in
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SetupStatus {
+	pub needed: bool,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct HealthStatus {
 	pub buckle: Health,
@@ -60,3 +133,40 @@ pub struct PromptResponsesWithName {
 	pub name: String,
 	pub responses: charon::PromptResponses,
 }
+
+impl PromptResponsesWithName {
+	// what goes in the audit log; secrets are only ever stored sealed
+	pub fn redacted(&self) -> Self {
+		Self {
+			name: self.name.clone(),
+			responses: self.responses.clone().redact_secrets(),
+		}
+	}
+}
+
+// a dataset or volume, with the installed package whose storage it is, if any
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnedZFSStat {
+	pub stat: ZFSStat,
+	pub owner: Option<charon::PackageTitle>,
+}
+
+// /systemd/list used to take only a unit name to filter on, which older clients still send
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UnitListFilter {
+	Name(String),
+	Filter(UnitFilter),
+}
+
+impl From<UnitListFilter> for UnitFilter {
+	fn from(value: UnitListFilter) -> Self {
+		match value {
+			UnitListFilter::Name(name) => Self {
+				name: Some(name),
+				..Default::default()
+			},
+			UnitListFilter::Filter(filter) => filter,
+		}
+	}
+}
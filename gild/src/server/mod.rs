@@ -1,20 +1,32 @@
+pub mod api;
 mod axum_support;
 mod handlers;
 pub mod messages;
 #[cfg(test)]
 mod tests;
+mod tls;
 
-use self::handlers::*;
-use crate::{config::Config, db::DB};
+use self::{
+	api::{BuckleApi, CharonApi},
+	axum_support::maintenance_guard,
+	handlers::*,
+};
+use crate::{
+	config::{Config, CorsConfig},
+	db::{DB, models::SigningKey},
+};
 use anyhow::Result;
 use axum::{
-	Router,
+	Router, middleware,
 	routing::{delete, get, post, put},
 };
-use buckle::client::Client as BuckleClient;
-use charon::Client as CharonClient;
-use http::{Method, header::*};
-use std::{net::SocketAddr, sync::Arc};
+use http::{HeaderName, HeaderValue, Method};
+use std::{
+	collections::HashMap,
+	net::SocketAddr,
+	str::FromStr,
+	sync::{Arc, Mutex, RwLock, atomic::AtomicBool},
+};
 use thiserror::Error;
 use tower::ServiceBuilder;
 use tower_http::cors::{AllowOrigin, CorsLayer};
@@ -32,14 +44,102 @@ pub enum HandlerError {
 	LoginError(String),
 	#[error("User Management Error: {0}")]
 	UserManagementError(String),
+	#[error("Account Locked: {0}")]
+	AccountLocked(String),
+	#[error("Session Error: {0}")]
+	SessionError(String),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Service {
+	Buckle,
+	Charon,
+}
+
+impl std::fmt::Display for Service {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Buckle => "buckle",
+			Self::Charon => "charon",
+		})
+	}
+}
+
+// a failed call into buckle or charon, with enough context for the audit log to say which call it
+// was. attach it with ServiceContext::service() at the call site.
+#[derive(Debug, Clone, Error)]
+#[error("{service} {method} failed ({code:?}): {message}")]
+pub struct ServiceError {
+	pub service: Service,
+	pub method: &'static str,
+	pub code: tonic::Code,
+	pub message: String,
+}
+
+pub trait ServiceContext<T> {
+	fn service(
+		self, service: Service, method: &'static str,
+	) -> std::result::Result<T, ServiceError>;
+}
+
+impl<T, E> ServiceContext<T> for std::result::Result<T, E>
+where
+	E: Into<anyhow::Error>,
+{
+	fn service(
+		self, service: Service, method: &'static str,
+	) -> std::result::Result<T, ServiceError> {
+		self.map_err(|e| {
+			let e: anyhow::Error = e.into();
+			let (code, message) = match e.downcast::<tonic::Status>() {
+				Ok(status) => (status.code(), status.message().to_string()),
+				// anything else means we never got an answer, f.e. the socket wasn't there
+				Err(e) => (tonic::Code::Unavailable, e.to_string()),
+			};
+
+			ServiceError {
+				service,
+				method,
+				code,
+				message,
+			}
+		})
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct ServerState {
-	buckle: BuckleClient,
-	charon: CharonClient,
+	buckle: Arc<dyn BuckleApi>,
+	charon: Arc<dyn CharonApi>,
 	db: DB,
 	config: Config,
+	// when set, routes that change anything answer with a 503 until it is cleared again. see
+	// maintenance_guard.
+	maintenance: Arc<AtomicBool>,
+	// starts as the configured key, or the last rotated one; see SigningKey.
+	signing_key: Arc<RwLock<Vec<u8>>>,
+	// one per user id. logins to the same account take turns, so that concurrent failures can't
+	// each count from the same stored total and slip past the lockout.
+	login_locks: Arc<Mutex<HashMap<u32, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl ServerState {
+	pub(crate) fn signing_key(&self) -> Vec<u8> {
+		self.signing_key.read().unwrap().clone()
+	}
+
+	pub(crate) fn set_signing_key(&self, key: Vec<u8>) {
+		*self.signing_key.write().unwrap() = key;
+	}
+
+	pub(crate) fn login_lock(&self, user_id: u32) -> Arc<tokio::sync::Mutex<()>> {
+		self.login_locks
+			.lock()
+			.unwrap()
+			.entry(user_id)
+			.or_default()
+			.clone()
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -50,43 +150,150 @@ pub struct Server {
 
 impl Server {
 	pub async fn new(config: Config) -> Result<Self> {
+		let buckle = Arc::new(config.buckle()?);
+		let charon = Arc::new(config.charon()?);
+		Self::with_services(config, buckle, charon).await
+	}
+
+	// builds the server against any implementation of the buckle and charon calls; new() uses the
+	// real clients.
+	pub async fn with_services(
+		config: Config, buckle: Arc<dyn BuckleApi>, charon: Arc<dyn CharonApi>,
+	) -> Result<Self> {
+		let db = config.get_db().await?;
+		let signing_key = SigningKey::current(&db)
+			.await?
+			.unwrap_or_else(|| config.signing_key.clone());
+
+		let state = Arc::new(ServerState {
+			buckle,
+			charon,
+			db,
+			config: config.clone(),
+			maintenance: Default::default(),
+			signing_key: Arc::new(RwLock::new(signing_key)),
+			login_locks: Default::default(),
+		});
+
+		let guard = || middleware::from_fn_with_state(state.clone(), maintenance_guard);
+
 		Ok(Self {
 			router: Router::new()
-				.route("/packages/uninstall", post(uninstall_package))
-				.route("/packages/install", post(install_package))
+				.route(
+					"/packages/uninstall",
+					post(uninstall_package).route_layer(guard()),
+				)
+				.route("/packages/plan", post(plan_install))
+				.route(
+					"/packages/install",
+					post(install_package).route_layer(guard()),
+				)
+				.route(
+					"/packages/install/progress",
+					post(install_package_progress).route_layer(guard()),
+				)
+				.route(
+					"/packages/uninstall/progress",
+					post(uninstall_package_progress).route_layer(guard()),
+				)
+				.route(
+					"/packages/reconfigure",
+					post(reconfigure_package).route_layer(guard()),
+				)
+				.route(
+					"/packages/rollback",
+					post(rollback_package).route_layer(guard()),
+				)
+				.route(
+					"/packages/remove_version",
+					post(remove_package_version).route_layer(guard()),
+				)
 				.route("/packages/prompts", post(get_prompts))
 				.route("/packages/get_responses", post(get_responses))
-				.route("/packages/set_responses", post(set_responses))
+				.route(
+					"/packages/set_responses",
+					post(set_responses).route_layer(guard()),
+				)
+				.route(
+					"/packages/set_responses_batch",
+					post(set_responses_batch).route_layer(guard()),
+				)
 				.route("/packages/installed", post(installed))
+				.route("/packages/reachable", post(check_reachable))
+				.route(
+					"/packages/prefetch/{name}/{version}",
+					get(prefetch_image).route_layer(guard()),
+				)
 				.route("/packages/list_installed", get(list_installed))
-				.route("/packages/list", get(list_packages))
+				.route("/packages/list", get(list_packages).post(search_packages))
+				.route("/packages/versions/{name}", get(list_package_versions))
+				.route("/packages/list_with_status", get(list_packages_with_status))
+				.route("/packages/consistency", get(check_consistency))
 				.route("/systemd/log", post(unit_log))
+				.route("/systemd/log/{name}/follow", get(unit_log_follow))
 				.route("/systemd/list", post(list_units))
-				.route("/systemd/set_unit", post(set_unit))
+				.route("/systemd/set_unit", post(set_unit).route_layer(guard()))
 				.route("/status/ping", get(ping))
+				.route("/status/setup", get(setup_status))
+				// not guarded by maintenance: rebooting is often how maintenance ends
+				.route("/status/power", post(power))
 				.route("/status/log", post(log))
+				.route(
+					"/status/maintenance",
+					get(get_maintenance).post(set_maintenance),
+				)
 				.route("/zfs/list", post(zfs_list))
-				.route("/zfs/create_volume", post(zfs_create_volume))
-				.route("/zfs/create_dataset", post(zfs_create_dataset))
-				.route("/zfs/modify_dataset", post(zfs_modify_dataset))
-				.route("/zfs/modify_volume", post(zfs_modify_volume))
-				.route("/zfs/destroy", post(zfs_destroy))
-				.route("/users", put(create_user).post(list_users))
+				.route("/zfs/list_owned", post(zfs_list_owned))
+				.route("/zfs/get", post(zfs_get))
+				.route(
+					"/zfs/create_volume",
+					post(zfs_create_volume).route_layer(guard()),
+				)
+				.route(
+					"/zfs/create_dataset",
+					post(zfs_create_dataset).route_layer(guard()),
+				)
+				.route(
+					"/zfs/modify_dataset",
+					post(zfs_modify_dataset).route_layer(guard()),
+				)
+				.route(
+					"/zfs/modify_volume",
+					post(zfs_modify_volume).route_layer(guard()),
+				)
+				.route("/zfs/destroy", post(zfs_destroy).route_layer(guard()))
+				.route("/zfs/clone", post(zfs_clone).route_layer(guard()))
+				.route("/zfs/promote", post(zfs_promote).route_layer(guard()))
+				.route("/zfs/mount", post(zfs_mount).route_layer(guard()))
+				.route("/zfs/unmount", post(zfs_unmount).route_layer(guard()))
+				.route(
+					"/users",
+					put(create_user).route_layer(guard()).post(list_users),
+				)
 				.route(
 					"/user/{id}",
 					delete(remove_user)
-						.get(get_user)
 						.post(update_user)
-						.patch(reactivate_user),
+						.patch(reactivate_user)
+						.route_layer(guard())
+						.get(get_user),
 				)
+				.route("/user/{id}/unlock", post(unlock_user).route_layer(guard()))
 				.route("/session/login", post(login))
 				.route("/session/me", get(me))
-				.with_state(Arc::new(ServerState {
-					buckle: config.buckle()?,
-					charon: config.charon()?,
-					db: config.get_db().await?,
-					config: config.clone(),
-				}))
+				// not guarded by maintenance: these are needed most when things are already going
+				// wrong
+				.route("/session/rotate_key", post(rotate_signing_key))
+				.route("/sessions", get(list_sessions).delete(revoke_all_sessions))
+				.route("/session/{id}", delete(revoke_session))
+				.route(
+					"/tokens",
+					put(create_api_token)
+						.route_layer(guard())
+						.get(list_api_tokens),
+				)
+				.route("/token/{id}", delete(revoke_api_token))
+				.with_state(state)
 				.layer(
 					ServiceBuilder::new()
 						.layer(
@@ -95,23 +302,7 @@ impl Server {
 								.on_request(DefaultOnRequest::new().level(Level::INFO))
 								.on_failure(DefaultOnFailure::new().level(Level::ERROR)),
 						)
-						.layer(
-							CorsLayer::new()
-								.allow_methods([
-									Method::GET,
-									Method::POST,
-									Method::DELETE,
-									Method::PUT,
-									Method::PATCH,
-									Method::HEAD,
-									Method::TRACE,
-									Method::OPTIONS,
-								])
-								.allow_credentials(true)
-								.allow_origin(AllowOrigin::mirror_request())
-								.allow_headers([CONTENT_TYPE, ACCEPT, AUTHORIZATION])
-								.allow_private_network(true),
-						),
+						.layer(cors_layer(&config.cors)?),
 				),
 			config,
 		})
@@ -120,13 +311,52 @@ impl Server {
 	pub async fn start(&self) -> Result<()> {
 		let handle = axum_server::Handle::new();
 		tokio::spawn(shutdown_signal(handle.clone()));
-		Ok(axum_server::bind(self.config.listen)
-			.handle(handle)
-			.serve(self.router.clone().into_make_service())
-			.await?)
+
+		if let Some(tls) = &self.config.tls {
+			let rustls = tls::rustls_config(tls)?;
+			tokio::spawn(tls::watch(tls.clone(), rustls.clone()));
+
+			Ok(axum_server::bind_rustls(self.config.listen, rustls)
+				.handle(handle)
+				.serve(self.router.clone().into_make_service())
+				.await?)
+		} else {
+			Ok(axum_server::bind(self.config.listen)
+				.handle(handle)
+				.serve(self.router.clone().into_make_service())
+				.await?)
+		}
 	}
 }
 
+fn cors_layer(config: &CorsConfig) -> Result<CorsLayer> {
+	// tower-http panics on a wildcard here instead of returning an error
+	config.validate()?;
+
+	let origins = config
+		.origins
+		.iter()
+		.map(|x| HeaderValue::from_str(x))
+		.collect::<std::result::Result<Vec<_>, _>>()?;
+	let methods = config
+		.methods
+		.iter()
+		.map(|x| Method::from_str(&x.to_uppercase()))
+		.collect::<std::result::Result<Vec<_>, _>>()?;
+	let headers = config
+		.headers
+		.iter()
+		.map(|x| HeaderName::from_str(x))
+		.collect::<std::result::Result<Vec<_>, _>>()?;
+
+	Ok(CorsLayer::new()
+		.allow_methods(methods)
+		.allow_credentials(true)
+		.allow_origin(AllowOrigin::list(origins))
+		.allow_headers(headers)
+		.allow_private_network(true))
+}
+
 async fn shutdown_signal(handle: axum_server::Handle<SocketAddr>) {
 	let ctrl_c = async {
 		tokio::signal::ctrl_c()
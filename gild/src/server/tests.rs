@@ -4,6 +4,7 @@ mod systemd {
 		server::messages::*,
 		testutil::{TestClient, start_server},
 	};
+	use buckle::systemd::{LastRunState, UnitFilter};
 
 	#[tokio::test]
 	async fn list() {
@@ -17,7 +18,7 @@ mod systemd {
 
 		assert!(
 			client
-				.post::<Option<String>, buckle::systemd::Unit>("/systemd/list", None)
+				.post::<Option<UnitFilter>, buckle::systemd::Unit>("/systemd/list", None)
 				.await
 				.is_err()
 		);
@@ -31,7 +32,7 @@ mod systemd {
 			.unwrap();
 
 		let list = client
-			.post::<Option<String>, Vec<buckle::systemd::Unit>>("/systemd/list", None)
+			.post::<Option<UnitFilter>, Vec<buckle::systemd::Unit>>("/systemd/list", None)
 			.await
 			.unwrap();
 
@@ -44,6 +45,20 @@ mod systemd {
 			1
 		);
 
+		let list = client
+			.post::<Option<UnitFilter>, Vec<buckle::systemd::Unit>>(
+				"/systemd/list",
+				Some(UnitFilter {
+					name: Some("network.target".into()),
+					..Default::default()
+				}),
+			)
+			.await
+			.unwrap();
+
+		assert_eq!(list.len(), 1);
+
+		// the bare name older clients send
 		let list = client
 			.post::<Option<String>, Vec<buckle::systemd::Unit>>(
 				"/systemd/list",
@@ -53,6 +68,40 @@ mod systemd {
 			.unwrap();
 
 		assert_eq!(list.len(), 1);
+
+		let running = client
+			.post::<Option<UnitFilter>, Vec<buckle::systemd::Unit>>(
+				"/systemd/list",
+				Some(UnitFilter {
+					last_run_state: Some(LastRunState::Running),
+					..Default::default()
+				}),
+			)
+			.await
+			.unwrap();
+
+		assert!(!running.is_empty());
+		assert!(
+			running
+				.iter()
+				.all(|x| x.status.last_run_state == LastRunState::Running)
+		);
+
+		let page = client
+			.post::<Option<UnitFilter>, Vec<buckle::systemd::Unit>>(
+				"/systemd/list",
+				Some(UnitFilter {
+					last_run_state: Some(LastRunState::Running),
+					offset: 1,
+					limit: Some(1),
+					..Default::default()
+				}),
+			)
+			.await
+			.unwrap();
+
+		assert_eq!(page.len(), 1);
+		assert_eq!(page[0], running[1]);
 	}
 
 	#[tokio::test]
@@ -84,7 +133,7 @@ mod systemd {
 			.unwrap();
 
 		let list = client
-			.post::<Option<String>, Vec<buckle::systemd::Unit>>("/systemd/list", None)
+			.post::<Option<UnitFilter>, Vec<buckle::systemd::Unit>>("/systemd/list", None)
 			.await
 			.unwrap();
 
@@ -98,9 +147,12 @@ mod systemd {
 		);
 
 		let list = client
-			.post::<Option<String>, Vec<buckle::systemd::Unit>>(
+			.post::<Option<UnitFilter>, Vec<buckle::systemd::Unit>>(
 				"/systemd/list",
-				Some("network.target".into()),
+				Some(UnitFilter {
+					name: Some("network.target".into()),
+					..Default::default()
+				}),
 			)
 			.await
 			.unwrap();
@@ -115,6 +167,7 @@ mod systemd {
 					count: 10,
 					cursor: None,
 					direction: None,
+					priority: None,
 				}),
 			)
 			.await
@@ -122,6 +175,62 @@ mod systemd {
 
 		assert!(entries.len() > 0);
 		assert!(entries.len() < 11);
+
+		let entries = client
+			.post::<Option<LogParameters>, Vec<buckle::systemd::LogMessage>>(
+				"/systemd/log",
+				Some(LogParameters {
+					name: "multi-user.target".into(),
+					count: 10,
+					cursor: None,
+					direction: None,
+					priority: Some(buckle::systemd::LogPriority::Notice),
+				}),
+			)
+			.await
+			.unwrap();
+
+		assert!(
+			entries
+				.iter()
+				.all(|x| x.priority() <= Some(buckle::systemd::LogPriority::Notice))
+		);
+	}
+
+	#[tokio::test]
+	async fn log_follow() {
+		let mut client = TestClient::new(start_server(None).await.unwrap());
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+		assert!(
+			client
+				.stream("/systemd/log/multi-user.target/follow")
+				.await
+				.is_err()
+		);
+
+		client
+			.login(Authentication {
+				username: "test-login".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		let resp = client
+			.stream("/systemd/log/multi-user.target/follow")
+			.await
+			.unwrap();
+
+		assert_eq!(
+			resp.headers().get("content-type").unwrap(),
+			"text/event-stream"
+		);
 	}
 
 	#[tokio::test]
@@ -132,12 +241,12 @@ mod systemd {
 
 mod packages {
 	use charon::{
-		Input, InputType, PackageTitle, Prompt, PromptCollection, PromptResponse, PromptResponses,
-		UninstallData,
+		Input, InputType, InstallStatus, PackageRuntimeStatus, PackageStatus, PackageTitle, Prompt,
+		PromptCollection, PromptResponse, PromptResponses, UninstallData,
 	};
 
 	use crate::{
-		db::models::User,
+		db::models::{AuditLog, User},
 		server::messages::*,
 		testutil::{TestClient, start_server},
 	};
@@ -192,16 +301,34 @@ mod packages {
 					template: "private_path".into(),
 					question: "Where do you want this mounted?".into(),
 					input_type: InputType::String,
+					options: Default::default(),
+					required: false,
+					default: None,
+					pattern: None,
+					min: None,
+					max: None,
 				},
 				Prompt {
 					template: "private_size".into(),
 					question: "How big should it be?".into(),
 					input_type: InputType::Integer,
+					options: Default::default(),
+					required: false,
+					default: None,
+					pattern: None,
+					min: None,
+					max: None,
 				},
 				Prompt {
 					template: "private_recreate".into(),
 					question: "Should we recreate this volume if it already exists?".into(),
 					input_type: InputType::Boolean,
+					options: Default::default(),
+					required: false,
+					default: None,
+					pattern: None,
+					min: None,
+					max: None,
 				},
 			])
 		)
@@ -282,6 +409,234 @@ mod packages {
 		);
 	}
 
+	#[tokio::test]
+	async fn list() {
+		let mut client = TestClient::new(start_server(None).await.unwrap());
+
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		client.put::<User, User>("/users", login).await.unwrap();
+		client
+			.login(Authentication {
+				username: "test-login".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		// the registry holds six packages across ten versions
+		let all = client
+			.get::<Vec<PackageStatus>>("/packages/list")
+			.await
+			.unwrap();
+		assert_eq!(all.len(), 10);
+
+		let list = client
+			.post::<(), PackageList>("/packages/list", ())
+			.await
+			.unwrap();
+		assert_eq!(list.total, 10);
+		assert_eq!(list.packages, all);
+
+		let list = client
+			.post::<_, PackageList>(
+				"/packages/list",
+				PackageFilter {
+					name_filter: Some("plex".into()),
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+		assert_eq!(list.total, 4);
+		assert!(list.packages.iter().all(|x| x.title.name.contains("plex")));
+
+		let mut seen = Vec::new();
+		for page in 0..4 {
+			let list = client
+				.post::<_, PackageList>(
+					"/packages/list",
+					PackageFilter {
+						per_page: Some(3),
+						page: Some(page),
+						..Default::default()
+					},
+				)
+				.await
+				.unwrap();
+			assert_eq!(list.total, 10);
+			assert_eq!(list.packages.len(), if page == 3 { 1 } else { 3 });
+			seen.extend(list.packages);
+		}
+		assert_eq!(seen, all);
+
+		let list = client
+			.post::<_, PackageList>(
+				"/packages/list",
+				PackageFilter {
+					installed_only: true,
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+		assert_eq!(list.total, 0);
+		assert!(list.packages.is_empty());
+	}
+
+	#[tokio::test]
+	async fn set_responses_batch() {
+		let responses = PromptResponses(vec![PromptResponse {
+			input: Input::String("/tmp/volroot".into()),
+			template: "private_path".into(),
+		}]);
+
+		let mut client = TestClient::new(start_server(None).await.unwrap());
+
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		client.put::<User, User>("/users", login).await.unwrap();
+		client
+			.login(Authentication {
+				username: "test-login".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		let batch = vec![
+			PromptResponsesWithName {
+				name: "with-prompts".into(),
+				responses: responses.clone(),
+			},
+			PromptResponsesWithName {
+				name: "podman-test".into(),
+				responses: responses.clone(),
+			},
+		];
+
+		client
+			.post::<Vec<PromptResponsesWithName>, ()>(
+				"/packages/set_responses_batch",
+				batch.clone(),
+			)
+			.await
+			.unwrap();
+
+		for item in &batch {
+			assert_eq!(
+				responses,
+				client
+					.post::<charon::PackageTitle, charon::PromptResponses>(
+						"/packages/get_responses",
+						charon::PackageTitle {
+							name: item.name.clone(),
+							version: "".into(),
+						}
+					)
+					.await
+					.unwrap()
+			);
+		}
+
+		// the same package twice is refused as a whole
+		let changed = PromptResponses(vec![PromptResponse {
+			input: Input::String("/tmp/elsewhere".into()),
+			template: "private_path".into(),
+		}]);
+		assert!(
+			client
+				.post::<Vec<PromptResponsesWithName>, ()>(
+					"/packages/set_responses_batch",
+					vec![
+						PromptResponsesWithName {
+							name: "with-prompts".into(),
+							responses: changed.clone(),
+						},
+						PromptResponsesWithName {
+							name: "with-prompts".into(),
+							responses: changed,
+						},
+					]
+				)
+				.await
+				.is_err()
+		);
+		assert_eq!(
+			responses,
+			client
+				.post::<charon::PackageTitle, charon::PromptResponses>(
+					"/packages/get_responses",
+					charon::PackageTitle {
+						name: "with-prompts".into(),
+						version: "".into(),
+					}
+				)
+				.await
+				.unwrap()
+		);
+	}
+
+	#[tokio::test]
+	async fn secrets_not_logged() {
+		let mut client = TestClient::new(start_server(None).await.unwrap());
+
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		client.put::<User, User>("/users", login).await.unwrap();
+		client
+			.login(Authentication {
+				username: "test-login".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		let secret = |value: &str| PromptResponsesWithName {
+			name: "with-prompts".into(),
+			responses: PromptResponses(vec![PromptResponse {
+				input: Input::Secret(value.into()),
+				template: "token".into(),
+			}]),
+		};
+
+		client
+			.post::<PromptResponsesWithName, ()>("/packages/set_responses", secret("hunter2"))
+			.await
+			.unwrap();
+		client
+			.post::<Vec<PromptResponsesWithName>, ()>(
+				"/packages/set_responses_batch",
+				vec![secret("correct-horse")],
+			)
+			.await
+			.unwrap();
+
+		let log = client
+			.post::<Pagination, Vec<AuditLog>>("/status/log", Pagination::default())
+			.await
+			.unwrap();
+		let entries = log
+			.iter()
+			.filter(|x| x.entry.starts_with("Set package responses"))
+			.collect::<Vec<_>>();
+		assert_eq!(entries.len(), 2);
+		for entry in entries {
+			assert!(entry.data.contains(charon::REDACTED), "{}", entry.data);
+			assert!(!entry.data.contains("hunter2"), "{}", entry.data);
+			assert!(!entry.data.contains("correct-horse"), "{}", entry.data);
+		}
+	}
+
 	#[tokio::test]
 	async fn install() {
 		let (pool, file) = buckle::testutil::create_zpool("gild-install").unwrap();
@@ -365,6 +720,21 @@ mod packages {
 			}]
 		);
 
+		let list = client
+			.get::<Vec<PackageRuntimeStatus>>("/packages/list_with_status")
+			.await
+			.unwrap();
+		let item = list
+			.iter()
+			.find(|x| x.title.name == "podman-test" && x.title.version == "0.0.1")
+			.unwrap();
+		assert!(matches!(item.status, InstallStatus::Installed(_)));
+		assert!(
+			list.iter()
+				.filter(|x| x.title.name != "podman-test" || x.title.version != "0.0.1")
+				.all(|x| x.status == InstallStatus::NotInstalled)
+		);
+
 		client
 			.post::<UninstallData, ()>(
 				"/packages/uninstall",
@@ -431,19 +801,10 @@ mod service {
 		assert_ne!(info.load_average, [0.0, 0.0, 0.0]);
 		assert_ne!(info.processes, 0);
 	}
-}
-
-mod user {
-	use crate::db::models::User;
-	use crate::server::messages::Authentication;
-	use crate::testutil::{TestClient, start_server};
 
 	#[tokio::test]
-	async fn login_logout() {
+	async fn maintenance() {
 		let mut client = TestClient::new(start_server(None).await.unwrap());
-
-		assert!(client.post::<(), Vec<User>>("/users", ()).await.is_err());
-
 		let login = User {
 			username: "test-login".into(),
 			plaintext_password: Some("test-password".into()),
@@ -451,6 +812,19 @@ mod user {
 		};
 		assert!(client.put::<User, User>("/users", login).await.is_ok());
 
+		assert!(
+			client
+				.get::<Maintenance>("/status/maintenance")
+				.await
+				.is_err()
+		);
+		assert!(
+			client
+				.post::<Maintenance, ()>("/status/maintenance", Maintenance { enabled: true })
+				.await
+				.is_err()
+		);
+
 		client
 			.login(Authentication {
 				username: "test-login".into(),
@@ -459,24 +833,434 @@ mod user {
 			.await
 			.unwrap();
 
-		assert!(client.post::<(), Vec<User>>("/users", ()).await.is_ok());
-	}
+		assert!(
+			!client
+				.get::<Maintenance>("/status/maintenance")
+				.await
+				.unwrap()
+				.enabled
+		);
 
-	#[tokio::test]
-	async fn first_time_setup() {
-		let mut client = TestClient::new(start_server(None).await.unwrap());
+		client
+			.post::<Maintenance, ()>("/status/maintenance", Maintenance { enabled: true })
+			.await
+			.unwrap();
+		assert!(
+			client
+				.get::<Maintenance>("/status/maintenance")
+				.await
+				.unwrap()
+				.enabled
+		);
 
-		let login = User {
-			username: "test-login".into(),
+		let user = User {
+			username: "second-user".into(),
 			plaintext_password: Some("test-password".into()),
 			..Default::default()
 		};
-		assert!(client.put::<User, User>("/users", login).await.is_ok());
 
-		let login = User {
-			username: "test-login2".into(),
-			plaintext_password: Some("test-password".into()),
-			..Default::default()
+		// writes are refused, reads still work
+		let err = client
+			.put::<User, User>("/users", user.clone())
+			.await
+			.unwrap_err();
+		assert!(err.to_string().contains("maintenance mode"));
+		assert_eq!(
+			client
+				.post::<(), Vec<User>>("/users", ())
+				.await
+				.unwrap()
+				.len(),
+			1
+		);
+		assert!(client.get::<User>("/user/1").await.is_ok());
+		assert!(client.delete::<()>("/user/1").await.is_err());
+
+		client
+			.post::<Maintenance, ()>("/status/maintenance", Maintenance { enabled: false })
+			.await
+			.unwrap();
+		assert!(client.put::<User, User>("/users", user).await.is_ok());
+	}
+}
+
+mod user {
+	use crate::db::models::{ApiToken, AuditLog, Session, User, UserField};
+	use crate::server::messages::{
+		Authentication, CreateApiToken, NewApiToken, Pagination, SetupStatus,
+	};
+	use crate::testutil::{TestClient, start_server};
+
+	#[tokio::test]
+	async fn rotate_signing_key() {
+		let addr = start_server(None).await.unwrap();
+		let mut admin = TestClient::new(addr);
+		let mut client = TestClient::new(addr);
+
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		assert!(admin.put::<User, User>("/users", login).await.is_ok());
+
+		let auth = Authentication {
+			username: "test-login".into(),
+			password: "test-password".into(),
+		};
+		admin.login(auth.clone()).await.unwrap();
+		client.login(auth.clone()).await.unwrap();
+		assert!(client.post::<(), Vec<User>>("/users", ()).await.is_ok());
+
+		admin
+			.post::<(), ()>("/session/rotate_key", ())
+			.await
+			.unwrap();
+
+		// every session is gone, including the one that asked
+		assert!(admin.post::<(), Vec<User>>("/users", ()).await.is_err());
+		assert!(client.post::<(), Vec<User>>("/users", ()).await.is_err());
+
+		client.login(auth).await.unwrap();
+		assert!(client.post::<(), Vec<User>>("/users", ()).await.is_ok());
+	}
+
+	#[tokio::test]
+	async fn sessions() {
+		let addr = start_server(None).await.unwrap();
+		let mut first = TestClient::new(addr);
+		let mut second = TestClient::new(addr);
+		let mut third = TestClient::new(addr);
+		let mut other = TestClient::new(addr);
+
+		let auth = Authentication {
+			username: "test-login".into(),
+			password: "test-password".into(),
+		};
+
+		for username in ["test-login", "test-other"] {
+			let login = User {
+				username: username.into(),
+				plaintext_password: Some("test-password".into()),
+				..Default::default()
+			};
+			assert!(first.put::<User, User>("/users", login).await.is_ok());
+			// only the first account can be made without logging in. logging in again would start
+			// a second session for first.
+			if username == "test-login" {
+				first.login(auth.clone()).await.unwrap();
+			}
+		}
+
+		second.login(auth.clone()).await.unwrap();
+		third.login(auth).await.unwrap();
+		other
+			.login(Authentication {
+				username: "test-other".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		assert!(
+			TestClient::new(addr)
+				.get::<Vec<Session>>("/sessions")
+				.await
+				.is_err()
+		);
+
+		// sessions are listed in the order they were created
+		let sessions = first.get::<Vec<Session>>("/sessions").await.unwrap();
+		assert_eq!(sessions.len(), 3);
+		assert_eq!(
+			other.get::<Vec<Session>>("/sessions").await.unwrap().len(),
+			1
+		);
+
+		// one user cannot revoke another's sessions
+		assert!(
+			other
+				.delete::<()>(&format!("/session/{}", sessions[0].id))
+				.await
+				.is_err()
+		);
+		assert!(first.get::<Vec<Session>>("/sessions").await.is_ok());
+		assert!(first.delete::<()>("/session/9999").await.is_err());
+
+		first
+			.delete::<()>(&format!("/session/{}", sessions[1].id))
+			.await
+			.unwrap();
+		assert!(second.get::<Vec<Session>>("/sessions").await.is_err());
+		assert_eq!(
+			third.get::<Vec<Session>>("/sessions").await.unwrap().len(),
+			2
+		);
+
+		third.delete::<()>("/sessions").await.unwrap();
+		assert!(first.get::<Vec<Session>>("/sessions").await.is_err());
+		let remaining = third.get::<Vec<Session>>("/sessions").await.unwrap();
+		assert_eq!(remaining.len(), 1);
+		assert_eq!(remaining[0].id, sessions[2].id);
+
+		// other users are left alone
+		assert_eq!(
+			other.get::<Vec<Session>>("/sessions").await.unwrap().len(),
+			1
+		);
+	}
+
+	#[tokio::test]
+	async fn api_tokens() {
+		let addr = start_server(None).await.unwrap();
+		let mut admin = TestClient::new(addr);
+		let mut script = TestClient::new(addr);
+
+		let auth = Authentication {
+			username: "test-login".into(),
+			password: "test-password".into(),
+		};
+
+		for username in ["test-login", "test-script"] {
+			let login = User {
+				username: username.into(),
+				plaintext_password: Some("test-password".into()),
+				..Default::default()
+			};
+			assert!(admin.put::<User, User>("/users", login).await.is_ok());
+			admin.login(auth.clone()).await.unwrap();
+		}
+
+		assert!(
+			admin
+				.put::<CreateApiToken, NewApiToken>(
+					"/tokens",
+					CreateApiToken {
+						name: "expired".into(),
+						expires: Some(chrono::Local::now() - chrono::Duration::hours(1)),
+					},
+				)
+				.await
+				.is_err()
+		);
+
+		script
+			.login(Authentication {
+				username: "test-script".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+		let new = script
+			.put::<CreateApiToken, NewApiToken>(
+				"/tokens",
+				CreateApiToken {
+					name: "ci".into(),
+					expires: None,
+				},
+			)
+			.await
+			.unwrap();
+
+		// the token stands in for a login
+		script.set_token(new.token.clone());
+		assert!(script.post::<(), Vec<User>>("/users", ()).await.is_ok());
+		let tokens = script.get::<Vec<ApiToken>>("/tokens").await.unwrap();
+		assert_eq!(tokens.len(), 1);
+		assert_eq!(tokens[0].id, new.id);
+		assert_eq!(tokens[0].name, "ci");
+		assert!(tokens[0].last_used_at.is_some());
+
+		let logs = admin
+			.post::<Pagination, Vec<AuditLog>>("/status/log", Pagination::default())
+			.await
+			.unwrap();
+		assert!(logs.iter().any(|l| l.entry == "Create API token"));
+		assert!(logs.iter().any(|l| l.entry == "API token used"));
+
+		// someone else's token can't be revoked
+		assert!(
+			admin
+				.delete::<()>(&format!("/token/{}", new.id))
+				.await
+				.is_err()
+		);
+		assert!(script.get::<Vec<ApiToken>>("/tokens").await.is_ok());
+
+		// deactivating the user takes their tokens with them
+		let user = admin
+			.post::<(), Vec<User>>("/users", ())
+			.await
+			.unwrap()
+			.into_iter()
+			.find(|u| u.username == "test-script")
+			.unwrap();
+		admin
+			.delete::<()>(&format!("/user/{}", user.id))
+			.await
+			.unwrap();
+		assert!(script.get::<Vec<ApiToken>>("/tokens").await.is_err());
+		admin
+			.patch::<()>(&format!("/user/{}", user.id))
+			.await
+			.unwrap();
+		assert!(script.get::<Vec<ApiToken>>("/tokens").await.is_err());
+
+		let new = admin
+			.put::<CreateApiToken, NewApiToken>(
+				"/tokens",
+				CreateApiToken {
+					name: "ci".into(),
+					expires: None,
+				},
+			)
+			.await
+			.unwrap();
+		script.set_token(new.token);
+		assert!(script.get::<Vec<ApiToken>>("/tokens").await.is_ok());
+		admin
+			.delete::<()>(&format!("/token/{}", new.id))
+			.await
+			.unwrap();
+		assert!(script.get::<Vec<ApiToken>>("/tokens").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn lockout_concurrent() {
+		let addr = start_server(None).await.unwrap();
+		let mut client = TestClient::new(addr);
+
+		client
+			.put::<User, User>(
+				"/users",
+				User {
+					username: "test-victim".into(),
+					plaintext_password: Some("test-password".into()),
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+
+		let attempt = |password: &str| Authentication {
+			username: "test-victim".into(),
+			password: password.into(),
+		};
+
+		// every one of these has to count, however they interleave
+		let mut tasks = tokio::task::JoinSet::new();
+		for _ in 0..5 {
+			let mut client = TestClient::new(addr);
+			let auth = attempt("wrong-password");
+			tasks.spawn(async move { client.login(auth).await.is_err() });
+		}
+		while let Some(failed) = tasks.join_next().await {
+			assert!(failed.unwrap());
+		}
+
+		let err = client.login(attempt("test-password")).await.unwrap_err();
+		assert!(err.to_string().contains("Account Locked"), "{}", err);
+	}
+
+	#[tokio::test]
+	async fn lockout() {
+		let addr = start_server(None).await.unwrap();
+		let mut admin = TestClient::new(addr);
+		let mut client = TestClient::new(addr);
+
+		let login = User {
+			username: "test-admin".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		assert!(admin.put::<User, User>("/users", login).await.is_ok());
+		admin
+			.login(Authentication {
+				username: "test-admin".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		let victim = admin
+			.put::<User, User>(
+				"/users",
+				User {
+					username: "test-victim".into(),
+					plaintext_password: Some("test-password".into()),
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+
+		let attempt = |password: &str| Authentication {
+			username: "test-victim".into(),
+			password: password.into(),
+		};
+
+		// failures below the threshold are forgotten after a successful login
+		for _ in 0..4 {
+			assert!(client.login(attempt("wrong-password")).await.is_err());
+		}
+		client.login(attempt("test-password")).await.unwrap();
+
+		for _ in 0..4 {
+			let err = client.login(attempt("wrong-password")).await.unwrap_err();
+			assert!(err.to_string().contains("Invalid Login"));
+		}
+
+		// the fifth failure in a row locks the account, even against the right password
+		assert!(client.login(attempt("wrong-password")).await.is_err());
+		let err = client.login(attempt("test-password")).await.unwrap_err();
+		assert!(err.to_string().contains("Account Locked"));
+
+		admin
+			.post::<(), ()>(&format!("/user/{}/unlock", victim.id), ())
+			.await
+			.unwrap();
+		client.login(attempt("test-password")).await.unwrap();
+	}
+
+	#[tokio::test]
+	async fn login_logout() {
+		let mut client = TestClient::new(start_server(None).await.unwrap());
+
+		assert!(client.post::<(), Vec<User>>("/users", ()).await.is_err());
+
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+		client
+			.login(Authentication {
+				username: "test-login".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		assert!(client.post::<(), Vec<User>>("/users", ()).await.is_ok());
+	}
+
+	#[tokio::test]
+	async fn first_time_setup() {
+		let mut client = TestClient::new(start_server(None).await.unwrap());
+
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+		let login = User {
+			username: "test-login2".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
 		};
 		assert!(client.put::<User, User>("/users", login).await.is_err());
 
@@ -677,6 +1461,25 @@ mod user {
 		}
 	}
 
+	#[tokio::test]
+	async fn setup_status() {
+		let client = TestClient::new(start_server(None).await.unwrap());
+
+		// asked before anyone could have logged in
+		let status = client.get::<SetupStatus>("/status/setup").await.unwrap();
+		assert!(status.needed);
+
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+		let status = client.get::<SetupStatus>("/status/setup").await.unwrap();
+		assert!(!status.needed);
+	}
+
 	#[tokio::test]
 	async fn users_crud() {
 		let mut client = TestClient::new(start_server(None).await.unwrap());
@@ -785,6 +1588,28 @@ mod user {
 			);
 		}
 
+		// null leaves a field alone; clearing it has to be asked for
+		let erikh = created[0].clone();
+		client
+			.post::<User, ()>(
+				&format!("/user/{}", erikh.id),
+				User {
+					clear_fields: vec![UserField::Email],
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+		let cleared = client
+			.get::<User>(&format!("/user/{}", erikh.id))
+			.await
+			.unwrap();
+		assert_eq!(cleared.email, None);
+		assert_eq!(cleared.username, erikh.username);
+		assert_eq!(cleared.realname, Some("new realname".into()));
+		assert_eq!(cleared.phone, erikh.phone);
+		assert!(cleared.clear_fields.is_empty());
+
 		for item in created.clone().into_iter() {
 			client
 				.delete::<()>(&format!("/user/{}", item.id))
@@ -853,7 +1678,7 @@ mod zfs {
 
 	use crate::{
 		db::models::User,
-		server::messages::Authentication,
+		server::messages::{Authentication, OwnedZFSStat},
 		testutil::{TestClient, start_server},
 	};
 	use buckle::client::ZFSStat;
@@ -933,6 +1758,7 @@ mod zfs {
 				buckle::client::Dataset {
 					name: "dataset".into(),
 					quota: None,
+					properties: Default::default(),
 				},
 			)
 			.await
@@ -949,6 +1775,21 @@ mod zfs {
 			result[0].mountpoint,
 			Some("/buckle-test-gild-basic/dataset".into())
 		);
+		// nothing is installed, so nothing owns it
+		let owned: Vec<OwnedZFSStat> = client.post("/zfs/list_owned", "").await.unwrap();
+		assert_eq!(owned.len(), 1);
+		assert_eq!(owned[0].stat.name, "dataset");
+		assert_eq!(owned[0].owner, None);
+		let detail: buckle::client::ZFSDetail = client.post("/zfs/get", "dataset").await.unwrap();
+		assert_eq!(detail.stat.full_name, "buckle-test-gild-basic/dataset");
+		assert_eq!(detail.quota, None);
+		assert!(detail.properties.contains_key("compression"));
+		assert!(
+			client
+				.post::<_, buckle::client::ZFSDetail>("/zfs/get", "missing")
+				.await
+				.is_err()
+		);
 		client
 			.post::<_, ()>(
 				"/zfs/create_volume",
@@ -1017,6 +1858,7 @@ mod zfs {
 					modifications: buckle::client::Dataset {
 						name: "dataset2".into(),
 						quota: Some(5 * 1024 * 1024),
+						properties: Default::default(),
 					},
 				},
 			)
@@ -1054,3 +1896,618 @@ mod zfs {
 		buckle::testutil::destroy_zpool("gild-basic", Some(&file)).unwrap();
 	}
 }
+
+mod errors {
+	use crate::server::{
+		HandlerError, Service, ServiceContext,
+		axum_support::{AppError, ProblemKind},
+	};
+	use http::StatusCode;
+	use validator::Validate;
+
+	fn assert_problem(err: AppError, status: StatusCode, kind: ProblemKind) {
+		assert_eq!(err.0.status, Some(status));
+		assert_eq!(
+			err.0.r#type.map(|x| x.to_string()),
+			Some(kind.code().to_string())
+		);
+	}
+
+	#[test]
+	fn problem_kinds() {
+		assert_problem(
+			AppError::from(tonic::Status::not_found("missing")),
+			StatusCode::NOT_FOUND,
+			ProblemKind::Upstream,
+		);
+		assert_problem(
+			AppError::from(
+				Err::<(), _>(tonic::Status::permission_denied("no"))
+					.service(Service::Buckle, "zfs_list")
+					.unwrap_err(),
+			),
+			StatusCode::FORBIDDEN,
+			ProblemKind::Upstream,
+		);
+
+		let table = vec![
+			(
+				HandlerError::LoginError("Invalid Login".into()),
+				StatusCode::UNAUTHORIZED,
+				ProblemKind::Auth,
+			),
+			(
+				HandlerError::AccountLocked("locked".into()),
+				StatusCode::FORBIDDEN,
+				ProblemKind::Auth,
+			),
+			(
+				HandlerError::UserManagementError("exists".into()),
+				StatusCode::BAD_REQUEST,
+				ProblemKind::Request,
+			),
+			(
+				HandlerError::SessionError("invalid session".into()),
+				StatusCode::BAD_REQUEST,
+				ProblemKind::Request,
+			),
+			(
+				HandlerError::Unknown,
+				StatusCode::INTERNAL_SERVER_ERROR,
+				ProblemKind::Request,
+			),
+		];
+
+		for (err, status, kind) in table {
+			assert_problem(AppError::from(err), status, kind);
+		}
+
+		let invalid = crate::server::messages::CreateApiToken {
+			name: String::new(),
+			expires: None,
+		};
+		assert_problem(
+			AppError::from(invalid.validate().unwrap_err()),
+			StatusCode::UNPROCESSABLE_ENTITY,
+			ProblemKind::Validation,
+		);
+
+		assert_problem(
+			AppError::from(anyhow::anyhow!("something broke")),
+			StatusCode::INTERNAL_SERVER_ERROR,
+			ProblemKind::Uncategorized,
+		);
+
+		let passthrough = ProblemKind::Maintenance
+			.problem()
+			.with_status(StatusCode::SERVICE_UNAVAILABLE);
+		assert_problem(
+			AppError::from(passthrough),
+			StatusCode::SERVICE_UNAVAILABLE,
+			ProblemKind::Maintenance,
+		);
+	}
+
+	#[test]
+	fn grpc_status_mapping() {
+		let table = vec![
+			(tonic::Status::not_found("missing"), StatusCode::NOT_FOUND),
+			(
+				tonic::Status::invalid_argument("bad"),
+				StatusCode::BAD_REQUEST,
+			),
+			(
+				tonic::Status::permission_denied("no"),
+				StatusCode::FORBIDDEN,
+			),
+			(
+				tonic::Status::resource_exhausted("full"),
+				StatusCode::TOO_MANY_REQUESTS,
+			),
+			(
+				tonic::Status::unavailable("down"),
+				StatusCode::SERVICE_UNAVAILABLE,
+			),
+			(
+				tonic::Status::internal("broken"),
+				StatusCode::INTERNAL_SERVER_ERROR,
+			),
+		];
+
+		for (status, code) in table {
+			assert_eq!(AppError::from(status.clone()).0.status, Some(code));
+			assert_eq!(
+				AppError::from(
+					Err::<(), _>(status)
+						.service(Service::Charon, "install")
+						.unwrap_err()
+				)
+				.0
+				.status,
+				Some(code)
+			);
+		}
+	}
+}
+
+mod tls {
+	use crate::{
+		config::TlsConfig,
+		server::Server,
+		testutil::{find_listener, make_config},
+	};
+
+	async fn start(client_ca: bool) -> std::net::SocketAddr {
+		let addr = find_listener().await.unwrap();
+		let mut config = make_config(Some(addr), None).await.unwrap();
+		config.tls = Some(TlsConfig {
+			cert: "testdata/tls/cert.pem".into(),
+			key: "testdata/tls/key.pem".into(),
+			client_ca: client_ca.then(|| "testdata/tls/cert.pem".into()),
+		});
+
+		tokio::spawn(async move { Server::new(config).await.unwrap().start().await.unwrap() });
+		tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+		addr
+	}
+
+	fn client() -> reqwest::Client {
+		reqwest::Client::builder()
+			.danger_accept_invalid_certs(true)
+			.build()
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn serve_tls() {
+		let addr = start(false).await;
+		let resp = client()
+			.get(format!("https://{}/status/ping", addr))
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(resp.status(), 200);
+
+		assert!(
+			reqwest::get(format!("http://{}/status/ping", addr))
+				.await
+				.is_err()
+		);
+	}
+
+	#[tokio::test]
+	async fn require_client_cert() {
+		let addr = start(true).await;
+		assert!(
+			client()
+				.get(format!("https://{}/status/ping", addr))
+				.send()
+				.await
+				.is_err()
+		);
+	}
+}
+
+mod cors {
+	use crate::{
+		config::{Config, CorsConfig},
+		server::Server,
+		testutil::{find_listener, make_config},
+	};
+
+	async fn start(origins: Vec<String>) -> std::net::SocketAddr {
+		let addr = find_listener().await.unwrap();
+		let mut config = make_config(Some(addr), None).await.unwrap();
+		config.cors.origins = origins;
+
+		tokio::spawn(async move { Server::new(config).await.unwrap().start().await.unwrap() });
+		tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+		addr
+	}
+
+	async fn preflight(addr: std::net::SocketAddr, origin: &str) -> reqwest::Response {
+		reqwest::Client::new()
+			.request(
+				reqwest::Method::OPTIONS,
+				format!("http://{}/packages/list", addr),
+			)
+			.header("Origin", origin)
+			.header("Access-Control-Request-Method", "GET")
+			.header("Access-Control-Request-Headers", "authorization")
+			.send()
+			.await
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn same_origin_default() {
+		let addr = start(Vec::new()).await;
+		let resp = preflight(addr, "https://ui.example.com").await;
+		assert!(resp.headers().get("access-control-allow-origin").is_none());
+	}
+
+	#[tokio::test]
+	async fn allowed_origin() {
+		let addr = start(vec!["https://ui.example.com".into()]).await;
+
+		let resp = preflight(addr, "https://ui.example.com").await;
+		assert_eq!(
+			resp.headers().get("access-control-allow-origin").unwrap(),
+			"https://ui.example.com"
+		);
+		assert!(
+			resp.headers()
+				.get("access-control-allow-headers")
+				.unwrap()
+				.to_str()
+				.unwrap()
+				.contains("authorization")
+		);
+
+		let resp = preflight(addr, "https://elsewhere.example.com").await;
+		assert!(resp.headers().get("access-control-allow-origin").is_none());
+	}
+
+	#[tokio::test]
+	async fn wildcards_refused() {
+		let mut cors = CorsConfig {
+			origins: vec!["*".into()],
+			..Default::default()
+		};
+		assert!(cors.validate().is_err());
+
+		cors.origins = vec!["https://ui.example.com".into()];
+		assert!(cors.validate().is_ok());
+		cors.headers.push("*".into());
+		assert!(cors.validate().is_err());
+
+		// a config error from the server too, rather than a panic
+		let mut config = make_config(Some(find_listener().await.unwrap()), None)
+			.await
+			.unwrap();
+		config.cors.origins = vec!["*".into()];
+		assert!(Server::new(config).await.is_err());
+
+		let mut file = tempfile::NamedTempFile::new().unwrap();
+		std::io::Write::write_all(
+			&mut file,
+			b"sockets: {}\nlog_level: info\ncors:\n  origins: [\"*\"]\n",
+		)
+		.unwrap();
+		let err = Config::from_file(file.path().to_path_buf()).unwrap_err();
+		assert!(err.to_string().contains("cors origins"), "{}", err);
+	}
+}
+
+mod mock {
+	use crate::{
+		db::models::{AuditLog, User},
+		server::{
+			api::{ApiStream, CharonApi},
+			messages::*,
+		},
+		testutil::{TestClient, start_server_with_services},
+	};
+	use anyhow::{Result, anyhow};
+	use charon::{
+		ConsistencyReport, DownloadProgress, InstallPlan, InstallProgress, InstallStatus,
+		InstallStep, PackageRuntimeStatus, PackageStatus, PackageTitle, PortReachability,
+		PromptCollection, PromptResponses, UninstallData,
+	};
+	use std::sync::Arc;
+
+	// answers listing from a fixed set and refuses every install; the streamed install gets as far
+	// as provisioning before charon goes away.
+	#[derive(Debug, Default)]
+	struct Charon;
+
+	#[tonic::async_trait]
+	impl CharonApi for Charon {
+		async fn ping(&self) -> Result<()> {
+			Ok(())
+		}
+
+		async fn get_prompts(&self, _: &str, _: &str) -> Result<PromptCollection> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn get_responses(&self, _: &str) -> Result<PromptResponses> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn set_responses(&self, _: &str, _: PromptResponses) -> Result<()> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn set_responses_batch(&self, _: Vec<(String, PromptResponses)>) -> Result<()> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn list_installed(&self) -> Result<Vec<PackageTitle>> {
+			Ok(vec![PackageTitle {
+				name: "mocked".into(),
+				version: "1.0.0".into(),
+			}])
+		}
+
+		async fn list(&self) -> Result<Vec<PackageStatus>> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn list_versions(&self, name: &str) -> Result<Vec<String>> {
+			if name == "mocked" {
+				Ok(vec!["1.0.0".into()])
+			} else {
+				Err(tonic::Status::not_found("no such package").into())
+			}
+		}
+
+		async fn list_with_status(&self) -> Result<Vec<PackageRuntimeStatus>> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn installed(&self, _: &str, _: &str) -> Result<Option<InstallStatus>> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn check_reachable(&self, _: &str, _: &str) -> Result<Vec<PortReachability>> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn plan_install(&self, _: &str, _: &str) -> Result<InstallPlan> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn check_consistency(&self) -> Result<ConsistencyReport> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn install(&self, name: &str, _: &str) -> Result<()> {
+			Err(tonic::Status::failed_precondition(format!("{} cannot be installed", name)).into())
+		}
+
+		async fn reconfigure(&self, _: &str, _: &str) -> Result<()> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn rollback(&self, _: &str, _: &str) -> Result<()> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn uninstall(&self, _: &str, _: &str, _: bool) -> Result<()> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn install_with_progress(
+			&self, _: &str, _: &str,
+		) -> Result<ApiStream<InstallProgress>> {
+			Ok(Box::pin(tokio_stream::iter(vec![
+				Ok(InstallProgress {
+					step: InstallStep::Pulling,
+					error: None,
+				}),
+				Ok(InstallProgress {
+					step: InstallStep::Provisioning,
+					error: None,
+				}),
+				Err(tonic::Status::unavailable("connection reset").into()),
+			])))
+		}
+
+		async fn uninstall_with_progress(
+			&self, _: &str, _: &str, _: bool,
+		) -> Result<ApiStream<InstallProgress>> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn remove_version(&self, _: &str, _: &str) -> Result<()> {
+			Err(anyhow!("not mocked"))
+		}
+
+		async fn prefetch_image(&self, _: &str, _: &str) -> Result<ApiStream<DownloadProgress>> {
+			Err(anyhow!("not mocked"))
+		}
+	}
+
+	#[tokio::test]
+	async fn handlers_without_services() {
+		// nothing listens on this socket, which stands in for buckle being down
+		let buckle = Arc::new(buckle::client::Client::new("tmp/no-buckle".into()).unwrap());
+		let mut client = TestClient::new(
+			start_server_with_services(buckle, Arc::new(Charon))
+				.await
+				.unwrap(),
+		);
+
+		let login = User {
+			username: "test-login".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		assert!(client.put::<User, User>("/users", login).await.is_ok());
+		client
+			.login(Authentication {
+				username: "test-login".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		let installed = client
+			.get::<Vec<PackageTitle>>("/packages/list_installed")
+			.await
+			.unwrap();
+		assert_eq!(installed.len(), 1);
+		assert_eq!(installed[0].name, "mocked");
+
+		assert_eq!(
+			client
+				.get::<Vec<String>>("/packages/versions/mocked")
+				.await
+				.unwrap(),
+			vec!["1.0.0"]
+		);
+		assert!(
+			client
+				.get::<Vec<String>>("/packages/versions/missing")
+				.await
+				.is_err()
+		);
+
+		let err = client
+			.post::<PackageTitle, ()>(
+				"/packages/install",
+				PackageTitle {
+					name: "mocked".into(),
+					version: "1.0.0".into(),
+				},
+			)
+			.await
+			.unwrap_err();
+		assert!(err.to_string().contains("mocked cannot be installed"));
+
+		let log = client
+			.post::<Pagination, Vec<AuditLog>>("/status/log", Pagination::default())
+			.await
+			.unwrap();
+		let entry = log.iter().find(|x| x.entry == "Install package").unwrap();
+		assert_eq!(entry.service.as_deref(), Some("charon"));
+		assert_eq!(entry.method.as_deref(), Some("install"));
+		assert_eq!(entry.code.as_deref(), Some("FailedPrecondition"));
+
+		let body = client
+			.stream_post(
+				"/packages/install/progress",
+				PackageTitle {
+					name: "mocked".into(),
+					version: "1.0.0".into(),
+				},
+			)
+			.await
+			.unwrap()
+			.text()
+			.await
+			.unwrap();
+		let steps = body
+			.lines()
+			.filter_map(|x| x.strip_prefix("data:"))
+			.map(|x| serde_json::from_str::<InstallProgress>(x.trim()).unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(
+			steps.iter().map(|x| x.step).collect::<Vec<_>>(),
+			vec![
+				InstallStep::Pulling,
+				InstallStep::Provisioning,
+				InstallStep::Finished
+			]
+		);
+		assert!(
+			steps
+				.last()
+				.unwrap()
+				.error
+				.as_ref()
+				.unwrap()
+				.contains("connection reset")
+		);
+
+		assert!(
+			client
+				.stream_post(
+					"/packages/uninstall/progress",
+					UninstallData {
+						name: "mocked".into(),
+						version: "1.0.0".into(),
+						purge: false,
+					},
+				)
+				.await
+				.is_err()
+		);
+
+		let log = client
+			.post::<Pagination, Vec<AuditLog>>("/status/log", Pagination::default())
+			.await
+			.unwrap();
+		assert_eq!(
+			log.iter()
+				.filter(|x| x.entry == "Install package" && x.error.is_some())
+				.count(),
+			2
+		);
+		let entry = log.iter().find(|x| x.entry == "Uninstall package").unwrap();
+		assert_eq!(entry.method.as_deref(), Some("uninstall_with_progress"));
+
+		let ping = client.get::<PingResult>("/status/ping").await.unwrap();
+		let health = ping.health.unwrap();
+		assert!(health.buckle.error.is_some());
+		assert!(health.charon.error.is_none());
+		assert!(ping.info.is_none());
+	}
+
+	#[tokio::test]
+	async fn power() {
+		use buckle::systemd::PowerAction;
+
+		// buckle isn't running, so nothing actually goes down
+		let buckle = Arc::new(buckle::client::Client::new("tmp/no-buckle".into()).unwrap());
+		let mut client = TestClient::new(
+			start_server_with_services(buckle, Arc::new(Charon))
+				.await
+				.unwrap(),
+		);
+
+		let login = User {
+			username: "test-power".into(),
+			plaintext_password: Some("test-password".into()),
+			..Default::default()
+		};
+		assert!(client.put::<User, User>("/users", login).await.is_ok());
+		client
+			.login(Authentication {
+				username: "test-power".into(),
+				password: "test-password".into(),
+			})
+			.await
+			.unwrap();
+
+		assert!(
+			client
+				.post::<PowerAction, ()>("/status/power", PowerAction::Reboot)
+				.await
+				.is_err()
+		);
+
+		let log = client
+			.post::<Pagination, Vec<AuditLog>>("/status/log", Pagination::default())
+			.await
+			.unwrap();
+		let entry = log.iter().find(|x| x.entry == "Reboot host").unwrap();
+		assert_eq!(entry.service.as_deref(), Some("buckle"));
+		assert_eq!(entry.method.as_deref(), Some("power"));
+
+		// scripts don't get to turn the host off
+		let token = client
+			.put::<CreateApiToken, NewApiToken>(
+				"/tokens",
+				CreateApiToken {
+					name: "power".into(),
+					expires: None,
+				},
+			)
+			.await
+			.unwrap();
+		client.set_token(token.token);
+		let err = client
+			.post::<PowerAction, ()>("/status/power", PowerAction::Poweroff)
+			.await
+			.unwrap_err();
+		assert!(err.to_string().contains("login session"), "{}", err);
+
+		let log = client
+			.post::<Pagination, Vec<AuditLog>>("/status/log", Pagination::default())
+			.await
+			.unwrap();
+		assert!(!log.iter().any(|x| x.entry == "Power off host"));
+	}
+}
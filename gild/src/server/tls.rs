@@ -0,0 +1,81 @@
+use crate::config::TlsConfig;
+use anyhow::{Result, anyhow};
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::{RootCertStore, ServerConfig, server::WebPkiClientVerifier};
+use std::{fs::File, io::BufReader, path::Path, sync::Arc, time::SystemTime};
+use tracing::{error, info};
+
+// certificates are usually renewed by something outside of gild (f.e. certbot), so we poll for
+// changes instead of requiring a restart.
+const RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn certs(path: &Path) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>> {
+	Ok(
+		rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))
+			.collect::<std::result::Result<Vec<_>, _>>()?,
+	)
+}
+
+fn server_config(config: &TlsConfig) -> Result<ServerConfig> {
+	let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&config.key)?))?
+		.ok_or_else(|| anyhow!("no private key found in {}", config.key.display()))?;
+
+	let builder = ServerConfig::builder();
+	let builder = if let Some(ca) = &config.client_ca {
+		let mut roots = RootCertStore::empty();
+		for cert in certs(ca)? {
+			roots.add(cert)?;
+		}
+
+		builder.with_client_cert_verifier(WebPkiClientVerifier::builder(Arc::new(roots)).build()?)
+	} else {
+		builder.with_no_client_auth()
+	};
+
+	let mut server = builder.with_single_cert(certs(&config.cert)?, key)?;
+	server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+	Ok(server)
+}
+
+fn modified(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+	[
+		Some(&config.cert),
+		Some(&config.key),
+		config.client_ca.as_ref(),
+	]
+	.into_iter()
+	.flatten()
+	.map(|path| std::fs::metadata(path).and_then(|x| x.modified()).ok())
+	.collect()
+}
+
+pub(crate) fn rustls_config(config: &TlsConfig) -> Result<RustlsConfig> {
+	Ok(RustlsConfig::from_config(Arc::new(server_config(config)?)))
+}
+
+pub(crate) async fn watch(config: TlsConfig, rustls: RustlsConfig) {
+	let mut last = modified(&config);
+
+	loop {
+		tokio::time::sleep(RELOAD_INTERVAL).await;
+
+		let current = modified(&config);
+		if current == last {
+			continue;
+		}
+
+		// the cert and key are rarely replaced at the same instant, so a failure here is retried
+		// on the next pass rather than being treated as final.
+		match server_config(&config) {
+			Ok(server) => {
+				rustls.reload_from_config(Arc::new(server));
+				info!("Reloaded TLS certificate from {}", config.cert.display());
+				last = current;
+			}
+			Err(e) => error!(
+				"Could not reload TLS certificate, keeping the old one: {}",
+				e
+			),
+		}
+	}
+}
@@ -1,6 +1,10 @@
 use crate::{
 	config::{Config, SocketConfig},
-	server::{Server, messages::*},
+	server::{
+		Server,
+		api::{BuckleApi, CharonApi},
+		messages::*,
+	},
 };
 use anyhow::{Result, anyhow};
 use buckle::{config::ZFSConfig, testutil::make_server};
@@ -26,26 +30,44 @@ pub async fn find_listener() -> Result<SocketAddr> {
 }
 
 pub async fn make_config(addr: Option<SocketAddr>, poolname: Option<String>) -> Result<Config> {
-	std::fs::create_dir_all("tmp")?;
-	let tf = NamedTempFile::new_in("tmp")?;
-	let (_, dbfile) = tf.keep()?;
-
-	let mut key: [u8; 64] = [0u8; 64];
-	let mut salt: [u8; 32] = [0u8; 32];
-	key.fill(&mut rand::rng());
-	salt.fill(&mut rand::rng());
-
 	let buckle_config = if let Some(poolname) = poolname {
+		let socket = buckle::testutil::find_listener()?;
 		Some(buckle::config::Config {
-			socket: buckle::testutil::find_listener()?,
-			zfs: ZFSConfig { pool: poolname },
+			port_state: socket.with_extension("ports.json"),
+			socket,
+			zfs: ZFSConfig {
+				pool: poolname,
+				timeout: buckle::config::DEFAULT_ZFS_TIMEOUT,
+				default_quota: None,
+			},
 			log_level: buckle::config::LogLevel::Error,
+			commands: Default::default(),
 		})
 	} else {
 		None
 	};
 
 	let socket = make_server(buckle_config.clone()).await?;
+	let sockets = SocketConfig {
+		buckle: socket.clone(),
+		charon: start_charon("testdata/charon".into(), socket).await?,
+	};
+
+	make_config_with_sockets(addr, sockets).await
+}
+
+// a config that doesn't start buckle or charon; the sockets are used as given.
+pub async fn make_config_with_sockets(
+	addr: Option<SocketAddr>, sockets: SocketConfig,
+) -> Result<Config> {
+	std::fs::create_dir_all("tmp")?;
+	let tf = NamedTempFile::new_in("tmp")?;
+	let (_, dbfile) = tf.keep()?;
+
+	let mut key: [u8; 64] = [0u8; 64];
+	let mut salt: [u8; 32] = [0u8; 32];
+	key.fill(&mut rand::rng());
+	salt.fill(&mut rand::rng());
 
 	Ok(Config {
 		listen: if let Some(addr) = addr {
@@ -53,15 +75,15 @@ pub async fn make_config(addr: Option<SocketAddr>, poolname: Option<String>) ->
 		} else {
 			find_listener().await?
 		},
-		sockets: SocketConfig {
-			buckle: socket.clone(),
-			charon: start_charon("testdata/charon".into(), socket).await?,
-		},
-
+		sockets,
 		db: dbfile,
 		signing_key: key.to_vec(),
 		signing_key_salt: salt.to_vec(),
 		log_level: buckle::config::LogLevel::Error,
+		tls: None,
+		cors: Default::default(),
+		password: Default::default(),
+		lockout: Default::default(),
 	})
 }
 
@@ -78,23 +100,49 @@ pub async fn start_server(poolname: Option<String>) -> Result<SocketAddr> {
 	Ok(ret)
 }
 
+// starts gild against the given buckle and charon implementations instead of running the real
+// services.
+pub async fn start_server_with_services(
+	buckle: Arc<dyn BuckleApi>, charon: Arc<dyn CharonApi>,
+) -> Result<SocketAddr> {
+	let addr = find_listener().await?;
+	let config = make_config_with_sockets(
+		Some(addr),
+		SocketConfig {
+			buckle: "tmp/no-buckle".into(),
+			charon: "tmp/no-charon".into(),
+		},
+	)
+	.await?;
+	let server = Server::with_services(config, buckle, charon).await?;
+	tokio::spawn(async move { server.start().await.unwrap() });
+	tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+	Ok(addr)
+}
+
 pub async fn start_charon(registry: PathBuf, buckle_socket: PathBuf) -> Result<PathBuf> {
 	std::fs::create_dir_all("tmp")?;
 	let tf = NamedTempFile::new_in("tmp")?;
 	let (_, path) = tf.keep()?;
 	let p2 = path.clone();
+	// kept with the registry, since the sealed secrets in it outlive any one test server
+	let secret_key = registry.join("secret.key");
 	tokio::spawn(async move {
 		charon::Server::new(charon::Config {
 			registry: charon::RegistryConfig {
 				path: registry,
 				url: None,
+				strict_sync: false,
 			},
 			socket: p2,
 			log_level: None,
 			debug: Some(true),
 			systemd_root: None,
 			charon_path: None,
+			secret_key: Some(secret_key),
 			buckle_socket,
+			operations: Default::default(),
+			commands: Default::default(),
 		})
 		.start()
 		.unwrap()
@@ -129,6 +177,11 @@ impl TestClient {
 		Ok(())
 	}
 
+	// authenticate with something other than a login, e.g. an API token
+	pub fn set_token(&mut self, token: String) {
+		self.token = Some(token);
+	}
+
 	pub async fn get<T>(&self, path: &str) -> Result<T>
 	where
 		T: for<'de> Deserialize<'de> + DeserializeOwned + Default,
@@ -157,6 +210,56 @@ impl TestClient {
 		}
 	}
 
+	// for endpoints that stream, returns the response once the headers have arrived
+	pub async fn stream(&self, path: &str) -> Result<reqwest::Response> {
+		let mut req = self.client.get(&format!("{}{}", self.baseurl, path));
+
+		if let Some(token) = &self.token {
+			req = req.header("Authorization", &format!("Bearer {}", token))
+		}
+
+		let resp = req.send().await?;
+
+		if resp.status() != 200 {
+			return Err(anyhow!(
+				"{}",
+				String::from_utf8(resp.bytes().await?.to_vec())?
+			));
+		}
+
+		Ok(resp)
+	}
+
+	// stream(), for endpoints that take a body
+	pub async fn stream_post<I>(&self, path: &str, input: I) -> Result<reqwest::Response>
+	where
+		I: Serialize,
+	{
+		let mut inner = Vec::with_capacity(65535);
+		let mut buf = std::io::Cursor::new(&mut inner);
+		ciborium::into_writer(&input, &mut buf)?;
+
+		let mut req = self
+			.client
+			.post(&format!("{}{}", self.baseurl, path))
+			.header("Content-type", "application/cbor");
+
+		if let Some(token) = &self.token {
+			req = req.header("Authorization", &format!("Bearer {}", token))
+		}
+
+		let resp = req.body(buf.into_inner().to_vec()).send().await?;
+
+		if resp.status() != 200 {
+			return Err(anyhow!(
+				"{}",
+				String::from_utf8(resp.bytes().await?.to_vec())?
+			));
+		}
+
+		Ok(resp)
+	}
+
 	pub async fn patch<T>(&self, path: &str) -> Result<T>
 	where
 		T: for<'de> Deserialize<'de> + DeserializeOwned + Default,